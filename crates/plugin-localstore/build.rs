@@ -6,7 +6,42 @@
 // option. This file may not be copied, modified, or distributed
 // except according to those terms.
 
-const COMMANDS: &[&str] = &[];
+const COMMANDS: &[&str] = &[
+    "acquire_lock",
+    "clear",
+    "compare_and_swap",
+    "create_backup",
+    "create_index",
+    "disable_history",
+    "disable_search",
+    "drop_index",
+    "enable_history",
+    "enable_search",
+    "export_file",
+    "find",
+    "get",
+    "get_many",
+    "get_revision",
+    "get_versioned",
+    "get_with_meta",
+    "history",
+    "import_file",
+    "increment",
+    "list",
+    "list_backups",
+    "list_page",
+    "release_lock",
+    "remove",
+    "remove_many",
+    "renew_lock",
+    "restore_backup",
+    "revert",
+    "search",
+    "set",
+    "set_many",
+    "set_versioned",
+    "set_with_ttl",
+];
 
 fn main() {
     tauri_plugin::Builder::new(COMMANDS)
@@ -0,0 +1,404 @@
+// Copyright © 2024 Crabby Craft - All Rights Reserved.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use serde::Serialize;
+use std::cmp::Reverse;
+use std::ffi::OsString;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use tokio::fs;
+
+use crate::keyv::{unix_millis, Keyv, KeyvError};
+
+/// Number of backups kept by a [`BackupManager`] unless configured otherwise.
+pub const DEFAULT_BACKUP_RETENTION: usize = 5;
+
+/// Name of the directory, next to the database, where backups are written.
+const BACKUP_DIR_NAME: &str = "backups";
+
+/// A backup of the database created by [`BackupManager::create`].
+#[derive(Debug, Clone, Serialize)]
+pub struct BackupInfo {
+    /// The file name of the backup, used to restore it.
+    pub name: String,
+    pub path: PathBuf,
+    /// When the backup was created, in milliseconds since the Unix epoch.
+    pub created_at: i64,
+    /// Size of the backup in bytes.
+    pub size: u64,
+}
+
+/// Creates timestamped backups of a database, keeps the most recent ones and
+/// restores one of them when the application starts.
+///
+/// Backups are written to a `backups` directory next to the database and named
+/// after the database file and their creation time, e.g. `app-1718000000000.db`.
+/// A restore cannot replace the database while it is open, so `schedule_restore`
+/// only records the backup to restore, and `apply_pending_restore` copies it over
+/// the database before it is opened on the next start.
+#[derive(Debug, Clone)]
+pub struct BackupManager {
+    db_path: PathBuf,
+    dir: PathBuf,
+    retention: usize,
+}
+
+impl BackupManager {
+    /// Creates a manager for the database at `db_path`, e.g. the path returned
+    /// by [`get_db_path`](crate::get_db_path).
+    pub fn new<P: Into<PathBuf>>(db_path: P) -> Self {
+        let db_path = db_path.into();
+        let dir = db_path
+            .parent()
+            .map(|parent| parent.join(BACKUP_DIR_NAME))
+            .unwrap_or_else(|| PathBuf::from(BACKUP_DIR_NAME));
+
+        Self {
+            db_path,
+            dir,
+            retention: DEFAULT_BACKUP_RETENTION,
+        }
+    }
+
+    /// Sets the directory backups are written to.
+    pub fn dir<P: Into<PathBuf>>(mut self, dir: P) -> Self {
+        self.dir = dir.into();
+        self
+    }
+
+    /// Sets how many backups are kept, the oldest being deleted first. At
+    /// least one backup is always kept.
+    pub fn retention(mut self, retention: usize) -> Self {
+        self.retention = retention.max(1);
+        self
+    }
+
+    /// Backs up the database behind `keyv` while it stays in use, then deletes
+    /// the backups exceeding the retention.
+    ///
+    /// # Arguments
+    ///
+    /// * `keyv` - Any handle of the store opened on the managed database.
+    ///
+    /// # Returns
+    ///
+    /// Returns the new backup, or a `KeyvError` if the copy fails.
+    ///
+    /// # Examples
+    ///
+    /// ```rust,no_run
+    /// # use tauri_plugin_localstore::keyv::Keyv;
+    /// # use tauri_plugin_localstore::BackupManager;
+    /// # async fn run(keyv: Keyv) -> Result<(), Box<dyn std::error::Error>> {
+    /// let backups = BackupManager::new("/path/to/app.db").retention(10);
+    /// let backup = backups.create(&keyv).await?;
+    /// println!("Backed up to {}", backup.path.display());
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn create(&self, keyv: &Keyv) -> Result<BackupInfo, KeyvError> {
+        fs::create_dir_all(&self.dir).await?;
+
+        let created_at = unix_millis();
+        let name = format!("{}-{}.db", self.stem(), created_at);
+        let path = self.dir.join(&name);
+
+        // Written under a temporary name, so that an interrupted backup is
+        // never mistaken for a complete one.
+        let mut temp_name = OsString::from(&name);
+        temp_name.push(".tmp");
+        let temp_path = self.dir.join(temp_name);
+
+        remove_if_exists(&temp_path).await?;
+
+        if let Err(e) = keyv.backup(&temp_path).await {
+            let _ = remove_if_exists(&temp_path).await;
+            return Err(e);
+        }
+
+        fs::rename(&temp_path, &path).await?;
+
+        let size = fs::metadata(&path).await?.len();
+        log::info!("Backed up the database to {}", path.display());
+
+        self.rotate().await?;
+
+        Ok(BackupInfo {
+            name,
+            path,
+            created_at,
+            size,
+        })
+    }
+
+    /// Lists the backups of the database, most recent first.
+    pub async fn list(&self) -> Result<Vec<BackupInfo>, KeyvError> {
+        let mut entries = match fs::read_dir(&self.dir).await {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e.into()),
+        };
+
+        let mut backups = Vec::new();
+
+        while let Some(entry) = entries.next_entry().await? {
+            let Some(name) = entry.file_name().to_str().map(|name| name.to_string()) else {
+                continue;
+            };
+            let Some(created_at) = self.parse_name(&name) else {
+                continue;
+            };
+
+            backups.push(BackupInfo {
+                name,
+                path: entry.path(),
+                created_at,
+                size: entry.metadata().await?.len(),
+            });
+        }
+
+        backups.sort_by_key(|backup| Reverse(backup.created_at));
+
+        Ok(backups)
+    }
+
+    /// Records a backup to be restored the next time the application starts,
+    /// replacing any restore scheduled before.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name of a backup returned by `list` or `create`.
+    ///
+    /// # Returns
+    ///
+    /// Returns the backup that will be restored, or `KeyvError::BackupNotFound`
+    /// if there is no backup with that name.
+    pub async fn schedule_restore(&self, name: &str) -> Result<BackupInfo, KeyvError> {
+        let backup = self.find(name).await?;
+
+        fs::write(self.restore_marker_path(), &backup.name).await?;
+        log::info!("Scheduled the restore of backup {} on the next start", backup.name);
+
+        Ok(backup)
+    }
+
+    /// Cancels the restore scheduled with `schedule_restore`. Returns `false`
+    /// if none was scheduled.
+    pub async fn cancel_restore(&self) -> Result<bool, KeyvError> {
+        remove_if_exists(&self.restore_marker_path()).await
+    }
+
+    /// Replaces the database with the backup scheduled by `schedule_restore`,
+    /// if any. Must be called before the database is opened.
+    ///
+    /// # Returns
+    ///
+    /// Returns the restored backup, `None` if no restore was scheduled, or a
+    /// `KeyvError` if the database could not be replaced.
+    pub async fn apply_pending_restore(&self) -> Result<Option<BackupInfo>, KeyvError> {
+        let marker_path = self.restore_marker_path();
+
+        let name = match fs::read_to_string(&marker_path).await {
+            Ok(name) => name.trim().to_string(),
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+
+        let backup = match self.find(&name).await {
+            Ok(backup) => backup,
+            Err(KeyvError::BackupNotFound(_)) => {
+                log::error!("The backup {} scheduled for restore no longer exists", name);
+                fs::remove_file(&marker_path).await?;
+                return Ok(None);
+            }
+            Err(e) => return Err(e),
+        };
+
+        let temp_path = self.sibling_path(".restoring");
+        fs::copy(&backup.path, &temp_path).await?;
+
+        // Left over journals of the current database would be applied to the
+        // restored one, so they are discarded with it.
+        for suffix in ["-wal", "-shm", "-journal"] {
+            remove_if_exists(&self.sibling_path(suffix)).await?;
+        }
+
+        fs::rename(&temp_path, &self.db_path).await?;
+        fs::remove_file(&marker_path).await?;
+
+        log::info!("Restored the database from backup {}", backup.name);
+
+        Ok(Some(backup))
+    }
+
+    async fn find(&self, name: &str) -> Result<BackupInfo, KeyvError> {
+        self.list()
+            .await?
+            .into_iter()
+            .find(|backup| backup.name == name)
+            .ok_or_else(|| KeyvError::BackupNotFound(name.to_string()))
+    }
+
+    /// Deletes the oldest backups exceeding the retention.
+    async fn rotate(&self) -> Result<(), KeyvError> {
+        for backup in self.list().await?.into_iter().skip(self.retention) {
+            fs::remove_file(&backup.path).await?;
+            log::debug!("Deleted the old backup {}", backup.name);
+        }
+
+        Ok(())
+    }
+
+    fn stem(&self) -> String {
+        self.db_path
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().into_owned())
+            .unwrap_or_default()
+    }
+
+    /// Returns the creation time encoded in a backup name, or `None` if `name`
+    /// is not a backup of this database.
+    fn parse_name(&self, name: &str) -> Option<i64> {
+        name.strip_prefix(&self.stem())?
+            .strip_prefix('-')?
+            .strip_suffix(".db")?
+            .parse()
+            .ok()
+    }
+
+    /// Returns the path of the database with `suffix` appended.
+    fn sibling_path(&self, suffix: &str) -> PathBuf {
+        let mut path = self.db_path.clone().into_os_string();
+        path.push(suffix);
+        PathBuf::from(path)
+    }
+
+    fn restore_marker_path(&self) -> PathBuf {
+        self.sibling_path(".restore")
+    }
+}
+
+/// Removes a file, returning `false` if it did not exist.
+async fn remove_if_exists(path: &Path) -> Result<bool, KeyvError> {
+    match fs::remove_file(path).await {
+        Ok(()) => Ok(true),
+        Err(e) if e.kind() == ErrorKind::NotFound => Ok(false),
+        Err(e) => Err(e.into()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::keyv::KeyvStoreBuilder;
+    use serde_json::json;
+    use std::time::Duration;
+
+    async fn open(path: &Path) -> Keyv {
+        let store = KeyvStoreBuilder::new().uri(path).build().await.unwrap();
+        Keyv::try_new(store).await.unwrap()
+    }
+
+    fn file_names(dir: &Path) -> Vec<String> {
+        let mut names: Vec<String> = std::fs::read_dir(dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+            .collect();
+        names.sort();
+        names
+    }
+
+    #[tokio::test]
+    async fn backups_are_rotated_to_the_retention() {
+        let dir = tempfile::tempdir().unwrap();
+        let db_path = dir.path().join("app.db");
+        let keyv = open(&db_path).await;
+        keyv.set("a", 1).await.unwrap();
+        let backups = BackupManager::new(&db_path).retention(2);
+
+        let mut created = Vec::new();
+        for _ in 0..3 {
+            created.push(backups.create(&keyv).await.unwrap());
+            // Backups are named after their creation time in milliseconds.
+            tokio::time::sleep(Duration::from_millis(2)).await;
+        }
+
+        let listed: Vec<String> = backups.list().await.unwrap().into_iter().map(|b| b.name).collect();
+        assert_eq!(listed, [created[2].name.clone(), created[1].name.clone()]);
+        assert!(created[0].name.starts_with("app-") && created[0].name.ends_with(".db"));
+        assert!(created[2].size > 0);
+
+        // Only complete backups of this database are listed and rotated.
+        std::fs::write(dir.path().join("backups/app-1.db.tmp"), "").unwrap();
+        std::fs::write(dir.path().join("backups/other-1.db"), "").unwrap();
+        assert_eq!(backups.list().await.unwrap().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn failed_backups_leave_no_temporary_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let backups = BackupManager::new(dir.path().join("app.db"));
+
+        // The in-memory backend cannot be backed up.
+        assert!(backups.create(&Keyv::default()).await.is_err());
+        assert!(file_names(&dir.path().join("backups")).is_empty());
+    }
+
+    #[tokio::test]
+    async fn scheduled_restores_replace_the_database_on_the_next_start() {
+        let dir = tempfile::tempdir().unwrap();
+        let db_path = dir.path().join("app.db");
+        let backups = BackupManager::new(&db_path);
+
+        let keyv = open(&db_path).await;
+        keyv.set("a", 1).await.unwrap();
+        let backup = backups.create(&keyv).await.unwrap();
+        keyv.set("a", 2).await.unwrap();
+        drop(keyv);
+
+        assert!(matches!(
+            backups.schedule_restore("app-1.db").await,
+            Err(KeyvError::BackupNotFound(_))
+        ));
+        backups.schedule_restore(&backup.name).await.unwrap();
+        assert_eq!(
+            std::fs::read_to_string(dir.path().join("app.db.restore")).unwrap(),
+            backup.name
+        );
+
+        for suffix in ["-wal", "-shm", "-journal"] {
+            std::fs::write(dir.path().join(format!("app.db{}", suffix)), "stale").unwrap();
+        }
+
+        let restored = backups.apply_pending_restore().await.unwrap().unwrap();
+        assert_eq!(restored.name, backup.name);
+        assert_eq!(file_names(dir.path()), ["app.db", "backups"]);
+        assert!(backups.apply_pending_restore().await.unwrap().is_none());
+
+        assert_eq!(open(&db_path).await.get("a").await.unwrap(), Some(json!(1)));
+    }
+
+    #[tokio::test]
+    async fn restores_can_be_cancelled_or_go_missing() {
+        let dir = tempfile::tempdir().unwrap();
+        let db_path = dir.path().join("app.db");
+        let backups = BackupManager::new(&db_path);
+        let keyv = open(&db_path).await;
+        let backup = backups.create(&keyv).await.unwrap();
+
+        backups.schedule_restore(&backup.name).await.unwrap();
+        assert!(backups.cancel_restore().await.unwrap());
+        assert!(!backups.cancel_restore().await.unwrap());
+
+        // A marker naming a deleted backup is dropped without touching the database.
+        backups.schedule_restore(&backup.name).await.unwrap();
+        std::fs::remove_file(&backup.path).unwrap();
+        assert!(backups.apply_pending_restore().await.unwrap().is_none());
+        assert!(!dir.path().join("app.db.restore").exists());
+    }
+}
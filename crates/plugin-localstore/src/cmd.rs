@@ -0,0 +1,445 @@
+// Copyright © 2024 Crabby Craft - All Rights Reserved.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use serde_json::value::Value as JsonValue;
+use std::collections::HashMap;
+use std::ffi::OsString;
+use std::io::{Error as IoError, ErrorKind};
+use std::path::{Component, Path, PathBuf};
+use std::time::Duration;
+use tauri::path::BaseDirectory;
+use tauri::{AppHandle, Manager, Runtime, State};
+
+use crate::keyv::{
+    lease_owner, ExportFormat, FindQuery, ImportMode, ImportReport, ListPage, ListQuery, Revision, SearchHit,
+    SearchQuery, StoreModel, VersionedValue,
+};
+use crate::{BackupInfo, Databases};
+
+#[tauri::command(rename_all = "snake_case")]
+pub async fn get(
+    databases: State<'_, Databases>,
+    database: Option<String>,
+    key: String,
+) -> tauri::Result<Option<JsonValue>> {
+    let keyv = &databases.resolve(database.as_deref())?.keyv;
+    Ok(keyv.get(&key).await?)
+}
+
+#[tauri::command(rename_all = "snake_case")]
+pub async fn get_many(
+    databases: State<'_, Databases>,
+    database: Option<String>,
+    keys: Vec<String>,
+) -> tauri::Result<HashMap<String, JsonValue>> {
+    let keyv = &databases.resolve(database.as_deref())?.keyv;
+    Ok(keyv.get_many(&keys).await?)
+}
+
+#[tauri::command(rename_all = "snake_case")]
+pub async fn get_versioned(
+    databases: State<'_, Databases>,
+    database: Option<String>,
+    key: String,
+) -> tauri::Result<Option<VersionedValue>> {
+    let keyv = &databases.resolve(database.as_deref())?.keyv;
+    Ok(keyv.get_versioned(&key).await?)
+}
+
+#[tauri::command(rename_all = "snake_case")]
+pub async fn get_with_meta(
+    databases: State<'_, Databases>,
+    database: Option<String>,
+    key: String,
+) -> tauri::Result<Option<StoreModel>> {
+    let keyv = &databases.resolve(database.as_deref())?.keyv;
+    Ok(keyv.get_with_meta(&key).await?)
+}
+
+#[tauri::command(rename_all = "snake_case")]
+pub async fn set(
+    databases: State<'_, Databases>,
+    database: Option<String>,
+    key: String,
+    value: JsonValue,
+) -> tauri::Result<Option<StoreModel>> {
+    let keyv = &databases.resolve(database.as_deref())?.keyv;
+    Ok(keyv.set(&key, value).await?)
+}
+
+#[tauri::command(rename_all = "snake_case")]
+pub async fn set_many(
+    databases: State<'_, Databases>,
+    database: Option<String>,
+    entries: HashMap<String, JsonValue>,
+) -> tauri::Result<()> {
+    let keyv = &databases.resolve(database.as_deref())?.keyv;
+    Ok(keyv.set_many(entries).await?)
+}
+
+#[tauri::command(rename_all = "snake_case")]
+pub async fn set_with_ttl(
+    databases: State<'_, Databases>,
+    database: Option<String>,
+    key: String,
+    value: JsonValue,
+    ttl: u64,
+) -> tauri::Result<Option<StoreModel>> {
+    let keyv = &databases.resolve(database.as_deref())?.keyv;
+    Ok(keyv.set_with_ttl(&key, value, ttl).await?)
+}
+
+#[tauri::command(rename_all = "snake_case")]
+pub async fn set_versioned(
+    databases: State<'_, Databases>,
+    database: Option<String>,
+    key: String,
+    value: JsonValue,
+    expected_version: Option<u64>,
+) -> tauri::Result<u64> {
+    let keyv = &databases.resolve(database.as_deref())?.keyv;
+    Ok(keyv.set_versioned(&key, value, expected_version).await?)
+}
+
+#[tauri::command(rename_all = "snake_case")]
+pub async fn compare_and_swap(
+    databases: State<'_, Databases>,
+    database: Option<String>,
+    key: String,
+    expected: Option<JsonValue>,
+    value: JsonValue,
+) -> tauri::Result<bool> {
+    let keyv = &databases.resolve(database.as_deref())?.keyv;
+    Ok(keyv.compare_and_swap(&key, expected, value).await?)
+}
+
+#[tauri::command(rename_all = "snake_case")]
+pub async fn increment(
+    databases: State<'_, Databases>,
+    database: Option<String>,
+    key: String,
+    delta: i64,
+) -> tauri::Result<i64> {
+    let keyv = &databases.resolve(database.as_deref())?.keyv;
+    Ok(keyv.increment(&key, delta).await?)
+}
+
+#[tauri::command(rename_all = "snake_case")]
+pub async fn list(databases: State<'_, Databases>, database: Option<String>) -> tauri::Result<Vec<StoreModel>> {
+    let keyv = &databases.resolve(database.as_deref())?.keyv;
+    Ok(keyv.list().await?)
+}
+
+#[tauri::command(rename_all = "snake_case")]
+pub async fn list_page(
+    databases: State<'_, Databases>,
+    database: Option<String>,
+    query: ListQuery,
+) -> tauri::Result<ListPage> {
+    let keyv = &databases.resolve(database.as_deref())?.keyv;
+    Ok(keyv.list_page(query).await?)
+}
+
+#[tauri::command(rename_all = "snake_case")]
+pub async fn find(
+    databases: State<'_, Databases>,
+    database: Option<String>,
+    query: FindQuery,
+) -> tauri::Result<Vec<StoreModel>> {
+    let keyv = &databases.resolve(database.as_deref())?.keyv;
+    Ok(keyv.find(query).await?)
+}
+
+#[tauri::command(rename_all = "snake_case")]
+pub async fn create_index(
+    databases: State<'_, Databases>,
+    database: Option<String>,
+    name: String,
+    path: String,
+) -> tauri::Result<()> {
+    let keyv = &databases.resolve(database.as_deref())?.keyv;
+    Ok(keyv.create_index(&name, &path).await?)
+}
+
+#[tauri::command(rename_all = "snake_case")]
+pub async fn drop_index(databases: State<'_, Databases>, database: Option<String>, name: String) -> tauri::Result<()> {
+    let keyv = &databases.resolve(database.as_deref())?.keyv;
+    Ok(keyv.drop_index(&name).await?)
+}
+
+#[tauri::command(rename_all = "snake_case")]
+pub async fn remove(databases: State<'_, Databases>, database: Option<String>, key: String) -> tauri::Result<()> {
+    let keyv = &databases.resolve(database.as_deref())?.keyv;
+    Ok(keyv.remove(&key).await?)
+}
+
+#[tauri::command(rename_all = "snake_case")]
+pub async fn remove_many(
+    databases: State<'_, Databases>,
+    database: Option<String>,
+    keys: Vec<String>,
+) -> tauri::Result<()> {
+    let keyv = &databases.resolve(database.as_deref())?.keyv;
+    Ok(keyv.remove_many(&keys).await?)
+}
+
+#[tauri::command(rename_all = "snake_case")]
+pub async fn clear(databases: State<'_, Databases>, database: Option<String>) -> tauri::Result<()> {
+    let keyv = &databases.resolve(database.as_deref())?.keyv;
+    Ok(keyv.clear().await?)
+}
+
+#[tauri::command(rename_all = "snake_case")]
+pub async fn enable_history(
+    databases: State<'_, Databases>,
+    database: Option<String>,
+    limit: usize,
+) -> tauri::Result<()> {
+    let keyv = &databases.resolve(database.as_deref())?.keyv;
+    Ok(keyv.enable_history(limit).await?)
+}
+
+#[tauri::command(rename_all = "snake_case")]
+pub async fn disable_history(databases: State<'_, Databases>, database: Option<String>) -> tauri::Result<()> {
+    let keyv = &databases.resolve(database.as_deref())?.keyv;
+    Ok(keyv.disable_history().await?)
+}
+
+#[tauri::command(rename_all = "snake_case")]
+pub async fn history(
+    databases: State<'_, Databases>,
+    database: Option<String>,
+    key: String,
+) -> tauri::Result<Vec<Revision>> {
+    let keyv = &databases.resolve(database.as_deref())?.keyv;
+    Ok(keyv.history(&key).await?)
+}
+
+#[tauri::command(rename_all = "snake_case")]
+pub async fn get_revision(
+    databases: State<'_, Databases>,
+    database: Option<String>,
+    key: String,
+    id: i64,
+) -> tauri::Result<Option<Revision>> {
+    let keyv = &databases.resolve(database.as_deref())?.keyv;
+    Ok(keyv.get_revision(&key, id).await?)
+}
+
+#[tauri::command(rename_all = "snake_case")]
+pub async fn revert(
+    databases: State<'_, Databases>,
+    database: Option<String>,
+    key: String,
+    id: i64,
+) -> tauri::Result<StoreModel> {
+    let keyv = &databases.resolve(database.as_deref())?.keyv;
+    Ok(keyv.revert(&key, id).await?)
+}
+
+#[tauri::command(rename_all = "snake_case")]
+pub async fn enable_search(databases: State<'_, Databases>, database: Option<String>) -> tauri::Result<()> {
+    let keyv = &databases.resolve(database.as_deref())?.keyv;
+    Ok(keyv.enable_search().await?)
+}
+
+#[tauri::command(rename_all = "snake_case")]
+pub async fn disable_search(databases: State<'_, Databases>, database: Option<String>) -> tauri::Result<()> {
+    let keyv = &databases.resolve(database.as_deref())?.keyv;
+    Ok(keyv.disable_search().await?)
+}
+
+#[tauri::command(rename_all = "snake_case")]
+pub async fn search(
+    databases: State<'_, Databases>,
+    database: Option<String>,
+    query: SearchQuery,
+) -> tauri::Result<Vec<SearchHit>> {
+    let keyv = &databases.resolve(database.as_deref())?.keyv;
+    Ok(keyv.search_with(query).await?)
+}
+
+/// Takes a lock without waiting and returns the owner token of its lease, to
+/// renew and release it with. Windows renew the lease themselves, within `ttl` seconds,
+/// which must be at least one.
+#[tauri::command(rename_all = "snake_case")]
+pub async fn acquire_lock(
+    databases: State<'_, Databases>,
+    database: Option<String>,
+    name: String,
+    ttl: u64,
+) -> tauri::Result<Option<String>> {
+    let keyv = &databases.resolve(database.as_deref())?.keyv;
+    let owner = lease_owner();
+    let acquired = keyv.acquire_lease(&name, &owner, Duration::from_secs(ttl)).await?;
+    Ok(acquired.then_some(owner))
+}
+
+#[tauri::command(rename_all = "snake_case")]
+pub async fn renew_lock(
+    databases: State<'_, Databases>,
+    database: Option<String>,
+    name: String,
+    owner: String,
+    ttl: u64,
+) -> tauri::Result<bool> {
+    let keyv = &databases.resolve(database.as_deref())?.keyv;
+    Ok(keyv.renew_lease(&name, &owner, Duration::from_secs(ttl)).await?)
+}
+
+#[tauri::command(rename_all = "snake_case")]
+pub async fn release_lock(
+    databases: State<'_, Databases>,
+    database: Option<String>,
+    name: String,
+    owner: String,
+) -> tauri::Result<()> {
+    let keyv = &databases.resolve(database.as_deref())?.keyv;
+    Ok(keyv.release_lease(&name, &owner).await?)
+}
+
+/// Name of the directory, in the app data directory, that `export_file` writes
+/// to and `import_file` reads from.
+const EXPORT_DIR_NAME: &str = "exports";
+
+/// Resolves a path given by the frontend inside the export directory, so that
+/// the commands can never read or write files elsewhere.
+fn resolve_export_path<R: Runtime>(app: &AppHandle<R>, path: &Path) -> tauri::Result<PathBuf> {
+    app.path().resolve(export_relative_path(path)?, BaseDirectory::AppData)
+}
+
+/// Returns `path` within the export directory, or an error if it is empty,
+/// absolute or could leave the directory.
+fn export_relative_path(path: &Path) -> Result<PathBuf, IoError> {
+    let is_relative = path.components().next().is_some()
+        && path
+            .components()
+            .all(|component| matches!(component, Component::Normal(_)));
+
+    if !is_relative {
+        return Err(IoError::new(
+            ErrorKind::InvalidInput,
+            format!(
+                "Export path must be relative to the export directory: {}",
+                path.display()
+            ),
+        ));
+    }
+
+    Ok(Path::new(EXPORT_DIR_NAME).join(path))
+}
+
+/// Exports the store to `path`, relative to the `exports` directory of the app
+/// data directory. The file is written under a temporary name and only renamed
+/// to `path` once complete, so a failed export never leaves a truncated file.
+#[tauri::command(rename_all = "snake_case")]
+pub async fn export_file<R: Runtime>(
+    app: AppHandle<R>,
+    databases: State<'_, Databases>,
+    database: Option<String>,
+    path: PathBuf,
+    format: Option<ExportFormat>,
+) -> tauri::Result<u64> {
+    let keyv = &databases.resolve(database.as_deref())?.keyv;
+    let path = resolve_export_path(&app, &path)?;
+
+    if let Some(parent) = path.parent() {
+        tokio::fs::create_dir_all(parent).await?;
+    }
+
+    let mut temp_path = OsString::from(&path);
+    temp_path.push(".tmp");
+    let temp_path = PathBuf::from(temp_path);
+
+    let export = async {
+        let mut file = tokio::fs::File::create(&temp_path).await?;
+        let exported = keyv.export(&mut file, format.unwrap_or_default()).await?;
+        file.sync_all().await?;
+        tokio::fs::rename(&temp_path, &path).await?;
+        Ok::<_, tauri::Error>(exported)
+    };
+
+    match export.await {
+        Ok(exported) => Ok(exported),
+        Err(e) => {
+            let _ = tokio::fs::remove_file(&temp_path).await;
+            Err(e)
+        }
+    }
+}
+
+/// Imports a file written by `export_file`, from `path` relative to the
+/// `exports` directory of the app data directory.
+#[tauri::command(rename_all = "snake_case")]
+pub async fn import_file<R: Runtime>(
+    app: AppHandle<R>,
+    databases: State<'_, Databases>,
+    database: Option<String>,
+    path: PathBuf,
+    mode: Option<ImportMode>,
+) -> tauri::Result<ImportReport> {
+    let keyv = &databases.resolve(database.as_deref())?.keyv;
+    let path = resolve_export_path(&app, &path)?;
+    let file = tokio::fs::File::open(&path).await?;
+    Ok(keyv.import(file, mode.unwrap_or_default()).await?)
+}
+
+#[tauri::command(rename_all = "snake_case")]
+pub async fn create_backup(databases: State<'_, Databases>, database: Option<String>) -> tauri::Result<BackupInfo> {
+    let database = databases.resolve(database.as_deref())?;
+    Ok(database.backups()?.create(&database.keyv).await?)
+}
+
+#[tauri::command(rename_all = "snake_case")]
+pub async fn list_backups(databases: State<'_, Databases>, database: Option<String>) -> tauri::Result<Vec<BackupInfo>> {
+    Ok(databases.resolve(database.as_deref())?.backups()?.list().await?)
+}
+
+/// Schedules the restore of a backup, which replaces the database the next time the application starts.
+#[tauri::command(rename_all = "snake_case")]
+pub async fn restore_backup(
+    databases: State<'_, Databases>,
+    database: Option<String>,
+    name: String,
+) -> tauri::Result<BackupInfo> {
+    Ok(databases
+        .resolve(database.as_deref())?
+        .backups()?
+        .schedule_restore(&name)
+        .await?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn export_paths_stay_in_the_export_directory() {
+        assert_eq!(
+            export_relative_path(Path::new("settings.json")).unwrap(),
+            Path::new("exports/settings.json")
+        );
+        assert_eq!(
+            export_relative_path(Path::new("2024/settings.json")).unwrap(),
+            Path::new("exports/2024/settings.json")
+        );
+
+        for path in [
+            "",
+            "..",
+            "../settings.json",
+            "a/../../settings.json",
+            "./settings.json",
+            "/etc/passwd",
+            "/",
+        ] {
+            let error = export_relative_path(Path::new(path)).unwrap_err();
+            assert_eq!(error.kind(), ErrorKind::InvalidInput, "{:?} was accepted", path);
+        }
+    }
+}
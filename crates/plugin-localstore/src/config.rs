@@ -0,0 +1,292 @@
+// Copyright © 2024 Crabby Craft - All Rights Reserved.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use serde::{Deserialize, Deserializer};
+use std::collections::{BTreeMap, HashMap};
+use std::path::PathBuf;
+use tauri::path::BaseDirectory;
+use tauri::{AppHandle, Runtime};
+
+use crate::keyv::{Keyv, KeyvError, StoreError};
+use crate::{resolve_db_path, BackupManager};
+
+/// The configuration of the plugin, read from `plugins.localstore` in
+/// `tauri.conf.json`. Options set there take precedence over the ones set on
+/// the [`Builder`](crate::Builder).
+///
+/// ```json
+/// {
+///   "plugins": {
+///     "localstore": {
+///       "fileName": "app",
+///       "baseDir": "$APPDATA",
+///       "debugSuffix": false,
+///       "databases": {
+///         "cache": { "baseDir": "$APPCACHE" },
+///         "session": { "inMemory": true }
+///       }
+///     }
+///   }
+/// }
+/// ```
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Config {
+    /// The options of the default database, behind the `Keyv` managed state.
+    #[serde(flatten)]
+    pub default: DatabaseConfig,
+    /// The options of the named databases, behind the [`Databases`] managed state.
+    #[serde(default)]
+    pub databases: BTreeMap<String, DatabaseConfig>,
+}
+
+/// Where and how a database of the plugin is stored. Unset options fall back
+/// to the ones of the default database, then to the defaults of each option.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DatabaseConfig {
+    /// The file name, without the `.db` extension. Defaults to the crate name
+    /// of the app for the default database, and to the name of named ones.
+    pub file_name: Option<String>,
+    /// The directory of the file, `$APPCONFIG` by default. Written in
+    /// `tauri.conf.json` as a path variable such as `$APPDATA`.
+    #[serde(default, deserialize_with = "deserialize_base_dir")]
+    pub base_dir: Option<BaseDirectory>,
+    /// Whether `-debug` is appended to the file name in debug builds. Defaults to `true`.
+    pub debug_suffix: Option<bool>,
+    /// Whether the database only lives in memory, and is lost when the app
+    /// exits. Defaults to `false`.
+    pub in_memory: Option<bool>,
+}
+
+impl DatabaseConfig {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn file_name<S: Into<String>>(mut self, file_name: S) -> Self {
+        self.file_name = Some(file_name.into());
+        self
+    }
+
+    pub fn base_dir(mut self, base_dir: BaseDirectory) -> Self {
+        self.base_dir = Some(base_dir);
+        self
+    }
+
+    pub fn debug_suffix(mut self, enabled: bool) -> Self {
+        self.debug_suffix = Some(enabled);
+        self
+    }
+
+    pub fn in_memory(mut self, enabled: bool) -> Self {
+        self.in_memory = Some(enabled);
+        self
+    }
+
+    /// Fills the options left unset with the ones of `fallback`.
+    pub(crate) fn or(self, fallback: &DatabaseConfig) -> Self {
+        Self {
+            file_name: self.file_name.or_else(|| fallback.file_name.clone()),
+            base_dir: self.base_dir.or(fallback.base_dir),
+            debug_suffix: self.debug_suffix.or(fallback.debug_suffix),
+            in_memory: self.in_memory.or(fallback.in_memory),
+        }
+    }
+
+    /// Fills the options of the named database `name` left unset with the
+    /// ones of the default database, except for the file name, which
+    /// defaults to `name` so that named databases never share the file of
+    /// the default one.
+    pub(crate) fn named(self, name: &str, default: &DatabaseConfig) -> Self {
+        let file_name = self.file_name.clone().unwrap_or_else(|| name.to_string());
+        self.file_name(file_name).or(default)
+    }
+
+    pub(crate) fn is_in_memory(&self) -> bool {
+        self.in_memory.unwrap_or(false)
+    }
+
+    /// Resolves the path of the database file, using `default_file_name` if
+    /// no file name is set.
+    pub(crate) fn path<R: Runtime>(&self, handle: &AppHandle<R>, default_file_name: &str) -> tauri::Result<PathBuf> {
+        resolve_db_path(
+            handle,
+            self.file_name.as_deref().unwrap_or(default_file_name),
+            self.base_dir.unwrap_or(BaseDirectory::AppConfig),
+            self.debug_suffix.unwrap_or(true),
+        )
+    }
+}
+
+fn deserialize_base_dir<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<BaseDirectory>, D::Error> {
+    match Option::<String>::deserialize(deserializer)? {
+        Some(variable) => BaseDirectory::from_variable(&variable).map(Some).ok_or_else(|| {
+            serde::de::Error::custom(format!("unknown base directory {variable}, expected e.g. $APPDATA"))
+        }),
+        None => Ok(None),
+    }
+}
+
+/// The named databases opened by the plugin, registered as managed state.
+///
+/// Each database has its own [`BackupManager`], and the plugin commands act on
+/// one of them when given its name as the `database` argument.
+///
+/// ```rust,no_run
+/// # use tauri::Manager;
+/// # use tauri_plugin_localstore::Databases;
+/// # async fn run(app: tauri::AppHandle) -> Result<(), Box<dyn std::error::Error>> {
+/// let cache = app.state::<Databases>().get("cache").expect("cache database").clone();
+/// cache.set("greeting", "hello").await?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone)]
+pub struct Databases {
+    default: Database,
+    databases: HashMap<String, Database>,
+}
+
+/// A database opened by the plugin, with the manager of its backups unless
+/// it only lives in memory.
+#[derive(Clone)]
+pub(crate) struct Database {
+    pub(crate) keyv: Keyv,
+    pub(crate) backups: Option<BackupManager>,
+}
+
+impl Database {
+    /// Returns the manager of the backups, or `StoreError::Unsupported` for
+    /// an in-memory database, which has no file to back up.
+    pub(crate) fn backups(&self) -> Result<&BackupManager, KeyvError> {
+        self.backups
+            .as_ref()
+            .ok_or_else(|| StoreError::Unsupported("backups of in-memory databases".to_string()).into())
+    }
+}
+
+impl Databases {
+    pub(crate) fn new(default: Database) -> Self {
+        Self {
+            default,
+            databases: HashMap::new(),
+        }
+    }
+
+    pub(crate) fn insert(&mut self, name: String, database: Database) {
+        self.databases.insert(name, database);
+    }
+
+    pub(crate) fn default_database(&self) -> &Database {
+        &self.default
+    }
+
+    /// Returns the named database a command acts on, or the default one if
+    /// `name` is `None`.
+    pub(crate) fn resolve(&self, name: Option<&str>) -> Result<&Database, KeyvError> {
+        match name {
+            Some(name) => self
+                .databases
+                .get(name)
+                .ok_or_else(|| KeyvError::DatabaseNotFound(name.to_string())),
+            None => Ok(self.default_database()),
+        }
+    }
+
+    /// Returns the database with the given name, or `None` if it was not configured.
+    pub fn get(&self, name: &str) -> Option<&Keyv> {
+        self.databases.get(name).map(|database| &database.keyv)
+    }
+
+    /// Returns the manager of the backups of the database with the given
+    /// name, or `None` if it was not configured or only lives in memory.
+    pub fn backups(&self, name: &str) -> Option<&BackupManager> {
+        self.databases.get(name).and_then(|database| database.backups.as_ref())
+    }
+
+    /// Returns the names of the databases.
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.databases.keys().map(String::as_str)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn options_set_in_the_config_file_override_the_builder() {
+        let config: Config = serde_json::from_value(serde_json::json!({
+            "fileName": "app",
+            "baseDir": "$APPDATA",
+        }))
+        .unwrap();
+        let builder = DatabaseConfig::new()
+            .file_name("builder")
+            .base_dir(BaseDirectory::AppCache)
+            .debug_suffix(false);
+
+        let database = config.default.or(&builder);
+        assert_eq!(database.file_name.as_deref(), Some("app"));
+        assert!(matches!(database.base_dir, Some(BaseDirectory::AppData)));
+        assert_eq!(database.debug_suffix, Some(false));
+        assert_eq!(database.in_memory, None);
+    }
+
+    #[test]
+    fn named_databases_default_to_a_file_of_their_own() {
+        let default = DatabaseConfig::new()
+            .file_name("app")
+            .base_dir(BaseDirectory::AppData)
+            .in_memory(true);
+
+        let cache = DatabaseConfig::new()
+            .base_dir(BaseDirectory::AppCache)
+            .named("cache", &default);
+        assert_eq!(cache.file_name.as_deref(), Some("cache"));
+        assert!(matches!(cache.base_dir, Some(BaseDirectory::AppCache)));
+        assert!(cache.is_in_memory());
+
+        let renamed = DatabaseConfig::new().file_name("other").named("cache", &default);
+        assert_eq!(renamed.file_name.as_deref(), Some("other"));
+    }
+
+    #[test]
+    fn unknown_base_directories_are_rejected() {
+        let error = serde_json::from_value::<Config>(serde_json::json!({ "baseDir": "$NOWHERE" })).unwrap_err();
+        assert!(error.to_string().contains("unknown base directory $NOWHERE"), "{error}");
+
+        let config: Config = serde_json::from_value(serde_json::json!({
+            "databases": { "session": { "inMemory": true } },
+        }))
+        .unwrap();
+        assert!(config.default.base_dir.is_none());
+        assert!(config.databases["session"].is_in_memory());
+    }
+
+    #[test]
+    fn commands_on_unknown_databases_are_rejected() {
+        let database = |keyv: Keyv| Database { keyv, backups: None };
+        let mut databases = Databases::new(database(Keyv::default()));
+        databases.insert("cache".to_string(), database(Keyv::default()));
+
+        assert!(databases.resolve(None).is_ok());
+        assert!(databases.resolve(Some("cache")).is_ok());
+        assert!(matches!(
+            databases.resolve(Some("missing")),
+            Err(KeyvError::DatabaseNotFound(name)) if name == "missing"
+        ));
+        assert!(databases.get("missing").is_none());
+        assert!(databases.backups("cache").is_none());
+        assert!(matches!(
+            databases.default_database().backups(),
+            Err(KeyvError::StoreError(StoreError::Unsupported(_)))
+        ));
+    }
+}
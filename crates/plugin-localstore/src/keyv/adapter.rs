@@ -14,16 +14,26 @@
  */
 
 use libsql::{params, params_from_iter};
-use libsql::{Builder, Connection};
+use libsql::{Builder, Connection, Row, Transaction, TransactionBehavior};
 use serde_json::Value;
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::future::Future;
-use std::path::PathBuf;
+use std::num::NonZeroUsize;
+use std::path::{Path, PathBuf};
 use std::pin::Pin;
-use std::sync::Arc;
-use std::time::Instant;
+use std::sync::{Arc, PoisonError, RwLock};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use tokio::sync::{Mutex, MutexGuard, OnceCell, OwnedMutexGuard};
 
 use crate::keyv::DEFAULT_NAMESPACE_NAME;
-use crate::keyv::{Store, StoreError, StoreModel};
+use crate::keyv::{
+    json_path, prefix_upper_bound, validate_lease_ttl, CacheStats, Compression, ConnectionOptions, EncodedValue,
+    EvictionPolicy, FindQuery, JournalMode, ListPage, ListQuery, NamespaceLimits, ReadConnection, ReadPool, Revision,
+    SearchHit, SearchQuery, Store, StoreError, StoreLimits, StoreModel, StoreTransaction, Synchronous, UpdateFn,
+    ValueCache, ValueCodec, VersionedValue, Written, DEFAULT_SEARCH_LIMIT, SEARCH_HIGHLIGHT,
+    XCHACHA20POLY1305_ENCODING,
+};
 
 /// Builder for creating a `KeyvStore`.
 ///
@@ -37,40 +47,54 @@ use crate::keyv::{Store, StoreError, StoreModel};
 /// ## Initializing with a Database File URI
 ///
 /// ```rust,no_run
-/// # use keyv::{KeyvStoreBuilder};
-/// # #[tokio::main]
-/// # async fn main(){
+/// # use tauri_plugin_localstore::keyv::KeyvStoreBuilder;
+/// # async fn run() -> Result<(), Box<dyn std::error::Error>> {
 /// let store = KeyvStoreBuilder::new()
-///     .uri("sqlite::memory:")
+///     .uri(":memory:")
 ///     .table_name("custom_table_name")
 ///     .build()
-///     .unwrap();
-///  }
+///     .await?;
+/// # Ok(())
+/// # }
 /// ```
 ///
-/// ## Using an Existing Connection Pool
+/// ## Using an Existing Connection
 ///
 /// ```rust,no_run
 /// # use std::sync::Arc;
-/// # use keyv::{KeyvStoreBuilder};
-/// # use libsql::Connection;
-///
-/// # #[tokio::main]
-/// # async fn main() {
-/// let conn: Arc<Connnection> = Arc::new(Connection::open(path).unwrap());
+/// # use tauri_plugin_localstore::keyv::KeyvStoreBuilder;
+/// # use tauri_plugin_localstore::sql::Builder;
+/// # async fn run() -> Result<(), Box<dyn std::error::Error>> {
+/// let db = Builder::new_local("app.db").build().await?;
+/// let conn = Arc::new(db.connect()?);
 ///
 /// let store = KeyvStoreBuilder::new()
 ///     .connnection(conn)
 ///     .table_name("custom_table_name")
 ///     .build()
-///     .unwrap();
-/// }
+///     .await?;
+/// # Ok(())
+/// # }
 /// ```
 pub struct KeyvStoreBuilder {
     uri: Option<PathBuf>,
     token: Option<String>,
     connnection: Option<Arc<Connection>>,
     table_name: Option<String>,
+    encryption_key: Option<[u8; 32]>,
+    previous_encryption_keys: Vec<[u8; 32]>,
+    compression: Option<Compression>,
+    cache_capacity: usize,
+    connection_options: ConnectionOptions,
+    read_connections: usize,
+    limits: NamespaceLimits,
+    namespace_limits: HashMap<String, NamespaceLimits>,
+}
+
+impl Default for KeyvStoreBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl KeyvStoreBuilder {
@@ -80,6 +104,14 @@ impl KeyvStoreBuilder {
             token: None,
             connnection: None,
             table_name: None,
+            encryption_key: None,
+            previous_encryption_keys: Vec::new(),
+            compression: None,
+            cache_capacity: 0,
+            connection_options: ConnectionOptions::default(),
+            read_connections: 0,
+            limits: NamespaceLimits::default(),
+            namespace_limits: HashMap::new(),
         }
     }
 
@@ -117,6 +149,199 @@ impl KeyvStoreBuilder {
         self
     }
 
+    /// Encrypts values at rest with the given 256-bit key.
+    ///
+    /// Values are encrypted with XChaCha20-Poly1305 before they are written and
+    /// decrypted when read. Keys, TTLs and versions stay in the clear. Values
+    /// written before the key was set remain readable, and are encrypted the
+    /// next time they are written or when `KeyvStore::rotate_encryption_key`
+    /// runs on their namespace.
+    ///
+    /// # Examples
+    ///
+    /// ```rust,no_run
+    /// # use tauri_plugin_localstore::keyv::KeyvStoreBuilder;
+    /// # async fn run() -> Result<(), Box<dyn std::error::Error>> {
+    /// let key = [0u8; 32]; // Load it from the OS keychain instead.
+    ///
+    /// let store = KeyvStoreBuilder::new()
+    ///     .uri("app.db")
+    ///     .encryption_key(key)
+    ///     .build()
+    ///     .await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn encryption_key(mut self, key: [u8; 32]) -> Self {
+        self.encryption_key = Some(key);
+        self
+    }
+
+    /// Adds a key that was used before the current `encryption_key`.
+    ///
+    /// Previous keys are only used for decryption, so namespaces that were not
+    /// rotated yet stay readable after a restart. Can be called several times.
+    pub fn previous_encryption_key(mut self, key: [u8; 32]) -> Self {
+        self.previous_encryption_keys.push(key);
+        self
+    }
+
+    /// Compresses values whose JSON is at least `threshold` bytes long.
+    ///
+    /// Values are compressed with zstd at the default level before they are
+    /// encrypted, and decompressed transparently when read. Values that do not
+    /// shrink are stored as is. The debug logs of `set` report the compressed size.
+    ///
+    /// # Examples
+    ///
+    /// ```rust,no_run
+    /// # use tauri_plugin_localstore::keyv::KeyvStoreBuilder;
+    /// # async fn run() -> Result<(), Box<dyn std::error::Error>> {
+    /// let store = KeyvStoreBuilder::new()
+    ///     .uri("app.db")
+    ///     .compression_threshold(64 * 1024)
+    ///     .build()
+    ///     .await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn compression_threshold(mut self, threshold: usize) -> Self {
+        let level = self.compression.map_or(DEFAULT_COMPRESSION_LEVEL, |c| c.level);
+        self.compression = Some(Compression { threshold, level });
+        self
+    }
+
+    /// Sets the zstd level used with `compression_threshold`, from 1 (fastest)
+    /// to 22 (smallest). Has no effect unless a threshold is set.
+    pub fn compression_level(mut self, level: i32) -> Self {
+        if let Some(compression) = self.compression.as_mut() {
+            compression.level = level;
+        }
+        self
+    }
+
+    /// Keeps up to `capacity` recently read keys in memory.
+    ///
+    /// `get` and `get_versioned` are answered from the cache when possible,
+    /// including for keys known to be absent. Every write made through the store
+    /// or any of its namespace handles and transactions invalidates the keys it
+    /// touches, and cached entries are dropped once their TTL elapses. Commits made
+    /// through another connection to the same database, e.g. another `KeyvStore`
+    /// or process, are noticed through `PRAGMA data_version` on the next cached
+    /// read and drop every cached value.
+    ///
+    /// The cache holds decrypted values. A capacity of `0`, the default, disables it.
+    ///
+    /// # Examples
+    ///
+    /// ```rust,no_run
+    /// # use tauri_plugin_localstore::keyv::KeyvStoreBuilder;
+    /// # async fn run() -> Result<(), Box<dyn std::error::Error>> {
+    /// let store = KeyvStoreBuilder::new()
+    ///     .uri("app.db")
+    ///     .cache_capacity(1024)
+    ///     .build()
+    ///     .await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn cache_capacity(mut self, capacity: usize) -> Self {
+        self.cache_capacity = capacity;
+        self
+    }
+
+    /// Sets the journal mode of the database.
+    ///
+    /// `JournalMode::Wal` lets reads run while a write is in progress, and is
+    /// recommended together with `read_connections`. The mode is stored in the
+    /// database file, so it also applies to other connections opened on it.
+    ///
+    /// # Examples
+    ///
+    /// ```rust,no_run
+    /// # use std::time::Duration;
+    /// # use tauri_plugin_localstore::keyv::{JournalMode, KeyvStoreBuilder, Synchronous};
+    /// # async fn run() -> Result<(), Box<dyn std::error::Error>> {
+    /// let store = KeyvStoreBuilder::new()
+    ///     .uri("app.db")
+    ///     .journal_mode(JournalMode::Wal)
+    ///     .synchronous(Synchronous::Normal)
+    ///     .busy_timeout(Duration::from_secs(5))
+    ///     .read_connections(4)
+    ///     .build()
+    ///     .await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn journal_mode(mut self, mode: JournalMode) -> Self {
+        self.connection_options.journal_mode = Some(mode);
+        self
+    }
+
+    /// Sets how often SQLite waits for data to reach the disk, trading
+    /// durability for write speed.
+    pub fn synchronous(mut self, synchronous: Synchronous) -> Self {
+        self.connection_options.synchronous = Some(synchronous);
+        self
+    }
+
+    /// Sets how long a connection waits for another one to release its lock
+    /// before failing with "database is locked".
+    pub fn busy_timeout(mut self, timeout: Duration) -> Self {
+        self.connection_options.busy_timeout = Some(timeout);
+        self
+    }
+
+    /// Sets the page cache size of every connection, as for `PRAGMA cache_size`:
+    /// a number of pages when positive, or a size in KiB when negative.
+    pub fn cache_size(mut self, cache_size: i64) -> Self {
+        self.connection_options.cache_size = Some(cache_size);
+        self
+    }
+
+    /// Opens `count` read-only connections next to the connection used for
+    /// writes, so that reads do not queue behind each other or behind writes.
+    ///
+    /// Writes stay serialized on a single connection. Ignored when using an
+    /// existing connection or an in-memory database, whose connections would
+    /// each see a different database. Defaults to `0`, which reads through the
+    /// writer connection, waiting for writes and transactions to finish.
+    pub fn read_connections(mut self, count: usize) -> Self {
+        self.read_connections = count;
+        self
+    }
+
+    /// Limits the size of every namespace without limits of its own, so that
+    /// a runaway writer cannot fill the disk.
+    ///
+    /// # Examples
+    ///
+    /// ```rust,no_run
+    /// # use tauri_plugin_localstore::keyv::{EvictionPolicy, KeyvStoreBuilder, NamespaceLimits};
+    /// # async fn run() -> Result<(), Box<dyn std::error::Error>> {
+    /// let store = KeyvStoreBuilder::new()
+    ///     .uri("app.db")
+    ///     .limits(NamespaceLimits::new().max_value_size(1 << 20).max_bytes(64 << 20))
+    ///     .namespace_limits(
+    ///         "http_cache",
+    ///         NamespaceLimits::new().max_keys(500).evict(EvictionPolicy::Lru),
+    ///     )
+    ///     .build()
+    ///     .await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn limits(mut self, limits: NamespaceLimits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    /// Sets the limits of one namespace, replacing the ones set with `limits`.
+    pub fn namespace_limits<S: Into<String>>(mut self, name: S, limits: NamespaceLimits) -> Self {
+        self.namespace_limits.insert(name.into(), limits);
+        self
+    }
+
     /// Builds the `KeyvStore` based on the provided configurations.
     ///
     /// Finalizes the builder and creates an `KeyvStore` instance.
@@ -126,12 +351,18 @@ impl KeyvStoreBuilder {
     /// This method returns a `Result` which, on success, contains the initialized `KeyvStore`.
     /// On failure, it returns a `StoreError` indicating what went wrong during the initialization.
     pub async fn build(self) -> Result<KeyvStore, StoreError> {
-        let connnection = match self.connnection {
-            Some(connnection) => connnection,
+        let options = self.connection_options;
+
+        let (connnection, readers) = match self.connnection {
+            Some(connnection) => {
+                options.apply_to_writer(&connnection).await?;
+                (connnection, None)
+            }
             None => {
                 let path = self
                     .uri
                     .expect("KeyvStore requires either a URI or an existing connnection to be set");
+                let in_memory = is_in_memory(&path);
 
                 // If the token is set, use the remote database connection.
                 let db = if let Some(token) = self.token {
@@ -149,8 +380,25 @@ impl KeyvStoreBuilder {
                 let conn = db
                     .connect()
                     .map_err(|_| StoreError::ConnectionError("Failed to create database connnection".to_string()))?;
+                options.apply_to_writer(&conn).await?;
+
+                let readers = if self.read_connections > 0 && !in_memory {
+                    let mut connections = Vec::with_capacity(self.read_connections);
+
+                    for _ in 0..self.read_connections {
+                        let reader = db.connect().map_err(|_| {
+                            StoreError::ConnectionError("Failed to create a read connnection".to_string())
+                        })?;
+                        options.apply_to_reader(&reader).await?;
+                        connections.push(reader);
+                    }
 
-                Arc::new(conn)
+                    Some(Arc::new(ReadPool::new(connections)))
+                } else {
+                    None
+                };
+
+                (Arc::new(conn), readers)
             }
         };
 
@@ -159,265 +407,3798 @@ impl KeyvStoreBuilder {
             DEFAULT_NAMESPACE_NAME.to_string()
         });
 
+        validate_namespace(&table_name)?;
+
+        let codec = self
+            .previous_encryption_keys
+            .iter()
+            .fold(ValueCodec::default(), |codec, key| codec.with_previous_key(key))
+            .with_encryption_key(self.encryption_key.as_ref())
+            .with_compression(self.compression);
+
         Ok(KeyvStore {
             connnection,
+            readers,
             table_name,
+            initialized: OnceCell::new(),
+            write_lock: Arc::new(Mutex::new(())),
+            codec: Arc::new(RwLock::new(codec)),
+            cache: NonZeroUsize::new(self.cache_capacity).map(|capacity| Arc::new(ValueCache::new(capacity))),
+            limits: Arc::new(StoreLimits::new(self.limits, self.namespace_limits)),
         })
     }
 }
 
+/// Returns `true` if `path` names an in-memory database rather than a file.
+fn is_in_memory(path: &Path) -> bool {
+    let path = path.to_string_lossy();
+    path == ":memory:" || path.starts_with("file::memory:") || path.contains("mode=memory")
+}
+
+/// SQL expression of the current time in UTC milliseconds since the Unix epoch,
+/// portable to SQLite versions without `unixepoch('subsec')`.
+const NOW_MILLIS_SQL: &str = "CAST((julianday('now') - 2440587.5) * 86400000 AS INTEGER)";
+
+/// Columns selected to build a `StoreModel` with `read_model`.
+const MODEL_COLUMNS: &str = "key, value, encoding, version, created_at, updated_at, expires_at";
+
+/// zstd level used when only a compression threshold is set.
+const DEFAULT_COMPRESSION_LEVEL: i32 = 3;
+
+/// Name of the table that records every namespace created through a `KeyvStore`.
+const NAMESPACE_REGISTRY_TABLE: &str = "keyv_namespaces";
+
+/// Name of the table holding the previous values of keys in namespaces with
+/// history enabled, shared by every namespace.
+const HISTORY_TABLE: &str = "keyv_history";
+
+/// Name of the table holding the lock leases, shared by every namespace.
+const LOCKS_TABLE: &str = "keyv_locks";
+
+/// Columns selected to build a `Revision` with `read_revision`.
+const REVISION_COLUMNS: &str = "id, key, value, encoding, version, updated_at, replaced_at, removed";
+
+/// Maximum number of keys bound to a single `get_many` query, kept well below
+/// the SQLite host parameter limit.
+const GET_MANY_CHUNK_SIZE: usize = 500;
+
+/// SQL expression of the value as JSON text, `NULL` for compressed or encrypted
+/// values, which SQLite cannot read. Secondary indexes and `find` filters are
+/// built on it, so JSON functions never see an encoded value.
+const JSON_VALUE_SQL: &str = "CASE WHEN encoding = 'json' THEN value END";
+
 pub struct KeyvStore {
     pub(crate) connnection: Arc<Connection>,
+    /// Read-only connections used for reads outside of writes, if enabled.
+    readers: Option<Arc<ReadPool>>,
     pub(crate) table_name: String,
+    initialized: OnceCell<()>,
+    /// Serializes writes across every namespace sharing the connection, so a
+    /// transaction never picks up statements issued by another task.
+    write_lock: Arc<Mutex<()>>,
+    /// Encodes values for storage, shared by every namespace so a key rotation
+    /// is seen by all handles.
+    codec: Arc<RwLock<ValueCodec>>,
+    /// Read-through cache shared by every namespace, if enabled.
+    cache: Option<Arc<ValueCache>>,
+    /// Size limits of every namespace.
+    limits: Arc<StoreLimits>,
 }
 
 impl KeyvStore {
     fn get_table_name(&self) -> String {
         self.table_name.clone()
     }
-}
-
-impl Store for KeyvStore {
-    fn initialize(&self) -> Pin<Box<dyn Future<Output = Result<(), StoreError>> + Send + '_>> {
-        let query = format!(
-            r#"
-                CREATE TABLE IF NOT EXISTS {table_name} (
-                    key TEXT PRIMARY KEY,
-                    value TEXT NOT NULL,
-                    updated_at TEXT DEFAULT (datetime('now', 'localtime')),
-                    UNIQUE(key)
-                ) STRICT;
-                CREATE INDEX IF NOT EXISTS {table_name}_key_idx ON {table_name} (key);
-                CREATE TRIGGER IF NOT EXISTS {table_name}_update_trigger
-                AFTER UPDATE ON {table_name}
-                BEGIN
-                    UPDATE {table_name} SET updated_at = datetime('now', 'localtime') WHERE key = NEW.key;
-                END;
-            "#,
-            table_name = self.get_table_name()
-        );
 
-        let conn = &*self.connnection;
+    async fn lock_writes(&self) -> MutexGuard<'_, ()> {
+        self.write_lock.lock().await
+    }
 
-        Box::pin(async move {
-            conn.execute_batch(&query)
-                .await
-                .map_err(|e| StoreError::QueryError(format!("Failed to initialize the database table: {}", e)))?;
+    /// Returns a store scoped to another namespace, sharing the connection,
+    /// write lock and encryption keys. Useful to rotate the key of a namespace.
+    pub fn scoped_namespace(&self, name: &str) -> Result<Self, StoreError> {
+        validate_namespace(name)?;
 
-            Ok(())
+        Ok(KeyvStore {
+            connnection: Arc::clone(&self.connnection),
+            readers: self.readers.clone(),
+            table_name: name.to_string(),
+            initialized: OnceCell::new(),
+            write_lock: Arc::clone(&self.write_lock),
+            codec: Arc::clone(&self.codec),
+            cache: self.cache.clone(),
+            limits: Arc::clone(&self.limits),
         })
     }
 
-    fn get(&self, key: &str) -> Pin<Box<dyn Future<Output = Result<Option<Value>, StoreError>> + Send + '_>> {
-        let query = format!("SELECT value FROM {} WHERE key = ?1 LIMIT 1", self.get_table_name());
+    /// Returns a connection for a read, from the read pool if there is one.
+    ///
+    /// Without a pool, reads share the writer connection and wait for the write
+    /// lock, as they would otherwise see the writes of an open transaction.
+    async fn read_connection(&self) -> ReadConnection<'_> {
+        match &self.readers {
+            Some(pool) => ReadConnection::Pooled(pool.get().await),
+            None => ReadConnection::Shared {
+                connection: &self.connnection,
+                _guard: self.lock_writes().await,
+            },
+        }
+    }
 
-        let conn = &*self.connnection;
-        let key = key.to_string();
+    /// Drops the cached value of `key` in this namespace.
+    fn invalidate(&self, key: &str) {
+        if let Some(cache) = &self.cache {
+            cache.invalidate(&self.table_name, key);
+        }
+    }
 
-        Box::pin(async move {
-            let start = Instant::now();
+    /// Drops every cached value of a namespace.
+    fn invalidate_namespace(&self, name: &str) {
+        if let Some(cache) = &self.cache {
+            cache.invalidate_namespace(name);
+        }
+    }
 
-            let mut stmt = conn
-                .prepare(&query)
-                .await
-                .map_err(|e| StoreError::QueryError(format!("Failed to set the statement: {:?}", e)))?;
+    /// Reads `key` through the cache, which must be enabled.
+    async fn read_through(&self, cache: &ValueCache, key: &str) -> Result<Option<VersionedValue>, StoreError> {
+        // Writes made through this connection invalidate the keys they touch, and
+        // commits made through any other connection change the data version.
+        cache.sync_data_version(data_version(&self.connnection).await?);
 
-            let result = stmt
-                .query_row(params![key.clone()])
-                .await
-                .map_err(|e| StoreError::QueryError(format!("Failed to fetch the value: {:?}", e)))?;
+        if let Some(hit) = cache.get(&self.table_name, key) {
+            log::debug!("Keyv store cache hit: {} | {}", self.table_name, key);
+            return Ok(hit);
+        }
 
-            let row_value: String = result
-                .get(0)
-                .map_err(|e| StoreError::QueryError(format!("Failed to get the value: {:?}", e)))?;
+        let generation = cache.generation();
 
-            let value = serde_json::to_value(row_value).map_err(|e| StoreError::SerializationError { source: e })?;
+        let (value, expires_at) =
+            match read_entry(&*self.read_connection().await, &self.codec(), &self.table_name, key).await? {
+                Some(model) => (
+                    Some(VersionedValue {
+                        value: model.value,
+                        version: model.version,
+                    }),
+                    model.expires_at,
+                ),
+                None => (None, None),
+            };
 
-            let duration = start.elapsed();
-            log::debug!("Keyv store get: {:?} | {} | {:?}", duration, key, value);
+        cache.insert(&self.table_name, key, value.clone(), expires_at, generation);
 
-            Ok(Some(value))
-        })
+        Ok(value)
     }
 
-    fn list(&self) -> Pin<Box<dyn Future<Output = Result<Vec<StoreModel>, StoreError>> + Send + '_>> {
-        let query = format!("SELECT key, value FROM {} ORDER BY key ASC;", self.get_table_name());
+    fn codec(&self) -> ValueCodec {
+        self.codec.read().unwrap_or_else(PoisonError::into_inner).clone()
+    }
 
-        let conn = &*self.connnection;
+    fn limits(&self) -> &NamespaceLimits {
+        self.limits.get(&self.table_name)
+    }
 
-        Box::pin(async move {
-            let start = Instant::now();
+    /// Records a read of `key` for LRU eviction.
+    fn record_access(&self, key: &str) {
+        self.limits.record_access(&self.table_name, [key], unix_millis());
+    }
 
-            let mut stmt = conn
-                .prepare(&query)
-                .await
-                .map_err(|e| StoreError::QueryError(format!("Failed to set the statement: {:?}", e)))?;
+    /// Drops the cached values of keys evicted by a write, once it is committed.
+    fn invalidate_evicted(&self, evicted: &[String]) {
+        for key in evicted {
+            self.invalidate(key);
+        }
 
-            let mut results = stmt
-                .query(params![])
-                .await
-                .map_err(|e| StoreError::QueryError(format!("Failed to fetch the value: {:?}", e)))?;
+        if !evicted.is_empty() {
+            log::debug!("Keyv store evicted {} keys from {}", evicted.len(), self.table_name);
+        }
+    }
 
-            let mut items: Vec<StoreModel> = Vec::new();
+    /// Re-encrypts every value of the namespace, revisions included, with `new_key`, in place.
+    ///
+    /// The previous key is kept for decryption, so other namespaces sharing the
+    /// connection stay readable until they are rotated too. Passing `None`
+    /// decrypts the namespace and stores new values in the clear. Versions are
+    /// not bumped and no change is published, as the values themselves do not change.
+    ///
+    /// Other stores opened on the same database must be built with the new key,
+    /// and the old one as a `previous_encryption_key` until every namespace is rotated.
+    ///
+    /// # Returns
+    /// The number of values that were re-encoded.
+    pub async fn rotate_encryption_key(&self, new_key: Option<[u8; 32]>) -> Result<u64, StoreError> {
+        self.ensure_table().await?;
+        let _guard = self.lock_writes().await;
 
-            while let Some(row) = results
-                .next()
-                .await
-                .map_err(|e| StoreError::QueryError(format!("Failed to iterate rows: {:?}", e)))?
-            {
-                let key: String = row
-                    .get(0)
-                    .map_err(|e| StoreError::QueryError(format!("Failed to get the value: {:?}", e)))?;
-                let row_value: String = row
-                    .get(1)
-                    .map_err(|e| StoreError::QueryError(format!("Failed to get the value: {:?}", e)))?;
-                let value =
-                    serde_json::to_value(row_value).map_err(|e| StoreError::SerializationError { source: e })?;
+        let start = Instant::now();
 
-                items.push(StoreModel { key, value });
-            }
+        let table_name = self.get_table_name();
+        let codec = self.codec();
+        let rotated = codec.rotated(new_key.as_ref());
 
-            let duration = start.elapsed();
-            log::debug!("Keyv store list: {:?} | {:?}", duration, items);
+        let tx = self
+            .connnection
+            .transaction_with_behavior(TransactionBehavior::Immediate)
+            .await
+            .map_err(|e| StoreError::QueryError(format!("Failed to begin the transaction: {:?}", e)))?;
 
-            Ok(items)
-        })
-    }
+        let (count, revision_count) =
+            reencode_namespace(&tx, &table_name, &codec, &rotated, &table_name, false).await?;
 
-    fn set(
-        &self,
-        key: &str,
-        value: Value,
-        _ttl: Option<u64>,
-    ) -> Pin<Box<dyn Future<Output = Result<Option<StoreModel>, StoreError>> + Send + '_>> {
-        let query = format!(
-            "INSERT INTO {} (key, value) VALUES (?1, ?2) ON CONFLICT(key) DO UPDATE SET value = EXCLUDED.value",
-            self.get_table_name()
+        tx.commit()
+            .await
+            .map_err(|e| StoreError::QueryError(format!("Failed to commit the transaction: {:?}", e)))?;
+
+        let encrypted = rotated.is_encrypted();
+        *self.codec.write().unwrap_or_else(PoisonError::into_inner) = rotated;
+
+        let duration = start.elapsed();
+        log::debug!(
+            "Keyv store rotate_encryption_key: {:?} | {} | {} | {} revisions | encrypted: {}",
+            duration,
+            table_name,
+            count,
+            revision_count,
+            encrypted
         );
 
-        let conn = &*self.connnection;
-        let key = key.to_string();
+        Ok(count)
+    }
 
-        Box::pin(async move {
-            let start = Instant::now();
+    /// Reads the current entry for `key` and, if `f` returns a new value, writes
+    /// it back in the same immediate transaction. The expiry of the entry is kept.
+    ///
+    /// Returns the result of `f` together with the new version, if anything was
+    /// written, and the keys evicted by the write.
+    async fn read_modify_write<R, F>(&self, key: &str, f: F) -> Result<(R, Option<u64>, Vec<String>), StoreError>
+    where
+        F: FnOnce(Option<VersionedValue>) -> Result<(R, Option<Value>), StoreError>,
+    {
+        self.ensure_table().await?;
+        let _guard = self.lock_writes().await;
 
-            let value_str = match value {
-                Value::String(ref s) => s.clone(), // If the value is a string, use the original string.
-                Value::Number(ref n) => n.to_string(), // If the value is a number, use the number string representation.
-                Value::Null => "".to_string(),         // If value is null, use the empty string.
-                _ => value.to_string(),                // If the value is an object or other type, serialize it as JSON.
-            };
+        let table_name = self.get_table_name();
 
-            let mut stmt = conn
-                .prepare(&query)
-                .await
-                .map_err(|_| StoreError::QueryError("Failed to set the statement".to_string()))?;
+        // An immediate transaction takes the write lock up front, so no other
+        // connection can change the entry between the read and the write.
+        let tx = self
+            .connnection
+            .transaction_with_behavior(TransactionBehavior::Immediate)
+            .await
+            .map_err(|e| StoreError::QueryError(format!("Failed to begin the transaction: {:?}", e)))?;
 
-            let mut response = stmt
-                .query(params![key.clone(), value_str.clone()])
-                .await
-                .map_err(|_| StoreError::QueryError("Failed to set the value".to_string()))?;
+        let codec = self.codec();
+        let current = read_versioned(&tx, &codec, &table_name, key).await?;
+        let (result, new_value) = f(current)?;
 
-            let result = match response
-                .next()
-                .await
-                .map_err(|e| StoreError::QueryError(format!("Failed to iterate rows: {:?}", e)))?
-            {
-                Some(row) => {
-                    let row_key = row
-                        .get_str(0)
-                        .map_err(|e| StoreError::QueryError(format!("Failed to get the key: {:?}", e)))?;
+        let (version, evicted) = match new_value {
+            Some(value) => {
+                let version =
+                    write_versioned(&tx, &codec, self.limits(), &table_name, key, &value, Expiry::Keep).await?;
+                let evicted = enforce_limits(&tx, &self.limits, &table_name, &[key]).await?;
+                (Some(version), evicted)
+            }
+            None => (None, Vec::new()),
+        };
 
-                    let row_value = row
-                        .get_value(1)
-                        .map_err(|e| StoreError::QueryError(format!("Failed to get the value: {:?}", e)))?;
+        tx.commit()
+            .await
+            .map_err(|e| StoreError::QueryError(format!("Failed to commit the transaction: {:?}", e)))?;
 
-                    Some(StoreModel {
-                        key: row_key.to_string(),
-                        value: serde_json::to_value(row_value)
-                            .map_err(|e| StoreError::SerializationError { source: e })?,
-                    })
-                }
-                None => None,
-            };
+        if version.is_some() {
+            self.invalidate(key);
+            self.invalidate_evicted(&evicted);
+        }
 
-            let duration = start.elapsed();
-            log::debug!("Keyv store set: {:?} | {} | {}", duration, key, value_str);
+        Ok((result, version, evicted))
+    }
 
-            Ok(result)
-        })
+    /// Creates the namespace table on first use. Every operation goes through
+    /// this, so handles returned by `namespace` never touch the database until
+    /// they are actually used.
+    async fn ensure_table(&self) -> Result<(), StoreError> {
+        self.initialized.get_or_try_init(|| self.create_table()).await?;
+        Ok(())
     }
 
-    fn remove(&self, key: &str) -> Pin<Box<dyn Future<Output = Result<(), StoreError>> + Send + '_>> {
-        let query = format!("DELETE FROM {} WHERE key = ?1", self.get_table_name());
+    async fn create_table(&self) -> Result<(), StoreError> {
+        let _guard = self.lock_writes().await;
+        create_schema(&self.connnection, &self.table_name).await
+    }
+}
 
-        let conn = &*self.connnection;
+/// Decodes every value of a namespace, revisions included, as written for
+/// `namespace` by `codec`, then encodes it again for `table_name` with `new_codec`.
+/// With `encrypted_only`, values stored in the clear are left untouched.
+///
+/// Versions are kept, so the history triggers do not record the re-encoding.
+///
+/// Returns the number of values and of revisions that were re-encoded.
+async fn reencode_namespace(
+    conn: &Connection,
+    namespace: &str,
+    codec: &ValueCodec,
+    new_codec: &ValueCodec,
+    table_name: &str,
+    encrypted_only: bool,
+) -> Result<(u64, u64), StoreError> {
+    let filter = if encrypted_only {
+        format!("encoding LIKE '%+{}'", XCHACHA20POLY1305_ENCODING)
+    } else {
+        "1".to_string()
+    };
 
-        let key = key.to_string();
+    let mut rows = conn
+        .query(
+            &format!("SELECT key, value, encoding FROM {} WHERE {}", table_name, filter),
+            params![],
+        )
+        .await
+        .map_err(|e| StoreError::QueryError(format!("Failed to fetch the values: {:?}", e)))?;
 
-        Box::pin(async move {
-            let start = Instant::now();
+    let mut encoded: Vec<(String, EncodedValue)> = Vec::new();
 
-            let mut stmt = conn
-                .prepare(&query)
-                .await
-                .map_err(|_| StoreError::QueryError("Failed to set the statement".to_string()))?;
+    while let Some(row) = rows
+        .next()
+        .await
+        .map_err(|e| StoreError::QueryError(format!("Failed to iterate rows: {:?}", e)))?
+    {
+        let key: String = row
+            .get(0)
+            .map_err(|e| StoreError::QueryError(format!("Failed to get the key: {:?}", e)))?;
+        let row_value: String = row
+            .get(1)
+            .map_err(|e| StoreError::QueryError(format!("Failed to get the value: {:?}", e)))?;
+        let encoding: String = row
+            .get(2)
+            .map_err(|e| StoreError::QueryError(format!("Failed to get the encoding: {:?}", e)))?;
 
-            stmt.execute(params![key.clone()])
-                .await
-                .map_err(|_| StoreError::QueryError("Failed to remove the key".to_string()))?;
+        let value = codec.decode(namespace, &key, &row_value, &encoding)?;
+        let value = new_codec.encode(table_name, &key, &value)?;
+        encoded.push((key, value));
+    }
 
-            let duration = start.elapsed();
-            log::debug!("Keyv store remove: {:?} | {}", duration, key);
+    drop(rows);
 
-            Ok(())
-        })
-    }
+    let mut stmt = conn
+        .prepare(&format!(
+            "UPDATE {} SET value = ?2, encoding = ?3 WHERE key = ?1",
+            table_name
+        ))
+        .await
+        .map_err(|e| StoreError::QueryError(format!("Failed to prepare query: {:?}", e)))?;
 
-    fn remove_many(&self, keys: &[&str]) -> Pin<Box<dyn Future<Output = Result<(), StoreError>> + Send + '_>> {
-        let conn = &*self.connnection;
+    let count = encoded.len() as u64;
 
-        let placeholder = keys
-            .iter()
-            .enumerate()
-            .map(|(i, _)| format!("?{}", i + 1))
-            .collect::<Vec<String>>()
-            .join(", ");
+    for (key, value) in encoded {
+        stmt.execute(params![key, value.text, value.encoding])
+            .await
+            .map_err(|e| StoreError::QueryError(format!("Failed to re-encode the value: {:?}", e)))?;
+        stmt.reset();
+    }
 
-        let query = format!("DELETE FROM {} WHERE key IN ({})", self.get_table_name(), placeholder);
+    drop(stmt);
 
-        let keys = keys.iter().map(|k| k.to_string()).collect::<Vec<String>>();
+    // Revisions kept by the history are encoded the same way.
+    let mut rows = conn
+        .query(
+            &format!(
+                "SELECT id, key, value, encoding FROM {} WHERE namespace = ?1 AND {}",
+                HISTORY_TABLE, filter
+            ),
+            params![table_name],
+        )
+        .await
+        .map_err(|e| StoreError::QueryError(format!("Failed to fetch the revisions: {:?}", e)))?;
 
-        Box::pin(async move {
-            let start = Instant::now();
+    let mut revisions: Vec<(i64, EncodedValue)> = Vec::new();
 
-            let mut stmt = conn
-                .prepare(&query)
-                .await
-                .map_err(|_| StoreError::QueryError("Failed to set the statement".to_string()))?;
+    while let Some(row) = rows
+        .next()
+        .await
+        .map_err(|e| StoreError::QueryError(format!("Failed to iterate rows: {:?}", e)))?
+    {
+        let id: i64 = row
+            .get(0)
+            .map_err(|e| StoreError::QueryError(format!("Failed to get the revision id: {:?}", e)))?;
+        let key: String = row
+            .get(1)
+            .map_err(|e| StoreError::QueryError(format!("Failed to get the key: {:?}", e)))?;
+        let row_value: String = row
+            .get(2)
+            .map_err(|e| StoreError::QueryError(format!("Failed to get the value: {:?}", e)))?;
+        let encoding: String = row
+            .get(3)
+            .map_err(|e| StoreError::QueryError(format!("Failed to get the encoding: {:?}", e)))?;
 
-            stmt.execute(params_from_iter(keys))
-                .await
-                .map_err(|_| StoreError::QueryError("Failed to remove the key".to_string()))?;
+        let value = codec.decode(namespace, &key, &row_value, &encoding)?;
+        revisions.push((id, new_codec.encode(table_name, &key, &value)?));
+    }
 
-            let duration = start.elapsed();
-            log::debug!("Keyv store remove_many: {:?}", duration);
+    drop(rows);
 
-            Ok(())
-        })
+    let revision_count = revisions.len() as u64;
+
+    for (id, value) in revisions {
+        conn.execute(
+            &format!("UPDATE {} SET value = ?2, encoding = ?3 WHERE id = ?1", HISTORY_TABLE),
+            params![id, value.text, value.encoding],
+        )
+        .await
+        .map_err(|e| StoreError::QueryError(format!("Failed to re-encode the revision: {:?}", e)))?;
     }
 
-    fn clear(&self) -> Pin<Box<dyn Future<Output = Result<(), StoreError>> + Send + '_>> {
-        let query = format!("DELETE FROM {}", self.get_table_name());
+    Ok((count, revision_count))
+}
 
-        let conn = &*self.connnection;
+/// Returns the statement creating a namespace table.
+///
+/// Timestamps are UTC milliseconds since the Unix epoch, written by every
+/// statement that sets a value. The defaults only cover rows inserted by hand.
+fn create_table_statement(table_name: &str) -> String {
+    format!(
+        r#"
+            CREATE TABLE IF NOT EXISTS {table_name} (
+                key TEXT PRIMARY KEY,
+                value TEXT NOT NULL,
+                encoding TEXT NOT NULL DEFAULT 'json',
+                expires_at INTEGER,
+                version INTEGER NOT NULL DEFAULT 1,
+                created_at INTEGER NOT NULL DEFAULT ({now}),
+                updated_at INTEGER NOT NULL DEFAULT ({now}),
+                UNIQUE(key)
+            ) STRICT
+        "#,
+        table_name = table_name,
+        now = NOW_MILLIS_SQL
+    )
+}
 
-        Box::pin(async move {
-            conn.execute(&query, params![])
-                .await
-                .map_err(|_| StoreError::QueryError("Failed to clear the table".to_string()))?;
+/// Creates the table, indexes and history triggers of a namespace, upgrades
+/// tables written by older versions of the store, and registers the namespace.
+/// Every statement is idempotent.
+async fn create_schema(conn: &Connection, table_name: &str) -> Result<(), StoreError> {
+    conn.execute(&create_table_statement(table_name), params![])
+        .await
+        .map_err(|e| StoreError::QueryError(format!("Failed to initialize the database table: {}", e)))?;
 
-            Ok(())
-        })
+    // Tables created before TTL support lack the expiry column.
+    if !column_exists(conn, table_name, "expires_at").await? {
+        conn.execute(
+            &format!("ALTER TABLE {} ADD COLUMN expires_at INTEGER", table_name),
+            params![],
+        )
+        .await
+        .map_err(|e| StoreError::QueryError(format!("Failed to upgrade the database table: {}", e)))?;
+    }
+
+    // Tables created before versioning start every existing entry at version 1.
+    if !column_exists(conn, table_name, "version").await? {
+        conn.execute(
+            &format!(
+                "ALTER TABLE {} ADD COLUMN version INTEGER NOT NULL DEFAULT 1",
+                table_name
+            ),
+            params![],
+        )
+        .await
+        .map_err(|e| StoreError::QueryError(format!("Failed to upgrade the database table: {}", e)))?;
+    }
+
+    // Tables created before typed values stored everything as raw text and read
+    // it back as strings. Convert them once, keeping every value a string.
+    if !column_exists(conn, table_name, "encoding").await? {
+        // A savepoint rather than a transaction, so the upgrade also works when the
+        // namespace is first used inside a `KeyvStoreTransaction`.
+        conn.execute("SAVEPOINT keyv_upgrade", params![])
+            .await
+            .map_err(|e| StoreError::QueryError(format!("Failed to begin the upgrade: {}", e)))?;
+
+        let upgraded = match upgrade_encoding(conn, table_name).await {
+            Ok(upgraded) => upgraded,
+            Err(e) => {
+                let _ = conn.execute("ROLLBACK TO keyv_upgrade", params![]).await;
+                let _ = conn.execute("RELEASE keyv_upgrade", params![]).await;
+                return Err(e);
+            }
+        };
+
+        conn.execute("RELEASE keyv_upgrade", params![])
+            .await
+            .map_err(|e| StoreError::QueryError(format!("Failed to commit the upgrade: {}", e)))?;
+
+        log::info!(
+            "Keyv store upgraded {} values in {} to JSON encoding",
+            upgraded,
+            table_name
+        );
+    }
+
+    // Tables created before metadata support stored `updated_at` as local time
+    // text and had no `created_at`, so they are rebuilt with UTC timestamps.
+    if !column_exists(conn, table_name, "created_at").await? {
+        conn.execute("SAVEPOINT keyv_upgrade", params![])
+            .await
+            .map_err(|e| StoreError::QueryError(format!("Failed to begin the upgrade: {}", e)))?;
+
+        if let Err(e) = upgrade_timestamps(conn, table_name).await {
+            let _ = conn.execute("ROLLBACK TO keyv_upgrade", params![]).await;
+            let _ = conn.execute("RELEASE keyv_upgrade", params![]).await;
+            return Err(e);
+        }
+
+        conn.execute("RELEASE keyv_upgrade", params![])
+            .await
+            .map_err(|e| StoreError::QueryError(format!("Failed to commit the upgrade: {}", e)))?;
+
+        log::info!("Keyv store upgraded {} to UTC timestamps", table_name);
+    }
+
+    conn.execute_batch(&format!(
+        r#"
+            CREATE INDEX IF NOT EXISTS {table_name}_key_idx ON {table_name} (key);
+            CREATE INDEX IF NOT EXISTS {table_name}_expires_at_idx ON {table_name} (expires_at);
+            CREATE INDEX IF NOT EXISTS {table_name}_encoded_idx ON {table_name} (key) WHERE encoding != 'json';
+        "#,
+        table_name = table_name
+    ))
+    .await
+    .map_err(|e| StoreError::QueryError(format!("Failed to initialize the database table: {}", e)))?;
+
+    ensure_registry(conn).await?;
+
+    conn.execute(
+        &format!("INSERT OR IGNORE INTO {} (name) VALUES (?1)", NAMESPACE_REGISTRY_TABLE),
+        params![table_name],
+    )
+    .await
+    .map_err(|e| StoreError::QueryError(format!("Failed to register the namespace: {}", e)))?;
+
+    // The triggers are dropped when the namespace is renamed, so they are
+    // recreated from the registry under the new name.
+    let settings = namespace_settings(conn, table_name).await?;
+
+    if let Some(limit) = settings.history_limit {
+        conn.execute_batch(&history_triggers_statement(table_name, limit))
+            .await
+            .map_err(|e| StoreError::QueryError(format!("Failed to create the history triggers: {}", e)))?;
+    }
+
+    for (name, path) in &settings.indexes {
+        conn.execute(&json_index_statement(table_name, name, path), params![])
+            .await
+            .map_err(|e| StoreError::QueryError(format!("Failed to create the index {}: {}", name, e)))?;
+    }
+
+    if settings.search {
+        conn.execute_batch(&search_schema_statement(table_name))
+            .await
+            .map_err(|e| StoreError::QueryError(format!("Failed to create the search index: {}", e)))?;
+    }
+
+    log::debug!("Keyv store initialized namespace: {}", table_name);
+
+    Ok(())
+}
+
+fn registry_table_statement(table_name: &str) -> String {
+    format!(
+        r#"
+            CREATE TABLE IF NOT EXISTS {table_name} (
+                name TEXT PRIMARY KEY,
+                created_at INTEGER NOT NULL DEFAULT ({now}),
+                history_limit INTEGER,
+                search_enabled INTEGER NOT NULL DEFAULT 0,
+                json_indexes TEXT
+            ) STRICT
+        "#,
+        table_name = table_name,
+        now = NOW_MILLIS_SQL
+    )
+}
+
+/// Rebuilds a namespace registry created before UTC timestamps, converting
+/// `created_at` from local time text to UTC milliseconds.
+async fn upgrade_registry_timestamps(conn: &Connection) -> Result<(), StoreError> {
+    let upgrade_table = format!("{}_keyv_upgrade", NAMESPACE_REGISTRY_TABLE);
+
+    let statements = [
+        registry_table_statement(&upgrade_table),
+        format!(
+            "INSERT INTO {upgrade_table} (name, created_at, history_limit, search_enabled, json_indexes) \
+             SELECT name, COALESCE(CAST(strftime('%s', created_at, 'utc') AS INTEGER) * 1000, {now}), \
+             history_limit, search_enabled, json_indexes FROM {registry}",
+            upgrade_table = upgrade_table,
+            now = NOW_MILLIS_SQL,
+            registry = NAMESPACE_REGISTRY_TABLE
+        ),
+        format!("DROP TABLE {}", NAMESPACE_REGISTRY_TABLE),
+        format!("ALTER TABLE {} RENAME TO {}", upgrade_table, NAMESPACE_REGISTRY_TABLE),
+    ];
+
+    for statement in statements {
+        conn.execute(&statement, params![])
+            .await
+            .map_err(|e| StoreError::QueryError(format!("Failed to upgrade the namespace registry: {}", e)))?;
+    }
+
+    Ok(())
+}
+
+/// Rebuilds a table created before metadata support, converting `updated_at`
+/// from local time text to UTC milliseconds and using it as `created_at`.
+/// Dropping the old table also drops the trigger that maintained `updated_at`.
+async fn upgrade_timestamps(conn: &Connection, table_name: &str) -> Result<(), StoreError> {
+    let upgrade_table = format!("{}_keyv_upgrade", table_name);
+    let timestamp = format!(
+        "COALESCE(CAST(strftime('%s', updated_at, 'utc') AS INTEGER) * 1000, {})",
+        NOW_MILLIS_SQL
+    );
+
+    let statements = [
+        create_table_statement(&upgrade_table),
+        format!(
+            "INSERT INTO {upgrade_table} (key, value, encoding, expires_at, version, created_at, updated_at) \
+             SELECT key, value, encoding, expires_at, version, {timestamp}, {timestamp} FROM {table_name}",
+            upgrade_table = upgrade_table,
+            timestamp = timestamp,
+            table_name = table_name
+        ),
+        format!("DROP TABLE {}", table_name),
+        format!("ALTER TABLE {} RENAME TO {}", upgrade_table, table_name),
+    ];
+
+    for statement in statements {
+        conn.execute(&statement, params![])
+            .await
+            .map_err(|e| StoreError::QueryError(format!("Failed to upgrade the database table: {}", e)))?;
+    }
+
+    Ok(())
+}
+
+/// Converts the values of a table created before typed values to JSON.
+///
+/// Those versions returned every value as a string, so each one is quoted as a
+/// JSON string, even text such as `true` or `42`. They wrote `null` as an empty
+/// string, which is converted back to `null`.
+async fn upgrade_encoding(conn: &Connection, table_name: &str) -> Result<u64, StoreError> {
+    conn.execute(
+        &format!(
+            "ALTER TABLE {} ADD COLUMN encoding TEXT NOT NULL DEFAULT 'json'",
+            table_name
+        ),
+        params![],
+    )
+    .await
+    .map_err(|e| StoreError::QueryError(format!("Failed to upgrade the database table: {}", e)))?;
+
+    conn.execute(
+        &format!(
+            "UPDATE {} SET value = CASE WHEN value = '' THEN 'null' ELSE json_quote(value) END",
+            table_name
+        ),
+        params![],
+    )
+    .await
+    .map_err(|e| StoreError::QueryError(format!("Failed to upgrade the stored values: {}", e)))
+}
+
+/// Suffixes of the tables the store creates next to a namespace table: its
+/// full-text index and the shadow tables of FTS5, and the table used while
+/// upgrading it. Namespaces ending with one of them could clash with the
+/// tables of another namespace, so they are reserved.
+const RESERVED_NAMESPACE_SUFFIXES: [&str; 7] = [
+    "_search",
+    "_search_data",
+    "_search_idx",
+    "_search_content",
+    "_search_docsize",
+    "_search_config",
+    "_keyv_upgrade",
+];
+
+/// Checks that a namespace name is safe to use as an SQLite table name.
+///
+/// Namespaces are interpolated into SQL, so only ASCII letters, digits and
+/// underscores are accepted, and names reserved by SQLite or by the store
+/// itself are rejected, including those ending with a reserved suffix.
+pub(crate) fn validate_namespace(name: &str) -> Result<(), StoreError> {
+    let mut chars = name.chars();
+
+    let valid = match chars.next() {
+        Some(first) => {
+            (first.is_ascii_alphabetic() || first == '_') && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+        }
+        None => false,
+    };
+
+    if !valid
+        || name.to_ascii_lowercase().starts_with("sqlite_")
+        || name == NAMESPACE_REGISTRY_TABLE
+        || name == HISTORY_TABLE
+        || name == LOCKS_TABLE
+        || RESERVED_NAMESPACE_SUFFIXES
+            .iter()
+            .any(|suffix| name.to_ascii_lowercase().ends_with(suffix))
+    {
+        return Err(StoreError::InvalidNamespace(name.to_string()));
+    }
+
+    Ok(())
+}
+
+/// Creates the namespace registry, history and lock tables if they do not exist yet.
+async fn ensure_registry(conn: &Connection) -> Result<(), StoreError> {
+    conn.execute(&registry_table_statement(NAMESPACE_REGISTRY_TABLE), params![])
+        .await
+        .map_err(|e| StoreError::QueryError(format!("Failed to initialize the namespace registry: {}", e)))?;
+
+    // Registries created by older versions lack the settings added since.
+    for (column, definition) in [
+        ("history_limit", "INTEGER"),
+        ("search_enabled", "INTEGER NOT NULL DEFAULT 0"),
+        ("json_indexes", "TEXT"),
+    ] {
+        if !column_exists(conn, NAMESPACE_REGISTRY_TABLE, column).await? {
+            conn.execute(
+                &format!(
+                    "ALTER TABLE {} ADD COLUMN {} {}",
+                    NAMESPACE_REGISTRY_TABLE, column, definition
+                ),
+                params![],
+            )
+            .await
+            .map_err(|e| StoreError::QueryError(format!("Failed to upgrade the namespace registry: {}", e)))?;
+        }
+    }
+
+    // Registries created by older versions stored `created_at` as local time text.
+    if column_type(conn, NAMESPACE_REGISTRY_TABLE, "created_at")
+        .await?
+        .as_deref()
+        == Some("TEXT")
+    {
+        conn.execute("SAVEPOINT keyv_upgrade", params![])
+            .await
+            .map_err(|e| StoreError::QueryError(format!("Failed to begin the upgrade: {}", e)))?;
+
+        if let Err(e) = upgrade_registry_timestamps(conn).await {
+            let _ = conn.execute("ROLLBACK TO keyv_upgrade", params![]).await;
+            let _ = conn.execute("RELEASE keyv_upgrade", params![]).await;
+            return Err(e);
+        }
+
+        conn.execute("RELEASE keyv_upgrade", params![])
+            .await
+            .map_err(|e| StoreError::QueryError(format!("Failed to commit the upgrade: {}", e)))?;
+
+        log::info!("Keyv store upgraded the namespace registry to UTC timestamps");
+    }
+
+    // Ids are never reused, so a revision id cannot later point to another value.
+    conn.execute_batch(&format!(
+        r#"
+            CREATE TABLE IF NOT EXISTS {history} (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                namespace TEXT NOT NULL,
+                key TEXT NOT NULL,
+                value TEXT NOT NULL,
+                encoding TEXT NOT NULL,
+                version INTEGER NOT NULL,
+                expires_at INTEGER,
+                updated_at INTEGER NOT NULL,
+                replaced_at INTEGER NOT NULL,
+                removed INTEGER NOT NULL DEFAULT 0
+            ) STRICT;
+            CREATE INDEX IF NOT EXISTS {history}_key_idx ON {history} (namespace, key, id);
+        "#,
+        history = HISTORY_TABLE
+    ))
+    .await
+    .map_err(|e| StoreError::QueryError(format!("Failed to initialize the history table: {}", e)))?;
+
+    conn.execute(
+        &format!(
+            "CREATE TABLE IF NOT EXISTS {} (
+                name TEXT PRIMARY KEY,
+                owner TEXT NOT NULL,
+                acquired_at INTEGER NOT NULL,
+                expires_at INTEGER NOT NULL
+            ) STRICT",
+            LOCKS_TABLE
+        ),
+        params![],
+    )
+    .await
+    .map_err(|e| StoreError::QueryError(format!("Failed to initialize the lock table: {}", e)))?;
+
+    Ok(())
+}
+
+/// The opt-in features of a namespace, stored in the registry.
+#[derive(Debug, Default)]
+struct NamespaceSettings {
+    /// Number of revisions kept per key, `None` if the history is disabled.
+    history_limit: Option<usize>,
+    /// Whether the values are indexed for full-text search.
+    search: bool,
+    /// The secondary indexes, by name, with the JSON path they index.
+    indexes: BTreeMap<String, String>,
+}
+
+/// Reads the settings of a namespace from the registry.
+async fn namespace_settings(conn: &Connection, table_name: &str) -> Result<NamespaceSettings, StoreError> {
+    let mut rows = conn
+        .query(
+            &format!(
+                "SELECT history_limit, search_enabled, json_indexes FROM {} WHERE name = ?1",
+                NAMESPACE_REGISTRY_TABLE
+            ),
+            params![table_name],
+        )
+        .await
+        .map_err(|e| StoreError::QueryError(format!("Failed to read the namespace settings: {:?}", e)))?;
+
+    let Some(row) = rows
+        .next()
+        .await
+        .map_err(|e| StoreError::QueryError(format!("Failed to iterate rows: {:?}", e)))?
+    else {
+        return Ok(NamespaceSettings::default());
+    };
+
+    let history_limit = row
+        .get::<Option<i64>>(0)
+        .map_err(|e| StoreError::QueryError(format!("Failed to get the history limit: {:?}", e)))?;
+    let search = row
+        .get::<i64>(1)
+        .map_err(|e| StoreError::QueryError(format!("Failed to get the search setting: {:?}", e)))?;
+    let indexes = row
+        .get::<Option<String>>(2)
+        .map_err(|e| StoreError::QueryError(format!("Failed to get the indexes: {:?}", e)))?
+        .map(|indexes| serde_json::from_str(&indexes))
+        .transpose()
+        .map_err(|e| StoreError::SerializationError { source: e })?;
+
+    Ok(NamespaceSettings {
+        history_limit: history_limit.map(|limit| limit as usize),
+        search: search != 0,
+        indexes: indexes.unwrap_or_default(),
+    })
+}
+
+/// Returns the statements creating the triggers that record the previous value
+/// of a key each time it is overwritten or removed, keeping the last `limit`
+/// revisions of the key.
+///
+/// Entries that had already expired are not recorded, and neither are updates
+/// that keep the version, such as the re-encoding done by a key rotation.
+fn history_triggers_statement(table_name: &str, limit: usize) -> String {
+    let prune = format!(
+        "DELETE FROM {history} WHERE namespace = '{table_name}' AND key = OLD.key AND id <= ( \
+             SELECT id FROM {history} WHERE namespace = '{table_name}' AND key = OLD.key \
+             ORDER BY id DESC LIMIT 1 OFFSET {limit} \
+         );",
+        history = HISTORY_TABLE,
+        table_name = table_name,
+        limit = limit
+    );
+
+    format!(
+        r#"
+            CREATE TRIGGER IF NOT EXISTS {table_name}_history_update AFTER UPDATE ON {table_name}
+            WHEN OLD.version IS NOT NEW.version AND (OLD.expires_at IS NULL OR OLD.expires_at > NEW.updated_at)
+            BEGIN
+                INSERT INTO {history} (namespace, key, value, encoding, version, expires_at, updated_at, replaced_at)
+                VALUES ('{table_name}', OLD.key, OLD.value, OLD.encoding, OLD.version, OLD.expires_at, OLD.updated_at, NEW.updated_at);
+                {prune}
+            END;
+            CREATE TRIGGER IF NOT EXISTS {table_name}_history_delete AFTER DELETE ON {table_name}
+            WHEN OLD.expires_at IS NULL OR OLD.expires_at > {now}
+            BEGIN
+                INSERT INTO {history} (namespace, key, value, encoding, version, expires_at, updated_at, replaced_at, removed)
+                VALUES ('{table_name}', OLD.key, OLD.value, OLD.encoding, OLD.version, OLD.expires_at, OLD.updated_at, {now}, 1);
+                {prune}
+            END;
+        "#,
+        table_name = table_name,
+        history = HISTORY_TABLE,
+        now = NOW_MILLIS_SQL,
+        prune = prune
+    )
+}
+
+/// SQL expression of the text indexed for a value: the strings it contains,
+/// separated by spaces, leaving out the keys of objects.
+fn search_content_sql(value: &str) -> String {
+    format!(
+        "(SELECT group_concat(atom, ' ') FROM json_tree({}) WHERE type = 'text')",
+        value
+    )
+}
+
+/// Returns the statements creating the full-text index of a namespace and the
+/// triggers keeping it in sync. Index rows share the rowid of their entry.
+///
+/// Only values stored as plain JSON are indexed: compressed values cannot be
+/// read from SQL, and encrypted ones must not be written to the index in the clear.
+fn search_schema_statement(table_name: &str) -> String {
+    format!(
+        r#"
+            CREATE VIRTUAL TABLE IF NOT EXISTS {table_name}_search USING fts5(key UNINDEXED, content);
+            CREATE TRIGGER IF NOT EXISTS {table_name}_search_insert AFTER INSERT ON {table_name}
+            WHEN NEW.encoding = 'json'
+            BEGIN
+                INSERT INTO {table_name}_search (rowid, key, content) VALUES (NEW.rowid, NEW.key, {content});
+            END;
+            CREATE TRIGGER IF NOT EXISTS {table_name}_search_update AFTER UPDATE OF value, encoding ON {table_name}
+            BEGIN
+                DELETE FROM {table_name}_search WHERE rowid = OLD.rowid;
+                INSERT INTO {table_name}_search (rowid, key, content)
+                SELECT NEW.rowid, NEW.key, {content} WHERE NEW.encoding = 'json';
+            END;
+            CREATE TRIGGER IF NOT EXISTS {table_name}_search_delete AFTER DELETE ON {table_name}
+            BEGIN
+                DELETE FROM {table_name}_search WHERE rowid = OLD.rowid;
+            END;
+        "#,
+        table_name = table_name,
+        content = search_content_sql("NEW.value")
+    )
+}
+
+/// Drops the full-text index of a namespace and its triggers, if any.
+fn drop_search_statement(table_name: &str) -> String {
+    format!(
+        r#"
+            DROP TRIGGER IF EXISTS {table_name}_search_insert;
+            DROP TRIGGER IF EXISTS {table_name}_search_update;
+            DROP TRIGGER IF EXISTS {table_name}_search_delete;
+            DROP TABLE IF EXISTS {table_name}_search;
+        "#,
+        table_name = table_name
+    )
+}
+
+/// Returns the statement creating a secondary index on the JSON field at `path`,
+/// which must come from `json_path` so it is safe to inline.
+fn json_index_statement(table_name: &str, name: &str, path: &str) -> String {
+    format!(
+        "CREATE INDEX IF NOT EXISTS {table_name}_json_{name}_idx ON {table_name} (json_extract({value}, '{path}'))",
+        table_name = table_name,
+        name = name,
+        value = JSON_VALUE_SQL,
+        path = path
+    )
+}
+
+/// Drops the history triggers of a namespace, if any.
+async fn drop_history_triggers(conn: &Connection, table_name: &str) -> Result<(), StoreError> {
+    conn.execute_batch(&format!(
+        r#"
+            DROP TRIGGER IF EXISTS {table_name}_history_update;
+            DROP TRIGGER IF EXISTS {table_name}_history_delete;
+        "#,
+        table_name = table_name
+    ))
+    .await
+    .map_err(|e| StoreError::QueryError(format!("Failed to drop the history triggers: {}", e)))?;
+
+    Ok(())
+}
+
+/// Returns the current time as milliseconds since the Unix epoch.
+///
+/// Expiry timestamps are stored in this form so they can be compared
+/// directly in SQL without depending on the connection timezone.
+pub(crate) fn unix_millis() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as i64)
+        .unwrap_or_default()
+}
+
+/// Converts a time-to-live in seconds into an absolute expiry timestamp.
+pub(crate) fn expires_at_from_ttl(ttl: Option<u64>) -> Option<i64> {
+    ttl.map(|ttl| unix_millis().saturating_add((ttl as i64).saturating_mul(1000)))
+}
+
+/// Checks whether `column` exists on `table`, used to upgrade tables created
+/// by older versions of the store.
+async fn column_exists(conn: &Connection, table: &str, column: &str) -> Result<bool, StoreError> {
+    Ok(column_type(conn, table, column).await?.is_some())
+}
+
+/// Returns the declared type of `column` on `table`, or `None` if the table
+/// has no such column.
+async fn column_type(conn: &Connection, table: &str, column: &str) -> Result<Option<String>, StoreError> {
+    let mut rows = conn
+        .query(&format!("PRAGMA table_info({})", table), params![])
+        .await
+        .map_err(|e| StoreError::QueryError(format!("Failed to read the table info: {:?}", e)))?;
+
+    while let Some(row) = rows
+        .next()
+        .await
+        .map_err(|e| StoreError::QueryError(format!("Failed to iterate rows: {:?}", e)))?
+    {
+        let name: String = row
+            .get(1)
+            .map_err(|e| StoreError::QueryError(format!("Failed to get the column name: {:?}", e)))?;
+
+        if name == column {
+            let column_type: String = row
+                .get(2)
+                .map_err(|e| StoreError::QueryError(format!("Failed to get the column type: {:?}", e)))?;
+            return Ok(Some(column_type));
+        }
+    }
+
+    Ok(None)
+}
+
+/// Returns the `PRAGMA data_version` of a connection, which changes each time
+/// another connection commits to the database.
+async fn data_version(conn: &Connection) -> Result<i64, StoreError> {
+    let mut rows = conn
+        .query("PRAGMA data_version", params![])
+        .await
+        .map_err(|e| StoreError::QueryError(format!("Failed to read the data version: {:?}", e)))?;
+
+    let row = rows
+        .next()
+        .await
+        .map_err(|e| StoreError::QueryError(format!("Failed to iterate rows: {:?}", e)))?
+        .ok_or_else(|| StoreError::QueryError("Failed to read the data version".to_string()))?;
+
+    row.get(0)
+        .map_err(|e| StoreError::QueryError(format!("Failed to get the data version: {:?}", e)))
+}
+
+/// How a versioned write treats the expiry of the entry it replaces.
+pub(crate) enum Expiry {
+    /// Keep the current expiry, unless the entry has already expired.
+    Keep,
+    /// Replace the expiry, `None` meaning the entry never expires.
+    Set(Option<i64>),
+}
+
+/// Reads the live entry for `key` together with its version.
+///
+/// Used inside transactions, which deref to a `Connection`.
+async fn read_versioned(
+    conn: &Connection,
+    codec: &ValueCodec,
+    table_name: &str,
+    key: &str,
+) -> Result<Option<VersionedValue>, StoreError> {
+    Ok(read_entry(conn, codec, table_name, key)
+        .await?
+        .map(|model| VersionedValue {
+            value: model.value,
+            version: model.version,
+        }))
+}
+
+/// Reads the live entry for `key` together with its metadata.
+async fn read_entry(
+    conn: &Connection,
+    codec: &ValueCodec,
+    table_name: &str,
+    key: &str,
+) -> Result<Option<StoreModel>, StoreError> {
+    let query = format!(
+        "SELECT {} FROM {} WHERE key = ?1 AND (expires_at IS NULL OR expires_at > ?2) LIMIT 1",
+        MODEL_COLUMNS, table_name
+    );
+
+    let mut rows = conn
+        .query(&query, params![key, unix_millis()])
+        .await
+        .map_err(|e| StoreError::QueryError(format!("Failed to fetch the value: {:?}", e)))?;
+
+    match rows
+        .next()
+        .await
+        .map_err(|e| StoreError::QueryError(format!("Failed to iterate rows: {:?}", e)))?
+    {
+        Some(row) => Ok(Some(read_model(&row, codec, table_name)?)),
+        None => Ok(None),
+    }
+}
+
+/// Decodes a row selected with [`MODEL_COLUMNS`].
+fn read_model(row: &Row, codec: &ValueCodec, table_name: &str) -> Result<StoreModel, StoreError> {
+    let key: String = row
+        .get(0)
+        .map_err(|e| StoreError::QueryError(format!("Failed to get the key: {:?}", e)))?;
+    let row_value: String = row
+        .get(1)
+        .map_err(|e| StoreError::QueryError(format!("Failed to get the value: {:?}", e)))?;
+    let encoding: String = row
+        .get(2)
+        .map_err(|e| StoreError::QueryError(format!("Failed to get the encoding: {:?}", e)))?;
+    let version: i64 = row
+        .get(3)
+        .map_err(|e| StoreError::QueryError(format!("Failed to get the version: {:?}", e)))?;
+    let created_at: i64 = row
+        .get(4)
+        .map_err(|e| StoreError::QueryError(format!("Failed to get the creation time: {:?}", e)))?;
+    let updated_at: i64 = row
+        .get(5)
+        .map_err(|e| StoreError::QueryError(format!("Failed to get the update time: {:?}", e)))?;
+    let expires_at: Option<i64> = row
+        .get(6)
+        .map_err(|e| StoreError::QueryError(format!("Failed to get the expiry: {:?}", e)))?;
+
+    let value = codec.decode(table_name, &key, &row_value, &encoding)?;
+
+    Ok(StoreModel {
+        key,
+        value,
+        version: version as u64,
+        created_at,
+        updated_at,
+        expires_at,
+    })
+}
+
+/// Reads the revisions of `key` in a namespace, most recent first, or only
+/// the revision `id` if given.
+async fn read_revisions(
+    conn: &Connection,
+    codec: &ValueCodec,
+    table_name: &str,
+    key: &str,
+    id: Option<i64>,
+) -> Result<Vec<Revision>, StoreError> {
+    let query = format!(
+        "SELECT {} FROM {} WHERE namespace = ?1 AND key = ?2 AND (?3 IS NULL OR id = ?3) ORDER BY id DESC",
+        REVISION_COLUMNS, HISTORY_TABLE
+    );
+
+    let mut rows = conn
+        .query(&query, params![table_name, key, id])
+        .await
+        .map_err(|e| StoreError::QueryError(format!("Failed to fetch the revisions: {:?}", e)))?;
+
+    let mut revisions = Vec::new();
+
+    while let Some(row) = rows
+        .next()
+        .await
+        .map_err(|e| StoreError::QueryError(format!("Failed to iterate rows: {:?}", e)))?
+    {
+        revisions.push(read_revision(&row, codec, table_name)?);
+    }
+
+    Ok(revisions)
+}
+
+/// Builds a `Revision` from a row selecting `REVISION_COLUMNS`.
+fn read_revision(row: &Row, codec: &ValueCodec, table_name: &str) -> Result<Revision, StoreError> {
+    let id: i64 = row
+        .get(0)
+        .map_err(|e| StoreError::QueryError(format!("Failed to get the revision id: {:?}", e)))?;
+    let key: String = row
+        .get(1)
+        .map_err(|e| StoreError::QueryError(format!("Failed to get the key: {:?}", e)))?;
+    let row_value: String = row
+        .get(2)
+        .map_err(|e| StoreError::QueryError(format!("Failed to get the value: {:?}", e)))?;
+    let encoding: String = row
+        .get(3)
+        .map_err(|e| StoreError::QueryError(format!("Failed to get the encoding: {:?}", e)))?;
+    let version: i64 = row
+        .get(4)
+        .map_err(|e| StoreError::QueryError(format!("Failed to get the version: {:?}", e)))?;
+    let updated_at: i64 = row
+        .get(5)
+        .map_err(|e| StoreError::QueryError(format!("Failed to get the update time: {:?}", e)))?;
+    let replaced_at: i64 = row
+        .get(6)
+        .map_err(|e| StoreError::QueryError(format!("Failed to get the replacement time: {:?}", e)))?;
+    let removed: i64 = row
+        .get(7)
+        .map_err(|e| StoreError::QueryError(format!("Failed to get the removal flag: {:?}", e)))?;
+
+    let value = codec.decode(table_name, &key, &row_value, &encoding)?;
+
+    Ok(Revision {
+        id,
+        key,
+        value,
+        version: version as u64,
+        updated_at,
+        replaced_at,
+        removed: removed != 0,
+    })
+}
+
+/// Checks the size of a namespace after a write and, if it is over its limits,
+/// evicts other entries when its policy allows it. Runs in the transaction of
+/// the write, which must be rolled back if the namespace is still over its limits.
+///
+/// Returns the evicted keys. Namespaces without a key count or byte limit are
+/// not checked.
+async fn enforce_limits(
+    conn: &Connection,
+    limits: &StoreLimits,
+    table_name: &str,
+    written: &[&str],
+) -> Result<Vec<String>, StoreError> {
+    let namespace_limits = limits.get(table_name);
+
+    if !namespace_limits.limits_size() {
+        return Ok(Vec::new());
+    }
+
+    let now = unix_millis();
+
+    let mut rows = conn
+        .query(
+            &format!(
+                "SELECT COUNT(*), COALESCE(SUM(length(CAST(key AS BLOB)) + length(CAST(value AS BLOB))), 0) \
+                 FROM {} WHERE expires_at IS NULL OR expires_at > ?1",
+                table_name
+            ),
+            params![now],
+        )
+        .await
+        .map_err(|e| StoreError::QueryError(format!("Failed to measure the namespace: {:?}", e)))?;
+
+    let row = rows
+        .next()
+        .await
+        .map_err(|e| StoreError::QueryError(format!("Failed to iterate rows: {:?}", e)))?
+        .ok_or_else(|| StoreError::QueryError("The namespace size query returned no row".to_string()))?;
+
+    let mut keys =
+        row.get::<i64>(0)
+            .map_err(|e| StoreError::QueryError(format!("Failed to get the key count: {:?}", e)))? as u64;
+    let mut bytes =
+        row.get::<i64>(1)
+            .map_err(|e| StoreError::QueryError(format!("Failed to get the namespace size: {:?}", e)))? as u64;
+
+    drop(rows);
+
+    let exceeded = match namespace_limits.check_size(table_name, keys, bytes) {
+        Ok(()) => return Ok(Vec::new()),
+        Err(e) => e,
+    };
+
+    let Some(policy) = namespace_limits.eviction else {
+        return Err(exceeded);
+    };
+
+    let mut rows = conn
+        .query(
+            &format!(
+                "SELECT key, length(CAST(key AS BLOB)) + length(CAST(value AS BLOB)), updated_at FROM {} \
+                 WHERE expires_at IS NULL OR expires_at > ?1 ORDER BY created_at ASC, key ASC",
+                table_name
+            ),
+            params![now],
+        )
+        .await
+        .map_err(|e| StoreError::QueryError(format!("Failed to list the eviction candidates: {:?}", e)))?;
+
+    let mut candidates: Vec<(String, u64, i64)> = Vec::new();
+
+    while let Some(row) = rows
+        .next()
+        .await
+        .map_err(|e| StoreError::QueryError(format!("Failed to iterate rows: {:?}", e)))?
+    {
+        let key: String = row
+            .get(0)
+            .map_err(|e| StoreError::QueryError(format!("Failed to get the key: {:?}", e)))?;
+
+        if written.contains(&key.as_str()) {
+            continue;
+        }
+
+        let size: i64 = row
+            .get(1)
+            .map_err(|e| StoreError::QueryError(format!("Failed to get the entry size: {:?}", e)))?;
+        let updated_at: i64 = row
+            .get(2)
+            .map_err(|e| StoreError::QueryError(format!("Failed to get the update time: {:?}", e)))?;
+
+        candidates.push((key, size as u64, updated_at));
+    }
+
+    drop(rows);
+
+    // Candidates come oldest first. A stable sort keeps that order between
+    // entries last used at the same time.
+    if policy == EvictionPolicy::Lru {
+        candidates.sort_by_key(|(key, _, updated_at)| {
+            limits
+                .last_access(table_name, key)
+                .map_or(*updated_at, |accessed| accessed.max(*updated_at))
+        });
+    }
+
+    let mut evicted = Vec::new();
+    let mut candidates = candidates.into_iter();
+
+    while namespace_limits.check_size(table_name, keys, bytes).is_err() {
+        // Only the written entries are left, and they are over the limits on their own.
+        let Some((key, size, _)) = candidates.next() else {
+            return Err(exceeded);
+        };
+
+        keys -= 1;
+        bytes = bytes.saturating_sub(size);
+        evicted.push(key);
+    }
+
+    // Evicted rows are marked as expired before being deleted, so that the
+    // history triggers, which skip expired rows, do not record an eviction as
+    // a removal that could be reverted.
+    let mut expire = conn
+        .prepare(&format!("UPDATE {} SET expires_at = 0 WHERE key = ?1", table_name))
+        .await
+        .map_err(|e| StoreError::QueryError(format!("Failed to prepare query: {:?}", e)))?;
+    let mut delete = conn
+        .prepare(&format!("DELETE FROM {} WHERE key = ?1", table_name))
+        .await
+        .map_err(|e| StoreError::QueryError(format!("Failed to prepare query: {:?}", e)))?;
+
+    for key in &evicted {
+        for stmt in [&mut expire, &mut delete] {
+            stmt.execute(params![key.clone()])
+                .await
+                .map_err(|e| StoreError::QueryError(format!("Failed to evict the key: {:?}", e)))?;
+            stmt.reset();
+        }
+    }
+
+    limits.forget(table_name, evicted.iter().map(|key| key.as_str()));
+
+    Ok(evicted)
+}
+
+/// Writes `value` for `key`, bumping its version, and returns the new version.
+/// An expired row is replaced as if it did not exist, with a new `created_at`
+/// and starting again at version 1.
+///
+/// Used inside transactions, which deref to a `Connection`.
+async fn write_versioned(
+    conn: &Connection,
+    codec: &ValueCodec,
+    limits: &NamespaceLimits,
+    table_name: &str,
+    key: &str,
+    value: &Value,
+    expiry: Expiry,
+) -> Result<u64, StoreError> {
+    let encoded = codec.encode(table_name, key, value)?;
+    limits.check_value_size(key, encoded.text.len())?;
+
+    let mut rows = match expiry {
+        Expiry::Keep => {
+            let query = format!(
+                "INSERT INTO {} (key, value, encoding, created_at, updated_at) VALUES (?1, ?2, ?3, ?4, ?4) \
+                 ON CONFLICT(key) DO UPDATE SET value = EXCLUDED.value, encoding = EXCLUDED.encoding, \
+                 version = CASE WHEN expires_at <= ?4 THEN 1 ELSE version + 1 END, \
+                 created_at = CASE WHEN expires_at <= ?4 THEN EXCLUDED.created_at ELSE created_at END, \
+                 updated_at = EXCLUDED.updated_at, expires_at = CASE WHEN expires_at > ?4 THEN expires_at END \
+                 RETURNING version",
+                table_name
+            );
+            conn.query(&query, params![key, encoded.text, encoded.encoding, unix_millis()])
+                .await
+        }
+        Expiry::Set(expires_at) => {
+            let query = format!(
+                "INSERT INTO {} (key, value, encoding, expires_at, created_at, updated_at) \
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?5) \
+                 ON CONFLICT(key) DO UPDATE SET value = EXCLUDED.value, encoding = EXCLUDED.encoding, \
+                 version = CASE WHEN expires_at <= ?5 THEN 1 ELSE version + 1 END, \
+                 created_at = CASE WHEN expires_at <= ?5 THEN EXCLUDED.created_at ELSE created_at END, \
+                 updated_at = EXCLUDED.updated_at, expires_at = EXCLUDED.expires_at \
+                 RETURNING version",
+                table_name
+            );
+            conn.query(
+                &query,
+                params![key, encoded.text, encoded.encoding, expires_at, unix_millis()],
+            )
+            .await
+        }
+    }
+    .map_err(|e| StoreError::QueryError(format!("Failed to set the value: {:?}", e)))?;
+
+    let row = rows
+        .next()
+        .await
+        .map_err(|e| StoreError::QueryError(format!("Failed to iterate rows: {:?}", e)))?
+        .ok_or_else(|| StoreError::QueryError("The write did not return a version".to_string()))?;
+
+    let version: i64 = row
+        .get(0)
+        .map_err(|e| StoreError::QueryError(format!("Failed to get the version: {:?}", e)))?;
+
+    Ok(version as u64)
+}
+
+impl Store for KeyvStore {
+    fn initialize(&self) -> Pin<Box<dyn Future<Output = Result<(), StoreError>> + Send + '_>> {
+        Box::pin(self.ensure_table())
+    }
+
+    fn get(&self, key: &str) -> Pin<Box<dyn Future<Output = Result<Option<Value>, StoreError>> + Send + '_>> {
+        let query = format!(
+            "SELECT value, encoding FROM {} WHERE key = ?1 AND (expires_at IS NULL OR expires_at > ?2) LIMIT 1",
+            self.get_table_name()
+        );
+
+        let key = key.to_string();
+
+        Box::pin(async move {
+            self.ensure_table().await?;
+
+            let start = Instant::now();
+
+            if let Some(cache) = &self.cache {
+                let value = self.read_through(cache, &key).await?.map(|v| v.value);
+                if value.is_some() {
+                    self.record_access(&key);
+                }
+                log::debug!(
+                    "Keyv store get: {:?} | {} | {}",
+                    start.elapsed(),
+                    key,
+                    if value.is_some() { "found" } else { "not found" }
+                );
+                return Ok(value);
+            }
+
+            let conn = self.read_connection().await;
+
+            let mut stmt = conn
+                .prepare(&query)
+                .await
+                .map_err(|e| StoreError::QueryError(format!("Failed to set the statement: {:?}", e)))?;
+
+            let mut rows = stmt
+                .query(params![key.clone(), unix_millis()])
+                .await
+                .map_err(|e| StoreError::QueryError(format!("Failed to fetch the value: {:?}", e)))?;
+
+            // Missing and expired keys are both reported as absent.
+            let result = match rows
+                .next()
+                .await
+                .map_err(|e| StoreError::QueryError(format!("Failed to iterate rows: {:?}", e)))?
+            {
+                Some(row) => row,
+                None => {
+                    log::debug!("Keyv store get: {:?} | {} | not found", start.elapsed(), key);
+                    return Ok(None);
+                }
+            };
+
+            let row_value: String = result
+                .get(0)
+                .map_err(|e| StoreError::QueryError(format!("Failed to get the value: {:?}", e)))?;
+            let encoding: String = result
+                .get(1)
+                .map_err(|e| StoreError::QueryError(format!("Failed to get the encoding: {:?}", e)))?;
+
+            let value = self.codec().decode(&self.table_name, &key, &row_value, &encoding)?;
+            self.record_access(&key);
+
+            let duration = start.elapsed();
+            log::debug!("Keyv store get: {:?} | {} | {} bytes", duration, key, row_value.len());
+
+            Ok(Some(value))
+        })
+    }
+
+    fn get_versioned(
+        &self,
+        key: &str,
+    ) -> Pin<Box<dyn Future<Output = Result<Option<VersionedValue>, StoreError>> + Send + '_>> {
+        let table_name = self.get_table_name();
+        let key = key.to_string();
+
+        Box::pin(async move {
+            self.ensure_table().await?;
+
+            let start = Instant::now();
+
+            let result = match &self.cache {
+                Some(cache) => self.read_through(cache, &key).await?,
+                None => read_versioned(&*self.read_connection().await, &self.codec(), &table_name, &key).await?,
+            };
+
+            if result.is_some() {
+                self.record_access(&key);
+            }
+
+            let duration = start.elapsed();
+            log::debug!(
+                "Keyv store get_versioned: {:?} | {} | {:?}",
+                duration,
+                key,
+                result.as_ref().map(|v| v.version)
+            );
+
+            Ok(result)
+        })
+    }
+
+    fn get_with_meta(
+        &self,
+        key: &str,
+    ) -> Pin<Box<dyn Future<Output = Result<Option<StoreModel>, StoreError>> + Send + '_>> {
+        let table_name = self.get_table_name();
+        let key = key.to_string();
+
+        Box::pin(async move {
+            self.ensure_table().await?;
+
+            let start = Instant::now();
+
+            let result = read_entry(&*self.read_connection().await, &self.codec(), &table_name, &key).await?;
+
+            if result.is_some() {
+                self.record_access(&key);
+            }
+
+            let duration = start.elapsed();
+            log::debug!(
+                "Keyv store get_with_meta: {:?} | {} | {:?}",
+                duration,
+                key,
+                result.as_ref().map(|model| model.version)
+            );
+
+            Ok(result)
+        })
+    }
+
+    fn get_many(
+        &self,
+        keys: &[&str],
+    ) -> Pin<Box<dyn Future<Output = Result<HashMap<String, Value>, StoreError>> + Send + '_>> {
+        let table_name = self.get_table_name();
+        let keys = keys.iter().map(|k| k.to_string()).collect::<Vec<String>>();
+
+        Box::pin(async move {
+            self.ensure_table().await?;
+            let conn = self.read_connection().await;
+
+            let start = Instant::now();
+
+            let codec = self.codec();
+            let mut values = HashMap::with_capacity(keys.len());
+
+            for chunk in keys.chunks(GET_MANY_CHUNK_SIZE) {
+                let placeholder = (0..chunk.len())
+                    .map(|i| format!("?{}", i + 2))
+                    .collect::<Vec<String>>()
+                    .join(", ");
+
+                let query = format!(
+                    "SELECT key, value, encoding FROM {} \
+                     WHERE key IN ({}) AND (expires_at IS NULL OR expires_at > ?1)",
+                    table_name, placeholder
+                );
+
+                let mut params: Vec<libsql::Value> = vec![unix_millis().into()];
+                params.extend(chunk.iter().map(|k| libsql::Value::from(k.clone())));
+
+                let mut rows = conn
+                    .query(&query, params_from_iter(params))
+                    .await
+                    .map_err(|e| StoreError::QueryError(format!("Failed to fetch the values: {:?}", e)))?;
+
+                while let Some(row) = rows
+                    .next()
+                    .await
+                    .map_err(|e| StoreError::QueryError(format!("Failed to iterate rows: {:?}", e)))?
+                {
+                    let key: String = row
+                        .get(0)
+                        .map_err(|e| StoreError::QueryError(format!("Failed to get the key: {:?}", e)))?;
+                    let row_value: String = row
+                        .get(1)
+                        .map_err(|e| StoreError::QueryError(format!("Failed to get the value: {:?}", e)))?;
+
+                    let encoding: String = row
+                        .get(2)
+                        .map_err(|e| StoreError::QueryError(format!("Failed to get the encoding: {:?}", e)))?;
+
+                    let value = codec.decode(&self.table_name, &key, &row_value, &encoding)?;
+                    values.insert(key, value);
+                }
+            }
+
+            self.limits
+                .record_access(&table_name, values.keys().map(|key| key.as_str()), unix_millis());
+
+            let duration = start.elapsed();
+            log::debug!(
+                "Keyv store get_many: {:?} | {} keys | {} found",
+                duration,
+                keys.len(),
+                values.len()
+            );
+
+            Ok(values)
+        })
+    }
+
+    fn list(&self) -> Pin<Box<dyn Future<Output = Result<Vec<StoreModel>, StoreError>> + Send + '_>> {
+        let query = format!(
+            "SELECT {} FROM {} WHERE expires_at IS NULL OR expires_at > ?1 ORDER BY key ASC;",
+            MODEL_COLUMNS,
+            self.get_table_name()
+        );
+
+        Box::pin(async move {
+            self.ensure_table().await?;
+            let conn = self.read_connection().await;
+
+            let start = Instant::now();
+
+            let mut stmt = conn
+                .prepare(&query)
+                .await
+                .map_err(|e| StoreError::QueryError(format!("Failed to set the statement: {:?}", e)))?;
+
+            let mut results = stmt
+                .query(params![unix_millis()])
+                .await
+                .map_err(|e| StoreError::QueryError(format!("Failed to fetch the value: {:?}", e)))?;
+
+            let codec = self.codec();
+            let mut items: Vec<StoreModel> = Vec::new();
+
+            while let Some(row) = results
+                .next()
+                .await
+                .map_err(|e| StoreError::QueryError(format!("Failed to iterate rows: {:?}", e)))?
+            {
+                items.push(read_model(&row, &codec, &self.table_name)?);
+            }
+
+            let duration = start.elapsed();
+            log::debug!("Keyv store list: {:?} | {} items", duration, items.len());
+
+            Ok(items)
+        })
+    }
+
+    fn list_page(&self, query: ListQuery) -> Pin<Box<dyn Future<Output = Result<ListPage, StoreError>> + Send + '_>> {
+        let mut conditions = vec!["(expires_at IS NULL OR expires_at > ?1)".to_string()];
+        let mut values: Vec<libsql::Value> = vec![unix_millis().into()];
+
+        let mut bind = |condition: &str, value: String| {
+            values.push(value.into());
+            conditions.push(format!("{} ?{}", condition, values.len()));
+        };
+
+        if let Some(prefix) = query.prefix.clone() {
+            if let Some(upper) = prefix_upper_bound(&prefix) {
+                bind("key <", upper);
+            }
+            bind("key >=", prefix);
+        }
+        if let Some(start) = query.start.clone() {
+            bind("key >=", start);
+        }
+        if let Some(end) = query.end.clone() {
+            bind("key <", end);
+        }
+        if let Some(after) = query.after.clone() {
+            bind("key >", after);
+        }
+
+        // Fetch one extra row to find out whether another page follows.
+        let limit_clause = match query.limit {
+            Some(limit) => format!(" LIMIT {}", limit.saturating_add(1)),
+            None => String::new(),
+        };
+
+        let sql = format!(
+            "SELECT {} FROM {} WHERE {} ORDER BY key ASC{}",
+            MODEL_COLUMNS,
+            self.get_table_name(),
+            conditions.join(" AND "),
+            limit_clause
+        );
+
+        Box::pin(async move {
+            self.ensure_table().await?;
+            let conn = self.read_connection().await;
+
+            let start = Instant::now();
+
+            let mut stmt = conn
+                .prepare(&sql)
+                .await
+                .map_err(|e| StoreError::QueryError(format!("Failed to set the statement: {:?}", e)))?;
+
+            let mut results = stmt
+                .query(params_from_iter(values))
+                .await
+                .map_err(|e| StoreError::QueryError(format!("Failed to fetch the value: {:?}", e)))?;
+
+            let codec = self.codec();
+            let mut items: Vec<StoreModel> = Vec::new();
+
+            while let Some(row) = results
+                .next()
+                .await
+                .map_err(|e| StoreError::QueryError(format!("Failed to iterate rows: {:?}", e)))?
+            {
+                items.push(read_model(&row, &codec, &self.table_name)?);
+            }
+
+            let next_cursor = match query.limit {
+                Some(limit) if items.len() as u64 > limit => {
+                    items.truncate(limit as usize);
+                    items.last().map(|item| item.key.clone())
+                }
+                _ => None,
+            };
+
+            let duration = start.elapsed();
+            log::debug!(
+                "Keyv store list_page: {:?} | {} items | {:?}",
+                duration,
+                items.len(),
+                query
+            );
+
+            Ok(ListPage { items, next_cursor })
+        })
+    }
+
+    fn find(&self, query: FindQuery) -> Pin<Box<dyn Future<Output = Result<Vec<StoreModel>, StoreError>> + Send + '_>> {
+        let table_name = self.get_table_name();
+
+        let mut bounds = vec!["(expires_at IS NULL OR expires_at > ?1)".to_string()];
+        let mut values: Vec<libsql::Value> = vec![unix_millis().into()];
+
+        if let Some(prefix) = query.prefix.clone() {
+            if let Some(upper) = prefix_upper_bound(&prefix) {
+                values.push(upper.into());
+                bounds.push(format!("key < ?{}", values.len()));
+            }
+            values.push(prefix.into());
+            bounds.push(format!("key >= ?{}", values.len()));
+        }
+
+        // Compressed and encrypted values cannot be filtered in SQL, so they are
+        // decoded and matched in memory. They are found through a partial index.
+        let scan_values = values.clone();
+        let scan_sql = format!(
+            "SELECT {} FROM {} WHERE encoding != 'json' AND {} ORDER BY key ASC",
+            MODEL_COLUMNS,
+            table_name,
+            bounds.join(" AND ")
+        );
+
+        let limit_clause = match query.limit {
+            Some(limit) => format!(" LIMIT {}", limit),
+            None => String::new(),
+        };
+
+        let sql = format!(
+            "SELECT {} FROM {} WHERE encoding = 'json' AND {} AND {} ORDER BY key ASC{}",
+            MODEL_COLUMNS,
+            table_name,
+            bounds.join(" AND "),
+            query.filter.to_sql(JSON_VALUE_SQL, &mut values),
+            limit_clause
+        );
+
+        Box::pin(async move {
+            self.ensure_table().await?;
+            let conn = self.read_connection().await;
+
+            let start = Instant::now();
+
+            let codec = self.codec();
+            let mut items: Vec<StoreModel> = Vec::new();
+
+            for (sql, values, filtered) in [(sql, values, true), (scan_sql, scan_values, false)] {
+                let mut stmt = conn
+                    .prepare(&sql)
+                    .await
+                    .map_err(|e| StoreError::QueryError(format!("Failed to set the statement: {:?}", e)))?;
+
+                let mut rows = stmt
+                    .query(params_from_iter(values))
+                    .await
+                    .map_err(|e| StoreError::QueryError(format!("Failed to find the values: {:?}", e)))?;
+
+                while let Some(row) = rows
+                    .next()
+                    .await
+                    .map_err(|e| StoreError::QueryError(format!("Failed to iterate rows: {:?}", e)))?
+                {
+                    let model = read_model(&row, &codec, &self.table_name)?;
+
+                    if filtered || query.filter.matches(&model.value) {
+                        items.push(model);
+                    }
+                }
+            }
+
+            items.sort_by(|a, b| a.key.cmp(&b.key));
+            if let Some(limit) = query.limit {
+                items.truncate(limit as usize);
+            }
+
+            self.limits
+                .record_access(&table_name, items.iter().map(|model| model.key.as_str()), unix_millis());
+
+            let duration = start.elapsed();
+            log::debug!("Keyv store find: {:?} | {} items | {:?}", duration, items.len(), query);
+
+            Ok(items)
+        })
+    }
+
+    fn set(
+        &self,
+        key: &str,
+        value: Value,
+        ttl: Option<u64>,
+    ) -> Pin<Box<dyn Future<Output = Result<Written<Option<StoreModel>>, StoreError>> + Send + '_>> {
+        let query = format!(
+            "INSERT INTO {} (key, value, encoding, expires_at, created_at, updated_at) VALUES (?1, ?2, ?3, ?4, ?5, ?5) \
+             ON CONFLICT(key) DO UPDATE SET value = EXCLUDED.value, encoding = EXCLUDED.encoding, \
+             version = CASE WHEN expires_at <= ?5 THEN 1 ELSE version + 1 END, \
+             created_at = CASE WHEN expires_at <= ?5 THEN EXCLUDED.created_at ELSE created_at END, \
+             expires_at = EXCLUDED.expires_at, updated_at = EXCLUDED.updated_at \
+             RETURNING {}",
+            self.get_table_name(),
+            MODEL_COLUMNS
+        );
+
+        let conn = &*self.connnection;
+        let key = key.to_string();
+
+        // Writing a key without a TTL clears any expiry it previously had.
+        let expires_at = expires_at_from_ttl(ttl);
+
+        Box::pin(async move {
+            self.ensure_table().await?;
+            let _guard = self.lock_writes().await;
+
+            let start = Instant::now();
+
+            let codec = self.codec();
+            let encoded = codec.encode(&self.table_name, &key, &value)?;
+            self.limits().check_value_size(&key, encoded.text.len())?;
+
+            // Dropping the transaction without committing rolls back a write
+            // that would take the namespace over its limits.
+            let tx = conn
+                .transaction()
+                .await
+                .map_err(|e| StoreError::QueryError(format!("Failed to begin the transaction: {:?}", e)))?;
+
+            let mut stmt = tx
+                .prepare(&query)
+                .await
+                .map_err(|_| StoreError::QueryError("Failed to set the statement".to_string()))?;
+
+            let mut response = stmt
+                .query(params![
+                    key.clone(),
+                    encoded.text.clone(),
+                    encoded.encoding.clone(),
+                    expires_at,
+                    unix_millis()
+                ])
+                .await
+                .map_err(|_| StoreError::QueryError("Failed to set the value".to_string()))?;
+
+            let result = match response
+                .next()
+                .await
+                .map_err(|e| StoreError::QueryError(format!("Failed to iterate rows: {:?}", e)))?
+            {
+                Some(row) => Some(read_model(&row, &codec, &self.table_name)?),
+                None => None,
+            };
+
+            drop(response);
+            drop(stmt);
+
+            let evicted = enforce_limits(&tx, &self.limits, &self.table_name, &[&key]).await?;
+
+            tx.commit()
+                .await
+                .map_err(|e| StoreError::QueryError(format!("Failed to commit the transaction: {:?}", e)))?;
+
+            self.invalidate(&key);
+            self.invalidate_evicted(&evicted);
+
+            let duration = start.elapsed();
+            log::debug!(
+                "Keyv store set: {:?} | {} | {} | {:?}",
+                duration,
+                key,
+                encoded.stats(),
+                expires_at
+            );
+
+            Ok(Written { value: result, evicted })
+        })
+    }
+
+    fn set_many(
+        &self,
+        entries: Vec<(String, Value)>,
+        ttl: Option<u64>,
+    ) -> Pin<Box<dyn Future<Output = Result<Written<()>, StoreError>> + Send + '_>> {
+        let query = format!(
+            "INSERT INTO {} (key, value, encoding, expires_at, created_at, updated_at) VALUES (?1, ?2, ?3, ?4, ?5, ?5) \
+             ON CONFLICT(key) DO UPDATE SET value = EXCLUDED.value, encoding = EXCLUDED.encoding, \
+             version = CASE WHEN expires_at <= ?5 THEN 1 ELSE version + 1 END, \
+             created_at = CASE WHEN expires_at <= ?5 THEN EXCLUDED.created_at ELSE created_at END, \
+             expires_at = EXCLUDED.expires_at, updated_at = EXCLUDED.updated_at",
+            self.get_table_name()
+        );
+
+        let conn = &*self.connnection;
+
+        let expires_at = expires_at_from_ttl(ttl);
+
+        Box::pin(async move {
+            self.ensure_table().await?;
+            let _guard = self.lock_writes().await;
+
+            let start = Instant::now();
+
+            // Encode everything up front so a serialization error never leaves
+            // a transaction open.
+            let codec = self.codec();
+            let limits = self.limits();
+            let encoded = entries
+                .iter()
+                .map(|(key, value)| {
+                    let encoded = codec.encode(&self.table_name, key, value)?;
+                    limits.check_value_size(key, encoded.text.len())?;
+                    Ok((key.clone(), encoded))
+                })
+                .collect::<Result<Vec<(String, EncodedValue)>, StoreError>>()?;
+
+            let json_len: usize = encoded.iter().map(|(_, e)| e.json_len).sum();
+            let stored_len: usize = encoded
+                .iter()
+                .map(|(_, e)| e.compressed_len.unwrap_or(e.json_len))
+                .sum();
+            let compressed = encoded.iter().filter(|(_, e)| e.compressed_len.is_some()).count();
+
+            let tx = conn
+                .transaction()
+                .await
+                .map_err(|e| StoreError::QueryError(format!("Failed to begin the transaction: {:?}", e)))?;
+
+            // Dropping the transaction without committing rolls back every entry.
+            let mut stmt = tx
+                .prepare(&query)
+                .await
+                .map_err(|e| StoreError::QueryError(format!("Failed to set the statement: {:?}", e)))?;
+
+            let now = unix_millis();
+
+            for (key, encoded) in encoded {
+                stmt.execute(params![key, encoded.text, encoded.encoding, expires_at, now])
+                    .await
+                    .map_err(|e| StoreError::QueryError(format!("Failed to set the value: {:?}", e)))?;
+                stmt.reset();
+            }
+
+            drop(stmt);
+
+            let written: Vec<&str> = entries.iter().map(|(key, _)| key.as_str()).collect();
+            let evicted = enforce_limits(&tx, &self.limits, &self.table_name, &written).await?;
+
+            tx.commit()
+                .await
+                .map_err(|e| StoreError::QueryError(format!("Failed to commit the transaction: {:?}", e)))?;
+
+            for (key, _) in &entries {
+                self.invalidate(key);
+            }
+            self.invalidate_evicted(&evicted);
+
+            let duration = start.elapsed();
+            log::debug!(
+                "Keyv store set_many: {:?} | {} entries | {} compressed | {} -> {} bytes | {:?}",
+                duration,
+                entries.len(),
+                compressed,
+                json_len,
+                stored_len,
+                expires_at
+            );
+
+            Ok(Written { value: (), evicted })
+        })
+    }
+
+    fn set_versioned(
+        &self,
+        key: &str,
+        value: Value,
+        expected_version: Option<u64>,
+        ttl: Option<u64>,
+    ) -> Pin<Box<dyn Future<Output = Result<Written<u64>, StoreError>> + Send + '_>> {
+        let table_name = self.get_table_name();
+        let conn = &*self.connnection;
+        let key = key.to_string();
+
+        let expires_at = expires_at_from_ttl(ttl);
+
+        Box::pin(async move {
+            self.ensure_table().await?;
+            let _guard = self.lock_writes().await;
+
+            let start = Instant::now();
+
+            let tx = conn
+                .transaction_with_behavior(TransactionBehavior::Immediate)
+                .await
+                .map_err(|e| StoreError::QueryError(format!("Failed to begin the transaction: {:?}", e)))?;
+
+            let codec = self.codec();
+            let actual = read_versioned(&tx, &codec, &table_name, &key).await?.map(|v| v.version);
+
+            if actual != expected_version {
+                return Err(StoreError::VersionConflict {
+                    key,
+                    expected: expected_version,
+                    actual,
+                });
+            }
+
+            let version = write_versioned(
+                &tx,
+                &codec,
+                self.limits(),
+                &table_name,
+                &key,
+                &value,
+                Expiry::Set(expires_at),
+            )
+            .await?;
+            let evicted = enforce_limits(&tx, &self.limits, &table_name, &[&key]).await?;
+
+            tx.commit()
+                .await
+                .map_err(|e| StoreError::QueryError(format!("Failed to commit the transaction: {:?}", e)))?;
+
+            self.invalidate(&key);
+            self.invalidate_evicted(&evicted);
+
+            let duration = start.elapsed();
+            log::debug!("Keyv store set_versioned: {:?} | {} | {}", duration, key, version);
+
+            Ok(Written {
+                value: version,
+                evicted,
+            })
+        })
+    }
+
+    fn compare_and_swap(
+        &self,
+        key: &str,
+        expected: Option<Value>,
+        new: Value,
+    ) -> Pin<Box<dyn Future<Output = Result<Written<bool>, StoreError>> + Send + '_>> {
+        let key = key.to_string();
+
+        Box::pin(async move {
+            let start = Instant::now();
+
+            let (swapped, _, evicted) = self
+                .read_modify_write(&key, |current| {
+                    if current.map(|c| c.value) == expected {
+                        Ok((true, Some(new)))
+                    } else {
+                        Ok((false, None))
+                    }
+                })
+                .await?;
+
+            let duration = start.elapsed();
+            log::debug!("Keyv store compare_and_swap: {:?} | {} | {}", duration, key, swapped);
+
+            Ok(Written {
+                value: swapped,
+                evicted,
+            })
+        })
+    }
+
+    fn increment(
+        &self,
+        key: &str,
+        delta: i64,
+    ) -> Pin<Box<dyn Future<Output = Result<Written<i64>, StoreError>> + Send + '_>> {
+        let key = key.to_string();
+
+        Box::pin(async move {
+            let start = Instant::now();
+
+            let (value, _, evicted) = self
+                .read_modify_write(&key, |current| {
+                    let current = match current {
+                        Some(current) => current
+                            .value
+                            .as_i64()
+                            .ok_or_else(|| StoreError::InvalidValue(format!("{} is not an integer", key)))?,
+                        None => 0,
+                    };
+
+                    let value = current
+                        .checked_add(delta)
+                        .ok_or_else(|| StoreError::InvalidValue(format!("Incrementing {} overflows", key)))?;
+
+                    Ok((value, Some(Value::from(value))))
+                })
+                .await?;
+
+            let duration = start.elapsed();
+            log::debug!("Keyv store increment: {:?} | {} | {}", duration, key, delta);
+
+            Ok(Written { value, evicted })
+        })
+    }
+
+    fn update<'a>(
+        &'a self,
+        key: &str,
+        f: UpdateFn<'a>,
+    ) -> Pin<Box<dyn Future<Output = Result<Written<Value>, StoreError>> + Send + 'a>> {
+        let key = key.to_string();
+
+        Box::pin(async move {
+            let start = Instant::now();
+
+            let (value, version, evicted) = self
+                .read_modify_write(&key, |current| {
+                    let value = f(current.map(|c| c.value))?;
+                    Ok((value.clone(), Some(value)))
+                })
+                .await?;
+
+            let duration = start.elapsed();
+            log::debug!("Keyv store update: {:?} | {} | {:?}", duration, key, version);
+
+            Ok(Written { value, evicted })
+        })
+    }
+
+    fn remove(&self, key: &str) -> Pin<Box<dyn Future<Output = Result<(), StoreError>> + Send + '_>> {
+        let query = format!("DELETE FROM {} WHERE key = ?1", self.get_table_name());
+
+        let conn = &*self.connnection;
+
+        let key = key.to_string();
+
+        Box::pin(async move {
+            self.ensure_table().await?;
+            let _guard = self.lock_writes().await;
+
+            let start = Instant::now();
+
+            let mut stmt = conn
+                .prepare(&query)
+                .await
+                .map_err(|_| StoreError::QueryError("Failed to set the statement".to_string()))?;
+
+            stmt.execute(params![key.clone()])
+                .await
+                .map_err(|_| StoreError::QueryError("Failed to remove the key".to_string()))?;
+
+            self.invalidate(&key);
+            self.limits.forget(&self.table_name, [key.as_str()]);
+
+            let duration = start.elapsed();
+            log::debug!("Keyv store remove: {:?} | {}", duration, key);
+
+            Ok(())
+        })
+    }
+
+    fn remove_many(&self, keys: &[&str]) -> Pin<Box<dyn Future<Output = Result<Vec<String>, StoreError>> + Send + '_>> {
+        let table_name = self.get_table_name();
+        let keys = keys.iter().map(|k| k.to_string()).collect::<Vec<String>>();
+
+        let conn = &*self.connnection;
+
+        Box::pin(async move {
+            self.ensure_table().await?;
+            let _guard = self.lock_writes().await;
+
+            let start = Instant::now();
+
+            // Deleting in chunks keeps each statement under SQLite's limit on
+            // host parameters, and the transaction keeps the batch atomic.
+            let tx = conn
+                .transaction()
+                .await
+                .map_err(|e| StoreError::QueryError(format!("Failed to begin the transaction: {:?}", e)))?;
+
+            let now = unix_millis();
+            let mut removed = Vec::new();
+
+            for chunk in keys.chunks(GET_MANY_CHUNK_SIZE) {
+                let placeholder = (0..chunk.len())
+                    .map(|i| format!("?{}", i + 1))
+                    .collect::<Vec<String>>()
+                    .join(", ");
+
+                let query = format!(
+                    "DELETE FROM {} WHERE key IN ({}) RETURNING key, expires_at",
+                    table_name, placeholder
+                );
+
+                let mut rows = tx
+                    .query(&query, params_from_iter(chunk.to_vec()))
+                    .await
+                    .map_err(|e| StoreError::QueryError(format!("Failed to remove the keys: {:?}", e)))?;
+
+                while let Some(row) = rows
+                    .next()
+                    .await
+                    .map_err(|e| StoreError::QueryError(format!("Failed to iterate rows: {:?}", e)))?
+                {
+                    let key: String = row
+                        .get(0)
+                        .map_err(|e| StoreError::QueryError(format!("Failed to get the key: {:?}", e)))?;
+                    let expires_at: Option<i64> = row
+                        .get(1)
+                        .map_err(|e| StoreError::QueryError(format!("Failed to get the expiry: {:?}", e)))?;
+
+                    // Expired rows that were not purged yet were already gone for readers.
+                    if expires_at.map_or(true, |expires_at| expires_at > now) {
+                        removed.push(key);
+                    }
+                }
+            }
+
+            tx.commit()
+                .await
+                .map_err(|e| StoreError::QueryError(format!("Failed to commit the transaction: {:?}", e)))?;
+
+            for key in &keys {
+                self.invalidate(key);
+            }
+            self.limits
+                .forget(&self.table_name, keys.iter().map(|key| key.as_str()));
+
+            let duration = start.elapsed();
+            log::debug!(
+                "Keyv store remove_many: {:?} | {} keys | {} removed",
+                duration,
+                keys.len(),
+                removed.len()
+            );
+
+            Ok(removed)
+        })
+    }
+
+    fn clear(&self) -> Pin<Box<dyn Future<Output = Result<(), StoreError>> + Send + '_>> {
+        let query = format!("DELETE FROM {}", self.get_table_name());
+
+        let conn = &*self.connnection;
+
+        Box::pin(async move {
+            self.ensure_table().await?;
+            let _guard = self.lock_writes().await;
+
+            conn.execute(&query, params![])
+                .await
+                .map_err(|_| StoreError::QueryError("Failed to clear the table".to_string()))?;
+
+            self.invalidate_namespace(&self.table_name);
+            self.limits.forget_namespace(&self.table_name);
+
+            Ok(())
+        })
+    }
+
+    fn purge_expired(&self) -> Pin<Box<dyn Future<Output = Result<Vec<String>, StoreError>> + Send + '_>> {
+        let query = format!(
+            "DELETE FROM {} WHERE expires_at IS NOT NULL AND expires_at <= ?1 RETURNING key",
+            self.get_table_name()
+        );
+
+        let conn = &*self.connnection;
+
+        Box::pin(async move {
+            self.ensure_table().await?;
+            let _guard = self.lock_writes().await;
+
+            let start = Instant::now();
+
+            let mut rows = conn
+                .query(&query, params![unix_millis()])
+                .await
+                .map_err(|_| StoreError::QueryError("Failed to purge the expired keys".to_string()))?;
+
+            let mut purged = Vec::new();
+
+            while let Some(row) = rows
+                .next()
+                .await
+                .map_err(|e| StoreError::QueryError(format!("Failed to iterate rows: {:?}", e)))?
+            {
+                let key: String = row
+                    .get(0)
+                    .map_err(|e| StoreError::QueryError(format!("Failed to get the key: {:?}", e)))?;
+                purged.push(key);
+            }
+
+            let duration = start.elapsed();
+            log::debug!("Keyv store purge_expired: {:?} | {} keys", duration, purged.len());
+
+            Ok(purged)
+        })
+    }
+
+    fn namespace_name(&self) -> &str {
+        &self.table_name
+    }
+
+    fn cache_stats(&self) -> Option<CacheStats> {
+        self.cache.as_ref().map(|cache| cache.stats())
+    }
+
+    fn backup(&self, path: &Path) -> Pin<Box<dyn Future<Output = Result<(), StoreError>> + Send + '_>> {
+        let path = path.to_string_lossy().into_owned();
+
+        let conn = &*self.connnection;
+
+        Box::pin(async move {
+            // VACUUM fails inside a transaction, so wait for any open one to finish.
+            let _guard = self.lock_writes().await;
+
+            let start = Instant::now();
+
+            conn.execute("VACUUM INTO ?1", params![path.clone()])
+                .await
+                .map_err(|e| StoreError::QueryError(format!("Failed to back up the database: {:?}", e)))?;
+
+            let duration = start.elapsed();
+            log::debug!("Keyv store backup: {:?} | {}", duration, path);
+
+            Ok(())
+        })
+    }
+
+    fn rotate_encryption_key(
+        &self,
+        new_key: Option<[u8; 32]>,
+    ) -> Pin<Box<dyn Future<Output = Result<u64, StoreError>> + Send + '_>> {
+        Box::pin(KeyvStore::rotate_encryption_key(self, new_key))
+    }
+
+    fn set_history(&self, limit: Option<usize>) -> Pin<Box<dyn Future<Output = Result<(), StoreError>> + Send + '_>> {
+        let table_name = self.get_table_name();
+        let conn = &*self.connnection;
+
+        // At least one revision is kept while the history is enabled.
+        let limit = limit.map(|limit| limit.max(1));
+
+        Box::pin(async move {
+            self.ensure_table().await?;
+            let _guard = self.lock_writes().await;
+
+            let start = Instant::now();
+
+            let tx = conn
+                .transaction()
+                .await
+                .map_err(|e| StoreError::QueryError(format!("Failed to begin the transaction: {:?}", e)))?;
+
+            tx.execute(
+                &format!(
+                    "UPDATE {} SET history_limit = ?2 WHERE name = ?1",
+                    NAMESPACE_REGISTRY_TABLE
+                ),
+                params![table_name.clone(), limit.map(|limit| limit as i64)],
+            )
+            .await
+            .map_err(|e| StoreError::QueryError(format!("Failed to set the history limit: {:?}", e)))?;
+
+            // The limit is part of the triggers, so they are recreated on every change.
+            drop_history_triggers(&tx, &table_name).await?;
+
+            match limit {
+                Some(limit) => {
+                    tx.execute_batch(&history_triggers_statement(&table_name, limit))
+                        .await
+                        .map_err(|e| {
+                            StoreError::QueryError(format!("Failed to create the history triggers: {:?}", e))
+                        })?;
+
+                    tx.execute(
+                        &format!(
+                            "DELETE FROM {history} WHERE id IN ( \
+                                 SELECT id FROM ( \
+                                     SELECT id, ROW_NUMBER() OVER (PARTITION BY key ORDER BY id DESC) AS position \
+                                     FROM {history} WHERE namespace = ?1 \
+                                 ) WHERE position > ?2 \
+                             )",
+                            history = HISTORY_TABLE
+                        ),
+                        params![table_name.clone(), limit as i64],
+                    )
+                    .await
+                    .map_err(|e| StoreError::QueryError(format!("Failed to prune the history: {:?}", e)))?;
+                }
+                None => {
+                    tx.execute(
+                        &format!("DELETE FROM {} WHERE namespace = ?1", HISTORY_TABLE),
+                        params![table_name.clone()],
+                    )
+                    .await
+                    .map_err(|e| StoreError::QueryError(format!("Failed to delete the history: {:?}", e)))?;
+                }
+            }
+
+            tx.commit()
+                .await
+                .map_err(|e| StoreError::QueryError(format!("Failed to commit the transaction: {:?}", e)))?;
+
+            let duration = start.elapsed();
+            log::debug!("Keyv store set_history: {:?} | {} | {:?}", duration, table_name, limit);
+
+            Ok(())
+        })
+    }
+
+    fn history(&self, key: &str) -> Pin<Box<dyn Future<Output = Result<Vec<Revision>, StoreError>> + Send + '_>> {
+        let key = key.to_string();
+
+        Box::pin(async move {
+            self.ensure_table().await?;
+            let conn = self.read_connection().await;
+
+            let start = Instant::now();
+
+            let revisions = read_revisions(&conn, &self.codec(), &self.table_name, &key, None).await?;
+
+            let duration = start.elapsed();
+            log::debug!(
+                "Keyv store history: {:?} | {} | {} revisions",
+                duration,
+                key,
+                revisions.len()
+            );
+
+            Ok(revisions)
+        })
+    }
+
+    fn get_revision(
+        &self,
+        key: &str,
+        id: i64,
+    ) -> Pin<Box<dyn Future<Output = Result<Option<Revision>, StoreError>> + Send + '_>> {
+        let key = key.to_string();
+
+        Box::pin(async move {
+            self.ensure_table().await?;
+            let conn = self.read_connection().await;
+
+            let start = Instant::now();
+
+            let revision = read_revisions(&conn, &self.codec(), &self.table_name, &key, Some(id))
+                .await?
+                .pop();
+
+            let duration = start.elapsed();
+            log::debug!(
+                "Keyv store get_revision: {:?} | {} | {} | {}",
+                duration,
+                key,
+                id,
+                revision.is_some()
+            );
+
+            Ok(revision)
+        })
+    }
+
+    fn revert(
+        &self,
+        key: &str,
+        id: i64,
+    ) -> Pin<Box<dyn Future<Output = Result<Written<StoreModel>, StoreError>> + Send + '_>> {
+        let table_name = self.get_table_name();
+        let conn = &*self.connnection;
+        let key = key.to_string();
+
+        Box::pin(async move {
+            self.ensure_table().await?;
+            let _guard = self.lock_writes().await;
+
+            let start = Instant::now();
+
+            let tx = conn
+                .transaction_with_behavior(TransactionBehavior::Immediate)
+                .await
+                .map_err(|e| StoreError::QueryError(format!("Failed to begin the transaction: {:?}", e)))?;
+
+            let codec = self.codec();
+            let revision = read_revisions(&tx, &codec, &table_name, &key, Some(id))
+                .await?
+                .pop()
+                .ok_or(StoreError::NotFound)?;
+
+            write_versioned(
+                &tx,
+                &codec,
+                self.limits(),
+                &table_name,
+                &key,
+                &revision.value,
+                Expiry::Keep,
+            )
+            .await?;
+            let evicted = enforce_limits(&tx, &self.limits, &table_name, &[&key]).await?;
+
+            let model = read_entry(&tx, &codec, &table_name, &key)
+                .await?
+                .ok_or_else(|| StoreError::QueryError("The reverted value was not written".to_string()))?;
+
+            tx.commit()
+                .await
+                .map_err(|e| StoreError::QueryError(format!("Failed to commit the transaction: {:?}", e)))?;
+
+            self.invalidate(&key);
+            self.invalidate_evicted(&evicted);
+
+            let duration = start.elapsed();
+            log::debug!(
+                "Keyv store revert: {:?} | {} | {} -> {}",
+                duration,
+                key,
+                id,
+                model.version
+            );
+
+            Ok(Written { value: model, evicted })
+        })
+    }
+
+    fn create_index(
+        &self,
+        name: &str,
+        path: &str,
+    ) -> Pin<Box<dyn Future<Output = Result<(), StoreError>> + Send + '_>> {
+        let table_name = self.get_table_name();
+        let conn = &*self.connnection;
+        let name = name.to_string();
+        let path = path.to_string();
+
+        Box::pin(async move {
+            if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+                return Err(StoreError::InvalidQuery(format!(
+                    "index names may only contain ASCII letters, digits and underscores: {}",
+                    name
+                )));
+            }
+            let json_path = json_path(&path)?;
+
+            self.ensure_table().await?;
+            let _guard = self.lock_writes().await;
+
+            let start = Instant::now();
+
+            let tx = conn
+                .transaction()
+                .await
+                .map_err(|e| StoreError::QueryError(format!("Failed to begin the transaction: {:?}", e)))?;
+
+            tx.execute(
+                &format!(
+                    "UPDATE {} SET json_indexes = json_set(COALESCE(json_indexes, '{{}}'), '$.' || ?2, ?3) WHERE name = ?1",
+                    NAMESPACE_REGISTRY_TABLE
+                ),
+                params![table_name.clone(), name.clone(), json_path.clone()],
+            )
+            .await
+            .map_err(|e| StoreError::QueryError(format!("Failed to register the index: {:?}", e)))?;
+
+            // Dropped first, so that creating an existing index changes its field.
+            tx.execute(
+                &format!("DROP INDEX IF EXISTS {}_json_{}_idx", table_name, name),
+                params![],
+            )
+            .await
+            .map_err(|e| StoreError::QueryError(format!("Failed to drop the index: {:?}", e)))?;
+
+            tx.execute(&json_index_statement(&table_name, &name, &json_path), params![])
+                .await
+                .map_err(|e| StoreError::QueryError(format!("Failed to create the index: {:?}", e)))?;
+
+            tx.commit()
+                .await
+                .map_err(|e| StoreError::QueryError(format!("Failed to commit the transaction: {:?}", e)))?;
+
+            let duration = start.elapsed();
+            log::debug!(
+                "Keyv store create_index: {:?} | {} | {} | {}",
+                duration,
+                table_name,
+                name,
+                json_path
+            );
+
+            Ok(())
+        })
+    }
+
+    fn drop_index(&self, name: &str) -> Pin<Box<dyn Future<Output = Result<(), StoreError>> + Send + '_>> {
+        let table_name = self.get_table_name();
+        let conn = &*self.connnection;
+        let name = name.to_string();
+
+        Box::pin(async move {
+            if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+                return Ok(());
+            }
+
+            self.ensure_table().await?;
+            let _guard = self.lock_writes().await;
+
+            let start = Instant::now();
+
+            let tx = conn
+                .transaction()
+                .await
+                .map_err(|e| StoreError::QueryError(format!("Failed to begin the transaction: {:?}", e)))?;
+
+            tx.execute(
+                &format!(
+                    "UPDATE {} SET json_indexes = json_remove(json_indexes, '$.' || ?2) WHERE name = ?1",
+                    NAMESPACE_REGISTRY_TABLE
+                ),
+                params![table_name.clone(), name.clone()],
+            )
+            .await
+            .map_err(|e| StoreError::QueryError(format!("Failed to unregister the index: {:?}", e)))?;
+
+            tx.execute(
+                &format!("DROP INDEX IF EXISTS {}_json_{}_idx", table_name, name),
+                params![],
+            )
+            .await
+            .map_err(|e| StoreError::QueryError(format!("Failed to drop the index: {:?}", e)))?;
+
+            tx.commit()
+                .await
+                .map_err(|e| StoreError::QueryError(format!("Failed to commit the transaction: {:?}", e)))?;
+
+            let duration = start.elapsed();
+            log::debug!("Keyv store drop_index: {:?} | {} | {}", duration, table_name, name);
+
+            Ok(())
+        })
+    }
+
+    fn set_search(&self, enabled: bool) -> Pin<Box<dyn Future<Output = Result<(), StoreError>> + Send + '_>> {
+        let table_name = self.get_table_name();
+        let conn = &*self.connnection;
+
+        Box::pin(async move {
+            // Only plain JSON values can be indexed, so the index would silently miss values.
+            let codec = self.codec();
+            if enabled && (codec.is_encrypted() || codec.is_compressed()) {
+                return Err(StoreError::Unsupported(
+                    "Search is not supported on compressed or encrypted values".to_string(),
+                ));
+            }
+
+            self.ensure_table().await?;
+            let _guard = self.lock_writes().await;
+
+            let start = Instant::now();
+
+            let tx = conn
+                .transaction()
+                .await
+                .map_err(|e| StoreError::QueryError(format!("Failed to begin the transaction: {:?}", e)))?;
+
+            tx.execute(
+                &format!(
+                    "UPDATE {} SET search_enabled = ?2 WHERE name = ?1",
+                    NAMESPACE_REGISTRY_TABLE
+                ),
+                params![table_name.clone(), enabled as i64],
+            )
+            .await
+            .map_err(|e| StoreError::QueryError(format!("Failed to set the search setting: {:?}", e)))?;
+
+            // The index is dropped and rebuilt from the values, so enabling it again repairs it.
+            tx.execute_batch(&drop_search_statement(&table_name))
+                .await
+                .map_err(|e| StoreError::QueryError(format!("Failed to drop the search index: {:?}", e)))?;
+
+            if enabled {
+                tx.execute_batch(&search_schema_statement(&table_name))
+                    .await
+                    .map_err(|e| StoreError::QueryError(format!("Failed to create the search index: {:?}", e)))?;
+
+                let indexed = tx
+                    .execute(
+                        &format!(
+                            "INSERT INTO {table_name}_search (rowid, key, content) \
+                             SELECT rowid, key, {content} FROM {table_name} WHERE encoding = 'json'",
+                            table_name = table_name,
+                            content = search_content_sql(&format!("{}.value", table_name))
+                        ),
+                        params![],
+                    )
+                    .await
+                    .map_err(|e| StoreError::QueryError(format!("Failed to build the search index: {:?}", e)))?;
+
+                log::debug!("Keyv store indexed {} values in {}", indexed, table_name);
+            }
+
+            tx.commit()
+                .await
+                .map_err(|e| StoreError::QueryError(format!("Failed to commit the transaction: {:?}", e)))?;
+
+            let duration = start.elapsed();
+            log::debug!("Keyv store set_search: {:?} | {} | {}", duration, table_name, enabled);
+
+            Ok(())
+        })
+    }
+
+    fn search(
+        &self,
+        query: SearchQuery,
+    ) -> Pin<Box<dyn Future<Output = Result<Vec<SearchHit>, StoreError>> + Send + '_>> {
+        let table_name = self.get_table_name();
+
+        let mut conditions = vec![
+            format!("{}_search MATCH ?1", table_name),
+            format!("({t}.expires_at IS NULL OR {t}.expires_at > ?2)", t = table_name),
+        ];
+        let mut values: Vec<libsql::Value> = vec![query.query.clone().into(), unix_millis().into()];
+
+        if let Some(prefix) = query.prefix.clone() {
+            if let Some(upper) = prefix_upper_bound(&prefix) {
+                values.push(upper.into());
+                conditions.push(format!("{}.key < ?{}", table_name, values.len()));
+            }
+            values.push(prefix.into());
+            conditions.push(format!("{}.key >= ?{}", table_name, values.len()));
+        }
+
+        // Snippets hold up to 16 tokens around the best match of the value.
+        let sql = format!(
+            "SELECT {t}.key, snippet({t}_search, 1, '{open}', '{close}', '…', 16), bm25({t}_search) \
+             FROM {t}_search JOIN {t} ON {t}.rowid = {t}_search.rowid \
+             WHERE {conditions} ORDER BY bm25({t}_search) LIMIT {limit}",
+            t = table_name,
+            open = SEARCH_HIGHLIGHT.0,
+            close = SEARCH_HIGHLIGHT.1,
+            conditions = conditions.join(" AND "),
+            limit = query.limit.unwrap_or(DEFAULT_SEARCH_LIMIT)
+        );
+
+        Box::pin(async move {
+            self.ensure_table().await?;
+            let conn = self.read_connection().await;
+
+            if !namespace_settings(&conn, &table_name).await?.search {
+                return Err(StoreError::Unsupported(format!(
+                    "search is not enabled for the namespace {}",
+                    table_name
+                )));
+            }
+
+            let start = Instant::now();
+
+            let mut stmt = conn
+                .prepare(&sql)
+                .await
+                .map_err(|e| StoreError::QueryError(format!("Failed to set the statement: {:?}", e)))?;
+
+            let mut rows = stmt
+                .query(params_from_iter(values))
+                .await
+                .map_err(|e| StoreError::QueryError(format!("Failed to search the values: {:?}", e)))?;
+
+            let mut hits: Vec<SearchHit> = Vec::new();
+
+            while let Some(row) = rows
+                .next()
+                .await
+                .map_err(|e| StoreError::QueryError(format!("Failed to iterate rows: {:?}", e)))?
+            {
+                hits.push(SearchHit {
+                    key: row
+                        .get(0)
+                        .map_err(|e| StoreError::QueryError(format!("Failed to get the key: {:?}", e)))?,
+                    snippet: row
+                        .get::<Option<String>>(1)
+                        .map_err(|e| StoreError::QueryError(format!("Failed to get the snippet: {:?}", e)))?
+                        .unwrap_or_default(),
+                    rank: row
+                        .get(2)
+                        .map_err(|e| StoreError::QueryError(format!("Failed to get the rank: {:?}", e)))?,
+                });
+            }
+
+            let duration = start.elapsed();
+            log::debug!("Keyv store search: {:?} | {} hits | {:?}", duration, hits.len(), query);
+
+            Ok(hits)
+        })
+    }
+
+    fn acquire_lease(
+        &self,
+        name: &str,
+        owner: &str,
+        ttl: Duration,
+    ) -> Pin<Box<dyn Future<Output = Result<bool, StoreError>> + Send + '_>> {
+        let conn = &*self.connnection;
+        let name = name.to_string();
+        let owner = owner.to_string();
+
+        // Expired leases are taken over, and an owner may take its own lease again.
+        let sql = format!(
+            "INSERT INTO {locks} (name, owner, acquired_at, expires_at) VALUES (?1, ?2, ?3, ?4) \
+             ON CONFLICT (name) DO UPDATE SET \
+                 owner = excluded.owner, acquired_at = excluded.acquired_at, expires_at = excluded.expires_at \
+             WHERE {locks}.expires_at <= excluded.acquired_at OR {locks}.owner = excluded.owner",
+            locks = LOCKS_TABLE
+        );
+
+        Box::pin(async move {
+            validate_lease_ttl(ttl)?;
+            self.ensure_table().await?;
+            let _guard = self.lock_writes().await;
+
+            let start = Instant::now();
+            let now = unix_millis();
+
+            let acquired = conn
+                .execute(
+                    &sql,
+                    params![name.clone(), owner.clone(), now, now + ttl.as_millis() as i64],
+                )
+                .await
+                .map_err(|e| StoreError::QueryError(format!("Failed to acquire the lease: {:?}", e)))?
+                > 0;
+
+            let duration = start.elapsed();
+            log::debug!(
+                "Keyv store acquire_lease: {:?} | {} | {} | {}",
+                duration,
+                name,
+                owner,
+                acquired
+            );
+
+            Ok(acquired)
+        })
+    }
+
+    fn renew_lease(
+        &self,
+        name: &str,
+        owner: &str,
+        ttl: Duration,
+    ) -> Pin<Box<dyn Future<Output = Result<bool, StoreError>> + Send + '_>> {
+        let conn = &*self.connnection;
+        let name = name.to_string();
+        let owner = owner.to_string();
+
+        Box::pin(async move {
+            validate_lease_ttl(ttl)?;
+            self.ensure_table().await?;
+            let _guard = self.lock_writes().await;
+
+            let start = Instant::now();
+            let now = unix_millis();
+
+            let renewed = conn
+                .execute(
+                    &format!(
+                        "UPDATE {} SET expires_at = ?3 WHERE name = ?1 AND owner = ?2 AND expires_at > ?4",
+                        LOCKS_TABLE
+                    ),
+                    params![name.clone(), owner.clone(), now + ttl.as_millis() as i64, now],
+                )
+                .await
+                .map_err(|e| StoreError::QueryError(format!("Failed to renew the lease: {:?}", e)))?
+                > 0;
+
+            let duration = start.elapsed();
+            log::debug!(
+                "Keyv store renew_lease: {:?} | {} | {} | {}",
+                duration,
+                name,
+                owner,
+                renewed
+            );
+
+            Ok(renewed)
+        })
+    }
+
+    fn release_lease(
+        &self,
+        name: &str,
+        owner: &str,
+    ) -> Pin<Box<dyn Future<Output = Result<(), StoreError>> + Send + '_>> {
+        let conn = &*self.connnection;
+        let name = name.to_string();
+        let owner = owner.to_string();
+
+        Box::pin(async move {
+            self.ensure_table().await?;
+            let _guard = self.lock_writes().await;
+
+            let start = Instant::now();
+
+            conn.execute(
+                &format!("DELETE FROM {} WHERE name = ?1 AND owner = ?2", LOCKS_TABLE),
+                params![name.clone(), owner.clone()],
+            )
+            .await
+            .map_err(|e| StoreError::QueryError(format!("Failed to release the lease: {:?}", e)))?;
+
+            let duration = start.elapsed();
+            log::debug!("Keyv store release_lease: {:?} | {} | {}", duration, name, owner);
+
+            Ok(())
+        })
+    }
+
+    fn namespace(&self, name: &str) -> Result<Arc<dyn Store>, StoreError> {
+        Ok(Arc::new(self.scoped_namespace(name)?))
+    }
+
+    fn list_namespaces(&self) -> Pin<Box<dyn Future<Output = Result<Vec<String>, StoreError>> + Send + '_>> {
+        let query = format!("SELECT name FROM {} ORDER BY name ASC", NAMESPACE_REGISTRY_TABLE);
+
+        Box::pin(async move {
+            self.ensure_table().await?;
+            let conn = self.read_connection().await;
+
+            let mut rows = conn
+                .query(&query, params![])
+                .await
+                .map_err(|e| StoreError::QueryError(format!("Failed to list the namespaces: {:?}", e)))?;
+
+            let mut namespaces = Vec::new();
+
+            while let Some(row) = rows
+                .next()
+                .await
+                .map_err(|e| StoreError::QueryError(format!("Failed to iterate rows: {:?}", e)))?
+            {
+                let name: String = row
+                    .get(0)
+                    .map_err(|e| StoreError::QueryError(format!("Failed to get the namespace: {:?}", e)))?;
+                namespaces.push(name);
+            }
+
+            Ok(namespaces)
+        })
+    }
+
+    fn rename_namespace(
+        &self,
+        from: &str,
+        to: &str,
+    ) -> Pin<Box<dyn Future<Output = Result<(), StoreError>> + Send + '_>> {
+        let conn = &*self.connnection;
+        let from = from.to_string();
+        let to = to.to_string();
+
+        Box::pin(async move {
+            validate_namespace(&from)?;
+            validate_namespace(&to)?;
+            ensure_registry(conn).await?;
+            let _guard = self.lock_writes().await;
+
+            let start = Instant::now();
+
+            let tx = conn
+                .transaction()
+                .await
+                .map_err(|e| StoreError::QueryError(format!("Failed to begin the transaction: {:?}", e)))?;
+
+            let registered = tx
+                .execute(
+                    &format!("UPDATE {} SET name = ?2 WHERE name = ?1", NAMESPACE_REGISTRY_TABLE),
+                    params![from.clone(), to.clone()],
+                )
+                .await
+                .map_err(|e| StoreError::QueryError(format!("Failed to rename the namespace: {:?}", e)))?;
+
+            if registered == 0 {
+                return Err(StoreError::NotFound);
+            }
+
+            tx.execute(&format!("ALTER TABLE {} RENAME TO {}", from, to), params![])
+                .await
+                .map_err(|e| StoreError::QueryError(format!("Failed to rename the namespace table: {:?}", e)))?;
+
+            tx.execute(
+                &format!("UPDATE {} SET namespace = ?2 WHERE namespace = ?1", HISTORY_TABLE),
+                params![from.clone(), to.clone()],
+            )
+            .await
+            .map_err(|e| StoreError::QueryError(format!("Failed to rename the namespace history: {:?}", e)))?;
+
+            if namespace_settings(&tx, &to).await?.search {
+                tx.execute(
+                    &format!("ALTER TABLE {}_search RENAME TO {}_search", from, to),
+                    params![],
+                )
+                .await
+                .map_err(|e| StoreError::QueryError(format!("Failed to rename the search index: {:?}", e)))?;
+            }
+
+            // Indexes and triggers keep their old names after a rename. Drop them so the
+            // first handle that touches the new namespace recreates them under its name.
+            let mut rows = tx
+                .query(
+                    "SELECT type, name FROM sqlite_master \
+                     WHERE tbl_name = ?1 AND type IN ('index', 'trigger') AND sql IS NOT NULL",
+                    params![to.clone()],
+                )
+                .await
+                .map_err(|e| StoreError::QueryError(format!("Failed to list the namespace objects: {:?}", e)))?;
+
+            let mut objects: Vec<(String, String)> = Vec::new();
+
+            while let Some(row) = rows
+                .next()
+                .await
+                .map_err(|e| StoreError::QueryError(format!("Failed to iterate rows: {:?}", e)))?
+            {
+                let kind: String = row
+                    .get(0)
+                    .map_err(|e| StoreError::QueryError(format!("Failed to get the object type: {:?}", e)))?;
+                let name: String = row
+                    .get(1)
+                    .map_err(|e| StoreError::QueryError(format!("Failed to get the object name: {:?}", e)))?;
+                objects.push((kind, name));
+            }
+
+            for (kind, name) in objects {
+                tx.execute(
+                    &format!("DROP {} IF EXISTS \"{}\"", kind.to_uppercase(), name),
+                    params![],
+                )
+                .await
+                .map_err(|e| StoreError::QueryError(format!("Failed to drop {} {}: {:?}", kind, name, e)))?;
+            }
+
+            // Encrypted values are bound to their namespace, so they are encrypted again for the new name.
+            let codec = self.codec();
+            reencode_namespace(&tx, &from, &codec, &codec, &to, true).await?;
+
+            tx.commit()
+                .await
+                .map_err(|e| StoreError::QueryError(format!("Failed to commit the transaction: {:?}", e)))?;
+
+            self.invalidate_namespace(&from);
+            self.invalidate_namespace(&to);
+            self.limits.forget_namespace(&from);
+            self.limits.forget_namespace(&to);
+
+            let duration = start.elapsed();
+            log::debug!("Keyv store rename_namespace: {:?} | {} -> {}", duration, from, to);
+
+            Ok(())
+        })
+    }
+
+    fn drop_namespace(&self, name: &str) -> Pin<Box<dyn Future<Output = Result<(), StoreError>> + Send + '_>> {
+        let conn = &*self.connnection;
+        let name = name.to_string();
+
+        Box::pin(async move {
+            validate_namespace(&name)?;
+            ensure_registry(conn).await?;
+            let _guard = self.lock_writes().await;
+
+            let start = Instant::now();
+
+            let tx = conn
+                .transaction()
+                .await
+                .map_err(|e| StoreError::QueryError(format!("Failed to begin the transaction: {:?}", e)))?;
+
+            tx.execute(&format!("DROP TABLE IF EXISTS {}", name), params![])
+                .await
+                .map_err(|e| StoreError::QueryError(format!("Failed to drop the namespace table: {:?}", e)))?;
+
+            tx.execute_batch(&drop_search_statement(&name))
+                .await
+                .map_err(|e| StoreError::QueryError(format!("Failed to drop the search index: {:?}", e)))?;
+
+            tx.execute(
+                &format!("DELETE FROM {} WHERE name = ?1", NAMESPACE_REGISTRY_TABLE),
+                params![name.clone()],
+            )
+            .await
+            .map_err(|e| StoreError::QueryError(format!("Failed to unregister the namespace: {:?}", e)))?;
+
+            tx.execute(
+                &format!("DELETE FROM {} WHERE namespace = ?1", HISTORY_TABLE),
+                params![name.clone()],
+            )
+            .await
+            .map_err(|e| StoreError::QueryError(format!("Failed to delete the namespace history: {:?}", e)))?;
+
+            tx.commit()
+                .await
+                .map_err(|e| StoreError::QueryError(format!("Failed to commit the transaction: {:?}", e)))?;
+
+            self.invalidate_namespace(&name);
+            self.limits.forget_namespace(&name);
+
+            let duration = start.elapsed();
+            log::debug!("Keyv store drop_namespace: {:?} | {}", duration, name);
+
+            Ok(())
+        })
+    }
+
+    fn begin(&self) -> Pin<Box<dyn Future<Output = Result<Box<dyn StoreTransaction>, StoreError>> + Send + '_>> {
+        Box::pin(async move {
+            // The lock is held until the transaction finishes, so that no write from
+            // another task ends up inside it.
+            let guard = Arc::clone(&self.write_lock).lock_owned().await;
+
+            let tx = self
+                .connnection
+                .transaction_with_behavior(TransactionBehavior::Immediate)
+                .await
+                .map_err(|e| StoreError::QueryError(format!("Failed to begin the transaction: {:?}", e)))?;
+
+            log::debug!("Keyv store begin transaction");
+
+            Ok(Box::new(KeyvStoreTransaction {
+                connnection: Arc::clone(&self.connnection),
+                active: Mutex::new(Some((tx, guard))),
+                ensured: Mutex::new(HashSet::new()),
+                codec: self.codec(),
+                cache: self.cache.clone(),
+                limits: Arc::clone(&self.limits),
+                touched: std::sync::Mutex::new(Vec::new()),
+            }) as Box<dyn StoreTransaction>)
+        })
+    }
+}
+
+/// A transaction over every namespace sharing a `KeyvStore` connection.
+///
+/// Statements are issued on the shared connection while the transaction is
+/// open, and other writers are held off by the store's write lock. So are
+/// readers, unless the store has a read pool whose connections only see
+/// committed data.
+pub struct KeyvStoreTransaction {
+    connnection: Arc<Connection>,
+    /// The open transaction and the write lock it holds, `None` once finished.
+    /// Dropping it rolls the transaction back before releasing the lock.
+    active: Mutex<Option<(Transaction, OwnedMutexGuard<()>)>>,
+    /// Namespaces whose table has already been checked in this transaction.
+    ensured: Mutex<HashSet<String>>,
+    codec: ValueCodec,
+    cache: Option<Arc<ValueCache>>,
+    limits: Arc<StoreLimits>,
+    /// Keys written in the transaction, invalidated again once it finishes so
+    /// that values read while it was open are not kept after a rollback.
+    touched: std::sync::Mutex<Vec<(String, String)>>,
+}
+
+impl KeyvStoreTransaction {
+    /// Fails if the transaction has finished, then creates the namespace table
+    /// inside the transaction if needed.
+    async fn prepare(&self, namespace: &str) -> Result<(), StoreError> {
+        if self.active.lock().await.is_none() {
+            return Err(StoreError::TransactionFinished);
+        }
+
+        let mut ensured = self.ensured.lock().await;
+
+        if !ensured.contains(namespace) {
+            validate_namespace(namespace)?;
+            create_schema(&self.connnection, namespace).await?;
+            ensured.insert(namespace.to_string());
+        }
+
+        Ok(())
+    }
+
+    /// Takes the open transaction and its write lock. The caller keeps the lock
+    /// until it has committed or rolled back.
+    async fn finish(&self) -> Result<(Transaction, OwnedMutexGuard<()>), StoreError> {
+        self.active.lock().await.take().ok_or(StoreError::TransactionFinished)
+    }
+
+    /// Writes a key of a namespace with size limits in a savepoint, so that
+    /// only this write is undone if it takes the namespace over its limits.
+    /// Returns the keys evicted to make room for it.
+    async fn set_within_limits(
+        &self,
+        namespace: &str,
+        key: &str,
+        value: &Value,
+        expires_at: Option<i64>,
+    ) -> Result<Vec<String>, StoreError> {
+        let conn = &*self.connnection;
+
+        conn.execute("SAVEPOINT keyv_limits", params![])
+            .await
+            .map_err(|e| StoreError::QueryError(format!("Failed to begin the savepoint: {:?}", e)))?;
+
+        let written = async {
+            let limits = self.limits.get(namespace);
+            write_versioned(
+                conn,
+                &self.codec,
+                limits,
+                namespace,
+                key,
+                value,
+                Expiry::Set(expires_at),
+            )
+            .await?;
+            enforce_limits(conn, &self.limits, namespace, &[key]).await
+        }
+        .await;
+
+        let evicted = match written {
+            Ok(evicted) => evicted,
+            Err(e) => {
+                let _ = conn.execute("ROLLBACK TO keyv_limits", params![]).await;
+                let _ = conn.execute("RELEASE keyv_limits", params![]).await;
+                return Err(e);
+            }
+        };
+
+        conn.execute("RELEASE keyv_limits", params![])
+            .await
+            .map_err(|e| StoreError::QueryError(format!("Failed to release the savepoint: {:?}", e)))?;
+
+        self.touch(namespace, key);
+        for evicted in &evicted {
+            self.touch(namespace, evicted);
+        }
+
+        Ok(evicted)
+    }
+
+    /// Drops the cached value of a key written in the transaction.
+    fn touch(&self, namespace: &str, key: &str) {
+        if let Some(cache) = &self.cache {
+            cache.invalidate(namespace, key);
+            self.touched
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .push((namespace.to_string(), key.to_string()));
+        }
+    }
+
+    /// Drops the cached values of every key written in the transaction.
+    fn invalidate_touched(&self) {
+        if let Some(cache) = &self.cache {
+            let touched = std::mem::take(&mut *self.touched.lock().unwrap_or_else(PoisonError::into_inner));
+            for (namespace, key) in touched {
+                cache.invalidate(&namespace, &key);
+            }
+        }
+    }
+}
+
+impl Drop for KeyvStoreTransaction {
+    fn drop(&mut self) {
+        // Roll back before invalidating, and only then release the write lock.
+        if let Some((tx, guard)) = self.active.get_mut().take() {
+            drop(tx);
+            self.invalidate_touched();
+            drop(guard);
+        }
+    }
+}
+
+impl StoreTransaction for KeyvStoreTransaction {
+    fn get(
+        &self,
+        namespace: &str,
+        key: &str,
+    ) -> Pin<Box<dyn Future<Output = Result<Option<Value>, StoreError>> + Send + '_>> {
+        let namespace = namespace.to_string();
+        let key = key.to_string();
+
+        Box::pin(async move {
+            self.prepare(&namespace).await?;
+
+            let value = read_versioned(&self.connnection, &self.codec, &namespace, &key).await?;
+
+            log::debug!("Keyv transaction get: {} | {}", namespace, key);
+
+            Ok(value.map(|v| v.value))
+        })
+    }
+
+    fn keys(&self, namespace: &str) -> Pin<Box<dyn Future<Output = Result<Vec<String>, StoreError>> + Send + '_>> {
+        let namespace = namespace.to_string();
+
+        Box::pin(async move {
+            self.prepare(&namespace).await?;
+
+            let mut results = self
+                .connnection
+                .query(
+                    &format!(
+                        "SELECT key FROM {} WHERE expires_at IS NULL OR expires_at > ?1 ORDER BY key ASC",
+                        namespace
+                    ),
+                    params![unix_millis()],
+                )
+                .await
+                .map_err(|e| StoreError::QueryError(format!("Failed to list the keys: {:?}", e)))?;
+
+            let mut keys = Vec::new();
+
+            while let Some(row) = results
+                .next()
+                .await
+                .map_err(|e| StoreError::QueryError(format!("Failed to iterate rows: {:?}", e)))?
+            {
+                keys.push(
+                    row.get::<String>(0)
+                        .map_err(|e| StoreError::QueryError(format!("Failed to read the key: {:?}", e)))?,
+                );
+            }
+
+            log::debug!("Keyv transaction keys: {} | {} keys", namespace, keys.len());
+
+            Ok(keys)
+        })
+    }
+
+    fn set(
+        &self,
+        namespace: &str,
+        key: &str,
+        value: Value,
+        ttl: Option<u64>,
+    ) -> Pin<Box<dyn Future<Output = Result<Written<()>, StoreError>> + Send + '_>> {
+        let namespace = namespace.to_string();
+        let key = key.to_string();
+
+        let expires_at = expires_at_from_ttl(ttl);
+
+        Box::pin(async move {
+            self.prepare(&namespace).await?;
+
+            let limits = self.limits.get(&namespace);
+
+            let evicted = if !limits.limits_size() {
+                write_versioned(
+                    &self.connnection,
+                    &self.codec,
+                    limits,
+                    &namespace,
+                    &key,
+                    &value,
+                    Expiry::Set(expires_at),
+                )
+                .await?;
+
+                self.touch(&namespace, &key);
+                Vec::new()
+            } else {
+                self.set_within_limits(&namespace, &key, &value, expires_at).await?
+            };
+
+            log::debug!("Keyv transaction set: {} | {} | {:?}", namespace, key, expires_at);
+
+            Ok(Written { value: (), evicted })
+        })
+    }
+
+    fn remove(&self, namespace: &str, key: &str) -> Pin<Box<dyn Future<Output = Result<(), StoreError>> + Send + '_>> {
+        let namespace = namespace.to_string();
+        let key = key.to_string();
+
+        Box::pin(async move {
+            self.prepare(&namespace).await?;
+
+            self.connnection
+                .execute(
+                    &format!("DELETE FROM {} WHERE key = ?1", namespace),
+                    params![key.clone()],
+                )
+                .await
+                .map_err(|e| StoreError::QueryError(format!("Failed to remove the key: {:?}", e)))?;
+
+            self.touch(&namespace, &key);
+            self.limits.forget(&namespace, [key.as_str()]);
+
+            log::debug!("Keyv transaction remove: {} | {}", namespace, key);
+
+            Ok(())
+        })
+    }
+
+    fn commit(&self) -> Pin<Box<dyn Future<Output = Result<(), StoreError>> + Send + '_>> {
+        Box::pin(async move {
+            let (tx, _guard) = self.finish().await?;
+
+            let committed = tx
+                .commit()
+                .await
+                .map_err(|e| StoreError::QueryError(format!("Failed to commit the transaction: {:?}", e)));
+
+            self.invalidate_touched();
+            committed?;
+
+            log::debug!("Keyv store commit transaction");
+
+            Ok(())
+        })
+    }
+
+    fn rollback(&self) -> Pin<Box<dyn Future<Output = Result<(), StoreError>> + Send + '_>> {
+        Box::pin(async move {
+            let (tx, _guard) = self.finish().await?;
+
+            let rolled_back = tx
+                .rollback()
+                .await
+                .map_err(|e| StoreError::QueryError(format!("Failed to roll back the transaction: {:?}", e)));
+
+            self.invalidate_touched();
+            rolled_back?;
+
+            log::debug!("Keyv store rollback transaction");
+
+            Ok(())
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::keyv::{Keyv, KeyvError};
+
+    async fn memory_keyv() -> Keyv {
+        let store = KeyvStoreBuilder::new().uri(":memory:").build().await.unwrap();
+        Keyv::try_new(store).await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn expired_keys_are_hidden_then_purged() {
+        let keyv = memory_keyv().await;
+        keyv.set("kept", 1).await.unwrap();
+        keyv.set_with_ttl("later", 2, 3600).await.unwrap();
+        keyv.set_with_ttl("expired", 3, 0).await.unwrap();
+
+        assert_eq!(keyv.get("expired").await.unwrap(), None);
+        assert!(keyv.get_with_meta("later").await.unwrap().unwrap().expires_at.is_some());
+
+        let keys: Vec<String> = keyv.list().await.unwrap().into_iter().map(|model| model.key).collect();
+        assert_eq!(keys, ["kept", "later"]);
+
+        assert_eq!(keyv.purge_expired().await.unwrap(), 1);
+        assert_eq!(keyv.purge_expired().await.unwrap(), 0);
+        assert_eq!(keyv.get("later").await.unwrap(), Some(serde_json::json!(2)));
+    }
+
+    #[tokio::test]
+    async fn rotating_the_encryption_key_reencodes_every_value() {
+        let db = Builder::new_local(":memory:").build().await.unwrap();
+        let conn = Arc::new(db.connect().unwrap());
+        let open = |key: Option<[u8; 32]>| {
+            let mut builder = KeyvStoreBuilder::new().connnection(Arc::clone(&conn));
+            if let Some(key) = key {
+                builder = builder.encryption_key(key);
+            }
+            async move { Keyv::try_new(builder.build().await.unwrap()).await.unwrap() }
+        };
+
+        let keyv = open(Some([1; 32])).await;
+        keyv.set("token", "secret").await.unwrap();
+        keyv.set("count", 3).await.unwrap();
+
+        assert_eq!(keyv.rotate_encryption_key(Some([2; 32])).await.unwrap(), 2);
+        assert_eq!(keyv.get("token").await.unwrap(), Some(serde_json::json!("secret")));
+
+        // The values are only readable with the new key now.
+        assert!(open(Some([1; 32])).await.get("token").await.is_err());
+        let reopened = open(Some([2; 32])).await;
+        assert_eq!(reopened.get("count").await.unwrap(), Some(serde_json::json!(3)));
+
+        // Rotating to no key decrypts the namespace back to plain JSON.
+        assert_eq!(reopened.rotate_encryption_key(None).await.unwrap(), 2);
+        let plain = open(None).await;
+        assert_eq!(plain.get("token").await.unwrap(), Some(serde_json::json!("secret")));
+        assert_eq!(plain.get_with_meta("token").await.unwrap().unwrap().version, 1);
+    }
+
+    #[tokio::test]
+    async fn legacy_tables_are_upgraded_to_json_values() {
+        let db = Builder::new_local(":memory:").build().await.unwrap();
+        let conn = db.connect().unwrap();
+        conn.execute_batch(
+            r#"
+                CREATE TABLE legacy (
+                    key TEXT PRIMARY KEY,
+                    value TEXT NOT NULL,
+                    updated_at TEXT DEFAULT (datetime('now', 'localtime')),
+                    UNIQUE(key)
+                ) STRICT;
+                CREATE TRIGGER legacy_update_trigger AFTER UPDATE ON legacy
+                BEGIN
+                    UPDATE legacy SET updated_at = datetime('now', 'localtime') WHERE key = NEW.key;
+                END;
+                INSERT INTO legacy (key, value) VALUES ('flag', 'true'), ('count', '42'), ('text', 'hello'), ('empty', '');
+            "#,
+        )
+        .await
+        .unwrap();
+
+        let store = KeyvStoreBuilder::new()
+            .connnection(Arc::new(conn))
+            .table_name("legacy")
+            .build()
+            .await
+            .unwrap();
+        let keyv = Keyv::try_new(store).await.unwrap();
+
+        // Legacy values were always read back as strings, so they stay strings.
+        assert_eq!(keyv.get("flag").await.unwrap(), Some(serde_json::json!("true")));
+        assert_eq!(keyv.get("count").await.unwrap(), Some(serde_json::json!("42")));
+        assert_eq!(keyv.get("text").await.unwrap(), Some(serde_json::json!("hello")));
+        assert_eq!(keyv.get("empty").await.unwrap(), Some(Value::Null));
+
+        let model = keyv.get_with_meta("count").await.unwrap().unwrap();
+        assert_eq!(model.version, 1);
+        assert_eq!(model.created_at, model.updated_at);
+
+        keyv.set("count", 43).await.unwrap();
+        assert_eq!(keyv.get("count").await.unwrap(), Some(serde_json::json!(43)));
+        assert_eq!(keyv.get_with_meta("count").await.unwrap().unwrap().version, 2);
+    }
+
+    #[tokio::test]
+    async fn overwriting_without_ttl_clears_the_expiry() {
+        let keyv = memory_keyv().await;
+        keyv.set_with_ttl("key", 1, 3600).await.unwrap();
+        keyv.set("key", 2).await.unwrap();
+
+        let model = keyv.get_with_meta("key").await.unwrap().unwrap();
+        assert_eq!(model.value, serde_json::json!(2));
+        assert_eq!(model.expires_at, None);
+    }
+
+    #[tokio::test]
+    async fn history_is_pruned_to_its_limit_and_can_be_reverted() {
+        let keyv = memory_keyv().await;
+        keyv.enable_history(2).await.unwrap();
+        for draft in ["v1", "v2", "v3", "v4"] {
+            keyv.set("draft", draft).await.unwrap();
+        }
+
+        let history = keyv.history("draft").await.unwrap();
+        let values: Vec<&Value> = history.iter().map(|revision| &revision.value).collect();
+        assert_eq!(values, [&serde_json::json!("v3"), &serde_json::json!("v2")]);
+        assert_eq!(history[0].version, 3);
+
+        let reverted = keyv.revert("draft", history[1].id).await.unwrap();
+        assert_eq!((reverted.value, reverted.version), (serde_json::json!("v2"), 5));
+        assert_eq!(keyv.get("draft").await.unwrap(), Some(serde_json::json!("v2")));
+
+        // The value replaced by the revert is kept, so it can be undone.
+        let history = keyv.history("draft").await.unwrap();
+        assert_eq!(history[0].value, serde_json::json!("v4"));
+        assert_eq!(history.len(), 2);
+        assert!(keyv.get_revision("other", history[0].id).await.unwrap().is_none());
+
+        keyv.remove("draft").await.unwrap();
+        let removed = &keyv.history("draft").await.unwrap()[0];
+        assert!(removed.removed);
+        assert_eq!(removed.value, serde_json::json!("v2"));
+
+        keyv.disable_history().await.unwrap();
+        assert!(keyv.history("draft").await.unwrap().is_empty());
+        keyv.set("draft", "v6").await.unwrap();
+        assert!(keyv.history("draft").await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn batches_larger_than_a_chunk_are_split() {
+        let keyv = memory_keyv().await;
+        let keys: Vec<String> = (0..GET_MANY_CHUNK_SIZE * 2 + 1).map(|i| format!("key:{}", i)).collect();
+        keyv.set_many(keys.iter().map(|key| (key.as_str(), 1))).await.unwrap();
+
+        // Duplicate and missing keys are looked up across the chunk boundary.
+        let mut lookup: Vec<&str> = keys.iter().map(|key| key.as_str()).collect();
+        lookup.extend(["key:0", "missing", &keys[GET_MANY_CHUNK_SIZE]]);
+        let values = keyv.get_many(&lookup).await.unwrap();
+        assert_eq!(values.len(), keys.len());
+        assert!(!values.contains_key("missing"));
+
+        let mut removed = keys[1..].to_vec();
+        removed.push("missing".to_string());
+        keyv.remove_many(&removed).await.unwrap();
+
+        let remaining: Vec<String> = keyv.list().await.unwrap().into_iter().map(|model| model.key).collect();
+        assert_eq!(remaining, ["key:0"]);
+    }
+
+    #[tokio::test]
+    async fn set_many_writes_nothing_when_an_entry_fails() {
+        let store = KeyvStoreBuilder::new()
+            .uri(":memory:")
+            .limits(NamespaceLimits::new().max_keys(2))
+            .build()
+            .await
+            .unwrap();
+        let keyv = Keyv::try_new(store).await.unwrap();
+        keyv.set("a", 1).await.unwrap();
+
+        // The limit is checked after the entries are inserted, so the transaction rolls back.
+        assert!(matches!(
+            keyv.set_many([("b", 2), ("c", 3)]).await,
+            Err(KeyvError::StoreError(StoreError::TooManyKeys { limit: 2, .. }))
+        ));
+        assert_eq!(keyv.list().await.unwrap().len(), 1);
+        assert_eq!(keyv.get("b").await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn clearing_removing_and_dropping_forget_tracked_reads() {
+        let store = KeyvStoreBuilder::new()
+            .uri(":memory:")
+            .limits(NamespaceLimits::new().evict(EvictionPolicy::Lru))
+            .build()
+            .await
+            .unwrap();
+        let limits = Arc::clone(&store.limits);
+        let keyv = Keyv::try_new(store).await.unwrap();
+        let read = |keyv: &Keyv, key: &'static str| {
+            let keyv = keyv.clone();
+            async move {
+                keyv.set(key, 1).await.unwrap();
+                keyv.get(key).await.unwrap();
+            }
+        };
+
+        read(&keyv, "a").await;
+        assert!(limits.last_access(DEFAULT_NAMESPACE_NAME, "a").is_some());
+        keyv.clear().await.unwrap();
+        assert_eq!(limits.last_access(DEFAULT_NAMESPACE_NAME, "a"), None);
+
+        read(&keyv, "b").await;
+        keyv.transaction(|tx| async move { tx.remove("b").await })
+            .await
+            .unwrap();
+        assert_eq!(limits.last_access(DEFAULT_NAMESPACE_NAME, "b"), None);
+
+        let other = keyv.namespace("other").unwrap();
+        read(&other, "c").await;
+        assert!(limits.last_access("other", "c").is_some());
+        keyv.drop_namespace("other").await.unwrap();
+        assert_eq!(limits.last_access("other", "c"), None);
+    }
+
+    #[tokio::test]
+    async fn namespaces_share_one_connection() {
+        let db = Builder::new_local(":memory:").build().await.unwrap();
+        let conn = Arc::new(db.connect().unwrap());
+        let store = KeyvStoreBuilder::new()
+            .connnection(Arc::clone(&conn))
+            .build()
+            .await
+            .unwrap();
+        let keyv = Keyv::try_new(store).await.unwrap();
+
+        let prefs = keyv.namespace("prefs").unwrap();
+        prefs.set("theme", "dark").await.unwrap();
+        keyv.set("theme", "light").await.unwrap();
+
+        assert_eq!(prefs.get("theme").await.unwrap(), Some(serde_json::json!("dark")));
+        assert_eq!(keyv.get("theme").await.unwrap(), Some(serde_json::json!("light")));
+        assert_eq!(keyv.namespaces().await.unwrap(), [DEFAULT_NAMESPACE_NAME, "prefs"]);
+
+        // A store opened on the same connection sees the namespace.
+        let reopened = KeyvStoreBuilder::new()
+            .connnection(conn)
+            .table_name("prefs")
+            .build()
+            .await
+            .unwrap();
+        let reopened = Keyv::try_new(reopened).await.unwrap();
+        assert_eq!(reopened.get("theme").await.unwrap(), Some(serde_json::json!("dark")));
+
+        prefs.clear().await.unwrap();
+        assert_eq!(keyv.get("theme").await.unwrap(), Some(serde_json::json!("light")));
+    }
+
+    #[tokio::test]
+    async fn unsafe_and_reserved_namespaces_are_rejected() {
+        let keyv = memory_keyv().await;
+
+        for name in [
+            "",
+            "1st",
+            "with space",
+            "drop;table",
+            "sqlite_master",
+            NAMESPACE_REGISTRY_TABLE,
+            HISTORY_TABLE,
+            LOCKS_TABLE,
+            "notes_search",
+            "notes_SEARCH_data",
+            "notes_keyv_upgrade",
+        ] {
+            assert!(
+                matches!(
+                    keyv.namespace(name),
+                    Err(KeyvError::StoreError(StoreError::InvalidNamespace(_)))
+                ),
+                "{:?} was accepted",
+                name
+            );
+        }
+
+        keyv.namespace("notes").unwrap().set("a", 1).await.unwrap();
+        assert!(matches!(
+            keyv.rename_namespace("notes", "notes_search").await,
+            Err(KeyvError::StoreError(StoreError::InvalidNamespace(_)))
+        ));
+        assert!(keyv.namespace("_notes_2").is_ok());
+    }
+
+    #[tokio::test]
+    async fn renaming_a_namespace_encrypts_its_values_for_the_new_name() {
+        let db = Builder::new_local(":memory:").build().await.unwrap();
+        let conn = Arc::new(db.connect().unwrap());
+        let open = |table_name: &'static str| {
+            let builder = KeyvStoreBuilder::new()
+                .connnection(Arc::clone(&conn))
+                .table_name(table_name)
+                .encryption_key([7; 32]);
+            async move { Keyv::try_new(builder.build().await.unwrap()).await.unwrap() }
+        };
+
+        let drafts = open("drafts").await;
+        drafts.set("note", "secret").await.unwrap();
+        drafts.rename_namespace("drafts", "notes").await.unwrap();
+
+        // Ciphertexts are bound to their namespace, so reading them only works if they were re-encrypted.
+        let notes = open("notes").await;
+        assert_eq!(notes.get("note").await.unwrap(), Some(serde_json::json!("secret")));
+        assert_eq!(notes.get_with_meta("note").await.unwrap().unwrap().version, 1);
+        assert_eq!(open("drafts").await.get("note").await.unwrap(), None);
     }
 }
@@ -0,0 +1,270 @@
+// Copyright © 2024 Crabby Craft - All Rights Reserved.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use lru::LruCache;
+use serde::Serialize;
+use std::num::NonZeroUsize;
+use std::sync::{Mutex, MutexGuard, PoisonError};
+
+use super::{unix_millis, VersionedValue};
+
+/// Counters of a read-through cache, see `KeyvStoreBuilder::cache_capacity`.
+#[derive(Debug, Clone, Copy, Default, Serialize)]
+pub struct CacheStats {
+    /// Reads answered from the cache.
+    pub hits: u64,
+    /// Reads that had to query the database.
+    pub misses: u64,
+    /// Number of keys currently cached.
+    pub entries: usize,
+    /// Maximum number of keys cached.
+    pub capacity: usize,
+}
+
+/// A cached read, including keys known to be absent.
+struct CachedEntry {
+    value: Option<VersionedValue>,
+    expires_at: Option<i64>,
+}
+
+struct CacheState {
+    entries: LruCache<(String, String), CachedEntry>,
+    /// Bumped by every invalidation, so that a read that raced with a write
+    /// does not cache the value it read before the write.
+    generation: u64,
+    /// The `PRAGMA data_version` the entries were read at, which changes when
+    /// another connection commits to the database.
+    data_version: Option<i64>,
+    hits: u64,
+    misses: u64,
+}
+
+/// A bounded LRU cache of the values read by a `KeyvStore`, keyed by namespace
+/// and key, and shared by every namespace handle of the store.
+pub(crate) struct ValueCache {
+    state: Mutex<CacheState>,
+}
+
+impl ValueCache {
+    pub(crate) fn new(capacity: NonZeroUsize) -> Self {
+        Self {
+            state: Mutex::new(CacheState {
+                entries: LruCache::new(capacity),
+                generation: 0,
+                data_version: None,
+                hits: 0,
+                misses: 0,
+            }),
+        }
+    }
+
+    fn state(&self) -> MutexGuard<'_, CacheState> {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Looks up a key. Returns `None` on a miss, or the cached read on a hit,
+    /// whose value is `None` if the key is known to be absent.
+    ///
+    /// Entries whose TTL has elapsed are dropped and count as a miss.
+    pub(crate) fn get(&self, namespace: &str, key: &str) -> Option<Option<VersionedValue>> {
+        let mut state = self.state();
+        let cache_key = (namespace.to_string(), key.to_string());
+
+        let hit = match state.entries.get(&cache_key) {
+            Some(entry) if entry.expires_at.map_or(true, |at| at > unix_millis()) => Some(entry.value.clone()),
+            Some(_) => {
+                state.entries.pop(&cache_key);
+                None
+            }
+            None => None,
+        };
+
+        match hit {
+            Some(_) => state.hits += 1,
+            None => state.misses += 1,
+        }
+
+        hit
+    }
+
+    /// Returns the current generation, to be passed to `insert` after reading
+    /// the database.
+    pub(crate) fn generation(&self) -> u64 {
+        self.state().generation
+    }
+
+    /// Caches a read, unless an invalidation happened since `generation` was taken.
+    pub(crate) fn insert(
+        &self,
+        namespace: &str,
+        key: &str,
+        value: Option<VersionedValue>,
+        expires_at: Option<i64>,
+        generation: u64,
+    ) {
+        let mut state = self.state();
+
+        if state.generation == generation {
+            state.entries.put(
+                (namespace.to_string(), key.to_string()),
+                CachedEntry { value, expires_at },
+            );
+        }
+    }
+
+    /// Drops every cached value if `data_version` differs from the one the
+    /// entries were read at, i.e. another connection wrote to the database.
+    pub(crate) fn sync_data_version(&self, data_version: i64) {
+        let mut state = self.state();
+
+        if state.data_version != Some(data_version) {
+            state.generation += 1;
+            state.data_version = Some(data_version);
+            state.entries.clear();
+        }
+    }
+
+    /// Drops the cached value of a key.
+    pub(crate) fn invalidate(&self, namespace: &str, key: &str) {
+        let mut state = self.state();
+        state.generation += 1;
+        state.entries.pop(&(namespace.to_string(), key.to_string()));
+    }
+
+    /// Drops every cached value of a namespace.
+    pub(crate) fn invalidate_namespace(&self, namespace: &str) {
+        let mut state = self.state();
+        state.generation += 1;
+
+        let keys: Vec<(String, String)> = state
+            .entries
+            .iter()
+            .filter(|((ns, _), _)| ns == namespace)
+            .map(|(key, _)| key.clone())
+            .collect();
+
+        for key in keys {
+            state.entries.pop(&key);
+        }
+    }
+
+    pub(crate) fn stats(&self) -> CacheStats {
+        let state = self.state();
+
+        CacheStats {
+            hits: state.hits,
+            misses: state.misses,
+            entries: state.entries.len(),
+            capacity: state.entries.cap().get(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::keyv::{Keyv, KeyvStoreBuilder};
+    use serde_json::json;
+
+    fn cache() -> ValueCache {
+        ValueCache::new(NonZeroUsize::new(8).unwrap())
+    }
+
+    fn versioned(value: i64) -> Option<VersionedValue> {
+        Some(VersionedValue {
+            value: json!(value),
+            version: 1,
+        })
+    }
+
+    fn cached_value(cache: &ValueCache, namespace: &str, key: &str) -> Option<Option<serde_json::Value>> {
+        cache.get(namespace, key).map(|read| read.map(|read| read.value))
+    }
+
+    #[test]
+    fn invalidation_drops_entries_and_stale_reads() {
+        let cache = cache();
+        cache.insert("notes", "a", versioned(1), None, cache.generation());
+        cache.insert("notes", "missing", None, None, cache.generation());
+        assert_eq!(cached_value(&cache, "notes", "a"), Some(Some(json!(1))));
+        assert_eq!(cached_value(&cache, "notes", "missing"), Some(None));
+
+        // A read taken before a write must not be cached after it.
+        let generation = cache.generation();
+        cache.invalidate("notes", "a");
+        cache.insert("notes", "a", versioned(1), None, generation);
+        assert_eq!(cached_value(&cache, "notes", "a"), None);
+
+        let stats = cache.stats();
+        assert_eq!((stats.hits, stats.misses, stats.entries), (2, 1, 1));
+    }
+
+    #[test]
+    fn namespace_invalidation_keeps_other_namespaces() {
+        let cache = cache();
+        cache.insert("notes", "a", versioned(1), None, cache.generation());
+        cache.insert("tasks", "a", versioned(2), None, cache.generation());
+
+        cache.invalidate_namespace("notes");
+        assert_eq!(cached_value(&cache, "notes", "a"), None);
+        assert_eq!(cached_value(&cache, "tasks", "a"), Some(Some(json!(2))));
+    }
+
+    #[test]
+    fn writes_of_other_connections_and_expiry_drop_entries() {
+        let cache = cache();
+        cache.sync_data_version(1);
+        cache.insert("notes", "a", versioned(1), None, cache.generation());
+        cache.insert(
+            "notes",
+            "old",
+            versioned(2),
+            Some(unix_millis() - 1),
+            cache.generation(),
+        );
+
+        assert_eq!(cached_value(&cache, "notes", "old"), None);
+
+        cache.sync_data_version(1);
+        assert_eq!(cached_value(&cache, "notes", "a"), Some(Some(json!(1))));
+
+        cache.sync_data_version(2);
+        assert_eq!(cached_value(&cache, "notes", "a"), None);
+    }
+
+    #[tokio::test]
+    async fn store_writes_invalidate_cached_reads() {
+        let store = KeyvStoreBuilder::new()
+            .uri(":memory:")
+            .cache_capacity(16)
+            .build()
+            .await
+            .unwrap();
+        let keyv = Keyv::try_new(store).await.unwrap();
+
+        keyv.set("theme", "light").await.unwrap();
+        assert_eq!(keyv.get("theme").await.unwrap(), Some(json!("light")));
+        assert_eq!(keyv.get("theme").await.unwrap(), Some(json!("light")));
+        assert!(keyv.cache_stats().unwrap().hits >= 1);
+
+        keyv.set("theme", "dark").await.unwrap();
+        assert_eq!(keyv.get("theme").await.unwrap(), Some(json!("dark")));
+
+        keyv.transaction(|tx| async move { tx.set("theme", "system").await })
+            .await
+            .unwrap();
+        assert_eq!(keyv.get("theme").await.unwrap(), Some(json!("system")));
+
+        keyv.remove("theme").await.unwrap();
+        assert_eq!(keyv.get("theme").await.unwrap(), None);
+
+        keyv.set("theme", "dark").await.unwrap();
+        keyv.clear().await.unwrap();
+        assert_eq!(keyv.get("theme").await.unwrap(), None);
+    }
+}
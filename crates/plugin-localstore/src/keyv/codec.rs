@@ -0,0 +1,398 @@
+// Copyright © 2024 Crabby Craft - All Rights Reserved.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use chacha20poly1305::aead::{Aead, AeadCore, KeyInit, OsRng, Payload};
+use chacha20poly1305::{XChaCha20Poly1305, XNonce};
+use serde_json::Value;
+use std::sync::Arc;
+
+use super::StoreError;
+
+/// Base layer of every stored value: the value serialized as JSON text.
+const JSON_ENCODING: &str = "json";
+
+/// Layer of a value compressed with zstd.
+const ZSTD_ENCODING: &str = "zstd";
+
+/// Layer of a value encrypted with XChaCha20-Poly1305, stored as the nonce
+/// followed by the ciphertext.
+pub(crate) const XCHACHA20POLY1305_ENCODING: &str = "xchacha20poly1305";
+
+/// Length in bytes of an XChaCha20-Poly1305 nonce.
+const NONCE_LENGTH: usize = 24;
+
+/// Compression settings of a `ValueCodec`.
+#[derive(Clone, Copy)]
+pub(crate) struct Compression {
+    /// Values whose JSON is at least this many bytes long are compressed.
+    pub(crate) threshold: usize,
+    /// The zstd compression level.
+    pub(crate) level: i32,
+}
+
+/// A value encoded for storage.
+pub(crate) struct EncodedValue {
+    /// The text stored in the `value` column.
+    pub(crate) text: String,
+    /// The layers applied to the value, stored in the `encoding` column.
+    pub(crate) encoding: String,
+    /// Length of the value serialized as JSON, in bytes.
+    pub(crate) json_len: usize,
+    /// Length of the value once compressed, if it was.
+    pub(crate) compressed_len: Option<usize>,
+}
+
+impl EncodedValue {
+    /// Describes how the value was encoded, for the debug logs.
+    pub(crate) fn stats(&self) -> String {
+        match self.compressed_len {
+            Some(compressed_len) => format!(
+                "{} | {} -> {} bytes ({:.1}%)",
+                self.encoding,
+                self.json_len,
+                compressed_len,
+                compressed_len as f64 * 100.0 / self.json_len.max(1) as f64
+            ),
+            None => format!("{} | {} bytes", self.encoding, self.json_len),
+        }
+    }
+}
+
+/// Turns values into the text stored in a namespace table and back.
+///
+/// Every value is serialized as JSON first, then goes through the optional
+/// layers configured on the `KeyvStoreBuilder`: compression, then encryption.
+/// The layers applied are joined with `+` in the `encoding` column, e.g. `json`
+/// or `json+zstd+xchacha20poly1305`, so rows written with different settings
+/// can be read side by side. Values
+/// with any layer beyond `json` are binary and stored as base64.
+#[derive(Clone, Default)]
+pub(crate) struct ValueCodec {
+    /// Cipher used to encrypt new values, `None` to store them in the clear.
+    cipher: Option<Arc<XChaCha20Poly1305>>,
+    /// Ciphers of previous keys, still tried when decrypting so namespaces
+    /// that have not been rotated yet stay readable.
+    previous: Vec<Arc<XChaCha20Poly1305>>,
+    /// Compression of large values, `None` to never compress.
+    compression: Option<Compression>,
+}
+
+impl ValueCodec {
+    /// Returns a codec that encrypts with `key`, or stores plain JSON if `None`.
+    pub(crate) fn with_encryption_key(&self, key: Option<&[u8; 32]>) -> Self {
+        Self {
+            cipher: key.map(|key| Arc::new(XChaCha20Poly1305::new(key.into()))),
+            previous: self.previous.clone(),
+            compression: self.compression,
+        }
+    }
+
+    /// Returns a codec that compresses values as configured by `compression`.
+    pub(crate) fn with_compression(&self, compression: Option<Compression>) -> Self {
+        Self {
+            compression,
+            ..self.clone()
+        }
+    }
+
+    /// Returns a codec that can also decrypt values written with `key`.
+    pub(crate) fn with_previous_key(&self, key: &[u8; 32]) -> Self {
+        let mut codec = self.clone();
+        codec.previous.push(Arc::new(XChaCha20Poly1305::new(key.into())));
+        codec
+    }
+
+    /// Returns a codec that encrypts with `key` and keeps the current key
+    /// around for decryption.
+    pub(crate) fn rotated(&self, key: Option<&[u8; 32]>) -> Self {
+        let mut codec = self.with_encryption_key(key);
+        if let Some(cipher) = &self.cipher {
+            codec.previous.insert(0, Arc::clone(cipher));
+        }
+        codec
+    }
+
+    /// Whether new values are encrypted.
+    pub(crate) fn is_encrypted(&self) -> bool {
+        self.cipher.is_some()
+    }
+
+    /// Whether large values are compressed.
+    pub(crate) fn is_compressed(&self) -> bool {
+        self.compression.is_some()
+    }
+
+    /// Encodes the value of `key` in `namespace`.
+    ///
+    /// The namespace and key are authenticated along with encrypted values, so
+    /// a ciphertext copied to another key or namespace fails to decrypt.
+    pub(crate) fn encode(&self, namespace: &str, key: &str, value: &Value) -> Result<EncodedValue, StoreError> {
+        let json = serde_json::to_vec(value)?;
+        let json_len = json.len();
+
+        let mut layers = vec![JSON_ENCODING];
+        let mut bytes = json;
+        let mut compressed_len = None;
+
+        if let Some(compression) = self.compression.filter(|c| json_len >= c.threshold) {
+            let compressed = zstd::bulk::compress(&bytes, compression.level)
+                .map_err(|e| StoreError::InvalidValue(format!("Failed to compress the value of {}: {}", key, e)))?;
+
+            // Values that do not shrink, e.g. already compressed data, are stored as is.
+            if compressed.len() < json_len {
+                compressed_len = Some(compressed.len());
+                layers.push(ZSTD_ENCODING);
+                bytes = compressed;
+            }
+        }
+
+        if let Some(cipher) = &self.cipher {
+            let nonce = XChaCha20Poly1305::generate_nonce(&mut OsRng);
+            let ciphertext = cipher
+                .encrypt(
+                    &nonce,
+                    Payload {
+                        msg: &bytes,
+                        aad: &associated_data(namespace, key),
+                    },
+                )
+                .map_err(|_| StoreError::EncryptionError(format!("Failed to encrypt the value of {}", key)))?;
+
+            layers.push(XCHACHA20POLY1305_ENCODING);
+            bytes = nonce.to_vec();
+            bytes.extend_from_slice(&ciphertext);
+        }
+
+        let text = if layers.len() == 1 {
+            // Plain JSON is stored as text, so the database stays readable.
+            String::from_utf8(bytes).map_err(|e| StoreError::InvalidValue(e.to_string()))?
+        } else {
+            BASE64.encode(bytes)
+        };
+
+        Ok(EncodedValue {
+            text,
+            encoding: layers.join("+"),
+            json_len,
+            compressed_len,
+        })
+    }
+
+    /// Decodes the value of `key` in `namespace` previously written by
+    /// [`ValueCodec::encode`], possibly with different settings.
+    pub(crate) fn decode(&self, namespace: &str, key: &str, text: &str, encoding: &str) -> Result<Value, StoreError> {
+        let mut layers = encoding.split('+');
+
+        if layers.next() != Some(JSON_ENCODING) {
+            return Err(StoreError::InvalidValue(format!(
+                "Unknown encoding {} for {}",
+                encoding, key
+            )));
+        }
+
+        let layers: Vec<&str> = layers.collect();
+
+        if layers.is_empty() {
+            return Ok(serde_json::from_str(text)?);
+        }
+
+        let mut bytes = BASE64
+            .decode(text)
+            .map_err(|e| StoreError::InvalidValue(format!("Failed to decode the value of {}: {}", key, e)))?;
+
+        for layer in layers.into_iter().rev() {
+            bytes = match layer {
+                ZSTD_ENCODING => zstd::stream::decode_all(bytes.as_slice()).map_err(|e| {
+                    StoreError::InvalidValue(format!("Failed to decompress the value of {}: {}", key, e))
+                })?,
+                XCHACHA20POLY1305_ENCODING => self.decrypt(namespace, key, &bytes)?,
+                _ => {
+                    return Err(StoreError::InvalidValue(format!(
+                        "Unknown encoding {} for {}",
+                        encoding, key
+                    )))
+                }
+            };
+        }
+
+        Ok(serde_json::from_slice(&bytes)?)
+    }
+
+    fn decrypt(&self, namespace: &str, key: &str, bytes: &[u8]) -> Result<Vec<u8>, StoreError> {
+        if self.cipher.is_none() && self.previous.is_empty() {
+            return Err(StoreError::EncryptionError(format!(
+                "{} is encrypted but no encryption key is set",
+                key
+            )));
+        }
+
+        if bytes.len() < NONCE_LENGTH {
+            return Err(StoreError::EncryptionError(format!(
+                "The encrypted value of {} is truncated",
+                key
+            )));
+        }
+
+        let (nonce, ciphertext) = bytes.split_at(NONCE_LENGTH);
+        let aad = associated_data(namespace, key);
+
+        self.cipher
+            .iter()
+            .chain(self.previous.iter())
+            .find_map(|cipher| {
+                cipher
+                    .decrypt(
+                        XNonce::from_slice(nonce),
+                        Payload {
+                            msg: ciphertext,
+                            aad: &aad,
+                        },
+                    )
+                    .ok()
+            })
+            .ok_or_else(|| StoreError::EncryptionError(format!("Failed to decrypt the value of {}", key)))
+    }
+}
+
+/// Returns the data authenticated along with the encrypted value of `key` in
+/// `namespace`. Namespace names cannot contain `/`, so the pair is unambiguous.
+fn associated_data(namespace: &str, key: &str) -> Vec<u8> {
+    format!("{}/{}", namespace, key).into_bytes()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    const KEY: [u8; 32] = [7; 32];
+    const OTHER_KEY: [u8; 32] = [9; 32];
+
+    fn is_encryption_error(result: Result<Value, StoreError>) -> bool {
+        matches!(result, Err(StoreError::EncryptionError(_)))
+    }
+
+    #[test]
+    fn encrypted_values_round_trip() {
+        let codec = ValueCodec::default().with_encryption_key(Some(&KEY));
+        let value = json!({ "token": "secret-token", "expires": 3600 });
+
+        let encoded = codec.encode("auth", "session", &value).unwrap();
+        assert_eq!(encoded.encoding, "json+xchacha20poly1305");
+        assert!(!encoded.text.contains("secret-token"));
+
+        let decoded = codec
+            .decode("auth", "session", &encoded.text, &encoded.encoding)
+            .unwrap();
+        assert_eq!(decoded, value);
+    }
+
+    #[test]
+    fn ciphertexts_are_bound_to_their_namespace_and_key() {
+        let codec = ValueCodec::default().with_encryption_key(Some(&KEY));
+        let encoded = codec.encode("auth", "session", &json!("secret")).unwrap();
+
+        assert!(is_encryption_error(codec.decode(
+            "auth",
+            "other",
+            &encoded.text,
+            &encoded.encoding
+        )));
+        assert!(is_encryption_error(codec.decode(
+            "other",
+            "session",
+            &encoded.text,
+            &encoded.encoding
+        )));
+    }
+
+    #[test]
+    fn previous_keys_still_decrypt() {
+        let old = ValueCodec::default().with_encryption_key(Some(&KEY));
+        let encoded = old.encode("auth", "session", &json!("secret")).unwrap();
+
+        let without_key = ValueCodec::default();
+        assert!(is_encryption_error(without_key.decode(
+            "auth",
+            "session",
+            &encoded.text,
+            &encoded.encoding
+        )));
+
+        let wrong_key = ValueCodec::default().with_encryption_key(Some(&OTHER_KEY));
+        assert!(is_encryption_error(wrong_key.decode(
+            "auth",
+            "session",
+            &encoded.text,
+            &encoded.encoding
+        )));
+
+        let rotated = old.rotated(Some(&OTHER_KEY));
+        let decoded = rotated
+            .decode("auth", "session", &encoded.text, &encoded.encoding)
+            .unwrap();
+        assert_eq!(decoded, json!("secret"));
+
+        let with_previous = wrong_key.with_previous_key(&KEY);
+        let decoded = with_previous
+            .decode("auth", "session", &encoded.text, &encoded.encoding)
+            .unwrap();
+        assert_eq!(decoded, json!("secret"));
+    }
+
+    fn compressing(threshold: usize) -> ValueCodec {
+        ValueCodec::default().with_compression(Some(Compression { threshold, level: 3 }))
+    }
+
+    #[test]
+    fn only_values_above_the_threshold_are_compressed() {
+        let codec = compressing(64);
+        let small = json!("short");
+        let large = json!("tauri ".repeat(100));
+
+        let encoded = codec.encode("docs", "small", &small).unwrap();
+        assert_eq!((encoded.encoding.as_str(), encoded.compressed_len), ("json", None));
+        assert_eq!(encoded.text, "\"short\"");
+
+        let encoded = codec.encode("docs", "large", &large).unwrap();
+        assert_eq!(encoded.encoding, "json+zstd");
+        assert!(encoded.compressed_len.unwrap() < encoded.json_len);
+        assert_eq!(
+            codec.decode("docs", "large", &encoded.text, &encoded.encoding).unwrap(),
+            large
+        );
+
+        // Values written compressed stay readable once compression is turned off.
+        let plain = ValueCodec::default();
+        assert_eq!(
+            plain.decode("docs", "large", &encoded.text, &encoded.encoding).unwrap(),
+            large
+        );
+    }
+
+    #[test]
+    fn values_that_do_not_shrink_are_stored_as_is() {
+        let encoded = compressing(1).encode("docs", "tiny", &json!(1)).unwrap();
+        assert_eq!((encoded.encoding.as_str(), encoded.text.as_str()), ("json", "1"));
+    }
+
+    #[test]
+    fn compressed_values_can_be_encrypted() {
+        let codec = compressing(64).with_encryption_key(Some(&KEY));
+        let value = json!({ "body": "tauri ".repeat(100) });
+
+        let encoded = codec.encode("docs", "page", &value).unwrap();
+        assert_eq!(encoded.encoding, "json+zstd+xchacha20poly1305");
+        assert_eq!(
+            codec.decode("docs", "page", &encoded.text, &encoded.encoding).unwrap(),
+            value
+        );
+    }
+}
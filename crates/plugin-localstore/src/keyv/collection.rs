@@ -0,0 +1,292 @@
+// Copyright © 2024 Crabby Craft - All Rights Reserved.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::marker::PhantomData;
+use std::sync::atomic::{AtomicU32, Ordering};
+
+use super::{unix_millis, Filter, FindQuery, Keyv, KeyvError, StoreError, StoreModel};
+
+/// Counter appended to generated ids, so ids generated in the same millisecond differ.
+static NEXT_ID: AtomicU32 = AtomicU32::new(0);
+
+/// Returns a new document id. Ids sort in the order they were generated.
+fn generate_id() -> String {
+    format!("{:012x}{:08x}", unix_millis(), NEXT_ID.fetch_add(1, Ordering::Relaxed))
+}
+
+/// A document of a [`Collection`], together with its id.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Document<T> {
+    pub id: String,
+    /// The version of the document, see `Keyv::get_versioned`.
+    pub version: u64,
+    pub data: T,
+}
+
+impl<T: DeserializeOwned> Document<T> {
+    fn from_model(model: StoreModel) -> Result<Self, KeyvError> {
+        Ok(Self {
+            data: serde_json::from_value(model.value).map_err(|e| StoreError::SerializationError { source: e })?,
+            id: model.key,
+            version: model.version,
+        })
+    }
+}
+
+/// A collection of JSON documents of type `T`, queried with Mongo-style
+/// [`Filter`]s. Created with `Keyv::collection`.
+///
+/// Each collection lives in its own namespace, with the document ids as keys,
+/// so every other `Keyv` feature, such as history, limits or change events,
+/// applies to it too. Filters on fields with a secondary index, see
+/// `create_index`, are answered from the index.
+pub struct Collection<T> {
+    keyv: Keyv,
+    marker: PhantomData<fn() -> T>,
+}
+
+impl<T> Clone for Collection<T> {
+    fn clone(&self) -> Self {
+        Self {
+            keyv: self.keyv.clone(),
+            marker: PhantomData,
+        }
+    }
+}
+
+impl<T: Serialize + DeserializeOwned> Collection<T> {
+    pub(crate) fn new(keyv: Keyv) -> Self {
+        Self {
+            keyv,
+            marker: PhantomData,
+        }
+    }
+
+    /// Inserts a document under a new id.
+    ///
+    /// # Arguments
+    ///
+    /// * `document` - The document to insert.
+    ///
+    /// # Returns
+    ///
+    /// Returns the id of the document, or a `KeyvError` on failure.
+    pub async fn insert(&self, document: &T) -> Result<String, KeyvError> {
+        loop {
+            let id = generate_id();
+
+            match self.insert_with_id(&id, document).await {
+                // Another process generated the same id, so try the next one.
+                Err(KeyvError::StoreError(StoreError::VersionConflict { .. })) => continue,
+                result => return result.map(|_| id),
+            }
+        }
+    }
+
+    /// Inserts a document under the given id.
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - The id of the document.
+    /// * `document` - The document to insert.
+    ///
+    /// # Returns
+    ///
+    /// Returns the version of the document, or `StoreError::VersionConflict`
+    /// wrapped in a `KeyvError` if a document with this id already exists.
+    pub async fn insert_with_id(&self, id: &str, document: &T) -> Result<u64, KeyvError> {
+        self.keyv.set_versioned(id, document, None).await
+    }
+
+    /// Retrieves a document by id.
+    ///
+    /// # Returns
+    ///
+    /// Returns an `Ok` result with `Option<T>`, where `None` indicates there is
+    /// no document with this id, or a `KeyvError` on failure.
+    pub async fn get(&self, id: &str) -> Result<Option<T>, KeyvError> {
+        self.keyv.get_as(id).await
+    }
+
+    /// Finds the documents matching a filter, in id order.
+    ///
+    /// # Arguments
+    ///
+    /// * `filter` - A [`Filter`], such as `{"status": "open", "age": {"$gt": 3}}`.
+    ///
+    /// # Returns
+    ///
+    /// Returns the matching documents, or a `KeyvError` if the filter is invalid
+    /// or a document does not match `T`.
+    ///
+    /// # Examples
+    ///
+    /// ```rust,no_run
+    /// # use tauri_plugin_localstore::keyv::Keyv;
+    /// # use serde::{Deserialize, Serialize};
+    /// #[derive(Serialize, Deserialize)]
+    /// struct Task {
+    ///     title: String,
+    ///     status: String,
+    ///     priority: u32,
+    /// }
+    ///
+    /// # async fn run(keyv: Keyv) -> Result<(), Box<dyn std::error::Error>> {
+    /// let tasks = keyv.collection::<Task>("tasks")?;
+    /// tasks.create_index("status", "status").await?;
+    ///
+    /// let urgent = tasks
+    ///     .find(serde_json::json!({ "status": "open", "priority": { "$gte": 3 } }))
+    ///     .await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn find(&self, filter: Value) -> Result<Vec<Document<T>>, KeyvError> {
+        self.find_with(FindQuery::new(Filter::parse(filter)?)).await
+    }
+
+    /// Finds the first document matching a filter, in id order.
+    ///
+    /// # Returns
+    ///
+    /// Returns an `Ok` result with `Option<Document<T>>`, where `None` indicates
+    /// no document matches, or a `KeyvError` on failure.
+    pub async fn find_one(&self, filter: Value) -> Result<Option<Document<T>>, KeyvError> {
+        Ok(self
+            .find_with(FindQuery::new(Filter::parse(filter)?).limit(1))
+            .await?
+            .pop())
+    }
+
+    /// Finds the documents matching a query, restricted to an id prefix or a
+    /// number of documents.
+    ///
+    /// # Returns
+    ///
+    /// Returns the matching documents, or a `KeyvError` on failure.
+    pub async fn find_with(&self, query: FindQuery) -> Result<Vec<Document<T>>, KeyvError> {
+        self.keyv
+            .find(query)
+            .await?
+            .into_iter()
+            .map(Document::from_model)
+            .collect()
+    }
+
+    /// Updates the documents matching a filter with `f`.
+    ///
+    /// Documents are matched first, then each one is replaced by the result of
+    /// `f` if it was not written in the meantime. A document written in the
+    /// meantime is passed to `f` again if it still matches, and skipped otherwise.
+    ///
+    /// # Arguments
+    ///
+    /// * `filter` - The documents to update.
+    /// * `f` - Computes the new document from the current one.
+    ///
+    /// # Returns
+    ///
+    /// Returns the number of documents updated, or a `KeyvError` on failure.
+    ///
+    /// # Examples
+    ///
+    /// ```rust,no_run
+    /// # use tauri_plugin_localstore::keyv::Keyv;
+    /// # async fn run(keyv: Keyv) -> Result<(), Box<dyn std::error::Error>> {
+    /// let tasks = keyv.collection::<serde_json::Value>("tasks")?;
+    ///
+    /// let closed = tasks
+    ///     .update(serde_json::json!({ "status": "open" }), |mut task| {
+    ///         task["status"] = "closed".into();
+    ///         task
+    ///     })
+    ///     .await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn update<F>(&self, filter: Value, mut f: F) -> Result<u64, KeyvError>
+    where
+        F: FnMut(T) -> T,
+    {
+        let filter = Filter::parse(filter)?;
+        let mut updated = 0;
+
+        for model in self.keyv.find(FindQuery::new(filter.clone())).await? {
+            let (mut value, mut version) = (model.value, model.version);
+
+            loop {
+                let document =
+                    serde_json::from_value(value).map_err(|e| StoreError::SerializationError { source: e })?;
+
+                match self.keyv.set_versioned(&model.key, f(document), Some(version)).await {
+                    Ok(_) => {
+                        updated += 1;
+                        break;
+                    }
+                    Err(KeyvError::StoreError(StoreError::VersionConflict { .. })) => {
+                        match self.keyv.get_versioned(&model.key).await? {
+                            Some(current) if filter.matches(&current.value) => {
+                                value = current.value;
+                                version = current.version;
+                            }
+                            _ => break,
+                        }
+                    }
+                    Err(e) => return Err(e),
+                }
+            }
+        }
+
+        Ok(updated)
+    }
+
+    /// Deletes the documents matching a filter.
+    ///
+    /// # Returns
+    ///
+    /// Returns the number of documents deleted, or a `KeyvError` on failure.
+    pub async fn delete(&self, filter: Value) -> Result<u64, KeyvError> {
+        let ids: Vec<String> = self
+            .keyv
+            .find(FindQuery::new(Filter::parse(filter)?))
+            .await?
+            .into_iter()
+            .map(|model| model.key)
+            .collect();
+
+        if !ids.is_empty() {
+            self.keyv.remove_many(&ids).await?;
+        }
+
+        Ok(ids.len() as u64)
+    }
+
+    /// Creates a secondary index on a field of the documents, so that filters
+    /// on it do not scan the whole collection. The index is kept with the
+    /// collection, so it only needs to be created once.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name of the index, made of ASCII letters, digits and underscores.
+    /// * `path` - The dotted path of the field, e.g. `author.name`.
+    ///
+    /// # Returns
+    ///
+    /// Returns an `Ok` result once the index is built, or a `KeyvError` on failure.
+    pub async fn create_index(&self, name: &str, path: &str) -> Result<(), KeyvError> {
+        self.keyv.create_index(name, path).await
+    }
+
+    /// Drops a secondary index of the collection.
+    pub async fn drop_index(&self, name: &str) -> Result<(), KeyvError> {
+        self.keyv.drop_index(name).await
+    }
+}
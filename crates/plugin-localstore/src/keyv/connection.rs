@@ -0,0 +1,329 @@
+// Copyright © 2024 Crabby Craft - All Rights Reserved.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use libsql::Connection;
+use serde::{Deserialize, Serialize};
+use std::ops::Deref;
+use std::sync::{Mutex, PoisonError};
+use std::time::Duration;
+use tokio::sync::{MutexGuard, Semaphore, SemaphorePermit};
+
+use super::StoreError;
+
+/// The SQLite journal mode, see `KeyvStoreBuilder::journal_mode`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum JournalMode {
+    Delete,
+    Truncate,
+    Persist,
+    Memory,
+    /// Write-ahead logging, which lets reads run while a write is in progress.
+    Wal,
+    Off,
+}
+
+impl JournalMode {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Self::Delete => "delete",
+            Self::Truncate => "truncate",
+            Self::Persist => "persist",
+            Self::Memory => "memory",
+            Self::Wal => "wal",
+            Self::Off => "off",
+        }
+    }
+}
+
+/// How often SQLite waits for data to reach the disk, see `KeyvStoreBuilder::synchronous`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Synchronous {
+    Off,
+    /// Safe from corruption in WAL mode, but the last commits may be lost on power loss.
+    Normal,
+    Full,
+    Extra,
+}
+
+impl Synchronous {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Self::Off => "OFF",
+            Self::Normal => "NORMAL",
+            Self::Full => "FULL",
+            Self::Extra => "EXTRA",
+        }
+    }
+}
+
+/// The pragmas applied to every connection opened by a `KeyvStoreBuilder`.
+/// Options left unset keep the SQLite defaults.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct ConnectionOptions {
+    pub(crate) journal_mode: Option<JournalMode>,
+    pub(crate) synchronous: Option<Synchronous>,
+    pub(crate) busy_timeout: Option<Duration>,
+    pub(crate) cache_size: Option<i64>,
+}
+
+impl ConnectionOptions {
+    /// Applies the options to the writer connection. The journal mode is
+    /// stored in the database file, so it only needs to be set here.
+    pub(crate) async fn apply_to_writer(&self, conn: &Connection) -> Result<(), StoreError> {
+        self.apply(conn).await?;
+
+        if let Some(mode) = self.journal_mode {
+            let actual = pragma(conn, &format!("PRAGMA journal_mode = {}", mode.as_str())).await?;
+
+            // In-memory databases, for one, cannot use every mode.
+            if actual.as_deref() != Some(mode.as_str()) {
+                log::warn!(
+                    "Failed to set the journal mode to {}, the database uses {:?}",
+                    mode.as_str(),
+                    actual
+                );
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Applies the options to a connection of the read pool, which is also
+    /// made read-only.
+    pub(crate) async fn apply_to_reader(&self, conn: &Connection) -> Result<(), StoreError> {
+        self.apply(conn).await?;
+        pragma(conn, "PRAGMA query_only = ON").await?;
+
+        Ok(())
+    }
+
+    async fn apply(&self, conn: &Connection) -> Result<(), StoreError> {
+        // Set first, so that the other pragmas wait for locks too.
+        if let Some(timeout) = self.busy_timeout {
+            pragma(conn, &format!("PRAGMA busy_timeout = {}", timeout.as_millis())).await?;
+        }
+        if let Some(synchronous) = self.synchronous {
+            pragma(conn, &format!("PRAGMA synchronous = {}", synchronous.as_str())).await?;
+        }
+        if let Some(cache_size) = self.cache_size {
+            pragma(conn, &format!("PRAGMA cache_size = {}", cache_size)).await?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Runs a pragma and returns the first column of its result, if any.
+async fn pragma(conn: &Connection, sql: &str) -> Result<Option<String>, StoreError> {
+    let mut rows = conn
+        .query(sql, ())
+        .await
+        .map_err(|e| StoreError::ConnectionError(format!("Failed to run {}: {:?}", sql, e)))?;
+
+    let row = rows
+        .next()
+        .await
+        .map_err(|e| StoreError::ConnectionError(format!("Failed to run {}: {:?}", sql, e)))?;
+
+    // Setting a pragma may return its new value as an integer, which `Row::get`
+    // cannot read as a `String` without panicking.
+    match row.map(|row| row.get_value(0)) {
+        Some(Ok(libsql::Value::Text(value))) => Ok(Some(value)),
+        _ => Ok(None),
+    }
+}
+
+/// Read-only connections lent to one read at a time, next to the single
+/// writer connection of a `KeyvStore`.
+pub(crate) struct ReadPool {
+    idle: Mutex<Vec<Connection>>,
+    available: Semaphore,
+}
+
+impl ReadPool {
+    pub(crate) fn new(connections: Vec<Connection>) -> Self {
+        Self {
+            available: Semaphore::new(connections.len()),
+            idle: Mutex::new(connections),
+        }
+    }
+
+    /// Waits for an idle connection.
+    pub(crate) async fn get(&self) -> PooledConnection<'_> {
+        let permit = self
+            .available
+            .acquire()
+            .await
+            .expect("the read pool semaphore is never closed");
+
+        let connection = self
+            .idle
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .pop()
+            .expect("a permit guarantees an idle connection");
+
+        PooledConnection {
+            pool: self,
+            connection: Some(connection),
+            _permit: permit,
+        }
+    }
+}
+
+/// A connection borrowed from a [`ReadPool`], returned to it on drop.
+pub(crate) struct PooledConnection<'a> {
+    pool: &'a ReadPool,
+    connection: Option<Connection>,
+    // Released after the connection is back in the pool.
+    _permit: SemaphorePermit<'a>,
+}
+
+impl Deref for PooledConnection<'_> {
+    type Target = Connection;
+
+    fn deref(&self) -> &Connection {
+        self.connection.as_ref().expect("the connection is only taken on drop")
+    }
+}
+
+impl Drop for PooledConnection<'_> {
+    fn drop(&mut self) {
+        if let Some(connection) = self.connection.take() {
+            self.pool
+                .idle
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .push(connection);
+        }
+    }
+}
+
+/// The connection used for a read: one from the read pool, or the writer
+/// connection when the store has no pool.
+///
+/// The writer connection is only lent together with the write lock, so that a
+/// read never sees the uncommitted writes of an open transaction.
+pub(crate) enum ReadConnection<'a> {
+    Pooled(PooledConnection<'a>),
+    Shared {
+        connection: &'a Connection,
+        // Released once the read is done.
+        _guard: MutexGuard<'a, ()>,
+    },
+}
+
+impl Deref for ReadConnection<'_> {
+    type Target = Connection;
+
+    fn deref(&self) -> &Connection {
+        match self {
+            Self::Pooled(connection) => connection,
+            Self::Shared { connection, .. } => connection,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::keyv::{Keyv, KeyvStoreBuilder};
+    use libsql::Builder;
+    use serde_json::json;
+
+    async fn pragma_int(conn: &Connection, name: &str) -> i64 {
+        let mut rows = conn.query(&format!("SELECT * FROM pragma_{}", name), ()).await.unwrap();
+        rows.next().await.unwrap().unwrap().get(0).unwrap()
+    }
+
+    async fn journal_mode(conn: &Connection) -> Option<String> {
+        pragma(conn, "PRAGMA journal_mode").await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn options_are_applied_to_the_writer_and_the_readers() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = Builder::new_local(dir.path().join("store.db")).build().await.unwrap();
+        let options = ConnectionOptions {
+            journal_mode: Some(JournalMode::Wal),
+            synchronous: Some(Synchronous::Normal),
+            busy_timeout: Some(Duration::from_millis(1500)),
+            cache_size: Some(-4000),
+        };
+
+        let writer = db.connect().unwrap();
+        options.apply_to_writer(&writer).await.unwrap();
+        let readers: Vec<Connection> = (0..2).map(|_| db.connect().unwrap()).collect();
+        for reader in &readers {
+            options.apply_to_reader(reader).await.unwrap();
+        }
+
+        assert_eq!(journal_mode(&writer).await.as_deref(), Some("wal"));
+        assert_eq!(pragma_int(&writer, "query_only").await, 0);
+
+        for conn in readers.iter().chain([&writer]) {
+            assert_eq!(pragma_int(conn, "synchronous").await, 1);
+            assert_eq!(pragma_int(conn, "busy_timeout").await, 1500);
+            assert_eq!(pragma_int(conn, "cache_size").await, -4000);
+        }
+
+        // The journal mode is stored in the file, and readers cannot write.
+        assert_eq!(journal_mode(&readers[0]).await.as_deref(), Some("wal"));
+        assert_eq!(pragma_int(&readers[0], "query_only").await, 1);
+        assert!(readers[0].execute("CREATE TABLE t (x)", ()).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn pooled_reads_see_committed_writes_only() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = KeyvStoreBuilder::new()
+            .uri(dir.path().join("store.db"))
+            .journal_mode(JournalMode::Wal)
+            .read_connections(2)
+            .build()
+            .await
+            .unwrap();
+        let keyv = Keyv::try_new(store).await.unwrap();
+        keyv.set("balance", 100).await.unwrap();
+
+        let reader = keyv.clone();
+        keyv.transaction(|tx| async move {
+            tx.set("balance", 90).await?;
+            tx.set("pending", true).await?;
+
+            // Reads outside the transaction do not wait for it, and do not see its writes.
+            assert_eq!(reader.get("balance").await?, Some(json!(100)));
+            assert_eq!(reader.get("pending").await?, None);
+            Ok(())
+        })
+        .await
+        .unwrap();
+
+        assert_eq!(keyv.get("balance").await.unwrap(), Some(json!(90)));
+        assert_eq!(keyv.get("pending").await.unwrap(), Some(json!(true)));
+    }
+
+    #[tokio::test]
+    async fn the_pool_lends_each_connection_once() {
+        let db = Builder::new_local(":memory:").build().await.unwrap();
+        let pool = ReadPool::new(vec![db.connect().unwrap(), db.connect().unwrap()]);
+
+        let first = pool.get().await;
+        let _second = pool.get().await;
+        assert!(tokio::time::timeout(Duration::from_millis(20), pool.get())
+            .await
+            .is_err());
+
+        drop(first);
+        assert!(tokio::time::timeout(Duration::from_millis(20), pool.get())
+            .await
+            .is_ok());
+    }
+}
@@ -0,0 +1,76 @@
+// Copyright © 2024 Crabby Craft - All Rights Reserved.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tokio::sync::broadcast;
+use tokio_stream::wrappers::errors::BroadcastStreamRecvError;
+use tokio_stream::wrappers::BroadcastStream;
+use tokio_stream::{Stream, StreamExt};
+
+/// Number of changes buffered for each subscriber before the oldest ones are dropped.
+pub(super) const CHANGE_CHANNEL_CAPACITY: usize = 256;
+
+/// The kind of write that produced a [`KeyvChange`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum KeyvChangeKind {
+    /// A value was written.
+    Set,
+    /// A key was removed, or evicted to keep its namespace within its limits.
+    Remove,
+    /// Every key in the namespace was removed.
+    Clear,
+    /// A key was purged because its TTL elapsed.
+    Expire,
+}
+
+/// A change made to a `Keyv` store.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct KeyvChange {
+    /// The namespace the change was made in.
+    pub namespace: String,
+    pub kind: KeyvChangeKind,
+    /// The affected key, `None` for [`KeyvChangeKind::Clear`].
+    pub key: Option<String>,
+    /// The new value, only present for [`KeyvChangeKind::Set`].
+    pub value: Option<Value>,
+}
+
+impl KeyvChange {
+    /// Returns `true` if the change affects `key` in `namespace`.
+    pub fn affects_key(&self, namespace: &str, key: &str) -> bool {
+        self.namespace == namespace && self.key.as_deref().map_or(true, |k| k == key)
+    }
+
+    /// Returns `true` if the change affects any key starting with `prefix` in `namespace`.
+    pub fn affects_prefix(&self, namespace: &str, prefix: &str) -> bool {
+        self.namespace == namespace && self.key.as_deref().map_or(true, |k| k.starts_with(prefix))
+    }
+}
+
+/// Turns a broadcast receiver into a stream of the changes accepted by `filter`.
+///
+/// Subscribers that fall behind skip the changes they missed rather than
+/// ending the stream.
+pub(super) fn change_stream<F>(
+    receiver: broadcast::Receiver<KeyvChange>,
+    filter: F,
+) -> impl Stream<Item = KeyvChange> + Send + Unpin + 'static
+where
+    F: Fn(&KeyvChange) -> bool + Send + 'static,
+{
+    BroadcastStream::new(receiver).filter_map(move |change| match change {
+        Ok(change) if filter(&change) => Some(change),
+        Ok(_) => None,
+        Err(BroadcastStreamRecvError::Lagged(skipped)) => {
+            log::warn!("Keyv change subscriber lagged behind, skipped {} changes", skipped);
+            None
+        }
+    })
+}
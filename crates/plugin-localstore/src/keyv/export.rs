@@ -0,0 +1,231 @@
+// Copyright © 2024 Crabby Craft - All Rights Reserved.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncWrite, AsyncWriteExt, BufReader, Lines};
+
+use super::{KeyvError, StoreError, StoreModel};
+
+/// Number of entries read per round trip by `Keyv::export`.
+pub(super) const EXPORT_PAGE_SIZE: u64 = 256;
+
+/// The file format written by `Keyv::export`.
+///
+/// `Keyv::import` detects the format of its input, so it does not need to be
+/// told which one was used.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ExportFormat {
+    /// A JSON array with one entry per line.
+    #[default]
+    Json,
+    /// Newline-delimited JSON, one entry per line.
+    Ndjson,
+}
+
+/// How `Keyv::import` treats the keys already in the namespace.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ImportMode {
+    /// Keep the existing keys, overwriting those present in the import.
+    #[default]
+    Merge,
+    /// Remove the existing keys that are not present in the import.
+    Replace,
+}
+
+/// What `Keyv::import` changed.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+pub struct ImportReport {
+    /// Number of entries written.
+    pub imported: u64,
+    /// Number of existing keys removed, in replace mode.
+    pub removed: u64,
+    /// Number of entries skipped because their TTL elapsed since the export.
+    pub expired: u64,
+}
+
+/// One entry of an export file.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExportEntry {
+    pub key: String,
+    pub value: Value,
+    /// When the entry expires, in milliseconds since the Unix epoch.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expires_at: Option<i64>,
+}
+
+impl From<StoreModel> for ExportEntry {
+    fn from(model: StoreModel) -> Self {
+        ExportEntry {
+            key: model.key,
+            value: model.value,
+            expires_at: model.expires_at,
+        }
+    }
+}
+
+/// Writes entries to `W` one at a time in an [`ExportFormat`].
+pub(super) struct ExportWriter<W> {
+    writer: W,
+    format: ExportFormat,
+    written: u64,
+}
+
+impl<W: AsyncWrite + Unpin> ExportWriter<W> {
+    pub(super) fn new(writer: W, format: ExportFormat) -> Self {
+        Self {
+            writer,
+            format,
+            written: 0,
+        }
+    }
+
+    pub(super) async fn write(&mut self, entry: &ExportEntry) -> Result<(), std::io::Error> {
+        let line = serde_json::to_string(entry)?;
+
+        let separator = match (self.format, self.written) {
+            (ExportFormat::Json, 0) => "[\n  ",
+            (ExportFormat::Json, _) => ",\n  ",
+            (ExportFormat::Ndjson, _) => "",
+        };
+
+        self.writer.write_all(separator.as_bytes()).await?;
+        self.writer.write_all(line.as_bytes()).await?;
+
+        if self.format == ExportFormat::Ndjson {
+            self.writer.write_all(b"\n").await?;
+        }
+
+        self.written += 1;
+
+        Ok(())
+    }
+
+    /// Terminates the output and flushes the writer, returning the number of
+    /// entries written.
+    pub(super) async fn finish(mut self) -> Result<u64, std::io::Error> {
+        match (self.format, self.written) {
+            (ExportFormat::Json, 0) => self.writer.write_all(b"[]\n").await?,
+            (ExportFormat::Json, _) => self.writer.write_all(b"\n]\n").await?,
+            (ExportFormat::Ndjson, _) => {}
+        }
+
+        self.writer.flush().await?;
+
+        Ok(self.written)
+    }
+}
+
+/// Reads the entries written by an [`ExportWriter`] one line at a time, so that
+/// the whole export is never held in memory.
+///
+/// Both formats put each entry on its own line; the brackets and commas of the
+/// JSON array are stripped from the lines around the entries.
+pub(super) struct ExportReader<R> {
+    lines: Lines<BufReader<R>>,
+}
+
+impl<R: AsyncRead + Unpin> ExportReader<R> {
+    pub(super) fn new(reader: R) -> Self {
+        Self {
+            lines: BufReader::new(reader).lines(),
+        }
+    }
+
+    /// Returns the next entry, or `None` at the end of the input.
+    pub(super) async fn next(&mut self) -> Result<Option<ExportEntry>, KeyvError> {
+        while let Some(line) = self.lines.next_line().await? {
+            let line = line
+                .trim()
+                .trim_start_matches('[')
+                .trim_end_matches(']')
+                .trim_end_matches(',')
+                .trim();
+
+            if line.is_empty() {
+                continue;
+            }
+
+            let entry = serde_json::from_str(line).map_err(StoreError::from)?;
+            return Ok(Some(entry));
+        }
+
+        Ok(None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::keyv::{Keyv, KeyvStoreBuilder};
+    use serde_json::json;
+
+    async fn memory_keyv() -> Keyv {
+        let store = KeyvStoreBuilder::new().uri(":memory:").build().await.unwrap();
+        Keyv::try_new(store).await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn exports_import_back_in_both_formats() {
+        let source = memory_keyv().await;
+        // More entries than a page, so the export spans several pages.
+        let entries = (0..EXPORT_PAGE_SIZE * 2 + 10).map(|i| (format!("key:{:04}", i), json!({ "n": i })));
+        source.set_many(entries).await.unwrap();
+        source.set_with_ttl("session", "token", 3600).await.unwrap();
+
+        for format in [ExportFormat::Json, ExportFormat::Ndjson] {
+            let mut exported = Vec::new();
+            let count = source.export(&mut exported, format).await.unwrap();
+            assert_eq!(count, EXPORT_PAGE_SIZE * 2 + 11);
+
+            let target = memory_keyv().await;
+            let report = target.import(exported.as_slice(), ImportMode::Merge).await.unwrap();
+            assert_eq!((report.imported, report.removed, report.expired), (count, 0, 0));
+
+            assert_eq!(target.list().await.unwrap().len() as u64, count);
+            assert_eq!(target.get("key:0300").await.unwrap(), Some(json!({ "n": 300 })));
+            let session = target.get_with_meta("session").await.unwrap().unwrap();
+            assert!(session.expires_at.is_some());
+        }
+    }
+
+    #[tokio::test]
+    async fn replace_mode_removes_keys_missing_from_the_import() {
+        let input = "{\"key\":\"a\",\"value\":1}\n\n{\"key\":\"b\",\"value\":[true]}\n";
+
+        let merged = Keyv::default();
+        merged.set("stale", 0).await.unwrap();
+        let report = merged.import(input.as_bytes(), ImportMode::Merge).await.unwrap();
+        assert_eq!((report.imported, report.removed), (2, 0));
+        assert_eq!(merged.get("stale").await.unwrap(), Some(json!(0)));
+
+        let replaced = Keyv::default();
+        replaced.set("stale", 0).await.unwrap();
+        replaced.set("a", 0).await.unwrap();
+        let report = replaced.import(input.as_bytes(), ImportMode::Replace).await.unwrap();
+        assert_eq!((report.imported, report.removed), (2, 1));
+        assert_eq!(replaced.get("stale").await.unwrap(), None);
+        assert_eq!(replaced.get("a").await.unwrap(), Some(json!(1)));
+        assert_eq!(replaced.get("b").await.unwrap(), Some(json!([true])));
+    }
+
+    #[tokio::test]
+    async fn expired_entries_are_skipped_and_invalid_files_rolled_back() {
+        let keyv = Keyv::default();
+        let input = "[\n{\"key\":\"old\",\"value\":1,\"expires_at\":1000},\n{\"key\":\"new\",\"value\":2}\n]\n";
+        let report = keyv.import(input.as_bytes(), ImportMode::Merge).await.unwrap();
+        assert_eq!((report.imported, report.expired), (1, 1));
+        assert_eq!(keyv.get("old").await.unwrap(), None);
+
+        let invalid = "{\"key\":\"first\",\"value\":1}\nnot json\n";
+        assert!(keyv.import(invalid.as_bytes(), ImportMode::Merge).await.is_err());
+        assert_eq!(keyv.get("first").await.unwrap(), None);
+    }
+}
@@ -0,0 +1,538 @@
+// Copyright © 2024 Crabby Craft - All Rights Reserved.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::cmp::Ordering;
+
+use super::StoreError;
+
+/// A Mongo-style filter over JSON values, such as
+/// `{"status": "open", "age": {"$gt": 3}}`.
+///
+/// Each field of the filter is a path into the value, with nested fields
+/// separated by dots and array positions given as numbers, e.g.
+/// `author.name` or `tags.0`. A field matches a value by equality, or by the
+/// operators `$eq`, `$ne`, `$gt`, `$gte`, `$lt`, `$lte`, `$in`, `$nin` and
+/// `$exists`. The fields are combined with AND; `$and` and `$or` take a list
+/// of filters. As in MongoDB, `null` matches missing fields, and ranges only
+/// match fields of the same type, numbers or strings.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(try_from = "Value", into = "Value")]
+pub struct Filter {
+    source: Value,
+    conditions: Vec<Condition>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Condition {
+    Field(FieldPath, Operator),
+    And(Vec<Vec<Condition>>),
+    Or(Vec<Vec<Condition>>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Operator {
+    Eq(Value),
+    Ne(Value),
+    Range(Range, Value),
+    In(Vec<Value>),
+    Nin(Vec<Value>),
+    Exists(bool),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Range {
+    Gt,
+    Gte,
+    Lt,
+    Lte,
+}
+
+impl Range {
+    fn sql(self) -> &'static str {
+        match self {
+            Range::Gt => ">",
+            Range::Gte => ">=",
+            Range::Lt => "<",
+            Range::Lte => "<=",
+        }
+    }
+
+    fn test(self, ordering: Ordering) -> bool {
+        match self {
+            Range::Gt => ordering == Ordering::Greater,
+            Range::Gte => ordering != Ordering::Less,
+            Range::Lt => ordering == Ordering::Less,
+            Range::Lte => ordering != Ordering::Greater,
+        }
+    }
+}
+
+/// A dotted path into a JSON value.
+#[derive(Debug, Clone, PartialEq)]
+struct FieldPath(Vec<Segment>);
+
+#[derive(Debug, Clone, PartialEq)]
+enum Segment {
+    Key(String),
+    Index(usize),
+}
+
+impl FieldPath {
+    fn parse(path: &str) -> Result<Self, StoreError> {
+        path.split('.')
+            .map(|segment| {
+                if segment.is_empty() || !segment.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+                    return Err(StoreError::InvalidQuery(format!(
+                        "field paths may only contain ASCII letters, digits, underscores and dots: {}",
+                        path
+                    )));
+                }
+
+                Ok(match segment.parse::<usize>() {
+                    Ok(index) => Segment::Index(index),
+                    Err(_) => Segment::Key(segment.to_string()),
+                })
+            })
+            .collect::<Result<Vec<_>, _>>()
+            .map(FieldPath)
+    }
+
+    /// Returns the path in the SQLite JSON path syntax, e.g. `$.tags[0]`.
+    /// Segments are restricted to safe characters, so it can be inlined in SQL.
+    fn json_path(&self) -> String {
+        self.0.iter().fold("$".to_string(), |path, segment| match segment {
+            Segment::Key(key) => format!("{}.{}", path, key),
+            Segment::Index(index) => format!("{}[{}]", path, index),
+        })
+    }
+
+    fn resolve<'a>(&self, value: &'a Value) -> Option<&'a Value> {
+        self.0.iter().try_fold(value, |value, segment| match (segment, value) {
+            (Segment::Key(key), Value::Object(map)) => map.get(key),
+            (Segment::Index(index), Value::Array(items)) => items.get(*index),
+            _ => None,
+        })
+    }
+}
+
+/// Converts a dotted field path, as used in filters, to a SQLite JSON path.
+pub(crate) fn json_path(path: &str) -> Result<String, StoreError> {
+    Ok(FieldPath::parse(path)?.json_path())
+}
+
+impl Filter {
+    /// Parses a filter, failing with `StoreError::InvalidQuery` if it is not
+    /// an object or uses an unknown operator.
+    pub fn parse(source: Value) -> Result<Self, StoreError> {
+        let conditions = parse_conditions(&source)?;
+        Ok(Self { source, conditions })
+    }
+
+    /// Returns the filter matching every value.
+    pub fn all() -> Self {
+        Self {
+            source: Value::Object(Map::new()),
+            conditions: Vec::new(),
+        }
+    }
+
+    /// Returns `true` if `value` matches the filter.
+    pub fn matches(&self, value: &Value) -> bool {
+        conditions_match(&self.conditions, value)
+    }
+
+    /// Compiles the filter to a SQL condition on the JSON text returned by
+    /// `column`, appending the values it binds to `params`.
+    pub(crate) fn to_sql(&self, column: &str, params: &mut Vec<libsql::Value>) -> String {
+        conditions_sql(&self.conditions, column, params)
+    }
+}
+
+impl Default for Filter {
+    fn default() -> Self {
+        Self::all()
+    }
+}
+
+impl TryFrom<Value> for Filter {
+    type Error = StoreError;
+
+    fn try_from(source: Value) -> Result<Self, Self::Error> {
+        Self::parse(source)
+    }
+}
+
+impl From<Filter> for Value {
+    fn from(filter: Filter) -> Self {
+        filter.source
+    }
+}
+
+fn invalid<T>(message: String) -> Result<T, StoreError> {
+    Err(StoreError::InvalidQuery(message))
+}
+
+fn parse_conditions(filter: &Value) -> Result<Vec<Condition>, StoreError> {
+    let Value::Object(fields) = filter else {
+        return invalid(format!("a filter must be an object, found {}", filter));
+    };
+
+    let mut conditions = Vec::new();
+
+    for (field, spec) in fields {
+        match field.as_str() {
+            "$and" | "$or" => {
+                let filters = match spec {
+                    Value::Array(filters) if !filters.is_empty() => {
+                        filters.iter().map(parse_conditions).collect::<Result<Vec<_>, _>>()?
+                    }
+                    _ => return invalid(format!("{} takes a non-empty list of filters", field)),
+                };
+
+                conditions.push(match field.as_str() {
+                    "$and" => Condition::And(filters),
+                    _ => Condition::Or(filters),
+                });
+            }
+            operator if operator.starts_with('$') => {
+                return invalid(format!("unknown operator {}", operator));
+            }
+            _ => {
+                let path = FieldPath::parse(field)?;
+
+                match spec {
+                    Value::Object(operators) if operators.keys().next().is_some_and(|op| op.starts_with('$')) => {
+                        for (operator, operand) in operators {
+                            conditions.push(Condition::Field(path.clone(), parse_operator(operator, operand)?));
+                        }
+                    }
+                    value => conditions.push(Condition::Field(path, Operator::Eq(value.clone()))),
+                }
+            }
+        }
+    }
+
+    Ok(conditions)
+}
+
+fn parse_operator(operator: &str, operand: &Value) -> Result<Operator, StoreError> {
+    let range = |range: Range| match operand {
+        Value::Number(_) | Value::String(_) => Ok(Operator::Range(range, operand.clone())),
+        _ => invalid(format!("{} takes a number or a string, found {}", operator, operand)),
+    };
+    let list = || match operand {
+        Value::Array(values) => Ok(values.clone()),
+        _ => invalid(format!("{} takes a list of values, found {}", operator, operand)),
+    };
+
+    match operator {
+        "$eq" => Ok(Operator::Eq(operand.clone())),
+        "$ne" => Ok(Operator::Ne(operand.clone())),
+        "$gt" => range(Range::Gt),
+        "$gte" => range(Range::Gte),
+        "$lt" => range(Range::Lt),
+        "$lte" => range(Range::Lte),
+        "$in" => Ok(Operator::In(list()?)),
+        "$nin" => Ok(Operator::Nin(list()?)),
+        "$exists" => match operand {
+            Value::Bool(exists) => Ok(Operator::Exists(*exists)),
+            _ => invalid(format!("$exists takes a boolean, found {}", operand)),
+        },
+        _ => invalid(format!("unknown operator {}", operator)),
+    }
+}
+
+fn conditions_match(conditions: &[Condition], value: &Value) -> bool {
+    conditions.iter().all(|condition| match condition {
+        Condition::Field(path, operator) => operator_matches(operator, path.resolve(value)),
+        Condition::And(filters) => filters.iter().all(|filter| conditions_match(filter, value)),
+        Condition::Or(filters) => filters.iter().any(|filter| conditions_match(filter, value)),
+    })
+}
+
+fn operator_matches(operator: &Operator, found: Option<&Value>) -> bool {
+    match operator {
+        Operator::Eq(expected) => equals(found, expected),
+        Operator::Ne(expected) => !equals(found, expected),
+        Operator::Range(range, bound) => compare(found, bound).is_some_and(|ordering| range.test(ordering)),
+        Operator::In(values) => values.iter().any(|expected| equals(found, expected)),
+        Operator::Nin(values) => !values.iter().any(|expected| equals(found, expected)),
+        Operator::Exists(exists) => found.is_some() == *exists,
+    }
+}
+
+fn equals(found: Option<&Value>, expected: &Value) -> bool {
+    match (found, expected) {
+        (None | Some(Value::Null), Value::Null) => true,
+        (Some(Value::Number(_)), Value::Number(_)) => compare(found, expected) == Some(Ordering::Equal),
+        (Some(found), expected) => found == expected,
+        (None, _) => false,
+    }
+}
+
+/// Orders two numbers or two strings, the way SQLite does. Values of other
+/// or different types are not ordered.
+fn compare(found: Option<&Value>, bound: &Value) -> Option<Ordering> {
+    match (found?, bound) {
+        (Value::Number(a), Value::Number(b)) => match (a.as_i64(), b.as_i64()) {
+            (Some(a), Some(b)) => Some(a.cmp(&b)),
+            _ => a.as_f64()?.partial_cmp(&b.as_f64()?),
+        },
+        (Value::String(a), Value::String(b)) => Some(a.as_bytes().cmp(b.as_bytes())),
+        _ => None,
+    }
+}
+
+fn conditions_sql(conditions: &[Condition], column: &str, params: &mut Vec<libsql::Value>) -> String {
+    if conditions.is_empty() {
+        return "1".to_string();
+    }
+
+    let sql: Vec<String> = conditions
+        .iter()
+        .map(|condition| match condition {
+            Condition::Field(path, operator) => operator_sql(operator, &path.json_path(), column, params),
+            Condition::And(filters) => combine_sql(filters, "AND", column, params),
+            Condition::Or(filters) => combine_sql(filters, "OR", column, params),
+        })
+        .collect();
+
+    format!("({})", sql.join(" AND "))
+}
+
+fn combine_sql(filters: &[Vec<Condition>], joiner: &str, column: &str, params: &mut Vec<libsql::Value>) -> String {
+    let sql: Vec<String> = filters
+        .iter()
+        .map(|filter| conditions_sql(filter, column, params))
+        .collect();
+
+    format!("({})", sql.join(&format!(" {} ", joiner)))
+}
+
+fn operator_sql(operator: &Operator, path: &str, column: &str, params: &mut Vec<libsql::Value>) -> String {
+    match operator {
+        Operator::Eq(expected) => equals_sql(expected, path, column, params),
+        // A missing field makes the equality NULL, which `$ne` must match.
+        Operator::Ne(expected) => format!("({}) IS NOT 1", equals_sql(expected, path, column, params)),
+        Operator::Range(range, bound) => format!(
+            "({} AND {} {} {})",
+            type_sql(bound, path, column),
+            extract_sql(path, column),
+            range.sql(),
+            bind(bound, params)
+        ),
+        Operator::In(values) => in_sql(values, path, column, params),
+        Operator::Nin(values) => format!("({}) IS NOT 1", in_sql(values, path, column, params)),
+        Operator::Exists(true) => format!("json_type({}, '{}') IS NOT NULL", column, path),
+        Operator::Exists(false) => format!("json_type({}, '{}') IS NULL", column, path),
+    }
+}
+
+fn in_sql(values: &[Value], path: &str, column: &str, params: &mut Vec<libsql::Value>) -> String {
+    if values.is_empty() {
+        return "0".to_string();
+    }
+
+    let sql: Vec<String> = values
+        .iter()
+        .map(|expected| equals_sql(expected, path, column, params))
+        .collect();
+
+    format!("({})", sql.join(" OR "))
+}
+
+fn extract_sql(path: &str, column: &str) -> String {
+    format!("json_extract({}, '{}')", column, path)
+}
+
+/// SQL condition on the JSON type of the field, so that e.g. the string `"3"`
+/// does not equal the number `3` and `true` does not equal `1`.
+fn type_sql(value: &Value, path: &str, column: &str) -> String {
+    let types = match value {
+        Value::Number(_) => "'integer', 'real'",
+        Value::String(_) => "'text'",
+        _ => "'array', 'object'",
+    };
+
+    format!("json_type({}, '{}') IN ({})", column, path, types)
+}
+
+fn equals_sql(expected: &Value, path: &str, column: &str, params: &mut Vec<libsql::Value>) -> String {
+    match expected {
+        Value::Null => format!("{} IS NULL", extract_sql(path, column)),
+        Value::Bool(value) => format!("json_type({}, '{}') = '{}'", column, path, value),
+        Value::Number(_) | Value::String(_) => format!(
+            "({} AND {} = {})",
+            type_sql(expected, path, column),
+            extract_sql(path, column),
+            bind(expected, params)
+        ),
+        Value::Array(_) | Value::Object(_) => format!(
+            "({} AND {} = json({}))",
+            type_sql(expected, path, column),
+            extract_sql(path, column),
+            bind(expected, params)
+        ),
+    }
+}
+
+/// Binds a JSON value as a SQL parameter and returns its placeholder.
+fn bind(value: &Value, params: &mut Vec<libsql::Value>) -> String {
+    params.push(match value {
+        Value::Number(number) => match number.as_i64() {
+            Some(integer) => integer.into(),
+            None => number.as_f64().unwrap_or_default().into(),
+        },
+        Value::String(text) => text.clone().into(),
+        value => value.to_string().into(),
+    });
+
+    format!("?{}", params.len())
+}
+
+/// A query selecting values with a [`Filter`], in ascending key order.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct FindQuery {
+    #[serde(default)]
+    pub filter: Filter,
+    /// Only include keys starting with this prefix.
+    pub prefix: Option<String>,
+    /// Maximum number of entries to return.
+    pub limit: Option<u64>,
+}
+
+impl FindQuery {
+    pub fn new(filter: Filter) -> Self {
+        Self {
+            filter,
+            ..Self::default()
+        }
+    }
+
+    pub fn prefix<S: Into<String>>(mut self, prefix: S) -> Self {
+        self.prefix = Some(prefix.into());
+        self
+    }
+
+    pub fn limit(mut self, limit: u64) -> Self {
+        self.limit = Some(limit);
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::keyv::{Keyv, KeyvStoreBuilder};
+    use serde_json::json;
+
+    fn documents() -> Vec<(&'static str, Value)> {
+        vec![
+            (
+                "a",
+                json!({ "status": "open", "age": 5, "tags": ["x", "y"], "author": { "name": "ann" } }),
+            ),
+            (
+                "b",
+                json!({ "status": "closed", "age": 2, "tags": ["y"], "author": { "name": "bob" } }),
+            ),
+            ("c", json!({ "status": "open", "age": "5" })),
+            ("d", json!({ "status": null, "age": 7.5 })),
+            ("e", json!({ "age": true, "tags": [] })),
+            ("f", json!({ "status": "open", "age": 10, "author": {} })),
+            ("g", json!("scalar")),
+            ("h", json!([1, 2])),
+        ]
+    }
+
+    fn filters() -> Vec<Value> {
+        vec![
+            json!({}),
+            json!({ "status": "open" }),
+            json!({ "status": null }),
+            json!({ "status": { "$eq": null } }),
+            json!({ "status": { "$ne": "open" } }),
+            json!({ "status": { "$ne": null } }),
+            json!({ "age": 5 }),
+            json!({ "age": 5.0 }),
+            json!({ "age": { "$gt": 3 } }),
+            json!({ "age": { "$gte": "5" } }),
+            json!({ "age": { "$lt": 6 } }),
+            json!({ "age": { "$lte": 5, "$gt": 2 } }),
+            json!({ "age": { "$in": [2, "5", true] } }),
+            json!({ "age": { "$nin": [5, 7.5] } }),
+            json!({ "status": { "$exists": false } }),
+            json!({ "author.name": { "$exists": true } }),
+            json!({ "author.name": "ann" }),
+            json!({ "tags.0": "y" }),
+            json!({ "tags.1": "y" }),
+            json!({ "$or": [{ "age": 2 }, { "status": "open" }] }),
+            json!({ "$and": [{ "status": "open" }, { "age": { "$gt": 4 } }] }),
+        ]
+    }
+
+    fn matching_keys(filter: &Filter) -> Vec<String> {
+        documents()
+            .into_iter()
+            .filter(|(_, value)| filter.matches(value))
+            .map(|(key, _)| key.to_string())
+            .collect()
+    }
+
+    async fn found_keys(keyv: &Keyv, filter: &Filter) -> Vec<String> {
+        let found = keyv.find(FindQuery::new(filter.clone())).await.unwrap();
+        found.into_iter().map(|model| model.key).collect()
+    }
+
+    #[tokio::test]
+    async fn sql_filters_match_the_same_values_as_in_memory_filters() {
+        let store = KeyvStoreBuilder::new().uri(":memory:").build().await.unwrap();
+        let sqlite = Keyv::try_new(store).await.unwrap();
+        let memory = Keyv::default();
+
+        for keyv in [&sqlite, &memory] {
+            keyv.set_many(documents()).await.unwrap();
+        }
+
+        for source in filters() {
+            let filter = Filter::parse(source.clone()).unwrap();
+            let expected = matching_keys(&filter);
+
+            assert_eq!(found_keys(&sqlite, &filter).await, expected, "SQL filter {}", source);
+            assert_eq!(found_keys(&memory, &filter).await, expected, "memory filter {}", source);
+        }
+    }
+
+    #[test]
+    fn null_matches_missing_fields_and_ranges_keep_types_apart() {
+        // Values that are not objects have no fields at all.
+        let filter = Filter::parse(json!({ "status": null })).unwrap();
+        assert_eq!(matching_keys(&filter), ["d", "e", "g", "h"]);
+
+        let filter = Filter::parse(json!({ "age": { "$gt": 3 } })).unwrap();
+        assert_eq!(matching_keys(&filter), ["a", "d", "f"]);
+    }
+
+    #[test]
+    fn invalid_filters_are_rejected() {
+        for source in [
+            json!("open"),
+            json!({ "age": { "$regex": "5" } }),
+            json!({ "age; DROP TABLE keyv": 1 }),
+            json!({ "$or": { "age": 1 } }),
+        ] {
+            assert!(
+                matches!(Filter::parse(source.clone()), Err(StoreError::InvalidQuery(_))),
+                "{}",
+                source
+            );
+        }
+    }
+}
@@ -0,0 +1,516 @@
+// Copyright © 2024 Crabby Craft - All Rights Reserved.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use serde_json::Value;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::time::Duration;
+
+use super::{
+    ForwardingStore, ForwardingTransaction, Store, StoreError, StoreLayer, StoreModel, StoreTransaction, UpdateFn,
+    Written,
+};
+
+/// A write about to be made through a [`GuardedStore`].
+#[derive(Debug)]
+pub struct StoreWrite<'a> {
+    /// The namespace written to.
+    pub namespace: &'a str,
+    /// The name of the `Store` method, prefixed with `transaction.` for
+    /// writes made in a transaction.
+    pub operation: &'static str,
+    /// The key written, `None` for operations on a whole namespace.
+    pub key: Option<&'a str>,
+    /// The value about to be stored, `None` for removals and counters.
+    pub value: Option<&'a Value>,
+}
+
+/// Decides whether a write may go through a [`GuardedStore`].
+///
+/// Writes computed by the store, such as `update`, are checked twice: once
+/// before they start without a value, then with the value about to be stored.
+pub trait WriteGuard: Send + Sync + 'static {
+    fn check(&self, write: &StoreWrite<'_>) -> Result<(), StoreError>;
+}
+
+/// A store that checks every write with a [`WriteGuard`] before forwarding it.
+/// Reads are forwarded as is.
+pub struct GuardedStore<G> {
+    inner: Arc<dyn Store>,
+    guard: Arc<G>,
+}
+
+impl<G: WriteGuard> GuardedStore<G> {
+    pub fn new(inner: Arc<dyn Store>, guard: Arc<G>) -> Self {
+        Self { inner, guard }
+    }
+
+    fn check(&self, operation: &'static str, key: Option<&str>, value: Option<&Value>) -> Result<(), StoreError> {
+        self.guard.check(&StoreWrite {
+            namespace: self.inner.namespace_name(),
+            operation,
+            key,
+            value,
+        })
+    }
+}
+
+/// Returns a future failing with `error`, for writes rejected up front.
+fn rejected<'a, T: Send + 'a>(error: StoreError) -> Pin<Box<dyn Future<Output = Result<T, StoreError>> + Send + 'a>> {
+    Box::pin(async move { Err(error) })
+}
+
+impl<G: WriteGuard> ForwardingStore for GuardedStore<G> {
+    fn inner(&self) -> &dyn Store {
+        &*self.inner
+    }
+
+    fn set(
+        &self,
+        key: &str,
+        value: Value,
+        ttl: Option<u64>,
+    ) -> Pin<Box<dyn Future<Output = Result<Written<Option<StoreModel>>, StoreError>> + Send + '_>> {
+        match self.check("set", Some(key), Some(&value)) {
+            Ok(()) => self.inner.set(key, value, ttl),
+            Err(e) => rejected(e),
+        }
+    }
+
+    fn set_many(
+        &self,
+        entries: Vec<(String, Value)>,
+        ttl: Option<u64>,
+    ) -> Pin<Box<dyn Future<Output = Result<Written<()>, StoreError>> + Send + '_>> {
+        let checked = entries
+            .iter()
+            .try_for_each(|(key, value)| self.check("set_many", Some(key), Some(value)));
+
+        match checked {
+            Ok(()) => self.inner.set_many(entries, ttl),
+            Err(e) => rejected(e),
+        }
+    }
+
+    fn set_versioned(
+        &self,
+        key: &str,
+        value: Value,
+        expected_version: Option<u64>,
+        ttl: Option<u64>,
+    ) -> Pin<Box<dyn Future<Output = Result<Written<u64>, StoreError>> + Send + '_>> {
+        match self.check("set_versioned", Some(key), Some(&value)) {
+            Ok(()) => self.inner.set_versioned(key, value, expected_version, ttl),
+            Err(e) => rejected(e),
+        }
+    }
+
+    fn compare_and_swap(
+        &self,
+        key: &str,
+        expected: Option<Value>,
+        new: Value,
+    ) -> Pin<Box<dyn Future<Output = Result<Written<bool>, StoreError>> + Send + '_>> {
+        match self.check("compare_and_swap", Some(key), Some(&new)) {
+            Ok(()) => self.inner.compare_and_swap(key, expected, new),
+            Err(e) => rejected(e),
+        }
+    }
+
+    fn increment(
+        &self,
+        key: &str,
+        delta: i64,
+    ) -> Pin<Box<dyn Future<Output = Result<Written<i64>, StoreError>> + Send + '_>> {
+        match self.check("increment", Some(key), None) {
+            Ok(()) => self.inner.increment(key, delta),
+            Err(e) => rejected(e),
+        }
+    }
+
+    fn update<'a>(
+        &'a self,
+        key: &str,
+        f: UpdateFn<'a>,
+    ) -> Pin<Box<dyn Future<Output = Result<Written<Value>, StoreError>> + Send + 'a>> {
+        if let Err(e) = self.check("update", Some(key), None) {
+            return rejected(e);
+        }
+
+        let owned_key = key.to_string();
+        let f: UpdateFn<'a> = Box::new(move |current| {
+            let value = f(current)?;
+            self.check("update", Some(&owned_key), Some(&value))?;
+            Ok(value)
+        });
+
+        self.inner.update(key, f)
+    }
+
+    fn remove(&self, key: &str) -> Pin<Box<dyn Future<Output = Result<(), StoreError>> + Send + '_>> {
+        match self.check("remove", Some(key), None) {
+            Ok(()) => self.inner.remove(key),
+            Err(e) => rejected(e),
+        }
+    }
+
+    fn remove_many(&self, keys: &[&str]) -> Pin<Box<dyn Future<Output = Result<Vec<String>, StoreError>> + Send + '_>> {
+        match keys
+            .iter()
+            .try_for_each(|key| self.check("remove_many", Some(key), None))
+        {
+            Ok(()) => self.inner.remove_many(keys),
+            Err(e) => rejected(e),
+        }
+    }
+
+    fn clear(&self) -> Pin<Box<dyn Future<Output = Result<(), StoreError>> + Send + '_>> {
+        match self.check("clear", None, None) {
+            Ok(()) => self.inner.clear(),
+            Err(e) => rejected(e),
+        }
+    }
+
+    /// Purging only reclaims entries that are already hidden, so it is not guarded.
+    fn purge_expired(&self) -> Pin<Box<dyn Future<Output = Result<Vec<String>, StoreError>> + Send + '_>> {
+        self.inner.purge_expired()
+    }
+
+    fn set_history(&self, limit: Option<usize>) -> Pin<Box<dyn Future<Output = Result<(), StoreError>> + Send + '_>> {
+        match self.check("set_history", None, None) {
+            Ok(()) => self.inner.set_history(limit),
+            Err(e) => rejected(e),
+        }
+    }
+
+    fn rotate_encryption_key(
+        &self,
+        new_key: Option<[u8; 32]>,
+    ) -> Pin<Box<dyn Future<Output = Result<u64, StoreError>> + Send + '_>> {
+        match self.check("rotate_encryption_key", None, None) {
+            Ok(()) => self.inner.rotate_encryption_key(new_key),
+            Err(e) => rejected(e),
+        }
+    }
+
+    /// The restored value is checked too, as it may predate the guard.
+    fn revert(
+        &self,
+        key: &str,
+        id: i64,
+    ) -> Pin<Box<dyn Future<Output = Result<Written<StoreModel>, StoreError>> + Send + '_>> {
+        if let Err(e) = self.check("revert", Some(key), None) {
+            return rejected(e);
+        }
+
+        let key = key.to_string();
+
+        Box::pin(async move {
+            let revision = self.inner.get_revision(&key, id).await?.ok_or(StoreError::NotFound)?;
+            self.check("revert", Some(&key), Some(&revision.value))?;
+            self.inner.revert(&key, id).await
+        })
+    }
+
+    fn set_search(&self, enabled: bool) -> Pin<Box<dyn Future<Output = Result<(), StoreError>> + Send + '_>> {
+        match self.check("set_search", None, None) {
+            Ok(()) => self.inner.set_search(enabled),
+            Err(e) => rejected(e),
+        }
+    }
+
+    fn create_index(
+        &self,
+        name: &str,
+        path: &str,
+    ) -> Pin<Box<dyn Future<Output = Result<(), StoreError>> + Send + '_>> {
+        match self.check("create_index", None, None) {
+            Ok(()) => self.inner.create_index(name, path),
+            Err(e) => rejected(e),
+        }
+    }
+
+    fn drop_index(&self, name: &str) -> Pin<Box<dyn Future<Output = Result<(), StoreError>> + Send + '_>> {
+        match self.check("drop_index", None, None) {
+            Ok(()) => self.inner.drop_index(name),
+            Err(e) => rejected(e),
+        }
+    }
+
+    fn acquire_lease(
+        &self,
+        name: &str,
+        owner: &str,
+        ttl: Duration,
+    ) -> Pin<Box<dyn Future<Output = Result<bool, StoreError>> + Send + '_>> {
+        match self.check("acquire_lease", None, None) {
+            Ok(()) => self.inner.acquire_lease(name, owner, ttl),
+            Err(e) => rejected(e),
+        }
+    }
+
+    fn namespace(&self, name: &str) -> Result<Arc<dyn Store>, StoreError> {
+        Ok(Arc::new(GuardedStore {
+            inner: self.inner.namespace(name)?,
+            guard: Arc::clone(&self.guard),
+        }))
+    }
+
+    fn rename_namespace(
+        &self,
+        from: &str,
+        to: &str,
+    ) -> Pin<Box<dyn Future<Output = Result<(), StoreError>> + Send + '_>> {
+        let checked = self.guard.check(&StoreWrite {
+            namespace: from,
+            operation: "rename_namespace",
+            key: None,
+            value: None,
+        });
+
+        match checked {
+            Ok(()) => self.inner.rename_namespace(from, to),
+            Err(e) => rejected(e),
+        }
+    }
+
+    fn drop_namespace(&self, name: &str) -> Pin<Box<dyn Future<Output = Result<(), StoreError>> + Send + '_>> {
+        let checked = self.guard.check(&StoreWrite {
+            namespace: name,
+            operation: "drop_namespace",
+            key: None,
+            value: None,
+        });
+
+        match checked {
+            Ok(()) => self.inner.drop_namespace(name),
+            Err(e) => rejected(e),
+        }
+    }
+
+    fn begin(&self) -> Pin<Box<dyn Future<Output = Result<Box<dyn StoreTransaction>, StoreError>> + Send + '_>> {
+        Box::pin(async move {
+            let tx = self.inner.begin().await?;
+
+            Ok(Box::new(GuardedTransaction {
+                inner: tx,
+                guard: Arc::clone(&self.guard),
+            }) as Box<dyn StoreTransaction>)
+        })
+    }
+}
+
+/// A transaction that checks every write with the guard of its store.
+struct GuardedTransaction<G> {
+    inner: Box<dyn StoreTransaction>,
+    guard: Arc<G>,
+}
+
+impl<G: WriteGuard> ForwardingTransaction for GuardedTransaction<G> {
+    fn inner(&self) -> &dyn StoreTransaction {
+        &*self.inner
+    }
+
+    fn set(
+        &self,
+        namespace: &str,
+        key: &str,
+        value: Value,
+        ttl: Option<u64>,
+    ) -> Pin<Box<dyn Future<Output = Result<Written<()>, StoreError>> + Send + '_>> {
+        let checked = self.guard.check(&StoreWrite {
+            namespace,
+            operation: "transaction.set",
+            key: Some(key),
+            value: Some(&value),
+        });
+
+        match checked {
+            Ok(()) => self.inner.set(namespace, key, value, ttl),
+            Err(e) => rejected(e),
+        }
+    }
+
+    fn remove(&self, namespace: &str, key: &str) -> Pin<Box<dyn Future<Output = Result<(), StoreError>> + Send + '_>> {
+        let checked = self.guard.check(&StoreWrite {
+            namespace,
+            operation: "transaction.remove",
+            key: Some(key),
+            value: None,
+        });
+
+        match checked {
+            Ok(()) => self.inner.remove(namespace, key),
+            Err(e) => rejected(e),
+        }
+    }
+}
+
+/// Rejects every write with `StoreError::ReadOnly`, e.g. to hand a view of the
+/// store to code that must not modify it. Expired entries are still purged.
+#[derive(Clone, Default)]
+pub struct ReadOnlyLayer;
+
+impl ReadOnlyLayer {
+    pub fn new() -> Self {
+        Self
+    }
+}
+
+impl WriteGuard for ReadOnlyLayer {
+    fn check(&self, write: &StoreWrite<'_>) -> Result<(), StoreError> {
+        Err(StoreError::ReadOnly(write.operation.to_string()))
+    }
+}
+
+impl StoreLayer for ReadOnlyLayer {
+    fn layer(&self, inner: Arc<dyn Store>) -> Arc<dyn Store> {
+        Arc::new(GuardedStore::new(inner, Arc::new(self.clone())))
+    }
+}
+
+/// Validates every value before it is stored.
+///
+/// The validator receives the key and the value about to be stored, and its
+/// error is returned by the write. Removals and `increment` are not validated.
+///
+/// # Examples
+///
+/// ```rust,no_run
+/// # use tauri_plugin_localstore::keyv::{Keyv, StoreError, ValidationLayer};
+/// let keyv = Keyv::default().layer(ValidationLayer::new(|key, value| {
+///     if key.starts_with("settings.") && !value.is_object() {
+///         return Err(StoreError::InvalidValue(format!("{} must be an object", key)));
+///     }
+///     Ok(())
+/// }));
+/// ```
+#[derive(Clone)]
+pub struct ValidationLayer {
+    validator: Arc<Validator>,
+}
+
+/// Checks the value about to be stored for a key, used by [`ValidationLayer`].
+pub type Validator = dyn Fn(&str, &Value) -> Result<(), StoreError> + Send + Sync;
+
+impl ValidationLayer {
+    pub fn new<F>(validator: F) -> Self
+    where
+        F: Fn(&str, &Value) -> Result<(), StoreError> + Send + Sync + 'static,
+    {
+        Self {
+            validator: Arc::new(validator),
+        }
+    }
+}
+
+impl WriteGuard for ValidationLayer {
+    fn check(&self, write: &StoreWrite<'_>) -> Result<(), StoreError> {
+        match (write.key, write.value) {
+            (Some(key), Some(value)) => (self.validator)(key, value),
+            _ => Ok(()),
+        }
+    }
+}
+
+impl StoreLayer for ValidationLayer {
+    fn layer(&self, inner: Arc<dyn Store>) -> Arc<dyn Store> {
+        Arc::new(GuardedStore::new(inner, Arc::new(self.clone())))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::keyv::{Keyv, KeyvError};
+    use serde_json::json;
+
+    fn assert_read_only<T>(result: Result<T, KeyvError>) {
+        match result {
+            Err(KeyvError::StoreError(StoreError::ReadOnly(_))) => {}
+            Err(e) => panic!("unexpected error: {}", e),
+            Ok(_) => panic!("the write was accepted"),
+        }
+    }
+
+    #[tokio::test]
+    async fn read_only_rejects_every_write() {
+        let keyv = Keyv::default();
+        keyv.set("a", 1).await.unwrap();
+        let read_only = keyv.clone().layer(ReadOnlyLayer::new());
+
+        assert_eq!(read_only.get("a").await.unwrap(), Some(json!(1)));
+        assert_eq!(read_only.list().await.unwrap().len(), 1);
+
+        assert_read_only(read_only.set("a", 2).await);
+        assert_read_only(read_only.set_with_ttl("b", 2, 60).await);
+        assert_read_only(read_only.set_many([("b", 2)]).await);
+        assert_read_only(read_only.set_versioned("a", 2, Some(1)).await);
+        assert_read_only(read_only.compare_and_swap("a", Some(1), 2).await);
+        assert_read_only(read_only.increment("a", 1).await);
+        assert_read_only(read_only.update("a", |a: Option<i64>| a.unwrap_or(0) + 1).await);
+        assert_read_only(read_only.remove("a").await);
+        assert_read_only(read_only.remove_many(&["a"]).await);
+        assert_read_only(read_only.clear().await);
+        assert_read_only(read_only.enable_history(5).await);
+        assert_read_only(read_only.disable_history().await);
+        assert_read_only(read_only.revert("a", 1).await);
+        assert_read_only(read_only.rotate_encryption_key(None).await);
+        assert_read_only(read_only.enable_search().await);
+        assert_read_only(read_only.create_index("by_name", "$.name").await);
+        assert_read_only(read_only.drop_index("by_name").await);
+        assert_read_only(read_only.try_acquire_lock("job", Duration::from_secs(1)).await);
+        assert_read_only(read_only.rename_namespace("kv_store", "renamed").await);
+        assert_read_only(read_only.drop_namespace("kv_store").await);
+        assert_read_only(read_only.namespace("other").unwrap().set("b", 2).await);
+        assert_read_only(read_only.transaction(|tx| async move { tx.set("b", 2).await }).await);
+        assert_read_only(read_only.transaction(|tx| async move { tx.remove("a").await }).await);
+
+        assert_eq!(keyv.get("a").await.unwrap(), Some(json!(1)));
+        assert_eq!(keyv.list().await.unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn validation_checks_the_values_about_to_be_stored() {
+        let keyv = Keyv::default().layer(ValidationLayer::new(|key, value| {
+            if key.starts_with("settings.") && !value.is_object() {
+                return Err(StoreError::InvalidValue(format!("{} must be an object", key)));
+            }
+            Ok(())
+        }));
+        let rejected = |result: Result<(), KeyvError>| {
+            assert!(matches!(
+                result,
+                Err(KeyvError::StoreError(StoreError::InvalidValue(_)))
+            ))
+        };
+
+        keyv.set("settings.ui", json!({ "theme": "dark" })).await.unwrap();
+        keyv.set("name", "tauri").await.unwrap();
+        rejected(keyv.set("settings.ui", 1).await.map(|_| ()));
+        rejected(keyv.set_many([("other", json!(1)), ("settings.x", json!(1))]).await);
+        assert_eq!(keyv.get("other").await.unwrap(), None);
+
+        // Computed values are checked once known.
+        rejected(
+            keyv.update("settings.ui", |_: Option<Value>| json!(2))
+                .await
+                .map(|_| ()),
+        );
+        assert_eq!(keyv.get("settings.ui").await.unwrap(), Some(json!({ "theme": "dark" })));
+        rejected(
+            keyv.transaction(|tx| async move { tx.set("settings.tx", 1).await })
+                .await,
+        );
+
+        // Removals and counters are not validated.
+        assert_eq!(keyv.increment("settings.count", 1).await.unwrap(), 1);
+        keyv.remove("settings.ui").await.unwrap();
+    }
+}
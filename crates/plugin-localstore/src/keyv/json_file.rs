@@ -0,0 +1,487 @@
+// Copyright © 2024 Crabby Craft - All Rights Reserved.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use serde_json::{Map, Value};
+use std::collections::HashMap;
+use std::future::Future;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::sync::Arc;
+use std::time::Instant;
+use tokio::io::AsyncWriteExt;
+use tokio::sync::Mutex;
+
+use crate::keyv::{
+    unix_millis, ForwardingStore, ForwardingTransaction, MemoryEntry, MemoryState, MemoryStore, Store, StoreError,
+    StoreModel, StoreTransaction, UpdateFn, Written,
+};
+
+/// Top-level key of the file holding the expiry of every entry that has one.
+///
+/// Namespace names cannot start with `$`, so it never clashes with a namespace.
+const EXPIRES_AT_KEY: &str = "$expires_at";
+
+/// A `Store` that keeps every namespace in a single JSON file.
+///
+/// The file maps each namespace to an object of its keys and values, so it is
+/// easy to read and edit by hand:
+///
+/// ```json
+/// {
+///   "kv_store": { "theme": "dark", "font_size": 14 }
+/// }
+/// ```
+///
+/// Entries with a TTL also get their expiry, in milliseconds since the Unix
+/// epoch, under a top-level `"$expires_at"` object. Row versions are not
+/// persisted and start again at 1 when the file is opened.
+///
+/// Reads are served from memory. Every write rewrites the whole file through a
+/// temporary file that is renamed over the original, so the file on disk is
+/// never left half written.
+///
+/// # Examples
+///
+/// ```rust,no_run
+/// # use tauri_plugin_localstore::keyv::{JsonFileStore, Keyv};
+/// # async fn run() -> Result<(), Box<dyn std::error::Error>> {
+/// let store = JsonFileStore::open("config.json").await?;
+/// let keyv = Keyv::try_new(store).await?;
+///
+/// keyv.set("theme", "dark").await?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone)]
+pub struct JsonFileStore {
+    inner: MemoryStore,
+    file: Arc<JsonFile>,
+}
+
+impl JsonFileStore {
+    /// Opens the store backed by the file at `path`, which is created on the
+    /// first write if it does not exist yet.
+    ///
+    /// # Returns
+    /// This method returns a `Result` which, on success, contains the store.
+    /// On failure, it returns a `StoreError` if the file cannot be read or is not
+    /// a valid store file.
+    pub async fn open<P: AsRef<Path>>(path: P) -> Result<Self, StoreError> {
+        let path = path.as_ref().to_path_buf();
+
+        let state = match tokio::fs::read(&path).await {
+            Ok(contents) => decode_file(&contents)?,
+            Err(e) if e.kind() == ErrorKind::NotFound => MemoryState::new(),
+            Err(e) => {
+                return Err(StoreError::DatabaseError { source: Box::new(e) });
+            }
+        };
+
+        log::debug!("JSON file store opened: {}", path.display());
+
+        Ok(Self {
+            inner: MemoryStore::from_state(state),
+            file: Arc::new(JsonFile {
+                path,
+                lock: Mutex::new(()),
+            }),
+        })
+    }
+
+    fn scoped(&self, inner: MemoryStore) -> Self {
+        Self {
+            inner,
+            file: Arc::clone(&self.file),
+        }
+    }
+
+    /// Writes the current state of every namespace to the file.
+    async fn save(&self) -> Result<(), StoreError> {
+        self.file.save(&self.inner).await
+    }
+}
+
+/// The file behind a [`JsonFileStore`], shared by all of its namespaces.
+struct JsonFile {
+    path: PathBuf,
+    /// Serializes saves, so an older snapshot never overwrites a newer one.
+    lock: Mutex<()>,
+}
+
+impl JsonFile {
+    async fn save(&self, store: &MemoryStore) -> Result<(), StoreError> {
+        let _guard = self.lock.lock().await;
+
+        let start = Instant::now();
+
+        // Taken after the lock, so it includes every write that finished before.
+        let contents = encode_file(&store.snapshot().await)?;
+
+        let mut temp_name = self.path.file_name().unwrap_or_default().to_os_string();
+        temp_name.push(".tmp");
+        let temp_path = self.path.with_file_name(temp_name);
+
+        let write = async {
+            let mut file = tokio::fs::File::create(&temp_path).await?;
+            file.write_all(&contents).await?;
+            file.sync_all().await?;
+            tokio::fs::rename(&temp_path, &self.path).await
+        };
+
+        write
+            .await
+            .map_err(|e| StoreError::DatabaseError { source: Box::new(e) })?;
+
+        log::debug!(
+            "JSON file store saved: {:?} | {} | {} bytes",
+            start.elapsed(),
+            self.path.display(),
+            contents.len()
+        );
+
+        Ok(())
+    }
+}
+
+fn decode_file(contents: &[u8]) -> Result<MemoryState, StoreError> {
+    let mut root: Map<String, Value> = serde_json::from_slice(contents)?;
+
+    let expiries: HashMap<String, HashMap<String, i64>> = match root.remove(EXPIRES_AT_KEY) {
+        Some(expiries) => serde_json::from_value(expiries)?,
+        None => HashMap::new(),
+    };
+
+    // The file does not record timestamps, so entries are dated from when it is loaded.
+    let loaded_at = unix_millis();
+    let mut state = MemoryState::new();
+
+    for (namespace, entries) in root {
+        let entries = match entries {
+            Value::Object(entries) => entries,
+            _ => {
+                return Err(StoreError::InvalidValue(format!(
+                    "Namespace {} in the store file is not an object",
+                    namespace
+                )))
+            }
+        };
+
+        let namespace_expiries = expiries.get(&namespace);
+
+        let entries = entries
+            .into_iter()
+            .map(|(key, value)| {
+                let expires_at = namespace_expiries.and_then(|e| e.get(&key)).copied();
+                (
+                    key,
+                    MemoryEntry {
+                        value,
+                        expires_at,
+                        version: 1,
+                        created_at: loaded_at,
+                        updated_at: loaded_at,
+                    },
+                )
+            })
+            .collect();
+
+        state.insert(namespace, entries);
+    }
+
+    Ok(state)
+}
+
+fn encode_file(state: &MemoryState) -> Result<Vec<u8>, StoreError> {
+    let mut root = Map::new();
+    let mut expiries = Map::new();
+
+    for (namespace, entries) in state {
+        let mut values = Map::new();
+        let mut namespace_expiries = Map::new();
+
+        for (key, entry) in entries {
+            values.insert(key.clone(), entry.value.clone());
+
+            if let Some(expires_at) = entry.expires_at {
+                namespace_expiries.insert(key.clone(), Value::from(expires_at));
+            }
+        }
+
+        root.insert(namespace.clone(), Value::Object(values));
+
+        if !namespace_expiries.is_empty() {
+            expiries.insert(namespace.clone(), Value::Object(namespace_expiries));
+        }
+    }
+
+    if !expiries.is_empty() {
+        root.insert(EXPIRES_AT_KEY.to_string(), Value::Object(expiries));
+    }
+
+    Ok(serde_json::to_vec_pretty(&root)?)
+}
+
+impl ForwardingStore for JsonFileStore {
+    fn inner(&self) -> &dyn Store {
+        &self.inner
+    }
+
+    fn set(
+        &self,
+        key: &str,
+        value: Value,
+        ttl: Option<u64>,
+    ) -> Pin<Box<dyn Future<Output = Result<Written<Option<StoreModel>>, StoreError>> + Send + '_>> {
+        let write = self.inner.set(key, value, ttl);
+
+        Box::pin(async move {
+            let result = write.await?;
+            self.save().await?;
+            Ok(result)
+        })
+    }
+
+    fn set_many(
+        &self,
+        entries: Vec<(String, Value)>,
+        ttl: Option<u64>,
+    ) -> Pin<Box<dyn Future<Output = Result<Written<()>, StoreError>> + Send + '_>> {
+        let write = self.inner.set_many(entries, ttl);
+
+        Box::pin(async move {
+            let written = write.await?;
+            self.save().await?;
+            Ok(written)
+        })
+    }
+
+    fn set_versioned(
+        &self,
+        key: &str,
+        value: Value,
+        expected_version: Option<u64>,
+        ttl: Option<u64>,
+    ) -> Pin<Box<dyn Future<Output = Result<Written<u64>, StoreError>> + Send + '_>> {
+        let write = self.inner.set_versioned(key, value, expected_version, ttl);
+
+        Box::pin(async move {
+            let version = write.await?;
+            self.save().await?;
+            Ok(version)
+        })
+    }
+
+    fn compare_and_swap(
+        &self,
+        key: &str,
+        expected: Option<Value>,
+        new: Value,
+    ) -> Pin<Box<dyn Future<Output = Result<Written<bool>, StoreError>> + Send + '_>> {
+        let write = self.inner.compare_and_swap(key, expected, new);
+
+        Box::pin(async move {
+            let swapped = write.await?;
+            if swapped.value {
+                self.save().await?;
+            }
+            Ok(swapped)
+        })
+    }
+
+    fn increment(
+        &self,
+        key: &str,
+        delta: i64,
+    ) -> Pin<Box<dyn Future<Output = Result<Written<i64>, StoreError>> + Send + '_>> {
+        let write = self.inner.increment(key, delta);
+
+        Box::pin(async move {
+            let value = write.await?;
+            self.save().await?;
+            Ok(value)
+        })
+    }
+
+    fn update<'a>(
+        &'a self,
+        key: &str,
+        f: UpdateFn<'a>,
+    ) -> Pin<Box<dyn Future<Output = Result<Written<Value>, StoreError>> + Send + 'a>> {
+        let write = self.inner.update(key, f);
+
+        Box::pin(async move {
+            let value = write.await?;
+            self.save().await?;
+            Ok(value)
+        })
+    }
+
+    fn remove(&self, key: &str) -> Pin<Box<dyn Future<Output = Result<(), StoreError>> + Send + '_>> {
+        let write = self.inner.remove(key);
+
+        Box::pin(async move {
+            write.await?;
+            self.save().await
+        })
+    }
+
+    fn remove_many(&self, keys: &[&str]) -> Pin<Box<dyn Future<Output = Result<Vec<String>, StoreError>> + Send + '_>> {
+        let write = self.inner.remove_many(keys);
+
+        Box::pin(async move {
+            let removed = write.await?;
+            self.save().await?;
+            Ok(removed)
+        })
+    }
+
+    fn clear(&self) -> Pin<Box<dyn Future<Output = Result<(), StoreError>> + Send + '_>> {
+        let write = self.inner.clear();
+
+        Box::pin(async move {
+            write.await?;
+            self.save().await
+        })
+    }
+
+    fn purge_expired(&self) -> Pin<Box<dyn Future<Output = Result<Vec<String>, StoreError>> + Send + '_>> {
+        let write = self.inner.purge_expired();
+
+        Box::pin(async move {
+            let purged = write.await?;
+            if !purged.is_empty() {
+                self.save().await?;
+            }
+            Ok(purged)
+        })
+    }
+
+    fn namespace(&self, name: &str) -> Result<Arc<dyn Store>, StoreError> {
+        Ok(Arc::new(self.scoped(self.inner.scoped_namespace(name)?)))
+    }
+
+    fn rename_namespace(
+        &self,
+        from: &str,
+        to: &str,
+    ) -> Pin<Box<dyn Future<Output = Result<(), StoreError>> + Send + '_>> {
+        let write = self.inner.rename_namespace(from, to);
+
+        Box::pin(async move {
+            write.await?;
+            self.save().await
+        })
+    }
+
+    fn drop_namespace(&self, name: &str) -> Pin<Box<dyn Future<Output = Result<(), StoreError>> + Send + '_>> {
+        let write = self.inner.drop_namespace(name);
+
+        Box::pin(async move {
+            write.await?;
+            self.save().await
+        })
+    }
+
+    fn begin(&self) -> Pin<Box<dyn Future<Output = Result<Box<dyn StoreTransaction>, StoreError>> + Send + '_>> {
+        Box::pin(async move {
+            let inner = self.inner.begin().await?;
+
+            Ok(Box::new(JsonFileTransaction {
+                inner,
+                store: self.clone(),
+            }) as Box<dyn StoreTransaction>)
+        })
+    }
+}
+
+/// A transaction over a [`JsonFileStore`], which saves the file once committed.
+struct JsonFileTransaction {
+    inner: Box<dyn StoreTransaction>,
+    store: JsonFileStore,
+}
+
+impl ForwardingTransaction for JsonFileTransaction {
+    fn inner(&self) -> &dyn StoreTransaction {
+        &*self.inner
+    }
+
+    fn commit(&self) -> Pin<Box<dyn Future<Output = Result<(), StoreError>> + Send + '_>> {
+        Box::pin(async move {
+            self.inner.commit().await?;
+            self.store.save().await
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::keyv::Keyv;
+    use serde_json::json;
+
+    #[tokio::test]
+    async fn writes_are_reloaded_when_the_file_is_reopened() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("store.json");
+
+        let keyv = Keyv::try_new(JsonFileStore::open(&path).await.unwrap()).await.unwrap();
+        keyv.set("theme", "dark").await.unwrap();
+        keyv.set_with_ttl("session", "abc", 3600).await.unwrap();
+        keyv.set_with_ttl("expired", true, 0).await.unwrap();
+        keyv.namespace("prefs").unwrap().set("font_size", 14).await.unwrap();
+        let expires_at = keyv.get_with_meta("session").await.unwrap().unwrap().expires_at;
+
+        // Saves go through a temporary file that is renamed over the store file.
+        let files: Vec<_> = std::fs::read_dir(dir.path())
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name())
+            .collect();
+        assert_eq!(files, ["store.json"]);
+        let contents: Value = serde_json::from_slice(&std::fs::read(&path).unwrap()).unwrap();
+        assert_eq!(contents["prefs"], json!({ "font_size": 14 }));
+
+        let reopened = Keyv::try_new(JsonFileStore::open(&path).await.unwrap()).await.unwrap();
+        assert_eq!(reopened.get("theme").await.unwrap(), Some(json!("dark")));
+        assert_eq!(
+            reopened.get_with_meta("session").await.unwrap().unwrap().expires_at,
+            expires_at
+        );
+        assert_eq!(reopened.get("expired").await.unwrap(), None);
+        assert_eq!(
+            reopened.namespace("prefs").unwrap().get("font_size").await.unwrap(),
+            Some(json!(14))
+        );
+
+        assert_eq!(reopened.purge_expired().await.unwrap(), 1);
+        let reopened = Keyv::try_new(JsonFileStore::open(&path).await.unwrap()).await.unwrap();
+        let keys: Vec<String> = reopened
+            .list()
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|model| model.key)
+            .collect();
+        assert_eq!(keys, ["session", "theme"]);
+    }
+
+    #[tokio::test]
+    async fn invalid_files_are_rejected() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("store.json");
+
+        std::fs::write(&path, r#"{ "kv_store": [1, 2] }"#).unwrap();
+        assert!(matches!(
+            JsonFileStore::open(&path).await,
+            Err(StoreError::InvalidValue(_))
+        ));
+
+        std::fs::write(&path, "not json").unwrap();
+        assert!(JsonFileStore::open(&path).await.is_err());
+    }
+}
@@ -13,59 +13,85 @@
  * Credits to Alexandru Bereghici: https://github.com/chrisllontop/keyv-rust
  */
 
+use serde::de::DeserializeOwned;
 use serde::Serialize;
 use serde_json::Value;
-use std::{path::Path, sync::Arc};
+use std::collections::{HashMap, HashSet};
+use std::future::Future;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::sync::broadcast;
+use tokio_stream::{Stream, StreamExt};
 
-use super::{KeyvError, KeyvStoreBuilder, Store, StoreError, StoreModel};
+use super::event::{change_stream, CHANGE_CHANNEL_CAPACITY};
+use super::export::{ExportReader, ExportWriter, EXPORT_PAGE_SIZE};
+use super::{
+    lease_owner, unix_millis, validate_lease_ttl, CacheStats, Collection, ExportEntry, ExportFormat, FindQuery,
+    GuardedStore, ImportMode, ImportReport, KeyvChange, KeyvChangeKind, KeyvError, KeyvTransaction, ListPage,
+    ListQuery, LockGuard, MemoryStore, Revision, SchemaRegistry, SchemaTarget, SearchHit, SearchQuery, Store,
+    StoreError, StoreLayer, StoreModel, StoreTransaction, VersionedValue, LOCK_RETRY_INTERVAL,
+};
 
 pub(super) const DEFAULT_NAMESPACE_NAME: &str = "kv_store";
 
 /// Key-Value Store Interface
 ///
-/// Provides an synchronous interface to a key-value store. This implementation
+/// Provides an asynchronous interface to a key-value store. This implementation
 /// allows for setting, getting, removing, and clearing key-value pairs in a
 /// datastore with an optional Time-to-Live (TTL) for keys.
 ///
 /// The `Keyv` struct is generic over any implementation of the `Store` trait,
-/// thus can be backed by various storage engines.
+/// thus can be backed by various storage engines. Cloning a `Keyv` is cheap,
+/// every clone shares the same underlying store.
+///
+/// Every write made through a `Keyv`, or through any namespace handle derived
+/// from it, is published as a [`KeyvChange`] that can be observed with `watch`,
+/// `watch_prefix` or `changes`.
 ///
 /// # Examples
 ///
 /// ## Create a new instance with in-memory store
 ///
-/// ```
-/// # use keyv::Keyv;
+/// ```rust,no_run
+/// # use tauri_plugin_localstore::keyv::Keyv;
 /// let keyv = Keyv::default();
 /// ```
 ///
 /// ## Set and get a value
 ///
-/// ```
-/// # use keyv::Keyv;
+/// ```rust,no_run
+/// # use tauri_plugin_localstore::keyv::Keyv;
+/// # async fn run() -> Result<(), Box<dyn std::error::Error>> {
 /// let keyv = Keyv::default();
 ///
-/// keyv.set("array", vec!["hola", "test"]).unwrap();
+/// keyv.set("array", vec!["hola", "test"]).await?;
 ///
-/// match keyv.get("array").unwrap() {
+/// match keyv.get("array").await? {
 ///     Some(array) => {
-///         let array: Vec<String> = serde_json::from_value(array).unwrap();
+///         let array: Vec<String> = serde_json::from_value(array)?;
 ///         assert_eq!(array, vec!["hola".to_string(), "test".to_string()])
 ///     }
 ///     None => assert!(false),
 /// }
 ///
-/// keyv.set("string", "life long").unwrap();
-/// match keyv.get("string").unwrap() {
+/// keyv.set("string", "life long").await?;
+/// match keyv.get("string").await? {
 ///     Some(string) => {
-///         let string: String = serde_json::from_value(string).unwrap();
+///         let string: String = serde_json::from_value(string)?;
 ///         assert_eq!(string, "life long");
 ///     }
 ///     None => assert!(false),
 /// }
+/// # Ok(())
+/// # }
 /// ```
+#[derive(Clone)]
 pub struct Keyv {
     store: Arc<dyn Store>,
+    changes: broadcast::Sender<KeyvChange>,
+    schemas: Arc<SchemaRegistry>,
 }
 
 impl Keyv {
@@ -84,21 +110,85 @@ impl Keyv {
     ///
     /// # Examples
     ///
-    /// ```
-    /// # use keyv::{Keyv};
-    /// # use keyv::{KeyvStoreBuilder};
-    ///
+    /// ```rust,no_run
+    /// # use tauri_plugin_localstore::keyv::{Keyv, KeyvStoreBuilder};
+    /// # async fn run() -> Result<(), Box<dyn std::error::Error>> {
     /// let store = KeyvStoreBuilder::new()
-    ///     .uri("sqlite::memory:")
+    ///     .uri(":memory:")
     ///     .table_name("custom_table_name")
     ///     .build()
-    ///     .unwrap();
+    ///     .await?;
     ///
-    /// let keyv = Keyv::try_new(store).unwrap();
+    /// let keyv = Keyv::try_new(store).await?;
+    /// # Ok(())
+    /// # }
     /// ```
     pub async fn try_new<S: Store + 'static>(store: S) -> Result<Self, KeyvError> {
         store.initialize().await?;
-        Ok(Self { store: Arc::new(store) })
+        Ok(Self::from_store(Arc::new(store)))
+    }
+
+    fn from_store(store: Arc<dyn Store>) -> Self {
+        let (changes, _) = broadcast::channel(CHANGE_CHANNEL_CAPACITY);
+
+        // Every write goes through the schema registry, which lets values through
+        // untouched until a schema is registered.
+        let schemas = Arc::new(SchemaRegistry::default());
+        let store = Arc::new(GuardedStore::new(store, Arc::clone(&schemas)));
+
+        Self {
+            store,
+            changes,
+            schemas,
+        }
+    }
+
+    /// Wraps the store of this instance with a layer.
+    ///
+    /// Each call wraps the store built so far, so the last layer added sees
+    /// every call first. Namespaces and transactions opened from the returned
+    /// instance go through the layer too, while clones made before the call
+    /// do not.
+    ///
+    /// # Arguments
+    ///
+    /// * `layer` - The layer to apply, such as `LoggingLayer`, `MetricsLayer`,
+    ///   `PrefixLayer`, `ValidationLayer` or `ReadOnlyLayer`.
+    ///
+    /// # Examples
+    ///
+    /// ```rust,no_run
+    /// # use tauri_plugin_localstore::keyv::{Keyv, LoggingLayer, ReadOnlyLayer};
+    /// # async fn run(keyv: Keyv) -> Result<(), Box<dyn std::error::Error>> {
+    /// let view = keyv.clone().layer(ReadOnlyLayer::new()).layer(LoggingLayer::new());
+    ///
+    /// assert!(view.set("key", "value").await.is_err());
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn layer<L: StoreLayer>(self, layer: L) -> Self {
+        Self {
+            store: layer.layer(self.store),
+            changes: self.changes,
+            schemas: self.schemas,
+        }
+    }
+
+    /// Publishes a change to every subscriber. Having no subscribers is not an error.
+    fn publish(&self, kind: KeyvChangeKind, key: Option<&str>, value: Option<Value>) {
+        let _ = self.changes.send(KeyvChange {
+            namespace: self.store.namespace_name().to_string(),
+            kind,
+            key: key.map(|k| k.to_string()),
+            value,
+        });
+    }
+
+    /// Publishes a remove change for each key evicted by a write.
+    fn publish_evicted(&self, evicted: &[String]) {
+        for key in evicted {
+            self.publish(KeyvChangeKind::Remove, Some(key), None);
+        }
     }
 
     /// Sets a value for a given key without a TTL.
@@ -114,18 +204,27 @@ impl Keyv {
     ///
     /// # Examples
     ///
-    /// ```
-    /// # use keyv::Keyv;
+    /// ```rust,no_run
+    /// # use tauri_plugin_localstore::keyv::Keyv;
+    /// # async fn run() -> Result<(), Box<dyn std::error::Error>> {
     /// let keyv = Keyv::default();
-    /// keyv.set("key", "hello world").unwrap();
+    /// keyv.set("key", "hello world").await?;
+    /// # Ok(())
+    /// # }
     /// ```
     pub async fn set<T: Serialize>(&self, key: &str, value: T) -> Result<Option<StoreModel>, KeyvError> {
         let json_value = serde_json::to_value(value).map_err(|e| StoreError::SerializationError { source: e })?;
-        Ok(self.store.set(key, json_value, None).await?)
+        let written = self.store.set(key, json_value.clone(), None).await?;
+        self.publish(KeyvChangeKind::Set, Some(key), Some(json_value));
+        self.publish_evicted(&written.evicted);
+        Ok(written.value)
     }
 
     /// Sets a value for a given key with an expiry TTL (Time-To-Live).
     ///
+    /// Once the TTL has elapsed the key is no longer returned by `get` or `list`,
+    /// and it is permanently deleted by the next call to `purge_expired`.
+    ///
     /// # Arguments
     ///
     /// * `key` - A string slice that holds the key.
@@ -138,10 +237,13 @@ impl Keyv {
     ///
     /// # Examples
     ///
-    /// ```
-    /// # use keyv::Keyv;
+    /// ```rust,no_run
+    /// # use tauri_plugin_localstore::keyv::Keyv;
+    /// # async fn run() -> Result<(), Box<dyn std::error::Error>> {
     /// let keyv = Keyv::default();
-    /// keyv.set_with_ttl("temp_key", "temp_value", 3600).unwrap(); // Expires in 1 hour
+    /// keyv.set_with_ttl("temp_key", "temp_value", 3600).await?; // Expires in 1 hour
+    /// # Ok(())
+    /// # }
     /// ```
     pub async fn set_with_ttl<T: Serialize>(
         &self,
@@ -150,7 +252,279 @@ impl Keyv {
         ttl: u64,
     ) -> Result<Option<StoreModel>, KeyvError> {
         let json_value = serde_json::to_value(value).map_err(|e| StoreError::SerializationError { source: e })?;
-        Ok(self.store.set(key, json_value, Some(ttl)).await?)
+        let written = self.store.set(key, json_value.clone(), Some(ttl)).await?;
+        self.publish(KeyvChangeKind::Set, Some(key), Some(json_value));
+        self.publish_evicted(&written.evicted);
+        Ok(written.value)
+    }
+
+    /// Sets several values in a single transaction, without a TTL.
+    ///
+    /// Either every entry is written or, if any write fails, none of them are.
+    ///
+    /// # Arguments
+    ///
+    /// * `entries` - The key-value pairs to store. Values must implement `Serialize`.
+    ///
+    /// # Returns
+    ///
+    /// Returns an `Ok` result if every entry has been stored, or a `KeyvError` on failure.
+    ///
+    /// # Examples
+    ///
+    /// ```rust,no_run
+    /// # use tauri_plugin_localstore::keyv::Keyv;
+    /// # async fn run() -> Result<(), Box<dyn std::error::Error>> {
+    /// let keyv = Keyv::default();
+    /// keyv.set_many([("theme", "dark"), ("locale", "en")]).await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn set_many<I, K, V>(&self, entries: I) -> Result<(), KeyvError>
+    where
+        I: IntoIterator<Item = (K, V)>,
+        K: Into<String>,
+        V: Serialize,
+    {
+        let entries = entries
+            .into_iter()
+            .map(|(key, value)| {
+                let value = serde_json::to_value(value).map_err(|e| StoreError::SerializationError { source: e })?;
+                Ok((key.into(), value))
+            })
+            .collect::<Result<Vec<(String, Value)>, StoreError>>()?;
+
+        let written = self.store.set_many(entries.clone(), None).await?;
+
+        for (key, value) in entries {
+            self.publish(KeyvChangeKind::Set, Some(&key), Some(value));
+        }
+        self.publish_evicted(&written.evicted);
+
+        Ok(())
+    }
+
+    /// Retrieves a value together with its metadata: its version, when it was
+    /// created and last updated, and when it expires.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - A string slice that holds the key to retrieve the value for.
+    ///
+    /// # Returns
+    ///
+    /// Returns an `Ok` result with `Option<StoreModel>` on success, where `None`
+    /// indicates the key does not exist or has expired, or a `KeyvError` on failure.
+    ///
+    /// # Examples
+    ///
+    /// ```rust,no_run
+    /// # use tauri_plugin_localstore::keyv::Keyv;
+    /// # async fn run(keyv: Keyv) -> Result<(), Box<dyn std::error::Error>> {
+    /// if let Some(model) = keyv.get_with_meta("draft").await? {
+    ///     println!("revision {} saved at {}", model.version, model.updated_at);
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn get_with_meta(&self, key: &str) -> Result<Option<StoreModel>, KeyvError> {
+        Ok(self.store.get_with_meta(key).await?)
+    }
+
+    /// Retrieves a value together with its version, for use with `set_versioned`.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - A string slice that holds the key to retrieve the value for.
+    ///
+    /// # Returns
+    ///
+    /// Returns an `Ok` result with `Option<VersionedValue>` on success, where `None`
+    /// indicates the key does not exist or has expired, or a `KeyvError` on failure.
+    pub async fn get_versioned(&self, key: &str) -> Result<Option<VersionedValue>, KeyvError> {
+        Ok(self.store.get_versioned(key).await?)
+    }
+
+    /// Sets a value only if nobody has written the key since it was read.
+    ///
+    /// This allows optimistic concurrency: read the value with `get_versioned`,
+    /// compute the new value, then write it back with the version that was read.
+    /// If another window wrote the key in the meantime the write is rejected and
+    /// can be retried.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The key under which the value is stored.
+    /// * `value` - The value to store. Must implement `Serialize`.
+    /// * `expected_version` - The version that was read, or `None` if the key must not exist yet.
+    ///
+    /// # Returns
+    ///
+    /// Returns the new version on success. Returns `StoreError::VersionConflict` wrapped
+    /// in a `KeyvError` if the key is no longer at `expected_version`.
+    ///
+    /// # Examples
+    ///
+    /// ```rust,no_run
+    /// # use tauri_plugin_localstore::keyv::Keyv;
+    /// # async fn run() -> Result<(), Box<dyn std::error::Error>> {
+    /// let keyv = Keyv::default();
+    ///
+    /// let current = keyv.get_versioned("settings").await?;
+    /// let version = current.as_ref().map(|c| c.version);
+    ///
+    /// keyv.set_versioned("settings", serde_json::json!({ "theme": "dark" }), version).await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn set_versioned<T: Serialize>(
+        &self,
+        key: &str,
+        value: T,
+        expected_version: Option<u64>,
+    ) -> Result<u64, KeyvError> {
+        let json_value = serde_json::to_value(value).map_err(|e| StoreError::SerializationError { source: e })?;
+        let written = self
+            .store
+            .set_versioned(key, json_value.clone(), expected_version, None)
+            .await?;
+        self.publish(KeyvChangeKind::Set, Some(key), Some(json_value));
+        self.publish_evicted(&written.evicted);
+        Ok(written.value)
+    }
+
+    /// Atomically replaces the value of `key` if it currently equals `expected`.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The key to update.
+    /// * `expected` - The value the key must hold, or `None` if the key must not exist.
+    /// * `new` - The value to store.
+    ///
+    /// # Returns
+    ///
+    /// Returns `true` if the value was replaced and `false` if it did not match, or a
+    /// `KeyvError` on failure.
+    ///
+    /// # Examples
+    ///
+    /// ```rust,no_run
+    /// # use tauri_plugin_localstore::keyv::Keyv;
+    /// # async fn run() -> Result<(), Box<dyn std::error::Error>> {
+    /// let keyv = Keyv::default();
+    ///
+    /// // Only the first window to get here becomes the leader.
+    /// let leader = keyv.compare_and_swap("leader", None, "main").await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn compare_and_swap<T: Serialize>(
+        &self,
+        key: &str,
+        expected: Option<T>,
+        new: T,
+    ) -> Result<bool, KeyvError> {
+        let expected = expected
+            .map(serde_json::to_value)
+            .transpose()
+            .map_err(|e| StoreError::SerializationError { source: e })?;
+        let json_value = serde_json::to_value(new).map_err(|e| StoreError::SerializationError { source: e })?;
+
+        let written = self.store.compare_and_swap(key, expected, json_value.clone()).await?;
+
+        if written.value {
+            self.publish(KeyvChangeKind::Set, Some(key), Some(json_value));
+            self.publish_evicted(&written.evicted);
+        }
+
+        Ok(written.value)
+    }
+
+    /// Atomically adds `delta` to an integer counter. A missing key counts as `0`.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The key of the counter.
+    /// * `delta` - The amount to add, which may be negative.
+    ///
+    /// # Returns
+    ///
+    /// Returns the new value of the counter, or a `KeyvError` if the stored value is
+    /// not an integer or the operation fails.
+    ///
+    /// # Examples
+    ///
+    /// ```rust,no_run
+    /// # use tauri_plugin_localstore::keyv::Keyv;
+    /// # async fn run() -> Result<(), Box<dyn std::error::Error>> {
+    /// let keyv = Keyv::default();
+    /// let visits = keyv.increment("visits", 1).await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn increment(&self, key: &str, delta: i64) -> Result<i64, KeyvError> {
+        let written = self.store.increment(key, delta).await?;
+        self.publish(KeyvChangeKind::Set, Some(key), Some(Value::from(written.value)));
+        self.publish_evicted(&written.evicted);
+        Ok(written.value)
+    }
+
+    /// Atomically replaces the value of `key` with the result of `f`.
+    ///
+    /// `f` receives the current value, or `None` if the key does not exist, and
+    /// no other write can happen between reading that value and storing the
+    /// result. Keep `f` short, since it runs while the store is locked for writing.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The key to update.
+    /// * `f` - Computes the new value from the current one.
+    ///
+    /// # Returns
+    ///
+    /// Returns the value that was stored, or a `KeyvError` if the current value does
+    /// not match `T` or the operation fails.
+    ///
+    /// # Examples
+    ///
+    /// ```rust,no_run
+    /// # use tauri_plugin_localstore::keyv::Keyv;
+    /// # async fn run() -> Result<(), Box<dyn std::error::Error>> {
+    /// let keyv = Keyv::default();
+    ///
+    /// let tags = keyv
+    ///     .update("tags", |tags: Option<Vec<String>>| {
+    ///         let mut tags = tags.unwrap_or_default();
+    ///         tags.push("new".to_string());
+    ///         tags
+    ///     })
+    ///     .await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn update<T, F>(&self, key: &str, f: F) -> Result<T, KeyvError>
+    where
+        T: Serialize + DeserializeOwned,
+        F: FnOnce(Option<T>) -> T + Send,
+    {
+        let written = self
+            .store
+            .update(
+                key,
+                Box::new(|current| {
+                    let current = current
+                        .map(serde_json::from_value)
+                        .transpose()
+                        .map_err(|e| StoreError::SerializationError { source: e })?;
+                    serde_json::to_value(f(current)).map_err(|e| StoreError::SerializationError { source: e })
+                }),
+            )
+            .await?;
+
+        self.publish(KeyvChangeKind::Set, Some(key), Some(written.value.clone()));
+        self.publish_evicted(&written.evicted);
+
+        serde_json::from_value(written.value).map_err(|e| KeyvError::from(StoreError::SerializationError { source: e }))
     }
 
     /// Retrieves a value based on a key.
@@ -162,57 +536,189 @@ impl Keyv {
     /// # Returns
     ///
     /// Returns an `Ok` result with `Option<Value>` on success, where `None` indicates the
-    /// key does not exist, or a `KeyvError` on failure.
+    /// key does not exist or has expired, or a `KeyvError` on failure.
     ///
     /// # Examples
     ///
-    /// ```
-    /// # use keyv::Keyv;
+    /// ```rust,no_run
+    /// # use tauri_plugin_localstore::keyv::Keyv;
+    /// # async fn run() -> Result<(), Box<dyn std::error::Error>> {
     /// let keyv = Keyv::default();
     ///
-    /// keyv.set("array", vec!["hola", "test"]).unwrap();
+    /// keyv.set("array", vec!["hola", "test"]).await?;
     ///
-    /// match keyv.get("array").unwrap() {
+    /// match keyv.get("array").await? {
     ///     Some(array) => {
-    ///         let array: Vec<String> = serde_json::from_value(array).unwrap();
+    ///         let array: Vec<String> = serde_json::from_value(array)?;
     ///         assert_eq!(array, vec!["hola".to_string(), "test".to_string()])
     ///     }
     ///     None => assert!(false),
     /// }
-    ///
-    /// keyv.set("string", "life long").unwrap();
-    /// match keyv.get("string").unwrap() {
-    ///     Some(string) => {
-    ///         let string: String = serde_json::from_value(string).unwrap();
-    ///         assert_eq!(string, "life long");
-    ///     }
-    ///     None => assert!(false),
-    /// }
+    /// # Ok(())
+    /// # }
     /// ```
     pub async fn get(&self, key: &str) -> Result<Option<Value>, KeyvError> {
         Ok(self.store.get(key).await?)
     }
 
-    /// Lists all key-value pairs stored in the Keyv store.
+    /// Retrieves a value based on a key and deserializes it into `T`.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - A string slice that holds the key to retrieve the value for.
+    ///
+    /// # Returns
+    ///
+    /// Returns an `Ok` result with `Option<T>` on success, where `None` indicates the
+    /// key does not exist or has expired. Returns a `KeyvError` if the lookup fails or
+    /// the stored value does not match `T`.
+    ///
+    /// # Examples
+    ///
+    /// ```rust,no_run
+    /// # use tauri_plugin_localstore::keyv::Keyv;
+    /// # async fn run() -> Result<(), Box<dyn std::error::Error>> {
+    /// let keyv = Keyv::default();
+    ///
+    /// keyv.set("count", 42).await?;
+    /// let count: Option<u32> = keyv.get_as("count").await?;
+    /// assert_eq!(count, Some(42));
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn get_as<T: DeserializeOwned>(&self, key: &str) -> Result<Option<T>, KeyvError> {
+        match self.store.get(key).await? {
+            Some(value) => {
+                let value = serde_json::from_value(value).map_err(|e| StoreError::SerializationError { source: e })?;
+                Ok(Some(value))
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// Retrieves the values of several keys in one round trip.
+    ///
+    /// # Arguments
+    ///
+    /// * `keys` - A slice of strings or string-like objects that represent the keys to retrieve.
     ///
     /// # Returns
     ///
-    /// Returns a `Result` containing a `Vec` of tuples, where each tuple contains the key (as a `String`) and the corresponding value (as a `Value`). If an error occurs, a `KeyvError` is returned.
+    /// Returns a map from key to value. Keys that do not exist or have expired are
+    /// not included. Returns a `KeyvError` on failure.
     ///
     /// # Examples
     ///
+    /// ```rust,no_run
+    /// # use tauri_plugin_localstore::keyv::Keyv;
+    /// # async fn run() -> Result<(), Box<dyn std::error::Error>> {
+    /// let keyv = Keyv::default();
+    /// let values = keyv.get_many(&["theme", "locale"]).await?;
+    ///
+    /// if let Some(theme) = values.get("theme") {
+    ///     println!("theme: {}", theme);
+    /// }
+    /// # Ok(())
+    /// # }
     /// ```
-    /// # use keyv::Keyv;
+    pub async fn get_many<T: AsRef<str> + Sync>(&self, keys: &[T]) -> Result<HashMap<String, Value>, KeyvError> {
+        let keys: Vec<&str> = keys.iter().map(|k| k.as_ref()).collect();
+        Ok(self.store.get_many(&keys).await?)
+    }
+
+    /// Lists all key-value pairs stored in the Keyv store.
+    ///
+    /// # Returns
+    ///
+    /// Returns a `Result` containing a `Vec` of `StoreModel`, one for each key that has not
+    /// expired. If an error occurs, a `KeyvError` is returned.
+    ///
+    /// # Examples
+    ///
+    /// ```rust,no_run
+    /// # use tauri_plugin_localstore::keyv::Keyv;
+    /// # async fn run() -> Result<(), Box<dyn std::error::Error>> {
     /// let keyv = Keyv::default();
-    /// let pairs = keyv.list().await.unwrap();
-    /// for (key, value) in pairs {
-    ///     println!("Key: {}, Value: {}", key, value);
+    /// for item in keyv.list().await? {
+    ///     println!("Key: {}, Value: {}", item.key, item.value);
     /// }
+    /// # Ok(())
+    /// # }
     /// ```
     pub async fn list(&self) -> Result<Vec<StoreModel>, KeyvError> {
         Ok(self.store.list().await?)
     }
 
+    /// Lists the key-value pairs whose key starts with `prefix`.
+    ///
+    /// # Arguments
+    ///
+    /// * `prefix` - The key prefix to match.
+    ///
+    /// # Returns
+    ///
+    /// Returns the matching pairs in ascending key order, or a `KeyvError` on failure.
+    pub async fn list_prefix(&self, prefix: &str) -> Result<Vec<StoreModel>, KeyvError> {
+        Ok(self.store.list_page(ListQuery::new().prefix(prefix)).await?.items)
+    }
+
+    /// Lists one page of the key-value pairs matching a query.
+    ///
+    /// # Arguments
+    ///
+    /// * `query` - The prefix, range, cursor and limit to apply.
+    ///
+    /// # Returns
+    ///
+    /// Returns the page, whose `next_cursor` can be passed to `ListQuery::after`
+    /// to fetch the following page, or a `KeyvError` on failure.
+    ///
+    /// # Examples
+    ///
+    /// ```rust,no_run
+    /// # use tauri_plugin_localstore::keyv::{Keyv, ListQuery};
+    /// # async fn run(keyv: Keyv) -> Result<(), Box<dyn std::error::Error>> {
+    /// let mut query = ListQuery::new().prefix("cache:").limit(100);
+    ///
+    /// loop {
+    ///     let page = keyv.list_page(query.clone()).await?;
+    ///     // ... render page.items
+    ///     match page.next_cursor {
+    ///         Some(cursor) => query = query.after(cursor),
+    ///         None => break,
+    ///     }
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn list_page(&self, query: ListQuery) -> Result<ListPage, KeyvError> {
+        Ok(self.store.list_page(query).await?)
+    }
+
+    /// Streams the key-value pairs matching a query without loading them all into memory.
+    ///
+    /// # Arguments
+    ///
+    /// * `query` - The prefix, range, cursor and limit to apply.
+    ///
+    /// # Examples
+    ///
+    /// ```rust,no_run
+    /// # use tauri_plugin_localstore::keyv::{Keyv, ListQuery};
+    /// # use tokio_stream::StreamExt;
+    /// # async fn run(keyv: Keyv) -> Result<(), Box<dyn std::error::Error>> {
+    /// let mut items = keyv.list_stream(ListQuery::new().prefix("cache:"));
+    ///
+    /// while let Some(item) = items.next().await {
+    ///     println!("{}", item?.key);
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn list_stream(&self, query: ListQuery) -> impl Stream<Item = Result<StoreModel, KeyvError>> + Send + '_ {
+        self.store.list_stream(query).map(|item| item.map_err(KeyvError::from))
+    }
+
     /// Removes a specified key from the store.
     ///
     /// # Arguments
@@ -226,13 +732,18 @@ impl Keyv {
     ///
     /// # Examples
     ///
-    /// ```
-    /// # use keyv::Keyv;
+    /// ```rust,no_run
+    /// # use tauri_plugin_localstore::keyv::Keyv;
+    /// # async fn run() -> Result<(), Box<dyn std::error::Error>> {
     /// let keyv = Keyv::default();
-    /// keyv.remove("my_key").unwrap(); // Removes "my_key" from the store
+    /// keyv.remove("my_key").await?; // Removes "my_key" from the store
+    /// # Ok(())
+    /// # }
     /// ```
     pub async fn remove(&self, key: &str) -> Result<(), KeyvError> {
-        Ok(self.store.remove(key).await?)
+        self.store.remove(key).await?;
+        self.publish(KeyvChangeKind::Remove, Some(key), None);
+        Ok(())
     }
 
     /// Removes multiple keys from the store in one operation.
@@ -244,18 +755,27 @@ impl Keyv {
     /// # Returns
     ///
     /// Returns an `Ok` result if the keys have been successfully removed, or a `KeyvError`
-    /// on failure.
+    /// on failure. A `Remove` change is published only for the keys that existed.
     ///
     /// # Examples
     ///
-    /// ```
-    /// # use keyv::Keyv;
+    /// ```rust,no_run
+    /// # use tauri_plugin_localstore::keyv::Keyv;
+    /// # async fn run() -> Result<(), Box<dyn std::error::Error>> {
     /// let keyv = Keyv::default();
-    /// keyv.remove_many(&["key1", "key2"]).unwrap(); // Removes "key1" and "key2"
+    /// keyv.remove_many(&["key1", "key2"]).await?; // Removes "key1" and "key2"
+    /// # Ok(())
+    /// # }
     /// ```
     pub async fn remove_many<T: AsRef<str> + Sync>(&self, keys: &[T]) -> Result<(), KeyvError> {
         let keys: Vec<&str> = keys.iter().map(|k| k.as_ref()).collect();
-        Ok(self.store.remove_many(&keys).await?)
+        let removed = self.store.remove_many(&keys).await?;
+
+        for key in &removed {
+            self.publish(KeyvChangeKind::Remove, Some(key), None);
+        }
+
+        Ok(())
     }
 
     /// Clears the entire store, removing all key-value pairs.
@@ -267,29 +787,973 @@ impl Keyv {
     ///
     /// # Examples
     ///
-    /// ```
-    /// # use keyv::Keyv;
+    /// ```rust,no_run
+    /// # use tauri_plugin_localstore::keyv::Keyv;
+    /// # async fn run() -> Result<(), Box<dyn std::error::Error>> {
     /// let keyv = Keyv::default();
-    /// keyv.clear().unwrap(); // Clears the entire store
+    /// keyv.clear().await?; // Clears the entire store
+    /// # Ok(())
+    /// # }
     /// ```
     pub async fn clear(&self) -> Result<(), KeyvError> {
-        Ok(self.store.clear().await?)
+        self.store.clear().await?;
+        self.publish(KeyvChangeKind::Clear, None, None);
+        Ok(())
     }
-}
 
-/// Provides a default implementation for the `Keyv` struct, which creates an in-memory store.
-/// This is useful for quickly setting up a `Keyv` instance without needing to configure a
-/// specific storage backend.
-impl Default for Keyv {
-    fn default() -> Self {
-        let runtime = tokio::runtime::Runtime::new().expect("Failed to create async runtime");
-        let store = runtime.block_on(async {
-            KeyvStoreBuilder::new()
-                .uri(Path::new(":memory:"))
-                .build()
-                .await
-                .expect("Failed to build KeyvStore")
-        });
-        Self { store: Arc::new(store) }
+    /// Enables the history of the namespace, keeping the last `limit` values
+    /// of every key each time it is overwritten or removed.
+    ///
+    /// The setting is stored with the namespace, so it only needs to be made
+    /// once. Calling it again changes the limit, dropping the revisions beyond it.
+    ///
+    /// # Arguments
+    ///
+    /// * `limit` - The number of revisions kept per key, at least one.
+    ///
+    /// # Returns
+    ///
+    /// Returns an `Ok` result once the history is enabled, or a `KeyvError` if
+    /// the store keeps no history.
+    ///
+    /// # Examples
+    ///
+    /// ```rust,no_run
+    /// # use tauri_plugin_localstore::keyv::Keyv;
+    /// # async fn run(keyv: Keyv) -> Result<(), Box<dyn std::error::Error>> {
+    /// let drafts = keyv.namespace("drafts")?;
+    /// drafts.enable_history(20).await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn enable_history(&self, limit: usize) -> Result<(), KeyvError> {
+        Ok(self.store.set_history(Some(limit)).await?)
+    }
+
+    /// Disables the history of the namespace and deletes every revision kept.
+    ///
+    /// # Returns
+    ///
+    /// Returns an `Ok` result once the history is disabled, or a `KeyvError`
+    /// if the store keeps no history.
+    pub async fn disable_history(&self) -> Result<(), KeyvError> {
+        Ok(self.store.set_history(None).await?)
+    }
+
+    /// Lists the previous values kept for a key, most recent first.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The key whose revisions are listed.
+    ///
+    /// # Returns
+    ///
+    /// Returns the revisions, empty if the key has none, or a `KeyvError` if
+    /// the store keeps no history.
+    ///
+    /// # Examples
+    ///
+    /// ```rust,no_run
+    /// # use tauri_plugin_localstore::keyv::Keyv;
+    /// # async fn run(keyv: Keyv) -> Result<(), Box<dyn std::error::Error>> {
+    /// for revision in keyv.history("draft").await? {
+    ///     println!("{}: {} (replaced at {})", revision.id, revision.value, revision.replaced_at);
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn history(&self, key: &str) -> Result<Vec<Revision>, KeyvError> {
+        Ok(self.store.history(key).await?)
+    }
+
+    /// Retrieves a single revision of a key.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The key the revision belongs to.
+    /// * `id` - The id of the revision, as returned by `history`.
+    ///
+    /// # Returns
+    ///
+    /// Returns an `Ok` result with `Option<Revision>`, where `None` indicates the
+    /// key has no revision with that id, or a `KeyvError` on failure.
+    pub async fn get_revision(&self, key: &str, id: i64) -> Result<Option<Revision>, KeyvError> {
+        Ok(self.store.get_revision(key, id).await?)
+    }
+
+    /// Restores the value of a revision, keeping the expiry of the key.
+    ///
+    /// The value replaced is recorded as a new revision, so the revert can be
+    /// undone the same way.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The key to revert.
+    /// * `id` - The id of the revision to restore, as returned by `history`.
+    ///
+    /// # Returns
+    ///
+    /// Returns the entry as written, or a `KeyvError` if the key has no
+    /// revision with that id.
+    ///
+    /// # Examples
+    ///
+    /// ```rust,no_run
+    /// # use tauri_plugin_localstore::keyv::Keyv;
+    /// # async fn run(keyv: Keyv) -> Result<(), Box<dyn std::error::Error>> {
+    /// if let Some(previous) = keyv.history("draft").await?.first() {
+    ///     keyv.revert("draft", previous.id).await?;
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn revert(&self, key: &str, id: i64) -> Result<StoreModel, KeyvError> {
+        let written = self.store.revert(key, id).await?;
+        self.publish(KeyvChangeKind::Set, Some(key), Some(written.value.value.clone()));
+        self.publish_evicted(&written.evicted);
+        Ok(written.value)
+    }
+
+    /// Enables the full-text index of the namespace, indexing the values
+    /// already stored and keeping the index in sync with every write.
+    ///
+    /// The text of a value is made of the strings it contains; object keys,
+    /// numbers and booleans are not indexed. The setting is stored with the
+    /// namespace, so it only needs to be made once; calling it again rebuilds
+    /// the index.
+    ///
+    /// # Returns
+    ///
+    /// Returns an `Ok` result once the index is built, or a `KeyvError` if the
+    /// store cannot search or compresses or encrypts its values.
+    pub async fn enable_search(&self) -> Result<(), KeyvError> {
+        Ok(self.store.set_search(true).await?)
+    }
+
+    /// Disables the full-text index of the namespace and drops it.
+    ///
+    /// # Returns
+    ///
+    /// Returns an `Ok` result once the index is dropped, or a `KeyvError` if
+    /// the store cannot search.
+    pub async fn disable_search(&self) -> Result<(), KeyvError> {
+        Ok(self.store.set_search(false).await?)
+    }
+
+    /// Searches the values of the namespace, returning the best
+    /// `DEFAULT_SEARCH_LIMIT` matches. The index must be enabled with
+    /// `enable_search` first.
+    ///
+    /// Only plain JSON values are indexed, so once `rotate_encryption_key`
+    /// has encrypted the namespace its values are no longer found.
+    ///
+    /// # Arguments
+    ///
+    /// * `query` - The terms to search for, in the SQLite FTS5 query syntax.
+    ///
+    /// # Returns
+    ///
+    /// Returns the matching keys with a highlighted snippet of their value,
+    /// most relevant first, or a `KeyvError` if the query is invalid or the
+    /// index is disabled.
+    ///
+    /// # Examples
+    ///
+    /// ```rust,no_run
+    /// # use tauri_plugin_localstore::keyv::Keyv;
+    /// # async fn run(keyv: Keyv) -> Result<(), Box<dyn std::error::Error>> {
+    /// let notes = keyv.namespace("notes")?;
+    /// notes.enable_search().await?;
+    /// for hit in notes.search("tauri AND plugin*").await? {
+    ///     println!("{}: {}", hit.key, hit.snippet);
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn search(&self, query: &str) -> Result<Vec<SearchHit>, KeyvError> {
+        self.search_with(SearchQuery::new(query)).await
+    }
+
+    /// Searches the values of the namespace, restricted to a key prefix or
+    /// a number of hits.
+    ///
+    /// # Arguments
+    ///
+    /// * `query` - The terms, key prefix and limit of the search.
+    ///
+    /// # Returns
+    ///
+    /// Returns the matching keys, most relevant first, or a `KeyvError` on failure.
+    ///
+    /// # Examples
+    ///
+    /// ```rust,no_run
+    /// # use tauri_plugin_localstore::keyv::{Keyv, SearchQuery};
+    /// # async fn run(keyv: Keyv) -> Result<(), Box<dyn std::error::Error>> {
+    /// let hits = keyv
+    ///     .search_with(SearchQuery::new("invoice").prefix("mail:").limit(5))
+    ///     .await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn search_with(&self, query: SearchQuery) -> Result<Vec<SearchHit>, KeyvError> {
+        Ok(self.store.search(query).await?)
+    }
+
+    /// Finds the entries whose value matches a [`Filter`](super::Filter), in
+    /// ascending key order.
+    ///
+    /// # Arguments
+    ///
+    /// * `query` - The filter, key prefix and limit of the search.
+    ///
+    /// # Returns
+    ///
+    /// Returns the matching entries, or a `KeyvError` on failure.
+    ///
+    /// # Examples
+    ///
+    /// ```rust,no_run
+    /// # use tauri_plugin_localstore::keyv::{Filter, FindQuery, Keyv};
+    /// # async fn run(keyv: Keyv) -> Result<(), Box<dyn std::error::Error>> {
+    /// let filter = Filter::parse(serde_json::json!({ "theme": { "$in": ["dark", "dim"] } }))?;
+    /// let dark = keyv.find(FindQuery::new(filter).prefix("profile:")).await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn find(&self, query: FindQuery) -> Result<Vec<StoreModel>, KeyvError> {
+        Ok(self.store.find(query).await?)
+    }
+
+    /// Creates a secondary index on a field of the values of the namespace,
+    /// used by `find` for filters on that field.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name of the index, made of ASCII letters, digits and underscores.
+    /// * `path` - The dotted path of the field, as used in filters.
+    ///
+    /// # Returns
+    ///
+    /// Returns an `Ok` result once the index is built, or a `KeyvError` on failure.
+    pub async fn create_index(&self, name: &str, path: &str) -> Result<(), KeyvError> {
+        Ok(self.store.create_index(name, path).await?)
+    }
+
+    /// Drops a secondary index created by `create_index`, if it exists.
+    pub async fn drop_index(&self, name: &str) -> Result<(), KeyvError> {
+        Ok(self.store.drop_index(name).await?)
+    }
+
+    /// Returns a collection of documents of type `T`, stored in the namespace `name`.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The collection name, following the rules of namespace names.
+    ///
+    /// # Returns
+    ///
+    /// Returns the `Collection`, or a `KeyvError` if the name is not valid.
+    pub fn collection<T: Serialize + DeserializeOwned>(&self, name: &str) -> Result<Collection<T>, KeyvError> {
+        Ok(Collection::new(self.namespace(name)?))
+    }
+
+    /// Returns a handle scoped to another namespace of the same store.
+    ///
+    /// The handle shares the connection of `self`; its table is created the
+    /// first time it is used. Namespace names may only contain ASCII letters,
+    /// digits and underscores, and may not end with `_search` or
+    /// `_keyv_upgrade`, which the store uses for the tables it creates.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The namespace name.
+    ///
+    /// # Returns
+    ///
+    /// Returns the scoped `Keyv`, or a `KeyvError` if the name is not valid.
+    ///
+    /// # Examples
+    ///
+    /// ```rust,no_run
+    /// # use tauri_plugin_localstore::keyv::Keyv;
+    /// # async fn run(keyv: Keyv) -> Result<(), Box<dyn std::error::Error>> {
+    /// let prefs = keyv.namespace("prefs")?;
+    /// prefs.set("theme", "dark").await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn namespace(&self, name: &str) -> Result<Keyv, KeyvError> {
+        Ok(Self {
+            store: self.store.namespace(name)?,
+            changes: self.changes.clone(),
+            schemas: Arc::clone(&self.schemas),
+        })
+    }
+
+    /// Registers a JSON Schema that values must match to be stored.
+    ///
+    /// The schema applies to this namespace only, either to a single key or to
+    /// every key starting with a prefix. When several schemas match a key, the
+    /// value must match all of them. Registering a schema again for the same
+    /// target replaces it. Values already stored are not checked.
+    ///
+    /// Writes with a value that does not match fail with
+    /// `StoreError::SchemaViolation`, which lists every violation. This applies
+    /// to every write, including `set_many`, `update` and transactions.
+    ///
+    /// # Arguments
+    ///
+    /// * `target` - The key or prefix the schema applies to.
+    /// * `schema` - The JSON Schema.
+    ///
+    /// # Errors
+    ///
+    /// Returns `StoreError::InvalidSchema` if the schema cannot be compiled.
+    ///
+    /// # Examples
+    ///
+    /// ```rust,no_run
+    /// # use serde_json::json;
+    /// # use tauri_plugin_localstore::keyv::{Keyv, SchemaTarget};
+    /// # async fn run(keyv: Keyv) -> Result<(), Box<dyn std::error::Error>> {
+    /// keyv.register_schema(
+    ///     SchemaTarget::key("theme"),
+    ///     &json!({ "type": "string", "enum": ["light", "dark"] }),
+    /// )?;
+    ///
+    /// assert!(keyv.set("theme", "blue").await.is_err());
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn register_schema(&self, target: SchemaTarget, schema: &Value) -> Result<(), KeyvError> {
+        Ok(self.schemas.register(self.store.namespace_name(), target, schema)?)
+    }
+
+    /// Removes a schema registered with `register_schema`.
+    ///
+    /// # Returns
+    ///
+    /// Returns `false` if no schema was registered for the target in this namespace.
+    pub fn unregister_schema(&self, target: &SchemaTarget) -> bool {
+        self.schemas.unregister(self.store.namespace_name(), target)
+    }
+
+    /// Returns the hit and miss counters of the read cache of the store.
+    ///
+    /// # Returns
+    ///
+    /// Returns `None` if the store does not cache reads, see
+    /// `KeyvStoreBuilder::cache_capacity`.
+    pub fn cache_stats(&self) -> Option<CacheStats> {
+        self.store.cache_stats()
+    }
+
+    /// Writes a consistent copy of the whole store, every namespace included, to
+    /// a new file. The store stays usable while the copy is written.
+    ///
+    /// See [`BackupManager`](crate::BackupManager) to keep rotated backups of
+    /// the plugin database and restore them.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The file to create. It must not exist yet.
+    ///
+    /// # Returns
+    ///
+    /// Returns an `Ok` result once the copy has been written, or a `KeyvError`
+    /// if the store does not support backups or the copy fails.
+    pub async fn backup<P: AsRef<Path>>(&self, path: P) -> Result<(), KeyvError> {
+        Ok(self.store.backup(path.as_ref()).await?)
+    }
+
+    /// Re-encrypts every value of this namespace, revisions included, with a
+    /// new key. Values keep their version and no change is published.
+    ///
+    /// The previous key is kept for decryption, so the other namespaces stay
+    /// readable until they are rotated too. Stores opened on the same database
+    /// later must be built with the new key, and the old one as a
+    /// `previous_encryption_key` until every namespace has been rotated.
+    ///
+    /// # Arguments
+    ///
+    /// * `new_key` - The key to encrypt with, `None` to store the values in the clear.
+    ///
+    /// # Returns
+    ///
+    /// Returns the number of values re-encoded, or a `KeyvError` if the store
+    /// does not encrypt values or a value cannot be decrypted.
+    pub async fn rotate_encryption_key(&self, new_key: Option<[u8; 32]>) -> Result<u64, KeyvError> {
+        Ok(self.store.rotate_encryption_key(new_key).await?)
+    }
+
+    /// Lists every namespace that has been created in the store.
+    ///
+    /// # Returns
+    ///
+    /// Returns the namespace names sorted by name, or a `KeyvError` on failure.
+    pub async fn namespaces(&self) -> Result<Vec<String>, KeyvError> {
+        Ok(self.store.list_namespaces().await?)
+    }
+
+    /// Renames a namespace, keeping all of its entries.
+    ///
+    /// Handles obtained for either name before the rename should be discarded.
+    ///
+    /// # Arguments
+    ///
+    /// * `from` - The current namespace name.
+    /// * `to` - The new namespace name, following the same rules as `namespace`.
+    ///
+    /// # Returns
+    ///
+    /// Returns an `Ok` result if the namespace has been renamed, or a `KeyvError`
+    /// on failure, e.g. if `to` is not a valid namespace name.
+    pub async fn rename_namespace(&self, from: &str, to: &str) -> Result<(), KeyvError> {
+        Ok(self.store.rename_namespace(from, to).await?)
+    }
+
+    /// Deletes a namespace together with all of its entries.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The namespace to delete.
+    ///
+    /// # Returns
+    ///
+    /// Returns an `Ok` result if the namespace has been deleted, or a `KeyvError`
+    /// on failure.
+    pub async fn drop_namespace(&self, name: &str) -> Result<(), KeyvError> {
+        Ok(self.store.drop_namespace(name).await?)
+    }
+
+    /// Acquires a lock shared by every window and process using the same
+    /// database, waiting until it is free.
+    ///
+    /// The lock is held through a lease lasting `ttl`, which the returned guard
+    /// renews in the background and releases when dropped. If the process
+    /// crashes, the lock becomes free once the lease expires. Lock names are
+    /// shared by every namespace.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name of the lock.
+    /// * `ttl` - How long the lock outlives a process that stops renewing it.
+    ///
+    /// # Returns
+    ///
+    /// Returns the `LockGuard` once the lock is held, or a `KeyvError` if the
+    /// store has no locks or the TTL is zero.
+    ///
+    /// # Examples
+    ///
+    /// ```rust,no_run
+    /// # use std::time::Duration;
+    /// # use tauri_plugin_localstore::keyv::Keyv;
+    /// # async fn run(keyv: Keyv) -> Result<(), Box<dyn std::error::Error>> {
+    /// let lock = keyv.acquire_lock("sync", Duration::from_secs(30)).await?;
+    ///
+    /// // Only one window or app instance runs the sync at a time.
+    ///
+    /// lock.release().await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn acquire_lock(&self, name: &str, ttl: Duration) -> Result<LockGuard, KeyvError> {
+        loop {
+            if let Some(guard) = self.try_acquire_lock(name, ttl).await? {
+                return Ok(guard);
+            }
+
+            tokio::time::sleep(LOCK_RETRY_INTERVAL.min(ttl)).await;
+        }
+    }
+
+    /// Acquires a lock if it is free, without waiting. See `acquire_lock`.
+    ///
+    /// # Returns
+    ///
+    /// Returns an `Ok` result with `Option<LockGuard>`, where `None` indicates
+    /// the lock is held elsewhere, or a `KeyvError` if the store has no locks
+    /// or the TTL is zero.
+    pub async fn try_acquire_lock(&self, name: &str, ttl: Duration) -> Result<Option<LockGuard>, KeyvError> {
+        let owner = lease_owner();
+
+        if !self.acquire_lease(name, &owner, ttl).await? {
+            return Ok(None);
+        }
+
+        Ok(Some(LockGuard::new(self.clone(), name.to_string(), owner, ttl)))
+    }
+
+    /// Takes the lease on a lock for `owner`, see `Store::acquire_lease`.
+    pub(crate) async fn acquire_lease(&self, name: &str, owner: &str, ttl: Duration) -> Result<bool, KeyvError> {
+        validate_lease_ttl(ttl)?;
+        Ok(self.store.acquire_lease(name, owner, ttl).await?)
+    }
+
+    /// Extends the lease of `owner` on a lock, see `Store::renew_lease`.
+    pub(crate) async fn renew_lease(&self, name: &str, owner: &str, ttl: Duration) -> Result<bool, KeyvError> {
+        validate_lease_ttl(ttl)?;
+        Ok(self.store.renew_lease(name, owner, ttl).await?)
+    }
+
+    /// Releases the lease of `owner` on a lock, see `Store::release_lease`.
+    pub(crate) async fn release_lease(&self, name: &str, owner: &str) -> Result<(), KeyvError> {
+        Ok(self.store.release_lease(name, owner).await?)
+    }
+
+    /// Runs `f` inside a transaction spanning every namespace of the store.
+    ///
+    /// All reads in `f` see a consistent state and all writes are applied
+    /// together: if `f` returns an error, or the commit fails, every write made
+    /// through the transaction is rolled back. Other writes to the store wait
+    /// until the transaction finishes, so keep `f` short and make every write
+    /// through the `KeyvTransaction` handle: writing through a `Keyv` from inside
+    /// `f` would wait for the transaction forever. Reads wait as well on stores
+    /// that read through their writer connection, such as in-memory databases.
+    ///
+    /// Changes are published to watchers only after a successful commit.
+    ///
+    /// # Arguments
+    ///
+    /// * `f` - Receives a `KeyvTransaction` scoped to this namespace and returns
+    ///   the result of the transaction.
+    ///
+    /// # Returns
+    ///
+    /// Returns the result of `f` once the transaction has been committed, or the
+    /// error of `f` or of the store after rolling back.
+    ///
+    /// # Examples
+    ///
+    /// ```rust,no_run
+    /// # use tauri_plugin_localstore::keyv::{Keyv, KeyvError};
+    /// # async fn run(keyv: Keyv) -> Result<(), Box<dyn std::error::Error>> {
+    /// keyv.transaction(|tx| async move {
+    ///     let balance: i64 = tx.get_as("balance").await?.unwrap_or(0);
+    ///
+    ///     if balance < 10 {
+    ///         return Err(KeyvError::TransactionAborted("insufficient balance".to_string()));
+    ///     }
+    ///
+    ///     tx.set("balance", balance - 10).await?;
+    ///     tx.namespace("audit").set("last_withdrawal", 10).await?;
+    ///     Ok(())
+    /// })
+    /// .await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn transaction<F, Fut, R>(&self, f: F) -> Result<R, KeyvError>
+    where
+        F: FnOnce(KeyvTransaction) -> Fut,
+        Fut: Future<Output = Result<R, KeyvError>>,
+    {
+        let inner: Arc<dyn StoreTransaction> = Arc::from(self.store.begin().await?);
+        let tx = KeyvTransaction::new(Arc::clone(&inner), self.store.namespace_name());
+
+        match f(tx.clone()).await {
+            Ok(result) => {
+                inner.commit().await?;
+
+                for change in tx.take_changes() {
+                    let _ = self.changes.send(change);
+                }
+
+                Ok(result)
+            }
+            Err(e) => {
+                if let Err(rollback_error) = inner.rollback().await {
+                    log::error!("Failed to roll back the Keyv transaction: {}", rollback_error);
+                }
+
+                Err(e)
+            }
+        }
+    }
+
+    /// Writes every entry of this namespace to `writer`, together with its expiry.
+    ///
+    /// Entries are read page by page, so the namespace is never held in memory
+    /// at once. Writes made while the export runs may or may not be included.
+    ///
+    /// # Arguments
+    ///
+    /// * `writer` - Where to write the export, e.g. a `tokio::fs::File`.
+    /// * `format` - Whether to write a JSON array or newline-delimited JSON.
+    ///
+    /// # Returns
+    ///
+    /// Returns the number of exported entries, or a `KeyvError` if reading the
+    /// store or writing the output fails.
+    ///
+    /// # Examples
+    ///
+    /// ```rust,no_run
+    /// # use tauri_plugin_localstore::keyv::{ExportFormat, Keyv};
+    /// # async fn run(keyv: Keyv) -> Result<(), Box<dyn std::error::Error>> {
+    /// let file = tokio::fs::File::create("settings.ndjson").await?;
+    /// let exported = keyv.export(file, ExportFormat::Ndjson).await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn export<W: AsyncWrite + Unpin + Send>(
+        &self,
+        writer: W,
+        format: ExportFormat,
+    ) -> Result<u64, KeyvError> {
+        let mut writer = ExportWriter::new(writer, format);
+        let mut query = ListQuery::new().limit(EXPORT_PAGE_SIZE);
+
+        loop {
+            let page = self.store.list_page(query.clone()).await?;
+
+            for model in page.items {
+                writer.write(&ExportEntry::from(model)).await?;
+            }
+
+            match page.next_cursor {
+                Some(cursor) => query = query.after(cursor),
+                None => break,
+            }
+        }
+
+        Ok(writer.finish().await?)
+    }
+
+    /// Restores entries written by `export` into this namespace.
+    ///
+    /// The input may be in either `ExportFormat`, with each entry on its own line
+    /// as `export` writes them, and is read line by line. Entries keep the
+    /// expiry they were exported with, and those that expired in the meantime
+    /// are skipped. Every write is applied in a single transaction, so either
+    /// the whole import succeeds or the namespace is left untouched.
+    ///
+    /// # Arguments
+    ///
+    /// * `reader` - The export to read, e.g. a `tokio::fs::File`.
+    /// * `mode` - Whether to keep or remove the keys missing from the import.
+    ///
+    /// # Returns
+    ///
+    /// Returns what was imported and removed, or a `KeyvError` if the input
+    /// cannot be read or parsed, or the transaction fails.
+    ///
+    /// # Examples
+    ///
+    /// ```rust,no_run
+    /// # use tauri_plugin_localstore::keyv::{ImportMode, Keyv};
+    /// # async fn run(keyv: Keyv) -> Result<(), Box<dyn std::error::Error>> {
+    /// let file = tokio::fs::File::open("settings.ndjson").await?;
+    /// let report = keyv.import(file, ImportMode::Replace).await?;
+    /// println!("{} imported, {} removed", report.imported, report.removed);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn import<R: AsyncRead + Unpin + Send>(
+        &self,
+        reader: R,
+        mode: ImportMode,
+    ) -> Result<ImportReport, KeyvError> {
+        let mut entries = ExportReader::new(reader);
+
+        self.transaction(|tx| async move {
+            let mut report = ImportReport::default();
+            let mut imported = HashSet::new();
+            let now = unix_millis();
+
+            while let Some(entry) = entries.next().await? {
+                match entry.expires_at {
+                    Some(at) if at <= now => {
+                        report.expired += 1;
+                        continue;
+                    }
+                    // Round up, so that an entry never expires before it did in the export.
+                    Some(at) => {
+                        tx.set_with_ttl(&entry.key, entry.value, ((at - now) as u64).div_ceil(1000))
+                            .await?
+                    }
+                    None => tx.set(&entry.key, entry.value).await?,
+                }
+                report.imported += 1;
+
+                if mode == ImportMode::Replace {
+                    imported.insert(entry.key);
+                }
+            }
+
+            if mode == ImportMode::Replace {
+                // Listed within the transaction, so keys written since it began are seen too.
+                for key in tx.keys().await? {
+                    if !imported.contains(&key) {
+                        tx.remove(&key).await?;
+                        report.removed += 1;
+                    }
+                }
+            }
+
+            Ok(report)
+        })
+        .await
+    }
+
+    /// Permanently deletes every key whose TTL has elapsed.
+    ///
+    /// The plugin runs this periodically in the background, so applications
+    /// rarely need to call it themselves. An expire change is published for
+    /// each deleted key.
+    ///
+    /// # Returns
+    ///
+    /// Returns the number of deleted keys, or a `KeyvError` on failure.
+    pub async fn purge_expired(&self) -> Result<u64, KeyvError> {
+        let purged = self.store.purge_expired().await?;
+
+        for key in &purged {
+            self.publish(KeyvChangeKind::Expire, Some(key.as_str()), None);
+        }
+
+        Ok(purged.len() as u64)
+    }
+
+    /// Returns a stream of the changes made to `key` in this namespace.
+    ///
+    /// Clearing the namespace is reported as well, since it removes the key.
+    ///
+    /// # Examples
+    ///
+    /// ```rust,no_run
+    /// # use tauri_plugin_localstore::keyv::Keyv;
+    /// # use tokio_stream::StreamExt;
+    /// # async fn run(keyv: Keyv) {
+    /// let mut changes = keyv.watch("theme");
+    ///
+    /// while let Some(change) = changes.next().await {
+    ///     println!("{:?} {:?}", change.kind, change.value);
+    /// }
+    /// # }
+    /// ```
+    pub fn watch(&self, key: &str) -> impl Stream<Item = KeyvChange> + Send + Unpin + 'static {
+        let namespace = self.store.namespace_name().to_string();
+        let key = key.to_string();
+        change_stream(self.changes.subscribe(), move |change| {
+            change.affects_key(&namespace, &key)
+        })
+    }
+
+    /// Returns a stream of the changes made to keys starting with `prefix` in this namespace.
+    ///
+    /// Clearing the namespace is reported as well, since it removes every key.
+    pub fn watch_prefix(&self, prefix: &str) -> impl Stream<Item = KeyvChange> + Send + Unpin + 'static {
+        let namespace = self.store.namespace_name().to_string();
+        let prefix = prefix.to_string();
+        change_stream(self.changes.subscribe(), move |change| {
+            change.affects_prefix(&namespace, &prefix)
+        })
+    }
+
+    /// Returns a stream of every change made through this `Keyv` and all of its
+    /// namespace handles.
+    pub fn changes(&self) -> impl Stream<Item = KeyvChange> + Send + Unpin + 'static {
+        change_stream(self.changes.subscribe(), |_| true)
+    }
+}
+
+/// Provides a default implementation for the `Keyv` struct, which creates an in-memory store.
+/// This is useful for quickly setting up a `Keyv` instance without needing to configure a
+/// specific storage backend. It does not touch any async runtime, so it can be called from
+/// both synchronous and asynchronous code.
+impl Default for Keyv {
+    fn default() -> Self {
+        Self::from_store(Arc::new(MemoryStore::new()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::keyv::{JsonFileStore, KeyvStoreBuilder, SchemaTarget};
+    use serde_json::json;
+    use tempfile::TempDir;
+    use tokio_stream::StreamExt;
+
+    /// Returns a `Keyv` on each backend with atomic writes, and the directory
+    /// holding the file of the JSON file store.
+    async fn backends() -> (TempDir, Vec<Keyv>) {
+        let dir = tempfile::tempdir().unwrap();
+        let sqlite = KeyvStoreBuilder::new().uri(":memory:").build().await.unwrap();
+        let json_file = JsonFileStore::open(dir.path().join("store.json")).await.unwrap();
+
+        let keyvs = vec![
+            Keyv::default(),
+            Keyv::try_new(sqlite).await.unwrap(),
+            Keyv::try_new(json_file).await.unwrap(),
+        ];
+        (dir, keyvs)
+    }
+
+    #[tokio::test]
+    async fn set_versioned_rejects_stale_versions() {
+        let (_dir, backends) = backends().await;
+        for keyv in backends {
+            assert_eq!(keyv.set_versioned("doc", "a", None).await.unwrap(), 1);
+            assert!(matches!(
+                keyv.set_versioned("doc", "b", None).await,
+                Err(KeyvError::StoreError(StoreError::VersionConflict {
+                    expected: None,
+                    actual: Some(1),
+                    ..
+                }))
+            ));
+
+            assert_eq!(keyv.set_versioned("doc", "b", Some(1)).await.unwrap(), 2);
+            assert!(matches!(
+                keyv.set_versioned("doc", "c", Some(1)).await,
+                Err(KeyvError::StoreError(StoreError::VersionConflict {
+                    expected: Some(1),
+                    actual: Some(2),
+                    ..
+                }))
+            ));
+
+            let current = keyv.get_versioned("doc").await.unwrap().unwrap();
+            assert_eq!((current.value, current.version), (json!("b"), 2));
+        }
+    }
+
+    #[tokio::test]
+    async fn compare_and_swap_only_replaces_the_expected_value() {
+        let (_dir, backends) = backends().await;
+        for keyv in backends {
+            assert!(keyv.compare_and_swap("leader", None, "main").await.unwrap());
+            assert!(!keyv.compare_and_swap("leader", None, "other").await.unwrap());
+            assert!(!keyv.compare_and_swap("leader", Some("other"), "third").await.unwrap());
+            assert_eq!(keyv.get("leader").await.unwrap(), Some(json!("main")));
+
+            assert!(keyv.compare_and_swap("leader", Some("main"), "other").await.unwrap());
+            assert_eq!(keyv.get("leader").await.unwrap(), Some(json!("other")));
+        }
+    }
+
+    #[tokio::test]
+    async fn increment_counts_from_zero_and_rejects_non_integers() {
+        let (_dir, backends) = backends().await;
+        for keyv in backends {
+            assert_eq!(keyv.increment("visits", 2).await.unwrap(), 2);
+            assert_eq!(keyv.increment("visits", -5).await.unwrap(), -3);
+
+            keyv.set("name", "tauri").await.unwrap();
+            assert!(matches!(
+                keyv.increment("name", 1).await,
+                Err(KeyvError::StoreError(StoreError::InvalidValue(_)))
+            ));
+
+            keyv.set("max", i64::MAX).await.unwrap();
+            assert!(keyv.increment("max", 1).await.is_err());
+            assert_eq!(keyv.get("max").await.unwrap(), Some(json!(i64::MAX)));
+        }
+    }
+
+    #[tokio::test]
+    async fn overwriting_an_expired_key_starts_a_new_entry() {
+        let (_dir, backends) = backends().await;
+        for keyv in backends {
+            keyv.set("session", "first").await.unwrap();
+            keyv.set("session", "second").await.unwrap();
+            let live = keyv.get_with_meta("session").await.unwrap().unwrap();
+            assert_eq!(live.version, 2);
+
+            keyv.set_with_ttl("session", "expiring", 0).await.unwrap();
+            tokio::time::sleep(Duration::from_millis(2)).await;
+            keyv.set("session", "new").await.unwrap();
+
+            let model = keyv.get_with_meta("session").await.unwrap().unwrap();
+            assert_eq!(model.version, 1);
+            assert!(model.created_at > live.created_at);
+            assert_eq!(model.created_at, model.updated_at);
+        }
+    }
+
+    #[tokio::test]
+    async fn get_many_skips_missing_keys_and_ignores_duplicates() {
+        let (_dir, backends) = backends().await;
+        for keyv in backends {
+            keyv.set_many([("a", 1), ("b", 2)]).await.unwrap();
+            keyv.set_with_ttl("expired", 3, 0).await.unwrap();
+
+            let values = keyv.get_many(&["a", "missing", "a", "b", "expired"]).await.unwrap();
+            assert_eq!(
+                values,
+                HashMap::from([("a".to_string(), json!(1)), ("b".to_string(), json!(2))])
+            );
+            assert!(keyv.get_many::<&str>(&[]).await.unwrap().is_empty());
+        }
+    }
+
+    #[tokio::test]
+    async fn set_many_writes_nothing_when_an_entry_is_rejected() {
+        let (_dir, backends) = backends().await;
+        for keyv in backends {
+            let schema = json!({ "type": "integer" });
+            keyv.register_schema(SchemaTarget::prefix("count:"), &schema).unwrap();
+            let mut changes = keyv.changes();
+
+            assert!(matches!(
+                keyv.set_many([("count:a", json!(1)), ("count:b", json!("two"))]).await,
+                Err(KeyvError::StoreError(StoreError::SchemaViolation { .. }))
+            ));
+            assert_eq!(keyv.get("count:a").await.unwrap(), None);
+
+            keyv.set_many([("count:a", 1), ("count:b", 2)]).await.unwrap();
+            assert_eq!(changes.next().await.unwrap().key.as_deref(), Some("count:a"));
+        }
+    }
+
+    #[tokio::test]
+    async fn remove_many_publishes_only_the_removed_keys() {
+        let (_dir, backends) = backends().await;
+        for keyv in backends {
+            keyv.set_many([("a", 1), ("b", 2)]).await.unwrap();
+            keyv.set_with_ttl("expired", 3, 0).await.unwrap();
+            let mut watch = keyv.watch("missing");
+            let mut changes = keyv.changes();
+
+            keyv.remove_many(&["a", "missing", "expired", "b"]).await.unwrap();
+            assert!(keyv.list().await.unwrap().is_empty());
+
+            keyv.set("missing", 4).await.unwrap();
+            let removed: Vec<KeyvChange> = vec![changes.next().await.unwrap(), changes.next().await.unwrap()];
+            let removed: Vec<(KeyvChangeKind, Option<&str>)> = removed
+                .iter()
+                .map(|change| (change.kind, change.key.as_deref()))
+                .collect();
+            assert_eq!(
+                removed,
+                [(KeyvChangeKind::Remove, Some("a")), (KeyvChangeKind::Remove, Some("b"))]
+            );
+
+            // The first change seen for the missing key is the write that followed.
+            let change = watch.next().await.unwrap();
+            assert_eq!((change.kind, change.value), (KeyvChangeKind::Set, Some(json!(4))));
+        }
+    }
+
+    #[tokio::test]
+    async fn expired_keys_are_hidden_until_purged() {
+        let (_dir, backends) = backends().await;
+        for keyv in backends {
+            keyv.set("kept", 1).await.unwrap();
+            keyv.set_with_ttl("later", 2, 3600).await.unwrap();
+            keyv.set_with_ttl("expired", 3, 0).await.unwrap();
+
+            assert_eq!(keyv.get("expired").await.unwrap(), None);
+            assert!(keyv.get_with_meta("later").await.unwrap().unwrap().expires_at.is_some());
+            let keys: Vec<String> = keyv.list().await.unwrap().into_iter().map(|model| model.key).collect();
+            assert_eq!(keys, ["kept", "later"]);
+
+            assert_eq!(keyv.purge_expired().await.unwrap(), 1);
+            assert_eq!(keyv.purge_expired().await.unwrap(), 0);
+        }
     }
 }
@@ -0,0 +1,703 @@
+// Copyright © 2024 Crabby Craft - All Rights Reserved.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use serde_json::Value;
+use std::collections::HashMap;
+use std::future::Future;
+use std::path::Path;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::time::Duration;
+
+use super::{
+    CacheStats, FindQuery, ListPage, ListQuery, Revision, SearchHit, SearchQuery, Store, StoreError, StoreModel,
+    StoreTransaction, UpdateFn, VersionedValue, Written,
+};
+
+/// Wraps a `Store` into another `Store`, adding behavior around every call.
+///
+/// Layers are the way to add cross-cutting concerns such as logging, metrics,
+/// key prefixing or validation to any backend without touching it. They are
+/// applied with `Keyv::layer`, each call wrapping the store built so far, so
+/// the last layer added is the outermost one and sees every call first.
+///
+/// The stores returned by a layer must wrap the handles returned by
+/// `Store::namespace` and the transactions returned by `Store::begin` as well,
+/// so that the behavior also applies to other namespaces and to transactions.
+/// Implementing [`ForwardingStore`] and [`ForwardingTransaction`] for those
+/// wrappers forwards every call the layer does not change to the inner store.
+///
+/// # Examples
+///
+/// ```rust,no_run
+/// # use tauri_plugin_localstore::keyv::{Keyv, LoggingLayer, MetricsLayer, PrefixLayer};
+/// let metrics = MetricsLayer::new();
+///
+/// let keyv = Keyv::default()
+///     .layer(PrefixLayer::new("user:42:"))
+///     .layer(metrics.clone())
+///     .layer(LoggingLayer::new());
+///
+/// let stats = metrics.snapshot();
+/// ```
+pub trait StoreLayer {
+    /// Wraps `inner`, returning the store to use in its place.
+    fn layer(&self, inner: Arc<dyn Store>) -> Arc<dyn Store>;
+}
+
+impl<L: StoreLayer + ?Sized> StoreLayer for Arc<L> {
+    fn layer(&self, inner: Arc<dyn Store>) -> Arc<dyn Store> {
+        (**self).layer(inner)
+    }
+}
+
+/// Applies two layers in turn, `inner` first then `outer` around it.
+///
+/// Useful to pass a whole stack where a single layer is expected.
+#[derive(Clone)]
+pub struct Stack<Inner, Outer> {
+    inner: Inner,
+    outer: Outer,
+}
+
+impl<Inner, Outer> Stack<Inner, Outer> {
+    pub fn new(inner: Inner, outer: Outer) -> Self {
+        Self { inner, outer }
+    }
+}
+
+impl<Inner: StoreLayer, Outer: StoreLayer> StoreLayer for Stack<Inner, Outer> {
+    fn layer(&self, inner: Arc<dyn Store>) -> Arc<dyn Store> {
+        self.outer.layer(self.inner.layer(inner))
+    }
+}
+
+/// A store wrapping another one, for use by layers.
+///
+/// Every method forwards to the store returned by `inner` unless it is
+/// overridden, so a wrapper only implements the calls it changes. Only the
+/// namespace handles and transactions, which the wrapper must wrap as well,
+/// have to be written. `Store` is implemented for every `ForwardingStore`.
+///
+/// `list_stream` is not forwarded: it walks `list_page`, so a wrapper that
+/// overrides `list_page` also applies to streams.
+pub trait ForwardingStore: Send + Sync {
+    /// Returns the wrapped store.
+    fn inner(&self) -> &dyn Store;
+
+    /// Returns the wrapped store scoped to another namespace, wrapped the same way.
+    fn namespace(&self, name: &str) -> Result<Arc<dyn Store>, StoreError>;
+
+    /// Starts a transaction on the wrapped store, wrapped the same way.
+    #[allow(clippy::type_complexity)]
+    fn begin(&self) -> Pin<Box<dyn Future<Output = Result<Box<dyn StoreTransaction>, StoreError>> + Send + '_>>;
+
+    fn initialize(&self) -> Pin<Box<dyn Future<Output = Result<(), StoreError>> + Send + '_>> {
+        self.inner().initialize()
+    }
+
+    fn get(&self, key: &str) -> Pin<Box<dyn Future<Output = Result<Option<Value>, StoreError>> + Send + '_>> {
+        self.inner().get(key)
+    }
+
+    #[allow(clippy::type_complexity)]
+    fn get_versioned(
+        &self,
+        key: &str,
+    ) -> Pin<Box<dyn Future<Output = Result<Option<VersionedValue>, StoreError>> + Send + '_>> {
+        self.inner().get_versioned(key)
+    }
+
+    #[allow(clippy::type_complexity)]
+    fn get_with_meta(
+        &self,
+        key: &str,
+    ) -> Pin<Box<dyn Future<Output = Result<Option<StoreModel>, StoreError>> + Send + '_>> {
+        self.inner().get_with_meta(key)
+    }
+
+    #[allow(clippy::type_complexity)]
+    fn get_many(
+        &self,
+        keys: &[&str],
+    ) -> Pin<Box<dyn Future<Output = Result<HashMap<String, Value>, StoreError>> + Send + '_>> {
+        self.inner().get_many(keys)
+    }
+
+    fn list(&self) -> Pin<Box<dyn Future<Output = Result<Vec<StoreModel>, StoreError>> + Send + '_>> {
+        self.inner().list()
+    }
+
+    fn list_page(&self, query: ListQuery) -> Pin<Box<dyn Future<Output = Result<ListPage, StoreError>> + Send + '_>> {
+        self.inner().list_page(query)
+    }
+
+    fn find(&self, query: FindQuery) -> Pin<Box<dyn Future<Output = Result<Vec<StoreModel>, StoreError>> + Send + '_>> {
+        self.inner().find(query)
+    }
+
+    #[allow(clippy::type_complexity)]
+    fn set(
+        &self,
+        key: &str,
+        value: Value,
+        ttl: Option<u64>,
+    ) -> Pin<Box<dyn Future<Output = Result<Written<Option<StoreModel>>, StoreError>> + Send + '_>> {
+        self.inner().set(key, value, ttl)
+    }
+
+    fn set_many(
+        &self,
+        entries: Vec<(String, Value)>,
+        ttl: Option<u64>,
+    ) -> Pin<Box<dyn Future<Output = Result<Written<()>, StoreError>> + Send + '_>> {
+        self.inner().set_many(entries, ttl)
+    }
+
+    fn set_versioned(
+        &self,
+        key: &str,
+        value: Value,
+        expected_version: Option<u64>,
+        ttl: Option<u64>,
+    ) -> Pin<Box<dyn Future<Output = Result<Written<u64>, StoreError>> + Send + '_>> {
+        self.inner().set_versioned(key, value, expected_version, ttl)
+    }
+
+    fn compare_and_swap(
+        &self,
+        key: &str,
+        expected: Option<Value>,
+        new: Value,
+    ) -> Pin<Box<dyn Future<Output = Result<Written<bool>, StoreError>> + Send + '_>> {
+        self.inner().compare_and_swap(key, expected, new)
+    }
+
+    fn increment(
+        &self,
+        key: &str,
+        delta: i64,
+    ) -> Pin<Box<dyn Future<Output = Result<Written<i64>, StoreError>> + Send + '_>> {
+        self.inner().increment(key, delta)
+    }
+
+    fn update<'a>(
+        &'a self,
+        key: &str,
+        f: UpdateFn<'a>,
+    ) -> Pin<Box<dyn Future<Output = Result<Written<Value>, StoreError>> + Send + 'a>> {
+        self.inner().update(key, f)
+    }
+
+    fn remove(&self, key: &str) -> Pin<Box<dyn Future<Output = Result<(), StoreError>> + Send + '_>> {
+        self.inner().remove(key)
+    }
+
+    fn remove_many(&self, keys: &[&str]) -> Pin<Box<dyn Future<Output = Result<Vec<String>, StoreError>> + Send + '_>> {
+        self.inner().remove_many(keys)
+    }
+
+    fn clear(&self) -> Pin<Box<dyn Future<Output = Result<(), StoreError>> + Send + '_>> {
+        self.inner().clear()
+    }
+
+    fn purge_expired(&self) -> Pin<Box<dyn Future<Output = Result<Vec<String>, StoreError>> + Send + '_>> {
+        self.inner().purge_expired()
+    }
+
+    fn namespace_name(&self) -> &str {
+        self.inner().namespace_name()
+    }
+
+    fn cache_stats(&self) -> Option<CacheStats> {
+        self.inner().cache_stats()
+    }
+
+    fn backup(&self, path: &Path) -> Pin<Box<dyn Future<Output = Result<(), StoreError>> + Send + '_>> {
+        self.inner().backup(path)
+    }
+
+    fn rotate_encryption_key(
+        &self,
+        new_key: Option<[u8; 32]>,
+    ) -> Pin<Box<dyn Future<Output = Result<u64, StoreError>> + Send + '_>> {
+        self.inner().rotate_encryption_key(new_key)
+    }
+
+    fn set_history(&self, limit: Option<usize>) -> Pin<Box<dyn Future<Output = Result<(), StoreError>> + Send + '_>> {
+        self.inner().set_history(limit)
+    }
+
+    fn history(&self, key: &str) -> Pin<Box<dyn Future<Output = Result<Vec<Revision>, StoreError>> + Send + '_>> {
+        self.inner().history(key)
+    }
+
+    fn get_revision(
+        &self,
+        key: &str,
+        id: i64,
+    ) -> Pin<Box<dyn Future<Output = Result<Option<Revision>, StoreError>> + Send + '_>> {
+        self.inner().get_revision(key, id)
+    }
+
+    fn revert(
+        &self,
+        key: &str,
+        id: i64,
+    ) -> Pin<Box<dyn Future<Output = Result<Written<StoreModel>, StoreError>> + Send + '_>> {
+        self.inner().revert(key, id)
+    }
+
+    fn set_search(&self, enabled: bool) -> Pin<Box<dyn Future<Output = Result<(), StoreError>> + Send + '_>> {
+        self.inner().set_search(enabled)
+    }
+
+    fn search(
+        &self,
+        query: SearchQuery,
+    ) -> Pin<Box<dyn Future<Output = Result<Vec<SearchHit>, StoreError>> + Send + '_>> {
+        self.inner().search(query)
+    }
+
+    fn create_index(
+        &self,
+        name: &str,
+        path: &str,
+    ) -> Pin<Box<dyn Future<Output = Result<(), StoreError>> + Send + '_>> {
+        self.inner().create_index(name, path)
+    }
+
+    fn drop_index(&self, name: &str) -> Pin<Box<dyn Future<Output = Result<(), StoreError>> + Send + '_>> {
+        self.inner().drop_index(name)
+    }
+
+    fn acquire_lease(
+        &self,
+        name: &str,
+        owner: &str,
+        ttl: Duration,
+    ) -> Pin<Box<dyn Future<Output = Result<bool, StoreError>> + Send + '_>> {
+        self.inner().acquire_lease(name, owner, ttl)
+    }
+
+    fn renew_lease(
+        &self,
+        name: &str,
+        owner: &str,
+        ttl: Duration,
+    ) -> Pin<Box<dyn Future<Output = Result<bool, StoreError>> + Send + '_>> {
+        self.inner().renew_lease(name, owner, ttl)
+    }
+
+    fn release_lease(
+        &self,
+        name: &str,
+        owner: &str,
+    ) -> Pin<Box<dyn Future<Output = Result<(), StoreError>> + Send + '_>> {
+        self.inner().release_lease(name, owner)
+    }
+
+    fn list_namespaces(&self) -> Pin<Box<dyn Future<Output = Result<Vec<String>, StoreError>> + Send + '_>> {
+        self.inner().list_namespaces()
+    }
+
+    fn rename_namespace(
+        &self,
+        from: &str,
+        to: &str,
+    ) -> Pin<Box<dyn Future<Output = Result<(), StoreError>> + Send + '_>> {
+        self.inner().rename_namespace(from, to)
+    }
+
+    fn drop_namespace(&self, name: &str) -> Pin<Box<dyn Future<Output = Result<(), StoreError>> + Send + '_>> {
+        self.inner().drop_namespace(name)
+    }
+}
+
+impl<S: ForwardingStore> Store for S {
+    fn initialize(&self) -> Pin<Box<dyn Future<Output = Result<(), StoreError>> + Send + '_>> {
+        ForwardingStore::initialize(self)
+    }
+
+    fn get(&self, key: &str) -> Pin<Box<dyn Future<Output = Result<Option<Value>, StoreError>> + Send + '_>> {
+        ForwardingStore::get(self, key)
+    }
+
+    fn get_versioned(
+        &self,
+        key: &str,
+    ) -> Pin<Box<dyn Future<Output = Result<Option<VersionedValue>, StoreError>> + Send + '_>> {
+        ForwardingStore::get_versioned(self, key)
+    }
+
+    fn get_with_meta(
+        &self,
+        key: &str,
+    ) -> Pin<Box<dyn Future<Output = Result<Option<StoreModel>, StoreError>> + Send + '_>> {
+        ForwardingStore::get_with_meta(self, key)
+    }
+
+    fn get_many(
+        &self,
+        keys: &[&str],
+    ) -> Pin<Box<dyn Future<Output = Result<HashMap<String, Value>, StoreError>> + Send + '_>> {
+        ForwardingStore::get_many(self, keys)
+    }
+
+    fn list(&self) -> Pin<Box<dyn Future<Output = Result<Vec<StoreModel>, StoreError>> + Send + '_>> {
+        ForwardingStore::list(self)
+    }
+
+    fn list_page(&self, query: ListQuery) -> Pin<Box<dyn Future<Output = Result<ListPage, StoreError>> + Send + '_>> {
+        ForwardingStore::list_page(self, query)
+    }
+
+    fn find(&self, query: FindQuery) -> Pin<Box<dyn Future<Output = Result<Vec<StoreModel>, StoreError>> + Send + '_>> {
+        ForwardingStore::find(self, query)
+    }
+
+    fn set(
+        &self,
+        key: &str,
+        value: Value,
+        ttl: Option<u64>,
+    ) -> Pin<Box<dyn Future<Output = Result<Written<Option<StoreModel>>, StoreError>> + Send + '_>> {
+        ForwardingStore::set(self, key, value, ttl)
+    }
+
+    fn set_many(
+        &self,
+        entries: Vec<(String, Value)>,
+        ttl: Option<u64>,
+    ) -> Pin<Box<dyn Future<Output = Result<Written<()>, StoreError>> + Send + '_>> {
+        ForwardingStore::set_many(self, entries, ttl)
+    }
+
+    fn set_versioned(
+        &self,
+        key: &str,
+        value: Value,
+        expected_version: Option<u64>,
+        ttl: Option<u64>,
+    ) -> Pin<Box<dyn Future<Output = Result<Written<u64>, StoreError>> + Send + '_>> {
+        ForwardingStore::set_versioned(self, key, value, expected_version, ttl)
+    }
+
+    fn compare_and_swap(
+        &self,
+        key: &str,
+        expected: Option<Value>,
+        new: Value,
+    ) -> Pin<Box<dyn Future<Output = Result<Written<bool>, StoreError>> + Send + '_>> {
+        ForwardingStore::compare_and_swap(self, key, expected, new)
+    }
+
+    fn increment(
+        &self,
+        key: &str,
+        delta: i64,
+    ) -> Pin<Box<dyn Future<Output = Result<Written<i64>, StoreError>> + Send + '_>> {
+        ForwardingStore::increment(self, key, delta)
+    }
+
+    fn update<'a>(
+        &'a self,
+        key: &str,
+        f: UpdateFn<'a>,
+    ) -> Pin<Box<dyn Future<Output = Result<Written<Value>, StoreError>> + Send + 'a>> {
+        ForwardingStore::update(self, key, f)
+    }
+
+    fn remove(&self, key: &str) -> Pin<Box<dyn Future<Output = Result<(), StoreError>> + Send + '_>> {
+        ForwardingStore::remove(self, key)
+    }
+
+    fn remove_many(&self, keys: &[&str]) -> Pin<Box<dyn Future<Output = Result<Vec<String>, StoreError>> + Send + '_>> {
+        ForwardingStore::remove_many(self, keys)
+    }
+
+    fn clear(&self) -> Pin<Box<dyn Future<Output = Result<(), StoreError>> + Send + '_>> {
+        ForwardingStore::clear(self)
+    }
+
+    fn purge_expired(&self) -> Pin<Box<dyn Future<Output = Result<Vec<String>, StoreError>> + Send + '_>> {
+        ForwardingStore::purge_expired(self)
+    }
+
+    fn namespace_name(&self) -> &str {
+        ForwardingStore::namespace_name(self)
+    }
+
+    fn cache_stats(&self) -> Option<CacheStats> {
+        ForwardingStore::cache_stats(self)
+    }
+
+    fn backup(&self, path: &Path) -> Pin<Box<dyn Future<Output = Result<(), StoreError>> + Send + '_>> {
+        ForwardingStore::backup(self, path)
+    }
+
+    fn rotate_encryption_key(
+        &self,
+        new_key: Option<[u8; 32]>,
+    ) -> Pin<Box<dyn Future<Output = Result<u64, StoreError>> + Send + '_>> {
+        ForwardingStore::rotate_encryption_key(self, new_key)
+    }
+
+    fn set_history(&self, limit: Option<usize>) -> Pin<Box<dyn Future<Output = Result<(), StoreError>> + Send + '_>> {
+        ForwardingStore::set_history(self, limit)
+    }
+
+    fn history(&self, key: &str) -> Pin<Box<dyn Future<Output = Result<Vec<Revision>, StoreError>> + Send + '_>> {
+        ForwardingStore::history(self, key)
+    }
+
+    fn get_revision(
+        &self,
+        key: &str,
+        id: i64,
+    ) -> Pin<Box<dyn Future<Output = Result<Option<Revision>, StoreError>> + Send + '_>> {
+        ForwardingStore::get_revision(self, key, id)
+    }
+
+    fn revert(
+        &self,
+        key: &str,
+        id: i64,
+    ) -> Pin<Box<dyn Future<Output = Result<Written<StoreModel>, StoreError>> + Send + '_>> {
+        ForwardingStore::revert(self, key, id)
+    }
+
+    fn set_search(&self, enabled: bool) -> Pin<Box<dyn Future<Output = Result<(), StoreError>> + Send + '_>> {
+        ForwardingStore::set_search(self, enabled)
+    }
+
+    fn search(
+        &self,
+        query: SearchQuery,
+    ) -> Pin<Box<dyn Future<Output = Result<Vec<SearchHit>, StoreError>> + Send + '_>> {
+        ForwardingStore::search(self, query)
+    }
+
+    fn create_index(
+        &self,
+        name: &str,
+        path: &str,
+    ) -> Pin<Box<dyn Future<Output = Result<(), StoreError>> + Send + '_>> {
+        ForwardingStore::create_index(self, name, path)
+    }
+
+    fn drop_index(&self, name: &str) -> Pin<Box<dyn Future<Output = Result<(), StoreError>> + Send + '_>> {
+        ForwardingStore::drop_index(self, name)
+    }
+
+    fn acquire_lease(
+        &self,
+        name: &str,
+        owner: &str,
+        ttl: Duration,
+    ) -> Pin<Box<dyn Future<Output = Result<bool, StoreError>> + Send + '_>> {
+        ForwardingStore::acquire_lease(self, name, owner, ttl)
+    }
+
+    fn renew_lease(
+        &self,
+        name: &str,
+        owner: &str,
+        ttl: Duration,
+    ) -> Pin<Box<dyn Future<Output = Result<bool, StoreError>> + Send + '_>> {
+        ForwardingStore::renew_lease(self, name, owner, ttl)
+    }
+
+    fn release_lease(
+        &self,
+        name: &str,
+        owner: &str,
+    ) -> Pin<Box<dyn Future<Output = Result<(), StoreError>> + Send + '_>> {
+        ForwardingStore::release_lease(self, name, owner)
+    }
+
+    fn namespace(&self, name: &str) -> Result<Arc<dyn Store>, StoreError> {
+        ForwardingStore::namespace(self, name)
+    }
+
+    fn list_namespaces(&self) -> Pin<Box<dyn Future<Output = Result<Vec<String>, StoreError>> + Send + '_>> {
+        ForwardingStore::list_namespaces(self)
+    }
+
+    fn rename_namespace(
+        &self,
+        from: &str,
+        to: &str,
+    ) -> Pin<Box<dyn Future<Output = Result<(), StoreError>> + Send + '_>> {
+        ForwardingStore::rename_namespace(self, from, to)
+    }
+
+    fn drop_namespace(&self, name: &str) -> Pin<Box<dyn Future<Output = Result<(), StoreError>> + Send + '_>> {
+        ForwardingStore::drop_namespace(self, name)
+    }
+
+    fn begin(&self) -> Pin<Box<dyn Future<Output = Result<Box<dyn StoreTransaction>, StoreError>> + Send + '_>> {
+        ForwardingStore::begin(self)
+    }
+}
+
+/// A transaction wrapping another one, returned by the `begin` of a [`ForwardingStore`].
+///
+/// Every method forwards to the transaction returned by `inner` unless it is
+/// overridden. `StoreTransaction` is implemented for every `ForwardingTransaction`.
+pub trait ForwardingTransaction: Send + Sync {
+    /// Returns the wrapped transaction.
+    fn inner(&self) -> &dyn StoreTransaction;
+
+    fn get(
+        &self,
+        namespace: &str,
+        key: &str,
+    ) -> Pin<Box<dyn Future<Output = Result<Option<Value>, StoreError>> + Send + '_>> {
+        self.inner().get(namespace, key)
+    }
+
+    fn keys(&self, namespace: &str) -> Pin<Box<dyn Future<Output = Result<Vec<String>, StoreError>> + Send + '_>> {
+        self.inner().keys(namespace)
+    }
+
+    fn set(
+        &self,
+        namespace: &str,
+        key: &str,
+        value: Value,
+        ttl: Option<u64>,
+    ) -> Pin<Box<dyn Future<Output = Result<Written<()>, StoreError>> + Send + '_>> {
+        self.inner().set(namespace, key, value, ttl)
+    }
+
+    fn remove(&self, namespace: &str, key: &str) -> Pin<Box<dyn Future<Output = Result<(), StoreError>> + Send + '_>> {
+        self.inner().remove(namespace, key)
+    }
+
+    fn commit(&self) -> Pin<Box<dyn Future<Output = Result<(), StoreError>> + Send + '_>> {
+        self.inner().commit()
+    }
+
+    fn rollback(&self) -> Pin<Box<dyn Future<Output = Result<(), StoreError>> + Send + '_>> {
+        self.inner().rollback()
+    }
+}
+
+impl<T: ForwardingTransaction> StoreTransaction for T {
+    fn get(
+        &self,
+        namespace: &str,
+        key: &str,
+    ) -> Pin<Box<dyn Future<Output = Result<Option<Value>, StoreError>> + Send + '_>> {
+        ForwardingTransaction::get(self, namespace, key)
+    }
+
+    fn keys(&self, namespace: &str) -> Pin<Box<dyn Future<Output = Result<Vec<String>, StoreError>> + Send + '_>> {
+        ForwardingTransaction::keys(self, namespace)
+    }
+
+    fn set(
+        &self,
+        namespace: &str,
+        key: &str,
+        value: Value,
+        ttl: Option<u64>,
+    ) -> Pin<Box<dyn Future<Output = Result<Written<()>, StoreError>> + Send + '_>> {
+        ForwardingTransaction::set(self, namespace, key, value, ttl)
+    }
+
+    fn remove(&self, namespace: &str, key: &str) -> Pin<Box<dyn Future<Output = Result<(), StoreError>> + Send + '_>> {
+        ForwardingTransaction::remove(self, namespace, key)
+    }
+
+    fn commit(&self) -> Pin<Box<dyn Future<Output = Result<(), StoreError>> + Send + '_>> {
+        ForwardingTransaction::commit(self)
+    }
+
+    fn rollback(&self) -> Pin<Box<dyn Future<Output = Result<(), StoreError>> + Send + '_>> {
+        ForwardingTransaction::rollback(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::keyv::{Keyv, KeyvError, ListQuery, MemoryStore, PrefixLayer};
+    use serde_json::json;
+
+    /// Overrides nothing, so every call goes through the default forwarding.
+    struct Passthrough(Arc<dyn Store>);
+
+    impl ForwardingStore for Passthrough {
+        fn inner(&self) -> &dyn Store {
+            &*self.0
+        }
+
+        fn namespace(&self, name: &str) -> Result<Arc<dyn Store>, StoreError> {
+            Ok(Arc::new(Passthrough(self.0.namespace(name)?)))
+        }
+
+        fn begin(&self) -> Pin<Box<dyn Future<Output = Result<Box<dyn StoreTransaction>, StoreError>> + Send + '_>> {
+            self.0.begin()
+        }
+    }
+
+    struct PassthroughLayer;
+
+    impl StoreLayer for PassthroughLayer {
+        fn layer(&self, inner: Arc<dyn Store>) -> Arc<dyn Store> {
+            Arc::new(Passthrough(inner))
+        }
+    }
+
+    #[tokio::test]
+    async fn unchanged_methods_are_forwarded() {
+        let keyv = Keyv::default().layer(PassthroughLayer);
+
+        keyv.set_many([("a", 1), ("b", 2)]).await.unwrap();
+        assert_eq!(keyv.set_versioned("c", 3, None).await.unwrap(), 1);
+        assert!(keyv.compare_and_swap("a", Some(1), 10).await.unwrap());
+        assert_eq!(keyv.increment("b", 5).await.unwrap(), 7);
+        assert_eq!(keyv.update("c", |c: Option<i64>| c.unwrap_or(0) * 2).await.unwrap(), 6);
+
+        let values = keyv.get_many(&["a", "b", "missing"]).await.unwrap();
+        assert_eq!(values.len(), 2);
+        let page = keyv.list_page(ListQuery::new().limit(2)).await.unwrap();
+        assert_eq!(page.next_cursor.as_deref(), Some("b"));
+
+        keyv.set_with_ttl("expired", true, 0).await.unwrap();
+        assert_eq!(keyv.purge_expired().await.unwrap(), 1);
+        keyv.remove_many(&["a", "b"]).await.unwrap();
+        assert_eq!(keyv.list().await.unwrap().len(), 1);
+
+        keyv.namespace("other").unwrap().set("x", 1).await.unwrap();
+        assert_eq!(keyv.namespaces().await.unwrap().len(), 2);
+        keyv.transaction(|tx| async move { tx.set("d", 4).await })
+            .await
+            .unwrap();
+        assert_eq!(keyv.get("d").await.unwrap(), Some(json!(4)));
+
+        // Errors of the inner store are forwarded as well.
+        assert!(matches!(
+            keyv.enable_search().await,
+            Err(KeyvError::StoreError(StoreError::Unsupported(_)))
+        ));
+    }
+
+    #[tokio::test]
+    async fn stacks_apply_the_outer_layer_first() {
+        let keyv = Keyv::try_new(MemoryStore::new()).await.unwrap();
+        let stacked = keyv
+            .clone()
+            .layer(Stack::new(PrefixLayer::new("inner:"), PrefixLayer::new("outer:")));
+
+        stacked.set("key", 1).await.unwrap();
+        assert_eq!(keyv.get("inner:outer:key").await.unwrap(), Some(json!(1)));
+    }
+}
@@ -0,0 +1,316 @@
+// Copyright © 2024 Crabby Craft - All Rights Reserved.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::{Mutex, PoisonError};
+
+use super::StoreError;
+
+/// Number of reads tracked per namespace for LRU eviction, unless the namespace
+/// has a higher key count limit.
+const TRACKED_READS: usize = 10_000;
+
+/// Which entries are removed to make room when a write would take a namespace
+/// over its key count or byte limit, see [`NamespaceLimits::evict`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EvictionPolicy {
+    /// Removes the entries created first.
+    OldestFirst,
+    /// Removes the entries read or written least recently. Reads are tracked
+    /// in memory, so after a restart entries are ordered by their last write.
+    Lru,
+}
+
+/// Limits on the size of a namespace, set with `KeyvStoreBuilder::limits` or
+/// `KeyvStoreBuilder::namespace_limits`.
+///
+/// Sizes are counted as stored, after compression and encryption. Expired
+/// entries are not counted. Without an eviction policy, a write that would
+/// exceed a limit fails and leaves the namespace unchanged.
+#[derive(Debug, Clone, Default)]
+pub struct NamespaceLimits {
+    pub(crate) max_value_size: Option<usize>,
+    pub(crate) max_keys: Option<u64>,
+    pub(crate) max_bytes: Option<u64>,
+    pub(crate) eviction: Option<EvictionPolicy>,
+}
+
+impl NamespaceLimits {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the maximum size of a single value in bytes. Larger values are
+    /// rejected with `StoreError::ValueTooLarge`, even with an eviction policy.
+    pub fn max_value_size(mut self, bytes: usize) -> Self {
+        self.max_value_size = Some(bytes);
+        self
+    }
+
+    /// Sets the maximum number of keys, beyond which writes fail with
+    /// `StoreError::TooManyKeys`.
+    pub fn max_keys(mut self, count: u64) -> Self {
+        self.max_keys = Some(count);
+        self
+    }
+
+    /// Sets the maximum total size of the keys and values in bytes, beyond
+    /// which writes fail with `StoreError::QuotaExceeded`.
+    pub fn max_bytes(mut self, bytes: u64) -> Self {
+        self.max_bytes = Some(bytes);
+        self
+    }
+
+    /// Evicts entries instead of failing when a write would exceed the key
+    /// count or byte limit, e.g. for namespaces used as a cache. The keys
+    /// being written are never evicted.
+    pub fn evict(mut self, policy: EvictionPolicy) -> Self {
+        self.eviction = Some(policy);
+        self
+    }
+
+    /// Returns `true` if the namespace size is limited, so writes must check it.
+    pub(crate) fn limits_size(&self) -> bool {
+        self.max_keys.is_some() || self.max_bytes.is_some()
+    }
+
+    /// Fails if a value of `size` bytes is too large to be stored for `key`.
+    pub(crate) fn check_value_size(&self, key: &str, size: usize) -> Result<(), StoreError> {
+        match self.max_value_size {
+            Some(limit) if size > limit => Err(StoreError::ValueTooLarge {
+                key: key.to_string(),
+                size,
+                limit,
+            }),
+            _ => Ok(()),
+        }
+    }
+
+    /// Fails if a namespace holding `keys` entries of `bytes` in total is over
+    /// the key count or byte limit.
+    pub(crate) fn check_size(&self, namespace: &str, keys: u64, bytes: u64) -> Result<(), StoreError> {
+        if let Some(limit) = self.max_keys.filter(|limit| keys > *limit) {
+            return Err(StoreError::TooManyKeys {
+                namespace: namespace.to_string(),
+                limit,
+            });
+        }
+
+        if let Some(limit) = self.max_bytes.filter(|limit| bytes > *limit) {
+            return Err(StoreError::QuotaExceeded {
+                namespace: namespace.to_string(),
+                limit,
+            });
+        }
+
+        Ok(())
+    }
+}
+
+/// The limits of every namespace of a `KeyvStore`, together with the reads
+/// tracked for LRU eviction. Shared by every namespace of the store.
+#[derive(Debug, Default)]
+pub(crate) struct StoreLimits {
+    default: NamespaceLimits,
+    namespaces: HashMap<String, NamespaceLimits>,
+    /// Last read of each key, in milliseconds since the Unix epoch, for the
+    /// namespaces evicting with `EvictionPolicy::Lru`. Bounded, see `record_access`.
+    accessed: Mutex<HashMap<String, HashMap<String, i64>>>,
+}
+
+impl StoreLimits {
+    pub(crate) fn new(default: NamespaceLimits, namespaces: HashMap<String, NamespaceLimits>) -> Self {
+        Self {
+            default,
+            namespaces,
+            accessed: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Returns the limits of a namespace, its own if set or the default ones.
+    pub(crate) fn get(&self, namespace: &str) -> &NamespaceLimits {
+        self.namespaces.get(namespace).unwrap_or(&self.default)
+    }
+
+    /// Records a read of `keys` if the namespace evicts the least recently used entries.
+    ///
+    /// Reads of keys removed without going through eviction are never
+    /// forgotten, so once twice as many keys as the namespace may hold are
+    /// tracked, only the most recent reads are kept. Keys whose read is dropped
+    /// are ordered by their last write, as after a restart.
+    pub(crate) fn record_access<'a, I>(&self, namespace: &str, keys: I, now: i64)
+    where
+        I: IntoIterator<Item = &'a str>,
+    {
+        let limits = self.get(namespace);
+
+        if limits.eviction != Some(EvictionPolicy::Lru) {
+            return;
+        }
+
+        let capacity = limits
+            .max_keys
+            .map_or(TRACKED_READS, |max_keys| (max_keys as usize).max(TRACKED_READS));
+
+        let mut accessed = self.accessed.lock().unwrap_or_else(PoisonError::into_inner);
+        let accessed = accessed.entry(namespace.to_string()).or_default();
+
+        for key in keys {
+            accessed.insert(key.to_string(), now);
+        }
+
+        if accessed.len() > capacity * 2 {
+            let mut times: Vec<i64> = accessed.values().copied().collect();
+            times.sort_unstable_by(|a, b| b.cmp(a));
+            let oldest_kept = times[capacity - 1];
+            accessed.retain(|_, accessed_at| *accessed_at >= oldest_kept);
+        }
+    }
+
+    /// Returns when `key` was last read, if tracked.
+    pub(crate) fn last_access(&self, namespace: &str, key: &str) -> Option<i64> {
+        self.accessed
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .get(namespace)
+            .and_then(|accessed| accessed.get(key).copied())
+    }
+
+    /// Forgets the reads of evicted or removed keys.
+    pub(crate) fn forget<'a, I>(&self, namespace: &str, keys: I)
+    where
+        I: IntoIterator<Item = &'a str>,
+    {
+        let mut accessed = self.accessed.lock().unwrap_or_else(PoisonError::into_inner);
+
+        if let Some(accessed) = accessed.get_mut(namespace) {
+            for key in keys {
+                accessed.remove(key);
+            }
+        }
+    }
+
+    /// Forgets the reads of every key in a cleared, renamed or dropped namespace.
+    pub(crate) fn forget_namespace(&self, namespace: &str) {
+        self.accessed
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .remove(namespace);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::keyv::{Keyv, KeyvChangeKind, KeyvError, KeyvStoreBuilder};
+    use serde_json::json;
+    use std::time::Duration;
+    use tokio_stream::StreamExt;
+
+    async fn limited_keyv(limits: NamespaceLimits) -> Keyv {
+        let store = KeyvStoreBuilder::new()
+            .uri(":memory:")
+            .limits(limits)
+            .build()
+            .await
+            .unwrap();
+        Keyv::try_new(store).await.unwrap()
+    }
+
+    /// Lets the clock move on, so that consecutive writes and reads are ordered.
+    async fn tick() {
+        tokio::time::sleep(Duration::from_millis(2)).await;
+    }
+
+    #[tokio::test]
+    async fn writes_over_the_limits_fail_without_an_eviction_policy() {
+        let keyv = limited_keyv(NamespaceLimits::new().max_keys(2).max_value_size(16)).await;
+        keyv.set("a", 1).await.unwrap();
+        keyv.set("b", 2).await.unwrap();
+
+        assert!(matches!(
+            keyv.set("c", 3).await,
+            Err(KeyvError::StoreError(StoreError::TooManyKeys { limit: 2, .. }))
+        ));
+        assert_eq!(keyv.get("c").await.unwrap(), None);
+
+        // Overwriting a key does not grow the namespace.
+        keyv.set("b", 20).await.unwrap();
+
+        assert!(matches!(
+            keyv.set("a", "a value longer than sixteen bytes").await,
+            Err(KeyvError::StoreError(StoreError::ValueTooLarge { limit: 16, .. }))
+        ));
+        assert_eq!(keyv.get("a").await.unwrap(), Some(json!(1)));
+    }
+
+    #[tokio::test]
+    async fn evicted_keys_are_published_and_kept_out_of_history() {
+        let keyv = limited_keyv(NamespaceLimits::new().max_keys(2).evict(EvictionPolicy::OldestFirst)).await;
+        keyv.enable_history(10).await.unwrap();
+        keyv.set("a", 1).await.unwrap();
+        tick().await;
+        keyv.set("b", 2).await.unwrap();
+        tick().await;
+
+        let mut changes = keyv.changes();
+        keyv.set("c", 3).await.unwrap();
+
+        assert_eq!(keyv.get("a").await.unwrap(), None);
+        assert_eq!(keyv.list().await.unwrap().len(), 2);
+
+        let set = changes.next().await.unwrap();
+        let evicted = changes.next().await.unwrap();
+        assert_eq!((set.kind, set.key.as_deref()), (KeyvChangeKind::Set, Some("c")));
+        assert_eq!(
+            (evicted.kind, evicted.key.as_deref()),
+            (KeyvChangeKind::Remove, Some("a"))
+        );
+
+        assert!(keyv.history("a").await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn lru_eviction_keeps_recently_read_keys() {
+        let keyv = limited_keyv(NamespaceLimits::new().max_keys(2).evict(EvictionPolicy::Lru)).await;
+        keyv.set("a", 1).await.unwrap();
+        tick().await;
+        keyv.set("b", 2).await.unwrap();
+        tick().await;
+        keyv.get("a").await.unwrap();
+        tick().await;
+
+        keyv.set("c", 3).await.unwrap();
+        assert_eq!(keyv.get("a").await.unwrap(), Some(json!(1)));
+        assert_eq!(keyv.get("b").await.unwrap(), None);
+    }
+
+    #[test]
+    fn tracked_reads_are_bounded() {
+        let limits = StoreLimits::new(NamespaceLimits::new().evict(EvictionPolicy::Lru), HashMap::new());
+
+        for i in 0..=TRACKED_READS * 2 {
+            limits.record_access("cache", [format!("key:{}", i).as_str()], i as i64);
+        }
+
+        let tracked = limits.accessed.lock().unwrap()["cache"].len();
+        assert_eq!(tracked, TRACKED_READS);
+        assert_eq!(limits.last_access("cache", "key:0"), None);
+        assert_eq!(
+            limits.last_access("cache", &format!("key:{}", TRACKED_READS * 2)),
+            Some((TRACKED_READS * 2) as i64)
+        );
+
+        // Namespaces that do not evict the least recently used keys track nothing.
+        let limits = StoreLimits::new(NamespaceLimits::new().max_keys(1), HashMap::new());
+        limits.record_access("cache", ["a"], 1);
+        assert_eq!(limits.last_access("cache", "a"), None);
+    }
+}
@@ -0,0 +1,281 @@
+// Copyright © 2024 Crabby Craft - All Rights Reserved.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use tokio_stream::Stream;
+
+use super::{StoreError, StoreModel};
+
+/// Number of entries fetched per round trip by [`ListStream`].
+pub(super) const LIST_STREAM_PAGE_SIZE: u64 = 256;
+
+/// Filters and pagination for listing entries, in ascending key order.
+///
+/// All bounds are combined, so a query can for example select a prefix and
+/// continue after a cursor at the same time.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ListQuery {
+    /// Only include keys starting with this prefix.
+    pub prefix: Option<String>,
+    /// Only include keys greater than or equal to this key.
+    pub start: Option<String>,
+    /// Only include keys strictly less than this key.
+    pub end: Option<String>,
+    /// Only include keys strictly greater than this key. Pass the
+    /// `next_cursor` of a previous page here to fetch the next one.
+    pub after: Option<String>,
+    /// Maximum number of entries to return.
+    pub limit: Option<u64>,
+}
+
+impl ListQuery {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn prefix<S: Into<String>>(mut self, prefix: S) -> Self {
+        self.prefix = Some(prefix.into());
+        self
+    }
+
+    pub fn range<S: Into<String>>(mut self, start: S, end: S) -> Self {
+        self.start = Some(start.into());
+        self.end = Some(end.into());
+        self
+    }
+
+    pub fn after<S: Into<String>>(mut self, cursor: S) -> Self {
+        self.after = Some(cursor.into());
+        self
+    }
+
+    pub fn limit(mut self, limit: u64) -> Self {
+        self.limit = Some(limit);
+        self
+    }
+
+    /// Returns `true` if `key` satisfies every bound of the query, ignoring `limit`.
+    pub fn matches(&self, key: &str) -> bool {
+        self.prefix.as_deref().map_or(true, |p| key.starts_with(p))
+            && self.start.as_deref().map_or(true, |s| key >= s)
+            && self.end.as_deref().map_or(true, |e| key < e)
+            && self.after.as_deref().map_or(true, |a| key > a)
+    }
+}
+
+/// One page of entries returned by a [`ListQuery`].
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ListPage {
+    pub items: Vec<StoreModel>,
+    /// Cursor for the next page, `None` when there are no more entries.
+    pub next_cursor: Option<String>,
+}
+
+/// Returns the smallest string that is greater than every string starting with
+/// `prefix`, or `None` if no such string exists.
+///
+/// Keys are compared by their UTF-8 bytes, which matches code point order, so
+/// incrementing the last character that is not `char::MAX` gives the bound.
+pub(crate) fn prefix_upper_bound(prefix: &str) -> Option<String> {
+    let mut chars: Vec<char> = prefix.chars().collect();
+
+    while let Some(last) = chars.pop() {
+        let next = match last {
+            '\u{D7FF}' => Some('\u{E000}'),
+            c => char::from_u32(c as u32 + 1),
+        };
+
+        if let Some(next) = next {
+            chars.push(next);
+            return Some(chars.into_iter().collect());
+        }
+    }
+
+    None
+}
+
+type PageFuture<'a> = Pin<Box<dyn Future<Output = Result<ListPage, StoreError>> + Send + 'a>>;
+type PageFetcher<'a> = Box<dyn FnMut(ListQuery) -> PageFuture<'a> + Send + 'a>;
+
+/// A stream over the entries matching a [`ListQuery`].
+///
+/// Entries are fetched one page at a time, so memory use stays bounded no
+/// matter how many entries match.
+pub struct ListStream<'a> {
+    query: ListQuery,
+    remaining: Option<u64>,
+    buffer: VecDeque<StoreModel>,
+    pending: Option<PageFuture<'a>>,
+    fetch: PageFetcher<'a>,
+    done: bool,
+}
+
+impl<'a> ListStream<'a> {
+    /// Creates a stream that calls `fetch` for each page of `query`.
+    pub fn new<F>(query: ListQuery, fetch: F) -> Self
+    where
+        F: FnMut(ListQuery) -> PageFuture<'a> + Send + 'a,
+    {
+        Self {
+            remaining: query.limit,
+            query,
+            buffer: VecDeque::new(),
+            pending: None,
+            fetch: Box::new(fetch),
+            done: false,
+        }
+    }
+}
+
+impl Stream for ListStream<'_> {
+    type Item = Result<StoreModel, StoreError>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = &mut *self;
+
+        loop {
+            if let Some(item) = this.buffer.pop_front() {
+                return Poll::Ready(Some(Ok(item)));
+            }
+
+            if this.done || this.remaining == Some(0) {
+                return Poll::Ready(None);
+            }
+
+            if this.pending.is_none() {
+                let page_size = this
+                    .remaining
+                    .map_or(LIST_STREAM_PAGE_SIZE, |r| r.min(LIST_STREAM_PAGE_SIZE));
+                let query = ListQuery {
+                    limit: Some(page_size),
+                    ..this.query.clone()
+                };
+                this.pending = Some((this.fetch)(query));
+            }
+
+            let page = match this.pending.as_mut().map(|f| f.as_mut().poll(cx)) {
+                Some(Poll::Ready(page)) => page,
+                _ => return Poll::Pending,
+            };
+
+            this.pending = None;
+
+            match page {
+                Ok(page) => {
+                    if let Some(remaining) = this.remaining.as_mut() {
+                        *remaining = remaining.saturating_sub(page.items.len() as u64);
+                    }
+
+                    match page.next_cursor {
+                        Some(cursor) => this.query.after = Some(cursor),
+                        None => this.done = true,
+                    }
+
+                    this.buffer.extend(page.items);
+                }
+                Err(e) => {
+                    this.done = true;
+                    return Poll::Ready(Some(Err(e)));
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::keyv::{Keyv, KeyvStoreBuilder};
+    use tokio_stream::StreamExt;
+
+    /// Returns a `Keyv` on each backend holding the keys `a`, `b1`, `b2`, `b3` and `c`.
+    async fn backends() -> Vec<Keyv> {
+        let store = KeyvStoreBuilder::new().uri(":memory:").build().await.unwrap();
+        let backends = vec![Keyv::default(), Keyv::try_new(store).await.unwrap()];
+
+        for keyv in &backends {
+            keyv.set_many(["c", "b2", "a", "b3", "b1"].map(|key| (key, 1)))
+                .await
+                .unwrap();
+            keyv.set_with_ttl("b0", 1, 0).await.unwrap();
+        }
+
+        backends
+    }
+
+    fn keys(page: &ListPage) -> Vec<&str> {
+        page.items.iter().map(|model| model.key.as_str()).collect()
+    }
+
+    #[test]
+    fn queries_include_the_start_and_exclude_the_end_and_cursor() {
+        let query = ListQuery::new().range("b", "c");
+        assert!(query.matches("b") && query.matches("b9") && !query.matches("c") && !query.matches("a"));
+
+        let query = ListQuery::new().prefix("b").after("b1");
+        assert!(!query.matches("b1") && query.matches("b2") && !query.matches("c"));
+        assert!(ListQuery::new().prefix("").matches("anything"));
+    }
+
+    #[test]
+    fn prefix_upper_bounds_are_the_next_possible_prefix() {
+        assert_eq!(prefix_upper_bound("ab").as_deref(), Some("ac"));
+        assert_eq!(prefix_upper_bound("a\u{10FFFF}").as_deref(), Some("b"));
+        assert_eq!(prefix_upper_bound("\u{D7FF}").as_deref(), Some("\u{E000}"));
+        assert_eq!(prefix_upper_bound(""), None);
+    }
+
+    #[tokio::test]
+    async fn pages_end_where_the_keys_do() {
+        for keyv in backends().await {
+            let page = keyv.list_page(ListQuery::new().prefix("b").limit(2)).await.unwrap();
+            assert_eq!(
+                (keys(&page), page.next_cursor.as_deref()),
+                (vec!["b1", "b2"], Some("b2"))
+            );
+
+            // A page that ends on the last matching key has no cursor.
+            let page = keyv
+                .list_page(ListQuery::new().prefix("b").after("b2").limit(1))
+                .await
+                .unwrap();
+            assert_eq!((keys(&page), page.next_cursor.as_deref()), (vec!["b3"], None));
+
+            let page = keyv.list_page(ListQuery::new().prefix("").limit(10)).await.unwrap();
+            assert_eq!(keys(&page), ["a", "b1", "b2", "b3", "c"]);
+
+            let page = keyv.list_page(ListQuery::new().range("a", "b2")).await.unwrap();
+            assert_eq!(keys(&page), ["a", "b1"]);
+            let page = keyv.list_page(ListQuery::new().range("b1", "b1")).await.unwrap();
+            assert!(keys(&page).is_empty());
+        }
+    }
+
+    #[tokio::test]
+    async fn streams_walk_every_page() {
+        for keyv in backends().await {
+            let keys: Vec<String> = keyv
+                .list_stream(ListQuery::new())
+                .map(|model| model.unwrap().key)
+                .collect()
+                .await;
+            assert_eq!(keys, ["a", "b1", "b2", "b3", "c"]);
+
+            let keys: Vec<String> = keyv
+                .list_stream(ListQuery::new().range("b", "c").limit(2))
+                .map(|model| model.unwrap().key)
+                .collect()
+                .await;
+            assert_eq!(keys, ["b1", "b2"]);
+        }
+    }
+}
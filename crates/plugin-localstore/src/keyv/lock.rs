@@ -0,0 +1,206 @@
+// Copyright © 2024 Crabby Craft - All Rights Reserved.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use tauri::async_runtime::{self, JoinHandle};
+
+use super::{unix_millis, Keyv, KeyvError, StoreError};
+
+/// Delay between two attempts of `Keyv::acquire_lock` while the lock is held elsewhere.
+pub const LOCK_RETRY_INTERVAL: Duration = Duration::from_millis(100);
+
+/// A held lease is renewed each time this fraction of its TTL has elapsed.
+const LOCK_RENEW_DIVISOR: u32 = 3;
+
+/// Counter included in lease owners, so leases taken in the same millisecond differ.
+static NEXT_OWNER: AtomicU32 = AtomicU32::new(0);
+
+/// Returns a new lease owner, unique across the processes sharing the database.
+pub(crate) fn lease_owner() -> String {
+    format!(
+        "{:x}-{:x}-{:x}",
+        std::process::id(),
+        unix_millis(),
+        NEXT_OWNER.fetch_add(1, Ordering::Relaxed)
+    )
+}
+
+/// Checks that a lease lasts some time: a zero TTL would expire it as soon
+/// as it is taken, letting another owner take it over at once.
+pub(crate) fn validate_lease_ttl(ttl: Duration) -> Result<(), StoreError> {
+    if ttl.is_zero() {
+        return Err(StoreError::InvalidValue(
+            "the TTL of a lock must be greater than zero".to_string(),
+        ));
+    }
+
+    Ok(())
+}
+
+/// A lock held through a lease in the store, returned by `Keyv::acquire_lock`.
+///
+/// The lease is renewed in the background while the guard is alive, and
+/// released when it is dropped. If the process dies, the lease expires after
+/// its TTL and the lock can be acquired again.
+pub struct LockGuard {
+    keyv: Keyv,
+    name: String,
+    owner: String,
+    /// Set once a renewal finds the lease taken by another owner.
+    lost: Arc<AtomicBool>,
+    renewal: JoinHandle<()>,
+    released: bool,
+}
+
+impl LockGuard {
+    pub(crate) fn new(keyv: Keyv, name: String, owner: String, ttl: Duration) -> Self {
+        let lost = Arc::new(AtomicBool::new(false));
+        let renewal = spawn_renewal(keyv.clone(), name.clone(), owner.clone(), ttl, Arc::clone(&lost));
+
+        Self {
+            keyv,
+            name,
+            owner,
+            lost,
+            renewal,
+            released: false,
+        }
+    }
+
+    /// Returns the name of the lock.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Returns `false` if the lease could not be renewed in time and another
+    /// owner may have acquired the lock since. Long jobs should check it
+    /// between steps and stop once it returns `false`.
+    pub fn is_held(&self) -> bool {
+        !self.lost.load(Ordering::Acquire)
+    }
+
+    /// Releases the lock and waits until the lease is deleted. Dropping the
+    /// guard releases it too, but in the background.
+    ///
+    /// # Returns
+    ///
+    /// Returns an `Ok` result once the lease is deleted, or a `KeyvError` on failure.
+    pub async fn release(mut self) -> Result<(), KeyvError> {
+        self.renewal.abort();
+        self.released = true;
+        self.keyv.release_lease(&self.name, &self.owner).await
+    }
+}
+
+impl Drop for LockGuard {
+    fn drop(&mut self) {
+        if self.released {
+            return;
+        }
+
+        self.renewal.abort();
+
+        let keyv = self.keyv.clone();
+        let name = std::mem::take(&mut self.name);
+        let owner = std::mem::take(&mut self.owner);
+
+        async_runtime::spawn(async move {
+            if let Err(e) = keyv.release_lease(&name, &owner).await {
+                log::error!("Failed to release the lock {}: {}", name, e);
+            }
+        });
+    }
+}
+
+/// Spawns the task renewing a lease until it is aborted or the lease is lost.
+fn spawn_renewal(keyv: Keyv, name: String, owner: String, ttl: Duration, lost: Arc<AtomicBool>) -> JoinHandle<()> {
+    async_runtime::spawn(async move {
+        loop {
+            tokio::time::sleep(ttl / LOCK_RENEW_DIVISOR).await;
+
+            match keyv.renew_lease(&name, &owner, ttl).await {
+                Ok(true) => {}
+                Ok(false) => {
+                    log::warn!("Lost the lock {}, its lease expired before it was renewed", name);
+                    lost.store(true, Ordering::Release);
+                    return;
+                }
+                // Keep trying: the lease stays valid until its TTL has elapsed.
+                Err(e) => log::error!("Failed to renew the lock {}: {}", name, e),
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::keyv::KeyvStoreBuilder;
+
+    const TTL: Duration = Duration::from_millis(150);
+
+    async fn memory_keyv() -> Keyv {
+        let store = KeyvStoreBuilder::new().uri(":memory:").build().await.unwrap();
+        Keyv::try_new(store).await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn expired_leases_are_taken_over() {
+        let keyv = memory_keyv().await;
+        assert!(keyv.acquire_lease("sync", "first", TTL).await.unwrap());
+        assert!(!keyv.acquire_lease("sync", "second", TTL).await.unwrap());
+        // An owner may take its own lease again.
+        assert!(keyv.acquire_lease("sync", "first", TTL).await.unwrap());
+        // Locks are independent of each other.
+        assert!(keyv.acquire_lease("backup", "second", TTL).await.unwrap());
+
+        assert!(!keyv.renew_lease("sync", "second", TTL).await.unwrap());
+        assert!(keyv.renew_lease("sync", "first", TTL).await.unwrap());
+
+        tokio::time::sleep(TTL * 2).await;
+        assert!(keyv.acquire_lease("sync", "second", TTL).await.unwrap());
+        assert!(!keyv.renew_lease("sync", "first", TTL).await.unwrap());
+
+        // Releasing a lease held by another owner does nothing.
+        keyv.release_lease("sync", "first").await.unwrap();
+        assert!(!keyv.acquire_lease("sync", "first", TTL).await.unwrap());
+        keyv.release_lease("sync", "second").await.unwrap();
+        assert!(keyv.acquire_lease("sync", "first", TTL).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn zero_ttls_are_rejected() {
+        let keyv = memory_keyv().await;
+        let invalid =
+            |result: Result<bool, KeyvError>| matches!(result, Err(KeyvError::StoreError(StoreError::InvalidValue(_))));
+
+        assert!(invalid(keyv.acquire_lease("sync", "first", Duration::ZERO).await));
+        assert!(keyv.acquire_lease("sync", "first", TTL).await.unwrap());
+        assert!(invalid(keyv.renew_lease("sync", "first", Duration::ZERO).await));
+        assert!(keyv.try_acquire_lock("other", Duration::ZERO).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn guards_renew_their_lease_until_released() {
+        let keyv = memory_keyv().await;
+        let guard = keyv.try_acquire_lock("sync", TTL).await.unwrap().unwrap();
+        assert_eq!(guard.name(), "sync");
+
+        // The lease outlives its TTL while the guard renews it.
+        tokio::time::sleep(TTL * 3).await;
+        assert!(guard.is_held());
+        assert!(keyv.try_acquire_lock("sync", TTL).await.unwrap().is_none());
+
+        guard.release().await.unwrap();
+        let guard = keyv.try_acquire_lock("sync", TTL).await.unwrap();
+        assert!(guard.is_some());
+    }
+}
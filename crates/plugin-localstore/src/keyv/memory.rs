@@ -0,0 +1,671 @@
+// Copyright © 2024 Crabby Craft - All Rights Reserved.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use serde_json::Value;
+use std::collections::{btree_map, BTreeMap, HashMap};
+use std::future::Future;
+use std::ops::Bound;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::time::Instant;
+use tokio::sync::{Mutex, OwnedMutexGuard};
+
+use crate::keyv::DEFAULT_NAMESPACE_NAME;
+use crate::keyv::{
+    expires_at_from_ttl, unix_millis, validate_namespace, Expiry, ListPage, ListQuery, Store, StoreError, StoreModel,
+    StoreTransaction, UpdateFn, VersionedValue, Written,
+};
+
+/// A single entry of a [`MemoryStore`].
+#[derive(Debug, Clone)]
+pub(crate) struct MemoryEntry {
+    pub(crate) value: Value,
+    pub(crate) expires_at: Option<i64>,
+    pub(crate) version: u64,
+    pub(crate) created_at: i64,
+    pub(crate) updated_at: i64,
+}
+
+impl MemoryEntry {
+    fn is_live(&self, now: i64) -> bool {
+        self.expires_at.map_or(true, |expires_at| expires_at > now)
+    }
+
+    fn to_model(&self, key: &str) -> StoreModel {
+        StoreModel {
+            key: key.to_string(),
+            value: self.value.clone(),
+            version: self.version,
+            created_at: self.created_at,
+            updated_at: self.updated_at,
+            expires_at: self.expires_at,
+        }
+    }
+}
+
+/// Every namespace of a [`MemoryStore`], keyed by namespace name and then by key.
+pub(crate) type MemoryState = BTreeMap<String, BTreeMap<String, MemoryEntry>>;
+
+/// A `Store` that keeps every namespace in memory.
+///
+/// Nothing is persisted, which makes it a good fit for tests and ephemeral
+/// data. It needs no database and no async runtime to be created, and all of
+/// its operations, including transactions, are atomic.
+///
+/// # Examples
+///
+/// ```rust,no_run
+/// # use tauri_plugin_localstore::keyv::{Keyv, MemoryStore};
+/// # async fn run() -> Result<(), Box<dyn std::error::Error>> {
+/// let keyv = Keyv::try_new(MemoryStore::new()).await?;
+/// keyv.set("theme", "dark").await?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone)]
+pub struct MemoryStore {
+    state: Arc<Mutex<MemoryState>>,
+    namespace: String,
+}
+
+impl Default for MemoryStore {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MemoryStore {
+    /// Creates an empty store scoped to the default namespace.
+    pub fn new() -> Self {
+        Self::from_state(MemoryState::new())
+    }
+
+    pub(crate) fn from_state(state: MemoryState) -> Self {
+        Self {
+            state: Arc::new(Mutex::new(state)),
+            namespace: DEFAULT_NAMESPACE_NAME.to_string(),
+        }
+    }
+
+    /// Returns a copy of every namespace, used to persist the store.
+    pub(crate) async fn snapshot(&self) -> MemoryState {
+        self.state.lock().await.clone()
+    }
+
+    /// Returns a store scoped to another namespace, sharing the same state.
+    pub(crate) fn scoped_namespace(&self, name: &str) -> Result<Self, StoreError> {
+        validate_namespace(name)?;
+
+        Ok(Self {
+            state: Arc::clone(&self.state),
+            namespace: name.to_string(),
+        })
+    }
+}
+
+/// Returns the live entry for `key` in `namespace`.
+fn read_entry<'a>(state: &'a MemoryState, namespace: &str, key: &str) -> Option<&'a MemoryEntry> {
+    let now = unix_millis();
+    state
+        .get(namespace)
+        .and_then(|entries| entries.get(key))
+        .filter(|entry| entry.is_live(now))
+}
+
+/// Writes `value` for `key`, bumping its version, and returns the written entry.
+/// An expired entry is replaced as if it did not exist, starting at version 1.
+fn write_entry<'a>(
+    state: &'a mut MemoryState,
+    namespace: &str,
+    key: &str,
+    value: Value,
+    expiry: Expiry,
+) -> &'a MemoryEntry {
+    let now = unix_millis();
+    let entries = state.entry(namespace.to_string()).or_default();
+
+    match entries.entry(key.to_string()) {
+        btree_map::Entry::Occupied(occupied) => {
+            let entry = occupied.into_mut();
+            // An expired entry is gone, so overwriting it starts a new one.
+            if !entry.is_live(now) {
+                entry.version = 0;
+                entry.created_at = now;
+            }
+            entry.expires_at = match expiry {
+                Expiry::Keep => entry.expires_at.filter(|&expires_at| expires_at > now),
+                Expiry::Set(expires_at) => expires_at,
+            };
+            entry.value = value;
+            entry.version += 1;
+            entry.updated_at = now;
+            entry
+        }
+        btree_map::Entry::Vacant(vacant) => {
+            let expires_at = match expiry {
+                Expiry::Keep => None,
+                Expiry::Set(expires_at) => expires_at,
+            };
+            vacant.insert(MemoryEntry {
+                value,
+                expires_at,
+                version: 1,
+                created_at: now,
+                updated_at: now,
+            })
+        }
+    }
+}
+
+/// Returns the lower bound of the keys matching `query`.
+fn lower_bound(query: &ListQuery) -> Bound<&str> {
+    let candidates = [
+        query.prefix.as_deref().map(Bound::Included),
+        query.start.as_deref().map(Bound::Included),
+        query.after.as_deref().map(Bound::Excluded),
+    ];
+
+    candidates
+        .into_iter()
+        .flatten()
+        .fold(Bound::Unbounded, |lower, candidate| match (lower, candidate) {
+            (Bound::Unbounded, _) => candidate,
+            (Bound::Included(a) | Bound::Excluded(a), Bound::Included(b) | Bound::Excluded(b)) if b > a => candidate,
+            (Bound::Included(a), Bound::Excluded(b)) if a == b => candidate,
+            _ => lower,
+        })
+}
+
+impl Store for MemoryStore {
+    fn initialize(&self) -> Pin<Box<dyn Future<Output = Result<(), StoreError>> + Send + '_>> {
+        Box::pin(async move {
+            self.state.lock().await.entry(self.namespace.clone()).or_default();
+            Ok(())
+        })
+    }
+
+    fn get(&self, key: &str) -> Pin<Box<dyn Future<Output = Result<Option<Value>, StoreError>> + Send + '_>> {
+        let key = key.to_string();
+
+        Box::pin(async move {
+            let state = self.state.lock().await;
+            let value = read_entry(&state, &self.namespace, &key).map(|entry| entry.value.clone());
+
+            log::debug!(
+                "Memory store get: {} | {}",
+                key,
+                if value.is_some() { "found" } else { "not found" }
+            );
+
+            Ok(value)
+        })
+    }
+
+    fn get_versioned(
+        &self,
+        key: &str,
+    ) -> Pin<Box<dyn Future<Output = Result<Option<VersionedValue>, StoreError>> + Send + '_>> {
+        let key = key.to_string();
+
+        Box::pin(async move {
+            let state = self.state.lock().await;
+
+            Ok(read_entry(&state, &self.namespace, &key).map(|entry| VersionedValue {
+                value: entry.value.clone(),
+                version: entry.version,
+            }))
+        })
+    }
+
+    fn get_with_meta(
+        &self,
+        key: &str,
+    ) -> Pin<Box<dyn Future<Output = Result<Option<StoreModel>, StoreError>> + Send + '_>> {
+        let key = key.to_string();
+
+        Box::pin(async move {
+            let state = self.state.lock().await;
+
+            Ok(read_entry(&state, &self.namespace, &key).map(|entry| entry.to_model(&key)))
+        })
+    }
+
+    fn get_many(
+        &self,
+        keys: &[&str],
+    ) -> Pin<Box<dyn Future<Output = Result<HashMap<String, Value>, StoreError>> + Send + '_>> {
+        let keys = keys.iter().map(|k| k.to_string()).collect::<Vec<String>>();
+
+        Box::pin(async move {
+            let state = self.state.lock().await;
+
+            Ok(keys
+                .into_iter()
+                .filter_map(|key| {
+                    let value = read_entry(&state, &self.namespace, &key)?.value.clone();
+                    Some((key, value))
+                })
+                .collect())
+        })
+    }
+
+    fn list(&self) -> Pin<Box<dyn Future<Output = Result<Vec<StoreModel>, StoreError>> + Send + '_>> {
+        Box::pin(async move {
+            let page = self.list_page(ListQuery::new()).await?;
+            Ok(page.items)
+        })
+    }
+
+    fn list_page(&self, query: ListQuery) -> Pin<Box<dyn Future<Output = Result<ListPage, StoreError>> + Send + '_>> {
+        Box::pin(async move {
+            let start = Instant::now();
+            let now = unix_millis();
+
+            let state = self.state.lock().await;
+
+            let mut items: Vec<StoreModel> = Vec::new();
+            let mut next_cursor = None;
+
+            if let Some(entries) = state.get(&self.namespace) {
+                let matching = entries
+                    .range::<str, _>((lower_bound(&query), Bound::Unbounded))
+                    .take_while(|(key, _)| {
+                        query.end.as_deref().map_or(true, |end| key.as_str() < end)
+                            && query.prefix.as_deref().map_or(true, |prefix| key.starts_with(prefix))
+                    })
+                    .filter(|(key, entry)| entry.is_live(now) && query.matches(key));
+
+                for (key, entry) in matching {
+                    if query.limit.is_some_and(|limit| items.len() as u64 >= limit) {
+                        next_cursor = items.last().map(|item| item.key.clone());
+                        break;
+                    }
+
+                    items.push(entry.to_model(key));
+                }
+            }
+
+            log::debug!(
+                "Memory store list_page: {:?} | {} items | {:?}",
+                start.elapsed(),
+                items.len(),
+                query
+            );
+
+            Ok(ListPage { items, next_cursor })
+        })
+    }
+
+    fn set(
+        &self,
+        key: &str,
+        value: Value,
+        ttl: Option<u64>,
+    ) -> Pin<Box<dyn Future<Output = Result<Written<Option<StoreModel>>, StoreError>> + Send + '_>> {
+        let key = key.to_string();
+        let expires_at = expires_at_from_ttl(ttl);
+
+        Box::pin(async move {
+            let mut state = self.state.lock().await;
+            let model = write_entry(&mut state, &self.namespace, &key, value, Expiry::Set(expires_at)).to_model(&key);
+
+            log::debug!("Memory store set: {} | {:?}", key, expires_at);
+
+            Ok(Written::new(Some(model)))
+        })
+    }
+
+    fn set_many(
+        &self,
+        entries: Vec<(String, Value)>,
+        ttl: Option<u64>,
+    ) -> Pin<Box<dyn Future<Output = Result<Written<()>, StoreError>> + Send + '_>> {
+        let expires_at = expires_at_from_ttl(ttl);
+
+        Box::pin(async move {
+            let mut state = self.state.lock().await;
+
+            for (key, value) in entries {
+                write_entry(&mut state, &self.namespace, &key, value, Expiry::Set(expires_at));
+            }
+
+            Ok(Written::new(()))
+        })
+    }
+
+    fn set_versioned(
+        &self,
+        key: &str,
+        value: Value,
+        expected_version: Option<u64>,
+        ttl: Option<u64>,
+    ) -> Pin<Box<dyn Future<Output = Result<Written<u64>, StoreError>> + Send + '_>> {
+        let key = key.to_string();
+        let expires_at = expires_at_from_ttl(ttl);
+
+        Box::pin(async move {
+            let mut state = self.state.lock().await;
+
+            let actual = read_entry(&state, &self.namespace, &key).map(|entry| entry.version);
+
+            if actual != expected_version {
+                return Err(StoreError::VersionConflict {
+                    key,
+                    expected: expected_version,
+                    actual,
+                });
+            }
+
+            Ok(Written::new(
+                write_entry(&mut state, &self.namespace, &key, value, Expiry::Set(expires_at)).version,
+            ))
+        })
+    }
+
+    fn compare_and_swap(
+        &self,
+        key: &str,
+        expected: Option<Value>,
+        new: Value,
+    ) -> Pin<Box<dyn Future<Output = Result<Written<bool>, StoreError>> + Send + '_>> {
+        let key = key.to_string();
+
+        Box::pin(async move {
+            let mut state = self.state.lock().await;
+
+            let current = read_entry(&state, &self.namespace, &key).map(|entry| &entry.value);
+
+            if current != expected.as_ref() {
+                return Ok(Written::new(false));
+            }
+
+            write_entry(&mut state, &self.namespace, &key, new, Expiry::Keep);
+
+            Ok(Written::new(true))
+        })
+    }
+
+    fn increment(
+        &self,
+        key: &str,
+        delta: i64,
+    ) -> Pin<Box<dyn Future<Output = Result<Written<i64>, StoreError>> + Send + '_>> {
+        let key = key.to_string();
+
+        Box::pin(async move {
+            let mut state = self.state.lock().await;
+
+            let current = match read_entry(&state, &self.namespace, &key) {
+                Some(entry) => entry
+                    .value
+                    .as_i64()
+                    .ok_or_else(|| StoreError::InvalidValue(format!("{} is not an integer", key)))?,
+                None => 0,
+            };
+
+            let value = current
+                .checked_add(delta)
+                .ok_or_else(|| StoreError::InvalidValue(format!("Incrementing {} overflows", key)))?;
+
+            write_entry(&mut state, &self.namespace, &key, Value::from(value), Expiry::Keep);
+
+            Ok(Written::new(value))
+        })
+    }
+
+    fn update<'a>(
+        &'a self,
+        key: &str,
+        f: UpdateFn<'a>,
+    ) -> Pin<Box<dyn Future<Output = Result<Written<Value>, StoreError>> + Send + 'a>> {
+        let key = key.to_string();
+
+        Box::pin(async move {
+            let mut state = self.state.lock().await;
+
+            let current = read_entry(&state, &self.namespace, &key).map(|entry| entry.value.clone());
+            let value = f(current)?;
+
+            write_entry(&mut state, &self.namespace, &key, value.clone(), Expiry::Keep);
+
+            Ok(Written::new(value))
+        })
+    }
+
+    fn remove(&self, key: &str) -> Pin<Box<dyn Future<Output = Result<(), StoreError>> + Send + '_>> {
+        let key = key.to_string();
+
+        Box::pin(async move {
+            if let Some(entries) = self.state.lock().await.get_mut(&self.namespace) {
+                entries.remove(&key);
+            }
+            Ok(())
+        })
+    }
+
+    fn remove_many(&self, keys: &[&str]) -> Pin<Box<dyn Future<Output = Result<Vec<String>, StoreError>> + Send + '_>> {
+        let keys = keys.iter().map(|k| k.to_string()).collect::<Vec<String>>();
+
+        Box::pin(async move {
+            let now = unix_millis();
+            let mut removed = Vec::new();
+            if let Some(entries) = self.state.lock().await.get_mut(&self.namespace) {
+                for key in keys {
+                    if entries.remove(&key).is_some_and(|entry| entry.is_live(now)) {
+                        removed.push(key);
+                    }
+                }
+            }
+            Ok(removed)
+        })
+    }
+
+    fn clear(&self) -> Pin<Box<dyn Future<Output = Result<(), StoreError>> + Send + '_>> {
+        Box::pin(async move {
+            if let Some(entries) = self.state.lock().await.get_mut(&self.namespace) {
+                entries.clear();
+            }
+            Ok(())
+        })
+    }
+
+    fn purge_expired(&self) -> Pin<Box<dyn Future<Output = Result<Vec<String>, StoreError>> + Send + '_>> {
+        Box::pin(async move {
+            let now = unix_millis();
+            let mut purged = Vec::new();
+
+            if let Some(entries) = self.state.lock().await.get_mut(&self.namespace) {
+                entries.retain(|key, entry| {
+                    let live = entry.is_live(now);
+                    if !live {
+                        purged.push(key.clone());
+                    }
+                    live
+                });
+            }
+
+            Ok(purged)
+        })
+    }
+
+    fn namespace_name(&self) -> &str {
+        &self.namespace
+    }
+
+    fn namespace(&self, name: &str) -> Result<Arc<dyn Store>, StoreError> {
+        Ok(Arc::new(self.scoped_namespace(name)?))
+    }
+
+    fn list_namespaces(&self) -> Pin<Box<dyn Future<Output = Result<Vec<String>, StoreError>> + Send + '_>> {
+        Box::pin(async move { Ok(self.state.lock().await.keys().cloned().collect()) })
+    }
+
+    fn rename_namespace(
+        &self,
+        from: &str,
+        to: &str,
+    ) -> Pin<Box<dyn Future<Output = Result<(), StoreError>> + Send + '_>> {
+        let from = from.to_string();
+        let to = to.to_string();
+
+        Box::pin(async move {
+            validate_namespace(&from)?;
+            validate_namespace(&to)?;
+
+            let mut state = self.state.lock().await;
+
+            if state.contains_key(&to) {
+                return Err(StoreError::InvalidNamespace(to));
+            }
+
+            let entries = state.remove(&from).ok_or(StoreError::NotFound)?;
+            state.insert(to, entries);
+
+            Ok(())
+        })
+    }
+
+    fn drop_namespace(&self, name: &str) -> Pin<Box<dyn Future<Output = Result<(), StoreError>> + Send + '_>> {
+        let name = name.to_string();
+
+        Box::pin(async move {
+            validate_namespace(&name)?;
+            self.state.lock().await.remove(&name);
+            Ok(())
+        })
+    }
+
+    fn begin(&self) -> Pin<Box<dyn Future<Output = Result<Box<dyn StoreTransaction>, StoreError>> + Send + '_>> {
+        Box::pin(async move {
+            let state = Arc::clone(&self.state).lock_owned().await;
+            let backup = state.clone();
+
+            Ok(Box::new(MemoryTransaction {
+                active: Mutex::new(Some((state, backup))),
+            }) as Box<dyn StoreTransaction>)
+        })
+    }
+}
+
+/// A transaction over a [`MemoryStore`].
+///
+/// The store stays locked for the whole transaction, and a copy of its state
+/// taken at the start is restored on rollback.
+struct MemoryTransaction {
+    /// The locked state and the copy to restore on rollback, `None` once finished.
+    active: Mutex<Option<(OwnedMutexGuard<MemoryState>, MemoryState)>>,
+}
+
+impl Drop for MemoryTransaction {
+    fn drop(&mut self) {
+        // Dropping an unfinished transaction rolls it back, like an SQLite transaction.
+        if let Some((mut state, backup)) = self.active.get_mut().take() {
+            *state = backup;
+        }
+    }
+}
+
+impl StoreTransaction for MemoryTransaction {
+    fn get(
+        &self,
+        namespace: &str,
+        key: &str,
+    ) -> Pin<Box<dyn Future<Output = Result<Option<Value>, StoreError>> + Send + '_>> {
+        let namespace = namespace.to_string();
+        let key = key.to_string();
+
+        Box::pin(async move {
+            validate_namespace(&namespace)?;
+
+            let active = self.active.lock().await;
+            let (state, _) = active.as_ref().ok_or(StoreError::TransactionFinished)?;
+
+            Ok(read_entry(state, &namespace, &key).map(|entry| entry.value.clone()))
+        })
+    }
+
+    fn keys(&self, namespace: &str) -> Pin<Box<dyn Future<Output = Result<Vec<String>, StoreError>> + Send + '_>> {
+        let namespace = namespace.to_string();
+
+        Box::pin(async move {
+            validate_namespace(&namespace)?;
+
+            let active = self.active.lock().await;
+            let (state, _) = active.as_ref().ok_or(StoreError::TransactionFinished)?;
+
+            let now = unix_millis();
+
+            Ok(state
+                .get(&namespace)
+                .map(|entries| {
+                    entries
+                        .iter()
+                        .filter(|(_, entry)| entry.is_live(now))
+                        .map(|(key, _)| key.clone())
+                        .collect()
+                })
+                .unwrap_or_default())
+        })
+    }
+
+    fn set(
+        &self,
+        namespace: &str,
+        key: &str,
+        value: Value,
+        ttl: Option<u64>,
+    ) -> Pin<Box<dyn Future<Output = Result<Written<()>, StoreError>> + Send + '_>> {
+        let namespace = namespace.to_string();
+        let key = key.to_string();
+        let expires_at = expires_at_from_ttl(ttl);
+
+        Box::pin(async move {
+            validate_namespace(&namespace)?;
+
+            let mut active = self.active.lock().await;
+            let (state, _) = active.as_mut().ok_or(StoreError::TransactionFinished)?;
+
+            write_entry(state, &namespace, &key, value, Expiry::Set(expires_at));
+
+            Ok(Written::new(()))
+        })
+    }
+
+    fn remove(&self, namespace: &str, key: &str) -> Pin<Box<dyn Future<Output = Result<(), StoreError>> + Send + '_>> {
+        let namespace = namespace.to_string();
+        let key = key.to_string();
+
+        Box::pin(async move {
+            validate_namespace(&namespace)?;
+
+            let mut active = self.active.lock().await;
+            let (state, _) = active.as_mut().ok_or(StoreError::TransactionFinished)?;
+
+            if let Some(entries) = state.get_mut(&namespace) {
+                entries.remove(&key);
+            }
+
+            Ok(())
+        })
+    }
+
+    fn commit(&self) -> Pin<Box<dyn Future<Output = Result<(), StoreError>> + Send + '_>> {
+        Box::pin(async move {
+            self.active.lock().await.take().ok_or(StoreError::TransactionFinished)?;
+            Ok(())
+        })
+    }
+
+    fn rollback(&self) -> Pin<Box<dyn Future<Output = Result<(), StoreError>> + Send + '_>> {
+        Box::pin(async move {
+            let (mut state, backup) = self.active.lock().await.take().ok_or(StoreError::TransactionFinished)?;
+            *state = backup;
+            Ok(())
+        })
+    }
+}
@@ -7,9 +7,48 @@
 // except according to those terms.
 
 mod adapter;
+mod cache;
+mod codec;
+mod collection;
+mod connection;
+mod event;
+mod export;
+mod filter;
+mod guard;
+mod json_file;
+#[allow(clippy::module_inception)]
 mod keyv;
+mod layer;
+mod limits;
+mod list;
+mod lock;
+mod memory;
+mod observe;
+mod prefix;
+mod schema;
+mod search;
 mod store;
+mod transaction;
 
 pub use adapter::*;
+pub use cache::*;
+pub(crate) use codec::*;
+pub use collection::*;
+pub use connection::*;
+pub use event::*;
+pub use export::*;
+pub use filter::*;
+pub use guard::*;
+pub use json_file::*;
 pub use keyv::*;
+pub use layer::*;
+pub use limits::*;
+pub use list::*;
+pub use lock::*;
+pub use memory::*;
+pub use observe::*;
+pub use prefix::*;
+pub use schema::*;
+pub use search::*;
 pub use store::*;
+pub use transaction::*;
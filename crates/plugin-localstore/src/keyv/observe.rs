@@ -0,0 +1,615 @@
+// Copyright © 2024 Crabby Craft - All Rights Reserved.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::{BTreeMap, HashMap};
+use std::future::Future;
+use std::path::Path;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::{Duration, Instant};
+
+use super::{
+    FindQuery, ForwardingStore, ForwardingTransaction, ListPage, ListQuery, Revision, SearchHit, SearchQuery, Store,
+    StoreError, StoreLayer, StoreModel, StoreTransaction, UpdateFn, VersionedValue, Written,
+};
+
+/// A call that went through an [`ObservedStore`], passed to its observer once finished.
+#[derive(Debug)]
+pub struct StoreCall<'a> {
+    /// The namespace the call applied to.
+    pub namespace: &'a str,
+    /// The name of the `Store` method, prefixed with `transaction.` for
+    /// calls made on a transaction.
+    pub operation: &'static str,
+    /// The key the call applied to, for single-key operations.
+    pub key: Option<&'a str>,
+    /// How long the call took.
+    pub elapsed: Duration,
+    /// The error returned by the call, if it failed.
+    pub error: Option<&'a StoreError>,
+}
+
+/// Receives every call made through an [`ObservedStore`].
+pub trait StoreObserver: Send + Sync + 'static {
+    fn on_call(&self, call: &StoreCall<'_>);
+}
+
+/// A store that reports every call to a [`StoreObserver`], then forwards its result.
+pub struct ObservedStore<O> {
+    inner: Arc<dyn Store>,
+    observer: Arc<O>,
+}
+
+impl<O: StoreObserver> ObservedStore<O> {
+    pub fn new(inner: Arc<dyn Store>, observer: Arc<O>) -> Self {
+        Self { inner, observer }
+    }
+
+    fn observe<'a, T: Send + 'a>(
+        &'a self,
+        operation: &'static str,
+        key: Option<String>,
+        future: Pin<Box<dyn Future<Output = Result<T, StoreError>> + Send + 'a>>,
+    ) -> Pin<Box<dyn Future<Output = Result<T, StoreError>> + Send + 'a>> {
+        observe(
+            &*self.observer,
+            self.inner.namespace_name().to_string(),
+            operation,
+            key,
+            future,
+        )
+    }
+}
+
+/// Awaits `future` and reports it to `observer`.
+fn observe<'a, O: StoreObserver, T: Send + 'a>(
+    observer: &'a O,
+    namespace: String,
+    operation: &'static str,
+    key: Option<String>,
+    future: Pin<Box<dyn Future<Output = Result<T, StoreError>> + Send + 'a>>,
+) -> Pin<Box<dyn Future<Output = Result<T, StoreError>> + Send + 'a>> {
+    Box::pin(async move {
+        let start = Instant::now();
+        let result = future.await;
+
+        observer.on_call(&StoreCall {
+            namespace: &namespace,
+            operation,
+            key: key.as_deref(),
+            elapsed: start.elapsed(),
+            error: result.as_ref().err(),
+        });
+
+        result
+    })
+}
+
+impl<O: StoreObserver> ForwardingStore for ObservedStore<O> {
+    fn inner(&self) -> &dyn Store {
+        &*self.inner
+    }
+
+    fn initialize(&self) -> Pin<Box<dyn Future<Output = Result<(), StoreError>> + Send + '_>> {
+        self.observe("initialize", None, self.inner.initialize())
+    }
+
+    fn get(&self, key: &str) -> Pin<Box<dyn Future<Output = Result<Option<Value>, StoreError>> + Send + '_>> {
+        self.observe("get", Some(key.to_string()), self.inner.get(key))
+    }
+
+    fn get_versioned(
+        &self,
+        key: &str,
+    ) -> Pin<Box<dyn Future<Output = Result<Option<VersionedValue>, StoreError>> + Send + '_>> {
+        self.observe("get_versioned", Some(key.to_string()), self.inner.get_versioned(key))
+    }
+
+    fn get_with_meta(
+        &self,
+        key: &str,
+    ) -> Pin<Box<dyn Future<Output = Result<Option<StoreModel>, StoreError>> + Send + '_>> {
+        self.observe("get_with_meta", Some(key.to_string()), self.inner.get_with_meta(key))
+    }
+
+    fn get_many(
+        &self,
+        keys: &[&str],
+    ) -> Pin<Box<dyn Future<Output = Result<HashMap<String, Value>, StoreError>> + Send + '_>> {
+        self.observe("get_many", None, self.inner.get_many(keys))
+    }
+
+    fn list(&self) -> Pin<Box<dyn Future<Output = Result<Vec<StoreModel>, StoreError>> + Send + '_>> {
+        self.observe("list", None, self.inner.list())
+    }
+
+    fn list_page(&self, query: ListQuery) -> Pin<Box<dyn Future<Output = Result<ListPage, StoreError>> + Send + '_>> {
+        self.observe("list_page", None, self.inner.list_page(query))
+    }
+
+    fn find(&self, query: FindQuery) -> Pin<Box<dyn Future<Output = Result<Vec<StoreModel>, StoreError>> + Send + '_>> {
+        self.observe("find", None, self.inner.find(query))
+    }
+
+    fn set(
+        &self,
+        key: &str,
+        value: Value,
+        ttl: Option<u64>,
+    ) -> Pin<Box<dyn Future<Output = Result<Written<Option<StoreModel>>, StoreError>> + Send + '_>> {
+        self.observe("set", Some(key.to_string()), self.inner.set(key, value, ttl))
+    }
+
+    fn set_many(
+        &self,
+        entries: Vec<(String, Value)>,
+        ttl: Option<u64>,
+    ) -> Pin<Box<dyn Future<Output = Result<Written<()>, StoreError>> + Send + '_>> {
+        self.observe("set_many", None, self.inner.set_many(entries, ttl))
+    }
+
+    fn set_versioned(
+        &self,
+        key: &str,
+        value: Value,
+        expected_version: Option<u64>,
+        ttl: Option<u64>,
+    ) -> Pin<Box<dyn Future<Output = Result<Written<u64>, StoreError>> + Send + '_>> {
+        self.observe(
+            "set_versioned",
+            Some(key.to_string()),
+            self.inner.set_versioned(key, value, expected_version, ttl),
+        )
+    }
+
+    fn compare_and_swap(
+        &self,
+        key: &str,
+        expected: Option<Value>,
+        new: Value,
+    ) -> Pin<Box<dyn Future<Output = Result<Written<bool>, StoreError>> + Send + '_>> {
+        self.observe(
+            "compare_and_swap",
+            Some(key.to_string()),
+            self.inner.compare_and_swap(key, expected, new),
+        )
+    }
+
+    fn increment(
+        &self,
+        key: &str,
+        delta: i64,
+    ) -> Pin<Box<dyn Future<Output = Result<Written<i64>, StoreError>> + Send + '_>> {
+        self.observe("increment", Some(key.to_string()), self.inner.increment(key, delta))
+    }
+
+    fn update<'a>(
+        &'a self,
+        key: &str,
+        f: UpdateFn<'a>,
+    ) -> Pin<Box<dyn Future<Output = Result<Written<Value>, StoreError>> + Send + 'a>> {
+        self.observe("update", Some(key.to_string()), self.inner.update(key, f))
+    }
+
+    fn remove(&self, key: &str) -> Pin<Box<dyn Future<Output = Result<(), StoreError>> + Send + '_>> {
+        self.observe("remove", Some(key.to_string()), self.inner.remove(key))
+    }
+
+    fn remove_many(&self, keys: &[&str]) -> Pin<Box<dyn Future<Output = Result<Vec<String>, StoreError>> + Send + '_>> {
+        self.observe("remove_many", None, self.inner.remove_many(keys))
+    }
+
+    fn clear(&self) -> Pin<Box<dyn Future<Output = Result<(), StoreError>> + Send + '_>> {
+        self.observe("clear", None, self.inner.clear())
+    }
+
+    fn purge_expired(&self) -> Pin<Box<dyn Future<Output = Result<Vec<String>, StoreError>> + Send + '_>> {
+        self.observe("purge_expired", None, self.inner.purge_expired())
+    }
+
+    fn backup(&self, path: &Path) -> Pin<Box<dyn Future<Output = Result<(), StoreError>> + Send + '_>> {
+        self.observe("backup", None, self.inner.backup(path))
+    }
+
+    fn rotate_encryption_key(
+        &self,
+        new_key: Option<[u8; 32]>,
+    ) -> Pin<Box<dyn Future<Output = Result<u64, StoreError>> + Send + '_>> {
+        self.observe("rotate_encryption_key", None, self.inner.rotate_encryption_key(new_key))
+    }
+
+    fn set_history(&self, limit: Option<usize>) -> Pin<Box<dyn Future<Output = Result<(), StoreError>> + Send + '_>> {
+        self.observe("set_history", None, self.inner.set_history(limit))
+    }
+
+    fn history(&self, key: &str) -> Pin<Box<dyn Future<Output = Result<Vec<Revision>, StoreError>> + Send + '_>> {
+        self.observe("history", Some(key.to_string()), self.inner.history(key))
+    }
+
+    fn get_revision(
+        &self,
+        key: &str,
+        id: i64,
+    ) -> Pin<Box<dyn Future<Output = Result<Option<Revision>, StoreError>> + Send + '_>> {
+        self.observe("get_revision", Some(key.to_string()), self.inner.get_revision(key, id))
+    }
+
+    fn revert(
+        &self,
+        key: &str,
+        id: i64,
+    ) -> Pin<Box<dyn Future<Output = Result<Written<StoreModel>, StoreError>> + Send + '_>> {
+        self.observe("revert", Some(key.to_string()), self.inner.revert(key, id))
+    }
+
+    fn set_search(&self, enabled: bool) -> Pin<Box<dyn Future<Output = Result<(), StoreError>> + Send + '_>> {
+        self.observe("set_search", None, self.inner.set_search(enabled))
+    }
+
+    fn search(
+        &self,
+        query: SearchQuery,
+    ) -> Pin<Box<dyn Future<Output = Result<Vec<SearchHit>, StoreError>> + Send + '_>> {
+        self.observe("search", None, self.inner.search(query))
+    }
+
+    fn create_index(
+        &self,
+        name: &str,
+        path: &str,
+    ) -> Pin<Box<dyn Future<Output = Result<(), StoreError>> + Send + '_>> {
+        self.observe("create_index", None, self.inner.create_index(name, path))
+    }
+
+    fn drop_index(&self, name: &str) -> Pin<Box<dyn Future<Output = Result<(), StoreError>> + Send + '_>> {
+        self.observe("drop_index", None, self.inner.drop_index(name))
+    }
+
+    fn acquire_lease(
+        &self,
+        name: &str,
+        owner: &str,
+        ttl: Duration,
+    ) -> Pin<Box<dyn Future<Output = Result<bool, StoreError>> + Send + '_>> {
+        self.observe("acquire_lease", None, self.inner.acquire_lease(name, owner, ttl))
+    }
+
+    fn renew_lease(
+        &self,
+        name: &str,
+        owner: &str,
+        ttl: Duration,
+    ) -> Pin<Box<dyn Future<Output = Result<bool, StoreError>> + Send + '_>> {
+        self.observe("renew_lease", None, self.inner.renew_lease(name, owner, ttl))
+    }
+
+    fn release_lease(
+        &self,
+        name: &str,
+        owner: &str,
+    ) -> Pin<Box<dyn Future<Output = Result<(), StoreError>> + Send + '_>> {
+        self.observe("release_lease", None, self.inner.release_lease(name, owner))
+    }
+
+    fn namespace(&self, name: &str) -> Result<Arc<dyn Store>, StoreError> {
+        Ok(Arc::new(ObservedStore {
+            inner: self.inner.namespace(name)?,
+            observer: Arc::clone(&self.observer),
+        }))
+    }
+
+    fn list_namespaces(&self) -> Pin<Box<dyn Future<Output = Result<Vec<String>, StoreError>> + Send + '_>> {
+        self.observe("list_namespaces", None, self.inner.list_namespaces())
+    }
+
+    fn rename_namespace(
+        &self,
+        from: &str,
+        to: &str,
+    ) -> Pin<Box<dyn Future<Output = Result<(), StoreError>> + Send + '_>> {
+        self.observe("rename_namespace", None, self.inner.rename_namespace(from, to))
+    }
+
+    fn drop_namespace(&self, name: &str) -> Pin<Box<dyn Future<Output = Result<(), StoreError>> + Send + '_>> {
+        self.observe("drop_namespace", None, self.inner.drop_namespace(name))
+    }
+
+    fn begin(&self) -> Pin<Box<dyn Future<Output = Result<Box<dyn StoreTransaction>, StoreError>> + Send + '_>> {
+        let begin = Box::pin(async move {
+            let tx = self.inner.begin().await?;
+
+            Ok(Box::new(ObservedTransaction {
+                inner: tx,
+                observer: Arc::clone(&self.observer),
+            }) as Box<dyn StoreTransaction>)
+        });
+
+        self.observe("begin", None, begin)
+    }
+}
+
+/// A transaction that reports every call to the observer of its store.
+struct ObservedTransaction<O> {
+    inner: Box<dyn StoreTransaction>,
+    observer: Arc<O>,
+}
+
+impl<O: StoreObserver> ForwardingTransaction for ObservedTransaction<O> {
+    fn inner(&self) -> &dyn StoreTransaction {
+        &*self.inner
+    }
+
+    fn get(
+        &self,
+        namespace: &str,
+        key: &str,
+    ) -> Pin<Box<dyn Future<Output = Result<Option<Value>, StoreError>> + Send + '_>> {
+        observe(
+            &*self.observer,
+            namespace.to_string(),
+            "transaction.get",
+            Some(key.to_string()),
+            self.inner.get(namespace, key),
+        )
+    }
+
+    fn keys(&self, namespace: &str) -> Pin<Box<dyn Future<Output = Result<Vec<String>, StoreError>> + Send + '_>> {
+        observe(
+            &*self.observer,
+            namespace.to_string(),
+            "transaction.keys",
+            None,
+            self.inner.keys(namespace),
+        )
+    }
+
+    fn set(
+        &self,
+        namespace: &str,
+        key: &str,
+        value: Value,
+        ttl: Option<u64>,
+    ) -> Pin<Box<dyn Future<Output = Result<Written<()>, StoreError>> + Send + '_>> {
+        observe(
+            &*self.observer,
+            namespace.to_string(),
+            "transaction.set",
+            Some(key.to_string()),
+            self.inner.set(namespace, key, value, ttl),
+        )
+    }
+
+    fn remove(&self, namespace: &str, key: &str) -> Pin<Box<dyn Future<Output = Result<(), StoreError>> + Send + '_>> {
+        observe(
+            &*self.observer,
+            namespace.to_string(),
+            "transaction.remove",
+            Some(key.to_string()),
+            self.inner.remove(namespace, key),
+        )
+    }
+
+    fn commit(&self) -> Pin<Box<dyn Future<Output = Result<(), StoreError>> + Send + '_>> {
+        observe(
+            &*self.observer,
+            String::new(),
+            "transaction.commit",
+            None,
+            self.inner.commit(),
+        )
+    }
+
+    fn rollback(&self) -> Pin<Box<dyn Future<Output = Result<(), StoreError>> + Send + '_>> {
+        observe(
+            &*self.observer,
+            String::new(),
+            "transaction.rollback",
+            None,
+            self.inner.rollback(),
+        )
+    }
+}
+
+/// Logs every store call, with its namespace, key and duration.
+///
+/// Successful calls are logged at the configured level, `Debug` by default,
+/// and failed calls as warnings together with their error.
+#[derive(Clone)]
+pub struct LoggingLayer {
+    level: log::Level,
+}
+
+impl Default for LoggingLayer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl LoggingLayer {
+    pub fn new() -> Self {
+        Self {
+            level: log::Level::Debug,
+        }
+    }
+
+    /// Sets the level successful calls are logged at.
+    pub fn level(mut self, level: log::Level) -> Self {
+        self.level = level;
+        self
+    }
+}
+
+impl StoreObserver for LoggingLayer {
+    fn on_call(&self, call: &StoreCall<'_>) {
+        let key = call.key.unwrap_or("-");
+
+        match call.error {
+            Some(error) => log::warn!(
+                "Keyv {}: {:?} | {} | {} | {}",
+                call.operation,
+                call.elapsed,
+                call.namespace,
+                key,
+                error
+            ),
+            None => log::log!(
+                self.level,
+                "Keyv {}: {:?} | {} | {}",
+                call.operation,
+                call.elapsed,
+                call.namespace,
+                key
+            ),
+        }
+    }
+}
+
+impl StoreLayer for LoggingLayer {
+    fn layer(&self, inner: Arc<dyn Store>) -> Arc<dyn Store> {
+        Arc::new(ObservedStore::new(inner, Arc::new(self.clone())))
+    }
+}
+
+/// Counters of the calls made to one store operation.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct OperationStats {
+    pub calls: u64,
+    pub errors: u64,
+    pub total_time: Duration,
+    pub max_time: Duration,
+}
+
+/// Counts the calls, errors and time spent in every store operation.
+///
+/// Clones share their counters, so keep a clone of the layer to read them
+/// with `snapshot`. Transaction calls are counted under `transaction.<op>`.
+#[derive(Clone, Default)]
+pub struct MetricsLayer {
+    stats: Arc<Mutex<BTreeMap<&'static str, OperationStats>>>,
+}
+
+impl MetricsLayer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the counters of every operation called so far, by operation name.
+    pub fn snapshot(&self) -> BTreeMap<String, OperationStats> {
+        self.stats
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .iter()
+            .map(|(operation, stats)| (operation.to_string(), stats.clone()))
+            .collect()
+    }
+
+    /// Resets every counter.
+    pub fn reset(&self) {
+        self.stats.lock().unwrap_or_else(PoisonError::into_inner).clear();
+    }
+}
+
+impl StoreObserver for MetricsLayer {
+    fn on_call(&self, call: &StoreCall<'_>) {
+        let mut stats = self.stats.lock().unwrap_or_else(PoisonError::into_inner);
+        let entry = stats.entry(call.operation).or_default();
+
+        entry.calls += 1;
+        entry.total_time += call.elapsed;
+        entry.max_time = entry.max_time.max(call.elapsed);
+
+        if call.error.is_some() {
+            entry.errors += 1;
+        }
+    }
+}
+
+impl StoreLayer for MetricsLayer {
+    fn layer(&self, inner: Arc<dyn Store>) -> Arc<dyn Store> {
+        Arc::new(ObservedStore::new(inner, Arc::new(self.clone())))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::keyv::{Keyv, MemoryStore};
+
+    /// The namespace, operation, key and whether the call failed.
+    type RecordedCall = (String, &'static str, Option<String>, bool);
+
+    /// Records every call made to the observed store.
+    #[derive(Default)]
+    struct Recorder {
+        calls: Mutex<Vec<RecordedCall>>,
+    }
+
+    impl StoreObserver for Recorder {
+        fn on_call(&self, call: &StoreCall<'_>) {
+            self.calls.lock().unwrap().push((
+                call.namespace.to_string(),
+                call.operation,
+                call.key.map(|key| key.to_string()),
+                call.error.is_some(),
+            ));
+        }
+    }
+
+    #[tokio::test]
+    async fn every_call_is_reported_to_the_observer() {
+        let recorder = Arc::new(Recorder::default());
+        let store = ObservedStore::new(Arc::new(MemoryStore::new()), Arc::clone(&recorder));
+        let keyv = Keyv::try_new(store).await.unwrap();
+        recorder.calls.lock().unwrap().clear();
+
+        keyv.set("a", 1).await.unwrap();
+        keyv.namespace("other").unwrap().get("b").await.unwrap();
+        assert!(keyv.enable_search().await.is_err());
+        keyv.transaction(|tx| async move { tx.set("c", 3).await })
+            .await
+            .unwrap();
+
+        let calls = recorder.calls.lock().unwrap().clone();
+        let key = |key: &str| Some(key.to_string());
+        assert_eq!(
+            calls,
+            [
+                ("kv_store".to_string(), "set", key("a"), false),
+                ("other".to_string(), "get", key("b"), false),
+                ("kv_store".to_string(), "set_search", None, true),
+                ("kv_store".to_string(), "begin", None, false),
+                ("kv_store".to_string(), "transaction.set", key("c"), false),
+                // A transaction may span namespaces, so its commit has none.
+                (String::new(), "transaction.commit", None, false),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn metrics_count_calls_and_errors_per_operation() {
+        let metrics = MetricsLayer::new();
+        let keyv = Keyv::default().layer(metrics.clone());
+
+        keyv.set("a", 1).await.unwrap();
+        keyv.set("b", 2).await.unwrap();
+        keyv.get("a").await.unwrap();
+        assert!(keyv.increment("missing", i64::MAX).await.is_ok());
+        assert!(keyv.increment("missing", 1).await.is_err());
+
+        let stats = metrics.snapshot();
+        assert_eq!((stats["set"].calls, stats["set"].errors), (2, 0));
+        assert_eq!(stats["get"].calls, 1);
+        assert_eq!((stats["increment"].calls, stats["increment"].errors), (2, 1));
+        assert!(stats["set"].max_time <= stats["set"].total_time);
+
+        metrics.reset();
+        assert!(metrics.snapshot().is_empty());
+    }
+}
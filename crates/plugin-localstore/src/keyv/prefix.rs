@@ -0,0 +1,533 @@
+// Copyright © 2024 Crabby Craft - All Rights Reserved.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use serde_json::Value;
+use std::collections::HashMap;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::time::Duration;
+
+use super::{
+    FindQuery, ForwardingStore, ForwardingTransaction, ListPage, ListQuery, Revision, SearchHit, SearchQuery, Store,
+    StoreError, StoreLayer, StoreModel, StoreTransaction, UpdateFn, VersionedValue, Written,
+};
+
+/// Scopes every key to a prefix, e.g. to share a namespace between users.
+///
+/// Keys are prefixed on the way in and the prefix is stripped from every key
+/// returned, so the wrapped store only sees and returns keys under the prefix.
+/// `clear` only removes the keys under the prefix.
+#[derive(Clone)]
+pub struct PrefixLayer {
+    prefix: Arc<str>,
+}
+
+impl PrefixLayer {
+    pub fn new<S: Into<String>>(prefix: S) -> Self {
+        Self {
+            prefix: Arc::from(prefix.into()),
+        }
+    }
+}
+
+impl StoreLayer for PrefixLayer {
+    fn layer(&self, inner: Arc<dyn Store>) -> Arc<dyn Store> {
+        Arc::new(PrefixedStore {
+            inner,
+            prefix: Arc::clone(&self.prefix),
+        })
+    }
+}
+
+/// A store whose keys are all scoped to a prefix, see [`PrefixLayer`].
+pub struct PrefixedStore {
+    inner: Arc<dyn Store>,
+    prefix: Arc<str>,
+}
+
+impl PrefixedStore {
+    fn key(&self, key: &str) -> String {
+        prefixed(&self.prefix, key)
+    }
+
+    fn strip(&self, key: String) -> Option<String> {
+        key.strip_prefix(&*self.prefix).map(|key| key.to_string())
+    }
+
+    fn strip_written<T>(&self, written: Written<T>) -> Written<T> {
+        strip_evicted(&self.prefix, written)
+    }
+
+    fn strip_model(&self, model: StoreModel) -> Option<StoreModel> {
+        Some(StoreModel {
+            key: self.strip(model.key)?,
+            ..model
+        })
+    }
+
+    fn strip_revision(&self, revision: Revision) -> Option<Revision> {
+        Some(Revision {
+            key: self.strip(revision.key)?,
+            ..revision
+        })
+    }
+
+    fn strip_error(&self, error: StoreError) -> StoreError {
+        match error {
+            StoreError::VersionConflict { key, expected, actual } => StoreError::VersionConflict {
+                key: key.strip_prefix(&*self.prefix).unwrap_or(&key).to_string(),
+                expected,
+                actual,
+            },
+            error => error,
+        }
+    }
+
+    /// Maps a query on unprefixed keys to the same query on prefixed keys.
+    fn query(&self, query: ListQuery) -> ListQuery {
+        ListQuery {
+            prefix: Some(prefixed(&self.prefix, query.prefix.as_deref().unwrap_or(""))),
+            start: query.start.map(|start| self.key(&start)),
+            end: query.end.map(|end| self.key(&end)),
+            after: query.after.map(|after| self.key(&after)),
+            limit: query.limit,
+        }
+    }
+}
+
+fn prefixed(prefix: &str, key: &str) -> String {
+    format!("{}{}", prefix, key)
+}
+
+/// Strips the prefix from the keys evicted by a write. Keys evicted outside the
+/// prefix are left out, as they cannot be named through a prefixed store.
+fn strip_evicted<T>(prefix: &str, written: Written<T>) -> Written<T> {
+    Written {
+        evicted: written
+            .evicted
+            .into_iter()
+            .filter_map(|key| key.strip_prefix(prefix).map(|key| key.to_string()))
+            .collect(),
+        ..written
+    }
+}
+
+impl ForwardingStore for PrefixedStore {
+    fn inner(&self) -> &dyn Store {
+        &*self.inner
+    }
+
+    fn get(&self, key: &str) -> Pin<Box<dyn Future<Output = Result<Option<Value>, StoreError>> + Send + '_>> {
+        self.inner.get(&self.key(key))
+    }
+
+    fn get_versioned(
+        &self,
+        key: &str,
+    ) -> Pin<Box<dyn Future<Output = Result<Option<VersionedValue>, StoreError>> + Send + '_>> {
+        self.inner.get_versioned(&self.key(key))
+    }
+
+    fn get_with_meta(
+        &self,
+        key: &str,
+    ) -> Pin<Box<dyn Future<Output = Result<Option<StoreModel>, StoreError>> + Send + '_>> {
+        let model = self.inner.get_with_meta(&self.key(key));
+
+        Box::pin(async move { Ok(model.await?.and_then(|model| self.strip_model(model))) })
+    }
+
+    fn get_many(
+        &self,
+        keys: &[&str],
+    ) -> Pin<Box<dyn Future<Output = Result<HashMap<String, Value>, StoreError>> + Send + '_>> {
+        let keys: Vec<String> = keys.iter().map(|key| self.key(key)).collect();
+        let keys: Vec<&str> = keys.iter().map(|key| key.as_str()).collect();
+        let values = self.inner.get_many(&keys);
+
+        Box::pin(async move {
+            Ok(values
+                .await?
+                .into_iter()
+                .filter_map(|(key, value)| Some((self.strip(key)?, value)))
+                .collect())
+        })
+    }
+
+    fn list(&self) -> Pin<Box<dyn Future<Output = Result<Vec<StoreModel>, StoreError>> + Send + '_>> {
+        let page = self.inner.list_page(self.query(ListQuery::new()));
+
+        Box::pin(async move {
+            Ok(page
+                .await?
+                .items
+                .into_iter()
+                .filter_map(|model| self.strip_model(model))
+                .collect())
+        })
+    }
+
+    fn list_page(&self, query: ListQuery) -> Pin<Box<dyn Future<Output = Result<ListPage, StoreError>> + Send + '_>> {
+        let page = self.inner.list_page(self.query(query));
+
+        Box::pin(async move {
+            let page = page.await?;
+
+            Ok(ListPage {
+                items: page
+                    .items
+                    .into_iter()
+                    .filter_map(|model| self.strip_model(model))
+                    .collect(),
+                next_cursor: page.next_cursor.and_then(|cursor| self.strip(cursor)),
+            })
+        })
+    }
+
+    fn find(&self, query: FindQuery) -> Pin<Box<dyn Future<Output = Result<Vec<StoreModel>, StoreError>> + Send + '_>> {
+        let items = self.inner.find(FindQuery {
+            prefix: Some(prefixed(&self.prefix, query.prefix.as_deref().unwrap_or(""))),
+            ..query
+        });
+
+        Box::pin(async move {
+            Ok(items
+                .await?
+                .into_iter()
+                .filter_map(|model| self.strip_model(model))
+                .collect())
+        })
+    }
+
+    fn set(
+        &self,
+        key: &str,
+        value: Value,
+        ttl: Option<u64>,
+    ) -> Pin<Box<dyn Future<Output = Result<Written<Option<StoreModel>>, StoreError>> + Send + '_>> {
+        let result = self.inner.set(&self.key(key), value, ttl);
+
+        Box::pin(async move {
+            Ok(self
+                .strip_written(result.await?)
+                .map(|model| model.and_then(|model| self.strip_model(model))))
+        })
+    }
+
+    fn set_many(
+        &self,
+        entries: Vec<(String, Value)>,
+        ttl: Option<u64>,
+    ) -> Pin<Box<dyn Future<Output = Result<Written<()>, StoreError>> + Send + '_>> {
+        let entries = entries
+            .into_iter()
+            .map(|(key, value)| (self.key(&key), value))
+            .collect();
+        let result = self.inner.set_many(entries, ttl);
+
+        Box::pin(async move { Ok(self.strip_written(result.await?)) })
+    }
+
+    fn set_versioned(
+        &self,
+        key: &str,
+        value: Value,
+        expected_version: Option<u64>,
+        ttl: Option<u64>,
+    ) -> Pin<Box<dyn Future<Output = Result<Written<u64>, StoreError>> + Send + '_>> {
+        let result = self.inner.set_versioned(&self.key(key), value, expected_version, ttl);
+
+        Box::pin(async move {
+            result
+                .await
+                .map(|written| self.strip_written(written))
+                .map_err(|e| self.strip_error(e))
+        })
+    }
+
+    fn compare_and_swap(
+        &self,
+        key: &str,
+        expected: Option<Value>,
+        new: Value,
+    ) -> Pin<Box<dyn Future<Output = Result<Written<bool>, StoreError>> + Send + '_>> {
+        let result = self.inner.compare_and_swap(&self.key(key), expected, new);
+
+        Box::pin(async move { Ok(self.strip_written(result.await?)) })
+    }
+
+    fn increment(
+        &self,
+        key: &str,
+        delta: i64,
+    ) -> Pin<Box<dyn Future<Output = Result<Written<i64>, StoreError>> + Send + '_>> {
+        let result = self.inner.increment(&self.key(key), delta);
+
+        Box::pin(async move { Ok(self.strip_written(result.await?)) })
+    }
+
+    fn update<'a>(
+        &'a self,
+        key: &str,
+        f: UpdateFn<'a>,
+    ) -> Pin<Box<dyn Future<Output = Result<Written<Value>, StoreError>> + Send + 'a>> {
+        let result = self.inner.update(&self.key(key), f);
+
+        Box::pin(async move { Ok(self.strip_written(result.await?)) })
+    }
+
+    fn remove(&self, key: &str) -> Pin<Box<dyn Future<Output = Result<(), StoreError>> + Send + '_>> {
+        self.inner.remove(&self.key(key))
+    }
+
+    fn remove_many(&self, keys: &[&str]) -> Pin<Box<dyn Future<Output = Result<Vec<String>, StoreError>> + Send + '_>> {
+        let keys: Vec<String> = keys.iter().map(|key| self.key(key)).collect();
+        let keys: Vec<&str> = keys.iter().map(|key| key.as_str()).collect();
+        let removed = self.inner.remove_many(&keys);
+
+        Box::pin(async move { Ok(removed.await?.into_iter().filter_map(|key| self.strip(key)).collect()) })
+    }
+
+    fn clear(&self) -> Pin<Box<dyn Future<Output = Result<(), StoreError>> + Send + '_>> {
+        Box::pin(async move {
+            let page = self.inner.list_page(self.query(ListQuery::new())).await?;
+
+            let keys: Vec<&str> = page.items.iter().map(|model| model.key.as_str()).collect();
+
+            if keys.is_empty() {
+                return Ok(());
+            }
+
+            self.inner.remove_many(&keys).await?;
+            Ok(())
+        })
+    }
+
+    fn purge_expired(&self) -> Pin<Box<dyn Future<Output = Result<Vec<String>, StoreError>> + Send + '_>> {
+        let purged = self.inner.purge_expired();
+
+        Box::pin(async move { Ok(purged.await?.into_iter().filter_map(|key| self.strip(key)).collect()) })
+    }
+
+    /// The history is configured for the whole namespace, not only the prefix.
+    fn set_history(&self, limit: Option<usize>) -> Pin<Box<dyn Future<Output = Result<(), StoreError>> + Send + '_>> {
+        self.inner.set_history(limit)
+    }
+
+    fn history(&self, key: &str) -> Pin<Box<dyn Future<Output = Result<Vec<Revision>, StoreError>> + Send + '_>> {
+        let revisions = self.inner.history(&self.key(key));
+
+        Box::pin(async move {
+            Ok(revisions
+                .await?
+                .into_iter()
+                .filter_map(|revision| self.strip_revision(revision))
+                .collect())
+        })
+    }
+
+    fn get_revision(
+        &self,
+        key: &str,
+        id: i64,
+    ) -> Pin<Box<dyn Future<Output = Result<Option<Revision>, StoreError>> + Send + '_>> {
+        let revision = self.inner.get_revision(&self.key(key), id);
+
+        Box::pin(async move { Ok(revision.await?.and_then(|revision| self.strip_revision(revision))) })
+    }
+
+    fn revert(
+        &self,
+        key: &str,
+        id: i64,
+    ) -> Pin<Box<dyn Future<Output = Result<Written<StoreModel>, StoreError>> + Send + '_>> {
+        let model = self.inner.revert(&self.key(key), id);
+
+        Box::pin(async move {
+            let written = self.strip_written(model.await?);
+            let model = self.strip_model(written.value).ok_or(StoreError::NotFound)?;
+
+            Ok(Written {
+                value: model,
+                evicted: written.evicted,
+            })
+        })
+    }
+
+    /// The index is configured for the whole namespace, not only the prefix.
+    fn set_search(&self, enabled: bool) -> Pin<Box<dyn Future<Output = Result<(), StoreError>> + Send + '_>> {
+        self.inner.set_search(enabled)
+    }
+
+    fn search(
+        &self,
+        query: SearchQuery,
+    ) -> Pin<Box<dyn Future<Output = Result<Vec<SearchHit>, StoreError>> + Send + '_>> {
+        let hits = self.inner.search(SearchQuery {
+            prefix: Some(prefixed(&self.prefix, query.prefix.as_deref().unwrap_or(""))),
+            ..query
+        });
+
+        Box::pin(async move {
+            Ok(hits
+                .await?
+                .into_iter()
+                .filter_map(|hit| {
+                    Some(SearchHit {
+                        key: self.strip(hit.key)?,
+                        ..hit
+                    })
+                })
+                .collect())
+        })
+    }
+
+    /// Indexes cover the whole namespace, not only the prefix.
+    fn create_index(
+        &self,
+        name: &str,
+        path: &str,
+    ) -> Pin<Box<dyn Future<Output = Result<(), StoreError>> + Send + '_>> {
+        self.inner.create_index(name, path)
+    }
+
+    /// Lock names are shared by the whole backend, so they are not prefixed.
+    fn acquire_lease(
+        &self,
+        name: &str,
+        owner: &str,
+        ttl: Duration,
+    ) -> Pin<Box<dyn Future<Output = Result<bool, StoreError>> + Send + '_>> {
+        self.inner.acquire_lease(name, owner, ttl)
+    }
+
+    fn namespace(&self, name: &str) -> Result<Arc<dyn Store>, StoreError> {
+        Ok(Arc::new(PrefixedStore {
+            inner: self.inner.namespace(name)?,
+            prefix: Arc::clone(&self.prefix),
+        }))
+    }
+
+    fn begin(&self) -> Pin<Box<dyn Future<Output = Result<Box<dyn StoreTransaction>, StoreError>> + Send + '_>> {
+        Box::pin(async move {
+            let tx = self.inner.begin().await?;
+
+            Ok(Box::new(PrefixedTransaction {
+                inner: tx,
+                prefix: Arc::clone(&self.prefix),
+            }) as Box<dyn StoreTransaction>)
+        })
+    }
+}
+
+/// A transaction whose keys are all scoped to a prefix.
+struct PrefixedTransaction {
+    inner: Box<dyn StoreTransaction>,
+    prefix: Arc<str>,
+}
+
+impl ForwardingTransaction for PrefixedTransaction {
+    fn inner(&self) -> &dyn StoreTransaction {
+        &*self.inner
+    }
+
+    fn get(
+        &self,
+        namespace: &str,
+        key: &str,
+    ) -> Pin<Box<dyn Future<Output = Result<Option<Value>, StoreError>> + Send + '_>> {
+        self.inner.get(namespace, &prefixed(&self.prefix, key))
+    }
+
+    fn keys(&self, namespace: &str) -> Pin<Box<dyn Future<Output = Result<Vec<String>, StoreError>> + Send + '_>> {
+        let keys = self.inner.keys(namespace);
+
+        Box::pin(async move {
+            Ok(keys
+                .await?
+                .into_iter()
+                .filter_map(|key| key.strip_prefix(&*self.prefix).map(|key| key.to_string()))
+                .collect())
+        })
+    }
+
+    fn set(
+        &self,
+        namespace: &str,
+        key: &str,
+        value: Value,
+        ttl: Option<u64>,
+    ) -> Pin<Box<dyn Future<Output = Result<Written<()>, StoreError>> + Send + '_>> {
+        let result = self.inner.set(namespace, &prefixed(&self.prefix, key), value, ttl);
+
+        Box::pin(async move { Ok(strip_evicted(&self.prefix, result.await?)) })
+    }
+
+    fn remove(&self, namespace: &str, key: &str) -> Pin<Box<dyn Future<Output = Result<(), StoreError>> + Send + '_>> {
+        self.inner.remove(namespace, &prefixed(&self.prefix, key))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::keyv::{Keyv, KeyvChangeKind, MemoryStore};
+    use serde_json::json;
+    use tokio_stream::StreamExt;
+
+    fn keys(models: Vec<StoreModel>) -> Vec<String> {
+        models.into_iter().map(|model| model.key).collect()
+    }
+
+    #[tokio::test]
+    async fn keys_are_scoped_to_the_prefix() {
+        let keyv = Keyv::try_new(MemoryStore::new()).await.unwrap();
+        let user = keyv.clone().layer(PrefixLayer::new("user:1:"));
+        keyv.set("other", 0).await.unwrap();
+        keyv.set("user:10:theme", "light").await.unwrap();
+        user.set_many([("font", json!(14)), ("lang", json!("en")), ("theme", json!("dark"))])
+            .await
+            .unwrap();
+
+        assert_eq!(keyv.get("user:1:theme").await.unwrap(), Some(json!("dark")));
+        assert_eq!(keys(user.list().await.unwrap()), ["font", "lang", "theme"]);
+        assert_eq!(keys(user.list_prefix("t").await.unwrap()), ["theme"]);
+
+        let page = user.list_page(ListQuery::new().range("f", "t").limit(1)).await.unwrap();
+        assert_eq!(
+            (keys(page.items), page.next_cursor.as_deref()),
+            (vec!["font".to_string()], Some("font"))
+        );
+        let page = user
+            .list_page(ListQuery::new().range("f", "t").after("font"))
+            .await
+            .unwrap();
+        assert_eq!((keys(page.items), page.next_cursor), (vec!["lang".to_string()], None));
+
+        let tx_keys = user
+            .transaction(|tx| async move {
+                tx.set("new", 1).await?;
+                tx.keys().await
+            })
+            .await
+            .unwrap();
+        assert_eq!(tx_keys, ["font", "lang", "new", "theme"]);
+
+        let mut changes = keyv.changes();
+        user.remove_many(&["font", "missing"]).await.unwrap();
+        let change = changes.next().await.unwrap();
+        assert_eq!(
+            (change.kind, change.key.as_deref()),
+            (KeyvChangeKind::Remove, Some("font"))
+        );
+
+        user.clear().await.unwrap();
+        assert_eq!(keys(keyv.list().await.unwrap()), ["other", "user:10:theme"]);
+    }
+}
@@ -0,0 +1,230 @@
+// Copyright © 2024 Crabby Craft - All Rights Reserved.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use jsonschema::JSONSchema;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::sync::{Arc, PoisonError, RwLock};
+
+use super::{StoreError, StoreWrite, WriteGuard};
+
+/// The keys a schema registered with `Keyv::register_schema` applies to.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SchemaTarget {
+    /// A single key.
+    Key(String),
+    /// Every key starting with the prefix.
+    Prefix(String),
+}
+
+impl SchemaTarget {
+    pub fn key<S: Into<String>>(key: S) -> Self {
+        Self::Key(key.into())
+    }
+
+    pub fn prefix<S: Into<String>>(prefix: S) -> Self {
+        Self::Prefix(prefix.into())
+    }
+
+    /// Returns `true` if the schema applies to `key`.
+    pub fn matches(&self, key: &str) -> bool {
+        match self {
+            Self::Key(target) => key == target,
+            Self::Prefix(prefix) => key.starts_with(prefix.as_str()),
+        }
+    }
+}
+
+/// One way in which a value does not match its schema.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SchemaViolation {
+    /// JSON Pointer to the part of the value that is invalid, empty for the value itself.
+    pub instance_path: String,
+    /// JSON Pointer to the schema keyword that rejected it.
+    pub schema_path: String,
+    /// A description of the violation.
+    pub message: String,
+}
+
+/// Formats violations for `StoreError::SchemaViolation`.
+pub(crate) fn describe_violations(violations: &[SchemaViolation]) -> String {
+    violations
+        .iter()
+        .map(|v| {
+            if v.instance_path.is_empty() {
+                v.message.clone()
+            } else {
+                format!("{}: {}", v.instance_path, v.message)
+            }
+        })
+        .collect::<Vec<String>>()
+        .join("; ")
+}
+
+struct RegisteredSchema {
+    namespace: String,
+    target: SchemaTarget,
+    schema: Arc<JSONSchema>,
+}
+
+/// The schemas registered on a `Keyv` and its namespaces, checked on every write.
+#[derive(Default)]
+pub(crate) struct SchemaRegistry {
+    schemas: RwLock<Vec<RegisteredSchema>>,
+}
+
+impl SchemaRegistry {
+    /// Compiles `schema` and registers it, replacing any schema registered for
+    /// the same namespace and target.
+    pub(crate) fn register(&self, namespace: &str, target: SchemaTarget, schema: &Value) -> Result<(), StoreError> {
+        let compiled = JSONSchema::compile(schema).map_err(|e| StoreError::InvalidSchema(e.to_string()))?;
+
+        let mut schemas = self.schemas.write().unwrap_or_else(PoisonError::into_inner);
+        schemas.retain(|s| !(s.namespace == namespace && s.target == target));
+        schemas.push(RegisteredSchema {
+            namespace: namespace.to_string(),
+            target,
+            schema: Arc::new(compiled),
+        });
+
+        Ok(())
+    }
+
+    /// Removes the schema registered for a namespace and target. Returns `false`
+    /// if there was none.
+    pub(crate) fn unregister(&self, namespace: &str, target: &SchemaTarget) -> bool {
+        let mut schemas = self.schemas.write().unwrap_or_else(PoisonError::into_inner);
+        let before = schemas.len();
+        schemas.retain(|s| !(s.namespace == namespace && &s.target == target));
+        schemas.len() != before
+    }
+
+    /// Validates `value` against every schema matching the key, collecting all
+    /// the violations.
+    pub(crate) fn validate(&self, namespace: &str, key: &str, value: &Value) -> Result<(), StoreError> {
+        let matching: Vec<Arc<JSONSchema>> = self
+            .schemas
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .iter()
+            .filter(|s| s.namespace == namespace && s.target.matches(key))
+            .map(|s| Arc::clone(&s.schema))
+            .collect();
+
+        let mut violations = Vec::new();
+
+        for schema in matching {
+            if let Err(errors) = schema.validate(value) {
+                violations.extend(errors.map(|e| SchemaViolation {
+                    instance_path: e.instance_path.to_string(),
+                    schema_path: e.schema_path.to_string(),
+                    message: e.to_string(),
+                }));
+            }
+        }
+
+        if violations.is_empty() {
+            return Ok(());
+        }
+
+        Err(StoreError::SchemaViolation {
+            key: key.to_string(),
+            violations,
+        })
+    }
+}
+
+impl WriteGuard for SchemaRegistry {
+    fn check(&self, write: &StoreWrite<'_>) -> Result<(), StoreError> {
+        match (write.key, write.value) {
+            (Some(key), Some(value)) => self.validate(write.namespace, key, value),
+            _ => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::keyv::{Keyv, KeyvError};
+    use serde_json::json;
+
+    fn settings_schema() -> Value {
+        json!({
+            "type": "object",
+            "properties": { "theme": { "enum": ["light", "dark"] } },
+            "required": ["theme"]
+        })
+    }
+
+    fn violations(result: Result<(), KeyvError>) -> Vec<SchemaViolation> {
+        match result {
+            Err(KeyvError::StoreError(StoreError::SchemaViolation { violations, .. })) => violations,
+            other => panic!("expected a schema violation, got {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn writes_not_matching_the_schema_are_rejected() {
+        let keyv = Keyv::default();
+        keyv.register_schema(SchemaTarget::prefix("settings:"), &settings_schema())
+            .unwrap();
+
+        keyv.set("settings:ui", json!({ "theme": "dark" })).await.unwrap();
+
+        let rejected = violations(keyv.set("settings:ui", json!({ "theme": "blue" })).await.map(|_| ()));
+        assert_eq!(rejected.len(), 1);
+        assert_eq!(rejected[0].instance_path, "/theme");
+        assert_eq!(keyv.get("settings:ui").await.unwrap(), Some(json!({ "theme": "dark" })));
+
+        // Batches are rejected as a whole.
+        let batch = keyv
+            .set_many([("settings:a", json!({ "theme": "light" })), ("settings:b", json!({}))])
+            .await;
+        assert_eq!(violations(batch)[0].instance_path, "");
+        assert_eq!(keyv.get("settings:a").await.unwrap(), None);
+
+        // Other keys and namespaces are not checked.
+        keyv.set("draft", "anything").await.unwrap();
+        let other = keyv.namespace("other").unwrap();
+        other.set("settings:ui", "anything").await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn violations_of_every_matching_schema_are_collected() {
+        let keyv = Keyv::default();
+        keyv.register_schema(SchemaTarget::prefix("settings:"), &settings_schema())
+            .unwrap();
+        keyv.register_schema(SchemaTarget::key("settings:ui"), &json!({ "maxProperties": 1 }))
+            .unwrap();
+
+        let rejected = violations(
+            keyv.set("settings:ui", json!({ "theme": "blue", "extra": true }))
+                .await
+                .map(|_| ()),
+        );
+        assert_eq!(rejected.len(), 2);
+
+        assert!(keyv.unregister_schema(&SchemaTarget::key("settings:ui")));
+        assert!(!keyv.unregister_schema(&SchemaTarget::key("settings:ui")));
+        keyv.set("settings:ui", json!({ "theme": "light", "extra": true }))
+            .await
+            .unwrap();
+    }
+
+    #[test]
+    fn invalid_schemas_are_not_registered() {
+        let keyv = Keyv::default();
+        let result = keyv.register_schema(SchemaTarget::key("a"), &json!({ "type": 42 }));
+        assert!(matches!(
+            result,
+            Err(KeyvError::StoreError(StoreError::InvalidSchema(_)))
+        ));
+    }
+}
@@ -0,0 +1,139 @@
+// Copyright © 2024 Crabby Craft - All Rights Reserved.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use serde::{Deserialize, Serialize};
+
+/// Number of hits returned by a [`SearchQuery`] without a limit.
+pub const DEFAULT_SEARCH_LIMIT: u64 = 20;
+
+/// Markers placed around the matched terms in [`SearchHit::snippet`].
+pub const SEARCH_HIGHLIGHT: (&str, &str) = ("<mark>", "</mark>");
+
+/// A full-text search over the values of a namespace, see `Keyv::search`.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SearchQuery {
+    /// The terms to search for, in the SQLite FTS5 query syntax, e.g.
+    /// `rust AND tauri` or `"exact phrase"`.
+    pub query: String,
+    /// Only include keys starting with this prefix.
+    pub prefix: Option<String>,
+    /// Maximum number of hits to return, [`DEFAULT_SEARCH_LIMIT`] if unset.
+    pub limit: Option<u64>,
+}
+
+impl SearchQuery {
+    pub fn new<S: Into<String>>(query: S) -> Self {
+        Self {
+            query: query.into(),
+            ..Self::default()
+        }
+    }
+
+    pub fn prefix<S: Into<String>>(mut self, prefix: S) -> Self {
+        self.prefix = Some(prefix.into());
+        self
+    }
+
+    pub fn limit(mut self, limit: u64) -> Self {
+        self.limit = Some(limit);
+        self
+    }
+}
+
+/// A key whose value matches a [`SearchQuery`].
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SearchHit {
+    pub key: String,
+    /// An excerpt of the value with the matched terms wrapped in [`SEARCH_HIGHLIGHT`].
+    pub snippet: String,
+    /// The BM25 rank of the hit. Lower is more relevant, and hits are sorted by it.
+    pub rank: f64,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::keyv::{Keyv, KeyvError, KeyvStoreBuilder, StoreError};
+    use serde_json::json;
+
+    async fn memory_keyv(builder: KeyvStoreBuilder) -> Keyv {
+        let store = builder.uri(":memory:").build().await.unwrap();
+        Keyv::try_new(store).await.unwrap()
+    }
+
+    async fn search_keys(keyv: &Keyv, query: &str) -> Vec<String> {
+        let mut keys: Vec<String> = keyv
+            .search(query)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|hit| hit.key)
+            .collect();
+        keys.sort();
+        keys
+    }
+
+    #[tokio::test]
+    async fn the_index_follows_every_write() {
+        let keyv = memory_keyv(KeyvStoreBuilder::new()).await;
+        keyv.set("note:1", json!({ "title": "Tauri plugins", "tags": ["rust"] }))
+            .await
+            .unwrap();
+        keyv.set("note:2", "Cooking with rust").await.unwrap();
+        keyv.set("count", 42).await.unwrap();
+
+        assert!(keyv.search("rust").await.is_err());
+
+        // Values written before the index was enabled are indexed too.
+        keyv.enable_search().await.unwrap();
+        assert_eq!(search_keys(&keyv, "rust").await, ["note:1", "note:2"]);
+
+        let hits = keyv.search("plugins").await.unwrap();
+        assert!(hits[0].snippet.contains("Tauri <mark>plugins</mark>"));
+
+        keyv.set("note:2", "Baking bread").await.unwrap();
+        assert_eq!(search_keys(&keyv, "rust").await, ["note:1"]);
+        assert_eq!(search_keys(&keyv, "bread").await, ["note:2"]);
+
+        keyv.transaction(|tx| async move { tx.set("note:3", "Rust in a transaction").await })
+            .await
+            .unwrap();
+        keyv.remove("note:1").await.unwrap();
+        assert_eq!(search_keys(&keyv, "rust").await, ["note:3"]);
+
+        let query = SearchQuery::new("bread OR rust").prefix("note:3").limit(5);
+        let hits = keyv.search_with(query).await.unwrap();
+        assert_eq!(hits.len(), 1);
+
+        keyv.clear().await.unwrap();
+        assert!(search_keys(&keyv, "rust OR bread").await.is_empty());
+
+        keyv.disable_search().await.unwrap();
+        assert!(keyv.search("rust").await.is_err());
+    }
+
+    #[tokio::test]
+    async fn search_is_rejected_on_encoded_values() {
+        let encrypted = memory_keyv(KeyvStoreBuilder::new().encryption_key([3; 32])).await;
+        assert!(matches!(
+            encrypted.enable_search().await,
+            Err(KeyvError::StoreError(StoreError::Unsupported(_)))
+        ));
+
+        let compressed = memory_keyv(KeyvStoreBuilder::new().compression_threshold(1024)).await;
+        assert!(compressed.enable_search().await.is_err());
+    }
+
+    #[tokio::test]
+    async fn namespaces_cannot_clash_with_search_tables() {
+        let keyv = memory_keyv(KeyvStoreBuilder::new()).await;
+        assert!(keyv.namespace("notes_search").is_err());
+        assert!(keyv.namespace("notes_search_data").is_err());
+        assert!(keyv.rename_namespace("keyv", "notes_keyv_upgrade").await.is_err());
+    }
+}
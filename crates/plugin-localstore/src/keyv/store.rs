@@ -15,15 +15,102 @@
 
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
+use std::collections::HashMap;
 use std::future::Future;
+use std::path::Path;
 use std::pin::Pin;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio_stream::{Stream, StreamExt};
 
+use super::{
+    describe_violations, CacheStats, FindQuery, ListPage, ListQuery, ListStream, SchemaViolation, SearchHit,
+    SearchQuery,
+};
+
+/// A key-value pair together with its metadata.
+///
+/// Timestamps are in milliseconds since the Unix epoch, in UTC.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct StoreModel {
     pub key: String,
     pub value: Value,
+    /// The revision of the key, see [`VersionedValue`].
+    pub version: u64,
+    /// When the key was first written.
+    pub created_at: i64,
+    /// When the key was last written.
+    pub updated_at: i64,
+    /// When the key expires, `None` if it has no TTL.
+    pub expires_at: Option<i64>,
+}
+
+/// A value together with the version of the row holding it.
+///
+/// The version starts at 1 when a key is first written and is incremented by
+/// every write, so it can be passed back to `set_versioned` to detect
+/// concurrent modifications.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct VersionedValue {
+    pub value: Value,
+    pub version: u64,
+}
+
+/// The result of a write, together with the keys evicted to make room for it
+/// in a namespace with an eviction policy, see
+/// [`NamespaceLimits::evict`](super::NamespaceLimits::evict).
+///
+/// Evicted keys are deleted like removed ones, so `Keyv` publishes a remove
+/// change for each of them.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Written<T> {
+    /// The result of the write itself.
+    pub value: T,
+    /// The keys evicted by the write, empty unless the namespace evicts entries.
+    pub evicted: Vec<String>,
+}
+
+impl<T> Written<T> {
+    /// A write that did not evict any key.
+    pub fn new(value: T) -> Self {
+        Self {
+            value,
+            evicted: Vec::new(),
+        }
+    }
+
+    /// Maps the result of the write, keeping the evicted keys.
+    pub fn map<U, F: FnOnce(T) -> U>(self, f: F) -> Written<U> {
+        Written {
+            value: f(self.value),
+            evicted: self.evicted,
+        }
+    }
+}
+
+/// A previous value of a key, recorded when the key was overwritten or removed
+/// in a namespace with history enabled.
+///
+/// Timestamps are in milliseconds since the Unix epoch, in UTC.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Revision {
+    /// Identifies the revision across the store, passed to `get_revision` and `revert`.
+    pub id: i64,
+    pub key: String,
+    pub value: Value,
+    /// The version the key had while holding this value.
+    pub version: u64,
+    /// When this value was written.
+    pub updated_at: i64,
+    /// When this value was overwritten or removed.
+    pub replaced_at: i64,
+    /// `true` if the value was removed rather than overwritten.
+    pub removed: bool,
 }
 
+/// Computes the new value of a key from its current value, used by [`Store::update`].
+pub type UpdateFn<'a> = Box<dyn FnOnce(Option<Value>) -> Result<Value, StoreError> + Send + 'a>;
+
 pub trait Store: Send + Sync {
     /// Initializes the storage backend.
     /// This method should perform any necessary setup for the storage backend, such as
@@ -45,6 +132,51 @@ pub trait Store: Send + Sync {
     /// - `Err(StoreError)` if there is an error retrieving the value.
     fn get(&self, key: &str) -> Pin<Box<dyn Future<Output = Result<Option<Value>, StoreError>> + Send + '_>>;
 
+    /// Retrieves a value together with its current version.
+    ///
+    /// # Arguments
+    /// - `key`: A string slice that holds the key for the value to be retrieved.
+    ///
+    /// # Returns
+    /// - `Ok(Some(VersionedValue))` if the key exists and has not expired.
+    /// - `Ok(None)` if the key does not exist.
+    /// - `Err(StoreError)` if there is an error retrieving the value.
+    #[allow(clippy::type_complexity)]
+    fn get_versioned(
+        &self,
+        key: &str,
+    ) -> Pin<Box<dyn Future<Output = Result<Option<VersionedValue>, StoreError>> + Send + '_>>;
+
+    /// Retrieves a value together with its version, timestamps and expiry.
+    ///
+    /// # Arguments
+    /// - `key`: A string slice that holds the key for the value to be retrieved.
+    ///
+    /// # Returns
+    /// - `Ok(Some(StoreModel))` if the key exists and has not expired.
+    /// - `Ok(None)` if the key does not exist.
+    /// - `Err(StoreError)` if there is an error retrieving the value.
+    #[allow(clippy::type_complexity)]
+    fn get_with_meta(
+        &self,
+        key: &str,
+    ) -> Pin<Box<dyn Future<Output = Result<Option<StoreModel>, StoreError>> + Send + '_>>;
+
+    /// Retrieves the values associated with several keys in one round trip.
+    ///
+    /// # Arguments
+    /// - `keys`: A slice of string slices representing the keys to retrieve.
+    ///
+    /// # Returns
+    /// - `Ok(HashMap<String, Value>)` with an entry for every key that exists and has not
+    ///   expired. Missing keys are left out of the map.
+    /// - `Err(StoreError)` if there is an error retrieving the values.
+    #[allow(clippy::type_complexity)]
+    fn get_many(
+        &self,
+        keys: &[&str],
+    ) -> Pin<Box<dyn Future<Output = Result<HashMap<String, Value>, StoreError>> + Send + '_>>;
+
     /// Lists all key-value pairs stored in the store.
     ///
     /// # Returns
@@ -52,6 +184,64 @@ pub trait Store: Send + Sync {
     /// - `Err(StoreError)` if there is an error listing the key-value pairs.
     fn list(&self) -> Pin<Box<dyn Future<Output = Result<Vec<StoreModel>, StoreError>> + Send + '_>>;
 
+    /// Lists the key-value pairs matching a query, in ascending key order.
+    ///
+    /// # Arguments
+    /// - `query`: The prefix, range, cursor and limit to apply.
+    ///
+    /// # Returns
+    /// - `Ok(ListPage)` with the matching pairs and the cursor of the next page, if any.
+    /// - `Err(StoreError)` if there is an error listing the key-value pairs.
+    fn list_page(&self, query: ListQuery) -> Pin<Box<dyn Future<Output = Result<ListPage, StoreError>> + Send + '_>>;
+
+    /// Streams the key-value pairs matching a query, in ascending key order.
+    ///
+    /// The default implementation walks the results page by page through
+    /// `list_page`, so the whole result set is never held in memory.
+    ///
+    /// # Arguments
+    /// - `query`: The prefix, range, cursor and limit to apply.
+    ///
+    /// # Returns
+    /// A stream yielding each matching pair, or a `StoreError` if fetching a page fails.
+    fn list_stream(&self, query: ListQuery) -> Pin<Box<dyn Stream<Item = Result<StoreModel, StoreError>> + Send + '_>> {
+        Box::pin(ListStream::new(query, move |page| self.list_page(page)))
+    }
+
+    /// Finds the key-value pairs whose value matches a filter, in ascending key order.
+    ///
+    /// The default implementation streams the pairs through `list_stream` and
+    /// matches each value in memory.
+    ///
+    /// # Arguments
+    /// - `query`: The filter, prefix and limit to apply.
+    ///
+    /// # Returns
+    /// - `Ok(Vec<StoreModel>)` with the matching pairs.
+    /// - `Err(StoreError)` if there is an error listing the key-value pairs.
+    fn find(&self, query: FindQuery) -> Pin<Box<dyn Future<Output = Result<Vec<StoreModel>, StoreError>> + Send + '_>> {
+        Box::pin(async move {
+            let mut stream = self.list_stream(ListQuery {
+                prefix: query.prefix.clone(),
+                ..ListQuery::default()
+            });
+            let mut items: Vec<StoreModel> = Vec::new();
+
+            while query.limit.map_or(true, |limit| (items.len() as u64) < limit) {
+                let Some(model) = stream.next().await else {
+                    break;
+                };
+                let model = model?;
+
+                if query.filter.matches(&model.value) {
+                    items.push(model);
+                }
+            }
+
+            Ok(items)
+        })
+    }
+
     /// Sets a value for a given key in the store, with an optional time-to-live (TTL).
     ///
     /// # Arguments
@@ -60,14 +250,109 @@ pub trait Store: Send + Sync {
     /// - `ttl`: An optional u64 representing the time-to-live in seconds.
     ///
     /// # Returns
-    /// - `Ok(())` if the value is successfully set.
+    /// - `Ok(Written)` with the entry as written, if the backend returns it.
     /// - `Err(StoreError)` if there is an error setting the value.
+    #[allow(clippy::type_complexity)]
     fn set(
         &self,
         key: &str,
         value: Value,
         ttl: Option<u64>,
-    ) -> Pin<Box<dyn Future<Output = Result<Option<StoreModel>, StoreError>> + Send + '_>>;
+    ) -> Pin<Box<dyn Future<Output = Result<Written<Option<StoreModel>>, StoreError>> + Send + '_>>;
+
+    /// Sets several values at once, all with the same optional time-to-live (TTL).
+    ///
+    /// The entries are written atomically: either all of them are stored or,
+    /// if any write fails, none of them are.
+    ///
+    /// # Arguments
+    /// - `entries`: The key-value pairs to store.
+    /// - `ttl`: An optional u64 representing the time-to-live in seconds.
+    ///
+    /// # Returns
+    /// - `Ok(Written)` if every value is successfully set.
+    /// - `Err(StoreError)` if there is an error, in which case no value is set.
+    fn set_many(
+        &self,
+        entries: Vec<(String, Value)>,
+        ttl: Option<u64>,
+    ) -> Pin<Box<dyn Future<Output = Result<Written<()>, StoreError>> + Send + '_>>;
+
+    /// Sets a value only if the key is still at the expected version.
+    ///
+    /// # Arguments
+    /// - `key`: The key under which the value is stored.
+    /// - `value`: The value to set.
+    /// - `expected_version`: The version read by the caller, or `None` if the key must not exist.
+    /// - `ttl`: An optional u64 representing the time-to-live in seconds.
+    ///
+    /// # Returns
+    /// - `Ok(Written)` with the new version if the value is successfully set.
+    /// - `Err(StoreError::VersionConflict)` if the key was modified in the meantime.
+    /// - `Err(StoreError)` if there is an error setting the value.
+    fn set_versioned(
+        &self,
+        key: &str,
+        value: Value,
+        expected_version: Option<u64>,
+        ttl: Option<u64>,
+    ) -> Pin<Box<dyn Future<Output = Result<Written<u64>, StoreError>> + Send + '_>>;
+
+    /// Atomically replaces a value if it is equal to `expected`.
+    ///
+    /// The expiry of an existing key is kept.
+    ///
+    /// # Arguments
+    /// - `key`: The key to update.
+    /// - `expected`: The value the key must currently hold, or `None` if the key must not exist.
+    /// - `new`: The value to store.
+    ///
+    /// # Returns
+    /// - `Ok(Written)` with `true` if the value matched and was replaced, or
+    ///   `false` if it did not match and nothing was written.
+    /// - `Err(StoreError)` if there is an error updating the value.
+    fn compare_and_swap(
+        &self,
+        key: &str,
+        expected: Option<Value>,
+        new: Value,
+    ) -> Pin<Box<dyn Future<Output = Result<Written<bool>, StoreError>> + Send + '_>>;
+
+    /// Atomically adds `delta` to an integer value, treating a missing key as `0`.
+    ///
+    /// The expiry of an existing key is kept.
+    ///
+    /// # Arguments
+    /// - `key`: The key of the counter.
+    /// - `delta`: The amount to add, which may be negative.
+    ///
+    /// # Returns
+    /// - `Ok(Written)` with the new value of the counter.
+    /// - `Err(StoreError::InvalidValue)` if the current value is not an integer or the result overflows.
+    /// - `Err(StoreError)` if there is an error updating the value.
+    fn increment(
+        &self,
+        key: &str,
+        delta: i64,
+    ) -> Pin<Box<dyn Future<Output = Result<Written<i64>, StoreError>> + Send + '_>>;
+
+    /// Atomically replaces a value with the result of `f` applied to the current value.
+    ///
+    /// No other write to the store can happen between reading the current value
+    /// and writing the new one. The expiry of an existing key is kept.
+    ///
+    /// # Arguments
+    /// - `key`: The key to update.
+    /// - `f`: Computes the new value from the current one, `None` if the key does not exist.
+    ///
+    /// # Returns
+    /// - `Ok(Written)` with the value that was written.
+    /// - `Err(StoreError)` if `f` fails or there is an error updating the value.
+    fn update<'a>(
+        &'a self,
+        key: &str,
+        f: UpdateFn<'a>,
+    ) -> Pin<Box<dyn Future<Output = Result<Written<Value>, StoreError>> + Send + 'a>>;
 
     /// Removes a value associated with a given key from the store.
     ///
@@ -85,9 +370,9 @@ pub trait Store: Send + Sync {
     /// - `keys`: A slice of string slices representing the keys for the values to be removed.
     ///
     /// # Returns
-    /// - `Ok(())` if the values are successfully removed.
+    /// - `Ok(Vec<String>)` with the keys that existed and were removed.
     /// - `Err(StoreError)` if there is an error removing the values.
-    fn remove_many(&self, keys: &[&str]) -> Pin<Box<dyn Future<Output = Result<(), StoreError>> + Send + '_>>;
+    fn remove_many(&self, keys: &[&str]) -> Pin<Box<dyn Future<Output = Result<Vec<String>, StoreError>> + Send + '_>>;
 
     /// Clears all values from the store.
     ///
@@ -95,12 +380,404 @@ pub trait Store: Send + Sync {
     /// - `Ok(())` if the store is successfully cleared.
     /// - `Err(StoreError)` if there is an error clearing the store.
     fn clear(&self) -> Pin<Box<dyn Future<Output = Result<(), StoreError>> + Send + '_>>;
+
+    /// Permanently deletes every entry whose time-to-live has elapsed.
+    ///
+    /// Expired entries are already hidden from `get` and `list`; this method
+    /// reclaims the space they occupy.
+    ///
+    /// # Returns
+    /// - `Ok(Vec<String>)` with the keys of the entries that were deleted.
+    /// - `Err(StoreError)` if there is an error deleting the entries.
+    fn purge_expired(&self) -> Pin<Box<dyn Future<Output = Result<Vec<String>, StoreError>> + Send + '_>>;
+
+    /// Returns the name of the namespace this store is scoped to.
+    fn namespace_name(&self) -> &str;
+
+    /// Returns the counters of the read cache of the backend.
+    ///
+    /// # Returns
+    /// - `Some(CacheStats)` if the backend caches reads.
+    /// - `None` otherwise, which is the default.
+    fn cache_stats(&self) -> Option<CacheStats> {
+        None
+    }
+
+    /// Writes a consistent copy of the whole backend, every namespace included,
+    /// to a new file while the store stays usable.
+    ///
+    /// # Arguments
+    /// - `path`: The file to create. It must not exist yet.
+    ///
+    /// # Returns
+    /// - `Ok(())` once the copy has been written.
+    /// - `Err(StoreError::Unsupported)` if the backend cannot be copied, which is the default.
+    /// - `Err(StoreError)` if there is an error writing the copy.
+    fn backup(&self, _path: &Path) -> Pin<Box<dyn Future<Output = Result<(), StoreError>> + Send + '_>> {
+        Box::pin(async { Err(StoreError::Unsupported("backups".to_string())) })
+    }
+
+    /// Re-encrypts every value of the namespace, revisions included, with a new key.
+    ///
+    /// The previous key is kept for decryption, so the other namespaces of the
+    /// backend stay readable until they are rotated too.
+    ///
+    /// # Arguments
+    /// - `new_key`: The key to encrypt with, `None` to store the values in the clear.
+    ///
+    /// # Returns
+    /// - `Ok(u64)` with the number of values that were re-encoded.
+    /// - `Err(StoreError::Unsupported)` if the backend does not encrypt values, which is the default.
+    /// - `Err(StoreError)` if a value cannot be decrypted or there is an error writing it.
+    fn rotate_encryption_key(
+        &self,
+        _new_key: Option<[u8; 32]>,
+    ) -> Pin<Box<dyn Future<Output = Result<u64, StoreError>> + Send + '_>> {
+        Box::pin(async { Err(StoreError::Unsupported("encryption".to_string())) })
+    }
+
+    /// Enables or disables the history of the namespace. While enabled, the
+    /// last `limit` values of every key are kept each time it is overwritten
+    /// or removed. Changing the limit drops the revisions beyond it, and
+    /// disabling the history drops every revision.
+    ///
+    /// # Arguments
+    /// - `limit`: The number of revisions kept per key, `None` to disable the history.
+    ///
+    /// # Returns
+    /// - `Ok(())` once the history is configured.
+    /// - `Err(StoreError::Unsupported)` if the backend keeps no history, which is the default.
+    /// - `Err(StoreError)` if there is an error configuring the history.
+    fn set_history(&self, _limit: Option<usize>) -> Pin<Box<dyn Future<Output = Result<(), StoreError>> + Send + '_>> {
+        Box::pin(async { Err(StoreError::Unsupported("history".to_string())) })
+    }
+
+    /// Lists the revisions kept for a key.
+    ///
+    /// # Arguments
+    /// - `key`: The key whose previous values are listed.
+    ///
+    /// # Returns
+    /// - `Ok(Vec<Revision>)` with the revisions, most recent first.
+    /// - `Err(StoreError::Unsupported)` if the backend keeps no history, which is the default.
+    /// - `Err(StoreError)` if there is an error listing the revisions.
+    fn history(&self, _key: &str) -> Pin<Box<dyn Future<Output = Result<Vec<Revision>, StoreError>> + Send + '_>> {
+        Box::pin(async { Err(StoreError::Unsupported("history".to_string())) })
+    }
+
+    /// Retrieves a single revision of a key.
+    ///
+    /// # Arguments
+    /// - `key`: The key the revision belongs to.
+    /// - `id`: The id of the revision.
+    ///
+    /// # Returns
+    /// - `Ok(Some(Revision))` if the key has a revision with that id.
+    /// - `Ok(None)` otherwise.
+    /// - `Err(StoreError::Unsupported)` if the backend keeps no history, which is the default.
+    /// - `Err(StoreError)` if there is an error retrieving the revision.
+    fn get_revision(
+        &self,
+        _key: &str,
+        _id: i64,
+    ) -> Pin<Box<dyn Future<Output = Result<Option<Revision>, StoreError>> + Send + '_>> {
+        Box::pin(async { Err(StoreError::Unsupported("history".to_string())) })
+    }
+
+    /// Writes the value of a revision back to its key, keeping the expiry of
+    /// the key. The value replaced is recorded as a new revision, so a revert
+    /// can be reverted too.
+    ///
+    /// # Arguments
+    /// - `key`: The key to revert.
+    /// - `id`: The id of the revision to restore.
+    ///
+    /// # Returns
+    /// - `Ok(Written)` with the entry as written.
+    /// - `Err(StoreError::NotFound)` if the key has no revision with that id.
+    /// - `Err(StoreError::Unsupported)` if the backend keeps no history, which is the default.
+    /// - `Err(StoreError)` if there is an error writing the value.
+    fn revert(
+        &self,
+        _key: &str,
+        _id: i64,
+    ) -> Pin<Box<dyn Future<Output = Result<Written<StoreModel>, StoreError>> + Send + '_>> {
+        Box::pin(async { Err(StoreError::Unsupported("history".to_string())) })
+    }
+
+    /// Enables or disables the full-text index of the namespace. Enabling it
+    /// indexes the values already stored, and enabling it again rebuilds the index.
+    ///
+    /// # Arguments
+    /// - `enabled`: Whether the values are indexed.
+    ///
+    /// # Returns
+    /// - `Ok(())` once the index is created or dropped.
+    /// - `Err(StoreError::Unsupported)` if the backend cannot search, which is the default.
+    /// - `Err(StoreError)` if there is an error building the index.
+    fn set_search(&self, _enabled: bool) -> Pin<Box<dyn Future<Output = Result<(), StoreError>> + Send + '_>> {
+        Box::pin(async { Err(StoreError::Unsupported("search".to_string())) })
+    }
+
+    /// Searches the values of the namespace, which must have its full-text index enabled.
+    ///
+    /// # Arguments
+    /// - `query`: The terms to search for and the hits to return.
+    ///
+    /// # Returns
+    /// - `Ok(Vec<SearchHit>)` with the matching keys, most relevant first.
+    /// - `Err(StoreError::Unsupported)` if the backend cannot search, which is the
+    ///   default, or the index of the namespace is disabled.
+    /// - `Err(StoreError)` if the query is invalid or there is an error searching.
+    fn search(
+        &self,
+        _query: SearchQuery,
+    ) -> Pin<Box<dyn Future<Output = Result<Vec<SearchHit>, StoreError>> + Send + '_>> {
+        Box::pin(async { Err(StoreError::Unsupported("search".to_string())) })
+    }
+
+    /// Creates a secondary index on a field of the values, which `find` uses for
+    /// filters on that field. Creating an index that exists replaces its field.
+    ///
+    /// # Arguments
+    /// - `name`: The name of the index, made of ASCII letters, digits and underscores.
+    /// - `path`: The dotted path of the indexed field, as used in filters.
+    ///
+    /// # Returns
+    /// - `Ok(())` once the index is created. Stores that cannot index values
+    ///   scan them on every `find`, so this does nothing by default.
+    /// - `Err(StoreError)` if the name or path is invalid, or there is an error creating the index.
+    fn create_index(
+        &self,
+        _name: &str,
+        _path: &str,
+    ) -> Pin<Box<dyn Future<Output = Result<(), StoreError>> + Send + '_>> {
+        Box::pin(async { Ok(()) })
+    }
+
+    /// Drops a secondary index created by `create_index`, if it exists.
+    ///
+    /// # Arguments
+    /// - `name`: The name of the index.
+    ///
+    /// # Returns
+    /// - `Ok(())` once the index is dropped. Does nothing by default.
+    /// - `Err(StoreError)` if there is an error dropping the index.
+    fn drop_index(&self, _name: &str) -> Pin<Box<dyn Future<Output = Result<(), StoreError>> + Send + '_>> {
+        Box::pin(async { Ok(()) })
+    }
+
+    /// Takes the lease on a lock for `ttl`, if no other owner holds an unexpired
+    /// lease on it. Leases are shared by every namespace of the backend.
+    ///
+    /// # Arguments
+    /// - `name`: The name of the lock.
+    /// - `owner`: A token identifying the holder, unique to each acquisition.
+    /// - `ttl`: How long the lease lasts unless it is renewed.
+    ///
+    /// # Returns
+    /// - `Ok(true)` if the lease was taken, `Ok(false)` if another owner holds it.
+    /// - `Err(StoreError::InvalidValue)` if `ttl` is zero.
+    /// - `Err(StoreError::Unsupported)` if the backend has no leases, which is the default.
+    /// - `Err(StoreError)` if there is an error taking the lease.
+    fn acquire_lease(
+        &self,
+        _name: &str,
+        _owner: &str,
+        _ttl: Duration,
+    ) -> Pin<Box<dyn Future<Output = Result<bool, StoreError>> + Send + '_>> {
+        Box::pin(async { Err(StoreError::Unsupported("locks".to_string())) })
+    }
+
+    /// Extends the lease of `owner` on a lock to `ttl` from now.
+    ///
+    /// # Returns
+    /// - `Ok(true)` if the lease was extended, `Ok(false)` if it expired or belongs to another owner.
+    /// - `Err(StoreError::InvalidValue)` if `ttl` is zero.
+    /// - `Err(StoreError::Unsupported)` if the backend has no leases, which is the default.
+    /// - `Err(StoreError)` if there is an error extending the lease.
+    fn renew_lease(
+        &self,
+        _name: &str,
+        _owner: &str,
+        _ttl: Duration,
+    ) -> Pin<Box<dyn Future<Output = Result<bool, StoreError>> + Send + '_>> {
+        Box::pin(async { Err(StoreError::Unsupported("locks".to_string())) })
+    }
+
+    /// Releases the lease of `owner` on a lock. Does nothing if `owner` no longer holds it.
+    ///
+    /// # Returns
+    /// - `Ok(())` once the lease is released.
+    /// - `Err(StoreError::Unsupported)` if the backend has no leases, which is the default.
+    /// - `Err(StoreError)` if there is an error releasing the lease.
+    fn release_lease(
+        &self,
+        _name: &str,
+        _owner: &str,
+    ) -> Pin<Box<dyn Future<Output = Result<(), StoreError>> + Send + '_>> {
+        Box::pin(async { Err(StoreError::Unsupported("locks".to_string())) })
+    }
+
+    /// Returns a store scoped to another namespace of the same backend.
+    ///
+    /// The returned store shares the underlying connection with `self`. Any
+    /// storage it needs is created lazily on first use.
+    ///
+    /// # Arguments
+    /// - `name`: The namespace name.
+    ///
+    /// # Returns
+    /// - `Ok(Arc<dyn Store>)` with the scoped store.
+    /// - `Err(StoreError::InvalidNamespace)` if the name is not allowed by the backend.
+    fn namespace(&self, name: &str) -> Result<Arc<dyn Store>, StoreError>;
+
+    /// Lists the namespaces known to the backend.
+    ///
+    /// # Returns
+    /// - `Ok(Vec<String>)` with the namespace names, sorted by name.
+    /// - `Err(StoreError)` if there is an error listing the namespaces.
+    fn list_namespaces(&self) -> Pin<Box<dyn Future<Output = Result<Vec<String>, StoreError>> + Send + '_>>;
+
+    /// Renames a namespace, keeping all of its entries.
+    ///
+    /// Handles previously obtained for either name should not be used afterwards.
+    ///
+    /// # Arguments
+    /// - `from`: The current namespace name.
+    /// - `to`: The new namespace name.
+    ///
+    /// # Returns
+    /// - `Ok(())` if the namespace is successfully renamed.
+    /// - `Err(StoreError::NotFound)` if `from` does not exist.
+    /// - `Err(StoreError)` if there is an error renaming the namespace.
+    fn rename_namespace(
+        &self,
+        from: &str,
+        to: &str,
+    ) -> Pin<Box<dyn Future<Output = Result<(), StoreError>> + Send + '_>>;
+
+    /// Deletes a namespace together with all of its entries.
+    ///
+    /// # Arguments
+    /// - `name`: The namespace to delete.
+    ///
+    /// # Returns
+    /// - `Ok(())` if the namespace is deleted or did not exist.
+    /// - `Err(StoreError)` if there is an error deleting the namespace.
+    fn drop_namespace(&self, name: &str) -> Pin<Box<dyn Future<Output = Result<(), StoreError>> + Send + '_>>;
+
+    /// Starts a transaction spanning every namespace of the backend.
+    ///
+    /// Other writes to the backend wait until the transaction is committed or
+    /// rolled back. Dropping the transaction without committing rolls it back.
+    ///
+    /// # Returns
+    /// - `Ok(Box<dyn StoreTransaction>)` with the open transaction.
+    /// - `Err(StoreError)` if the transaction cannot be started.
+    #[allow(clippy::type_complexity)]
+    fn begin(&self) -> Pin<Box<dyn Future<Output = Result<Box<dyn StoreTransaction>, StoreError>> + Send + '_>>;
+}
+
+/// An open transaction returned by [`Store::begin`].
+///
+/// Every operation names the namespace it applies to, so a single transaction
+/// can keep related keys in different namespaces consistent.
+pub trait StoreTransaction: Send + Sync {
+    /// Retrieves a value, seeing the writes already made in the transaction.
+    ///
+    /// # Arguments
+    /// - `namespace`: The namespace of the key.
+    /// - `key`: The key for the value to be retrieved.
+    ///
+    /// # Returns
+    /// - `Ok(Some(Value))` if the key exists and has not expired.
+    /// - `Ok(None)` if the key does not exist.
+    /// - `Err(StoreError)` if there is an error retrieving the value.
+    fn get(
+        &self,
+        namespace: &str,
+        key: &str,
+    ) -> Pin<Box<dyn Future<Output = Result<Option<Value>, StoreError>> + Send + '_>>;
+
+    /// Lists the keys of a namespace that have not expired, seeing the writes
+    /// already made in the transaction.
+    ///
+    /// # Arguments
+    /// - `namespace`: The namespace to list.
+    ///
+    /// # Returns
+    /// - `Ok(Vec<String>)` with the keys, in ascending order.
+    /// - `Err(StoreError)` if there is an error listing the keys.
+    fn keys(&self, namespace: &str) -> Pin<Box<dyn Future<Output = Result<Vec<String>, StoreError>> + Send + '_>>;
+
+    /// Sets a value, with an optional time-to-live (TTL) in seconds.
+    ///
+    /// # Arguments
+    /// - `namespace`: The namespace of the key.
+    /// - `key`: The key under which the value is stored.
+    /// - `value`: The value to set.
+    /// - `ttl`: An optional u64 representing the time-to-live in seconds.
+    ///
+    /// # Returns
+    /// - `Ok(Written)` with the keys evicted by the write, if any.
+    /// - `Err(StoreError)` if there is an error setting the value.
+    fn set(
+        &self,
+        namespace: &str,
+        key: &str,
+        value: Value,
+        ttl: Option<u64>,
+    ) -> Pin<Box<dyn Future<Output = Result<Written<()>, StoreError>> + Send + '_>>;
+
+    /// Removes a key.
+    ///
+    /// # Arguments
+    /// - `namespace`: The namespace of the key.
+    /// - `key`: The key to be removed.
+    ///
+    /// # Returns
+    /// - `Ok(())` if the key is removed or did not exist.
+    /// - `Err(StoreError)` if there is an error removing the key.
+    fn remove(&self, namespace: &str, key: &str) -> Pin<Box<dyn Future<Output = Result<(), StoreError>> + Send + '_>>;
+
+    /// Makes every write of the transaction permanent.
+    ///
+    /// # Returns
+    /// - `Ok(())` if the transaction is committed.
+    /// - `Err(StoreError)` if the commit fails or the transaction has already finished.
+    fn commit(&self) -> Pin<Box<dyn Future<Output = Result<(), StoreError>> + Send + '_>>;
+
+    /// Discards every write of the transaction.
+    ///
+    /// # Returns
+    /// - `Ok(())` if the transaction is rolled back.
+    /// - `Err(StoreError)` if the rollback fails or the transaction has already finished.
+    fn rollback(&self) -> Pin<Box<dyn Future<Output = Result<(), StoreError>> + Send + '_>>;
 }
 
 #[derive(thiserror::Error, Debug)]
 pub enum KeyvError {
     #[error("Store error: {0}")]
     StoreError(#[from] StoreError),
+
+    #[error("Transaction aborted: {0}")]
+    TransactionAborted(String),
+
+    #[error("I/O error: {0}")]
+    IoError(#[from] std::io::Error),
+
+    #[error("Backup not found: {0}")]
+    BackupNotFound(String),
+
+    #[error("Database not found: {0}")]
+    DatabaseNotFound(String),
+}
+
+impl From<KeyvError> for tauri::Error {
+    fn from(error: KeyvError) -> Self {
+        tauri::Error::Anyhow(error.into())
+    }
 }
 
 #[derive(thiserror::Error, Debug)]
@@ -126,6 +803,52 @@ pub enum StoreError {
     #[error("The requested key was not found")]
     NotFound,
 
+    #[error("Invalid namespace name: {0}")]
+    InvalidNamespace(String),
+
+    #[error("Version conflict on key {key}: expected {expected:?}, found {actual:?}")]
+    VersionConflict {
+        key: String,
+        expected: Option<u64>,
+        actual: Option<u64>,
+    },
+
+    #[error("Invalid value: {0}")]
+    InvalidValue(String),
+
+    #[error("Invalid query: {0}")]
+    InvalidQuery(String),
+
+    #[error("The transaction has already been committed or rolled back")]
+    TransactionFinished,
+
+    #[error("Failed to encrypt or decrypt a value: {0}")]
+    EncryptionError(String),
+
+    #[error("The store is read-only, {0} is not allowed")]
+    ReadOnly(String),
+
+    #[error("Invalid JSON Schema: {0}")]
+    InvalidSchema(String),
+
+    #[error("The value of {key} does not match its schema: {}", describe_violations(.violations))]
+    SchemaViolation {
+        key: String,
+        violations: Vec<SchemaViolation>,
+    },
+
+    #[error("The store does not support {0}")]
+    Unsupported(String),
+
+    #[error("The value of {key} is {size} bytes, over the limit of {limit} bytes")]
+    ValueTooLarge { key: String, size: usize, limit: usize },
+
+    #[error("The namespace {namespace} would exceed its limit of {limit} keys")]
+    TooManyKeys { namespace: String, limit: u64 },
+
+    #[error("The namespace {namespace} would exceed its limit of {limit} bytes")]
+    QuotaExceeded { namespace: String, limit: u64 },
+
     #[error("An unknown error has occurred")]
     Unknown,
 }
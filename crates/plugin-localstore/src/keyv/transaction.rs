@@ -0,0 +1,198 @@
+// Copyright © 2024 Crabby Craft - All Rights Reserved.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::Value;
+use std::sync::{Arc, Mutex, PoisonError};
+
+use super::{KeyvChange, KeyvChangeKind, KeyvError, StoreError, StoreTransaction};
+
+/// A handle to an open transaction, passed to the closure of `Keyv::transaction`.
+///
+/// The handle is scoped to a namespace like `Keyv` itself; use `namespace` to
+/// read or write another namespace within the same transaction. Changes are
+/// only published to watchers once the transaction commits.
+#[derive(Clone)]
+pub struct KeyvTransaction {
+    inner: Arc<dyn StoreTransaction>,
+    namespace: String,
+    pending: Arc<Mutex<Vec<KeyvChange>>>,
+}
+
+impl KeyvTransaction {
+    pub(super) fn new(inner: Arc<dyn StoreTransaction>, namespace: &str) -> Self {
+        Self {
+            inner,
+            namespace: namespace.to_string(),
+            pending: Arc::new(Mutex::new(Vec::new())),
+        }
+    }
+
+    /// Takes the changes recorded so far, to be published after the commit.
+    pub(super) fn take_changes(&self) -> Vec<KeyvChange> {
+        std::mem::take(&mut *self.pending.lock().unwrap_or_else(PoisonError::into_inner))
+    }
+
+    fn record(&self, kind: KeyvChangeKind, key: &str, value: Option<Value>) {
+        self.pending
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .push(KeyvChange {
+                namespace: self.namespace.clone(),
+                kind,
+                key: Some(key.to_string()),
+                value,
+            });
+    }
+
+    /// Records a remove change for each key evicted by a write.
+    fn record_evicted(&self, evicted: &[String]) {
+        for key in evicted {
+            self.record(KeyvChangeKind::Remove, key, None);
+        }
+    }
+
+    /// Returns a handle to another namespace within the same transaction.
+    ///
+    /// The name is validated by the first operation made through the handle.
+    pub fn namespace(&self, name: &str) -> KeyvTransaction {
+        Self {
+            inner: Arc::clone(&self.inner),
+            namespace: name.to_string(),
+            pending: Arc::clone(&self.pending),
+        }
+    }
+
+    /// Retrieves a value, including the writes already made in the transaction.
+    pub async fn get(&self, key: &str) -> Result<Option<Value>, KeyvError> {
+        Ok(self.inner.get(&self.namespace, key).await?)
+    }
+
+    /// Retrieves a value and deserializes it into `T`.
+    pub async fn get_as<T: DeserializeOwned>(&self, key: &str) -> Result<Option<T>, KeyvError> {
+        match self.inner.get(&self.namespace, key).await? {
+            Some(value) => {
+                let value = serde_json::from_value(value).map_err(|e| StoreError::SerializationError { source: e })?;
+                Ok(Some(value))
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// Lists the keys that have not expired, including the writes already made
+    /// in the transaction.
+    pub async fn keys(&self) -> Result<Vec<String>, KeyvError> {
+        Ok(self.inner.keys(&self.namespace).await?)
+    }
+
+    /// Sets a value for a given key without a TTL.
+    pub async fn set<T: Serialize>(&self, key: &str, value: T) -> Result<(), KeyvError> {
+        let json_value = serde_json::to_value(value).map_err(|e| StoreError::SerializationError { source: e })?;
+        let written = self.inner.set(&self.namespace, key, json_value.clone(), None).await?;
+        self.record(KeyvChangeKind::Set, key, Some(json_value));
+        self.record_evicted(&written.evicted);
+        Ok(())
+    }
+
+    /// Sets a value for a given key with a TTL in seconds.
+    pub async fn set_with_ttl<T: Serialize>(&self, key: &str, value: T, ttl: u64) -> Result<(), KeyvError> {
+        let json_value = serde_json::to_value(value).map_err(|e| StoreError::SerializationError { source: e })?;
+        let written = self
+            .inner
+            .set(&self.namespace, key, json_value.clone(), Some(ttl))
+            .await?;
+        self.record(KeyvChangeKind::Set, key, Some(json_value));
+        self.record_evicted(&written.evicted);
+        Ok(())
+    }
+
+    /// Removes a key.
+    pub async fn remove(&self, key: &str) -> Result<(), KeyvError> {
+        self.inner.remove(&self.namespace, key).await?;
+        self.record(KeyvChangeKind::Remove, key, None);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::keyv::{Keyv, KeyvStoreBuilder};
+    use serde_json::json;
+    use tokio_stream::StreamExt;
+
+    /// Returns a `Keyv` on each backend with transactions.
+    async fn backends() -> Vec<Keyv> {
+        let store = KeyvStoreBuilder::new().uri(":memory:").build().await.unwrap();
+        vec![Keyv::default(), Keyv::try_new(store).await.unwrap()]
+    }
+
+    #[tokio::test]
+    async fn commit_applies_every_write_then_publishes() {
+        for keyv in backends().await {
+            let audit = keyv.namespace("audit").unwrap();
+            let mut changes = keyv.changes();
+
+            let result = keyv
+                .transaction(|tx| async move {
+                    tx.set("balance", 90).await?;
+                    tx.namespace("audit").set("last_withdrawal", 10).await?;
+                    assert_eq!(tx.get("balance").await?, Some(json!(90)));
+                    Ok("done")
+                })
+                .await
+                .unwrap();
+
+            assert_eq!(result, "done");
+            assert_eq!(keyv.get("balance").await.unwrap(), Some(json!(90)));
+            assert_eq!(audit.get("last_withdrawal").await.unwrap(), Some(json!(10)));
+
+            let first = changes.next().await.unwrap();
+            let second = changes.next().await.unwrap();
+            assert_eq!(first.key.as_deref(), Some("balance"));
+            assert_eq!(
+                (second.namespace.as_str(), second.key.as_deref()),
+                ("audit", Some("last_withdrawal"))
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn errors_roll_back_every_write() {
+        for keyv in backends().await {
+            let audit = keyv.namespace("audit").unwrap();
+            keyv.set("balance", 100).await.unwrap();
+            keyv.set("owner", "alice").await.unwrap();
+            let mut changes = keyv.changes();
+
+            let result: Result<(), KeyvError> = keyv
+                .transaction(|tx| async move {
+                    tx.set("balance", 0).await?;
+                    tx.remove("owner").await?;
+                    tx.namespace("audit").set("last_withdrawal", 100).await?;
+                    Err(KeyvError::TransactionAborted("insufficient balance".to_string()))
+                })
+                .await;
+
+            assert!(matches!(result, Err(KeyvError::TransactionAborted(_))));
+            assert_eq!(keyv.get("balance").await.unwrap(), Some(json!(100)));
+            assert_eq!(keyv.get("owner").await.unwrap(), Some(json!("alice")));
+            assert_eq!(audit.get("last_withdrawal").await.unwrap(), None);
+
+            // Nothing was published for the rolled back writes, and the store
+            // accepts writes again.
+            keyv.set("balance", 50).await.unwrap();
+            let change = changes.next().await.unwrap();
+            assert_eq!(
+                (change.key.as_deref(), change.value),
+                (Some("balance"), Some(json!(50)))
+            );
+        }
+    }
+}
@@ -6,11 +6,19 @@
 // option. This file may not be copied, modified, or distributed
 // except according to those terms.
 
+mod backup;
+mod cmd;
+mod config;
+
+pub mod keyv;
 pub mod migration;
 pub mod sql;
 
 mod plugin;
 pub use plugin::*;
 
+pub use backup::*;
+pub use config::*;
+
 mod utils;
 pub use utils::*;
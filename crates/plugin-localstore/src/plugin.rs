@@ -6,17 +6,291 @@
 // option. This file may not be copied, modified, or distributed
 // except according to those terms.
 
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::time::Duration;
+use tauri::async_runtime;
+use tauri::path::BaseDirectory;
 use tauri::plugin::Builder as PluginBuilder;
 use tauri::plugin::TauriPlugin;
-use tauri::Runtime;
-
-pub fn init<R: Runtime>() -> TauriPlugin<R> {
-    PluginBuilder::new("localstore")
-        .setup(|_app, _api| Ok(()))
-        .invoke_handler(tauri::generate_handler![])
-        .on_navigation(|window, url| {
-            log::debug!("navigation {} {url}", window.label());
-            true
-        })
-        .build()
+use tauri::{AppHandle, Emitter, Manager, Runtime};
+use tokio_stream::StreamExt;
+
+use crate::cmd;
+use crate::config::Database;
+use crate::keyv::{JournalMode, Keyv, KeyvChange, KeyvError, KeyvStoreBuilder, Synchronous};
+use crate::{BackupManager, Config, DatabaseConfig, Databases};
+
+/// How often the background task purges expired keys from the store.
+const EXPIRED_SWEEP_INTERVAL: Duration = Duration::from_secs(60);
+
+/// How long a connection waits for a lock held by another one before failing.
+const BUSY_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Number of read-only connections opened next to the writer connection.
+const READ_CONNECTIONS: usize = 4;
+
+/// Name of the event emitted to every webview for each change made to a database.
+/// The payload is a serialized [`KeyvChange`](crate::keyv::KeyvChange), with a
+/// `database` field naming the database unless the change was made in the default one.
+pub const CHANGE_EVENT: &str = "localstore://change";
+
+/// Initializes the plugin with the default options, see [`Builder`].
+pub fn init<R: Runtime>() -> TauriPlugin<R, Option<Config>> {
+    Builder::new().build()
+}
+
+/// Builds the localstore plugin.
+///
+/// The default database is registered as `Keyv` managed state, next to its
+/// `BackupManager` unless it only lives in memory. Named databases are opened next to it, each with its own
+/// backups, and registered as [`Databases`] managed state; the plugin commands
+/// act on one of them when given its name as the `database` argument. Every
+/// option can also be set in `tauri.conf.json`, see [`Config`].
+///
+/// # Examples
+///
+/// ```rust,no_run
+/// use tauri::path::BaseDirectory;
+/// use tauri_plugin_localstore::{Builder, DatabaseConfig};
+///
+/// # fn run(context: tauri::Context) {
+/// tauri::Builder::default()
+///     .plugin(
+///         Builder::new()
+///             .file_name("app")
+///             .base_dir(BaseDirectory::AppData)
+///             .database("cache", DatabaseConfig::new().base_dir(BaseDirectory::AppCache))
+///             .database("session", DatabaseConfig::new().in_memory(true))
+///             .build(),
+///     )
+///     .run(context)
+///     .expect("error while running tauri application");
+/// # }
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct Builder {
+    default: DatabaseConfig,
+    databases: BTreeMap<String, DatabaseConfig>,
+}
+
+impl Builder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the file name of the default database, without the `.db`
+    /// extension. Defaults to the crate name of the app.
+    pub fn file_name<S: Into<String>>(mut self, file_name: S) -> Self {
+        self.default = self.default.file_name(file_name);
+        self
+    }
+
+    /// Sets the directory of the database files. Defaults to `BaseDirectory::AppConfig`.
+    pub fn base_dir(mut self, base_dir: BaseDirectory) -> Self {
+        self.default = self.default.base_dir(base_dir);
+        self
+    }
+
+    /// Sets whether `-debug` is appended to the file names in debug builds,
+    /// so that development builds do not touch the data of installed ones.
+    /// Defaults to `true`.
+    pub fn debug_suffix(mut self, enabled: bool) -> Self {
+        self.default = self.default.debug_suffix(enabled);
+        self
+    }
+
+    /// Keeps the databases in memory only, so their data is lost when the app
+    /// exits. Defaults to `false`.
+    pub fn in_memory(mut self, enabled: bool) -> Self {
+        self.default = self.default.in_memory(enabled);
+        self
+    }
+
+    /// Opens a named database next to the default one. Its unset options fall
+    /// back to the ones of the default database, and its file name to `name`.
+    pub fn database<S: Into<String>>(mut self, name: S, config: DatabaseConfig) -> Self {
+        self.databases.insert(name.into(), config);
+        self
+    }
+
+    pub fn build<R: Runtime>(self) -> TauriPlugin<R, Option<Config>> {
+        PluginBuilder::<R, Option<Config>>::new("localstore")
+            .setup(move |app, api| {
+                let config = api.config().clone().unwrap_or_default();
+                let default = config.default.or(&self.default);
+
+                let mut named = self.databases;
+                for (name, database) in config.databases {
+                    let database = match named.remove(&name) {
+                        Some(builder) => database.or(&builder),
+                        None => database,
+                    };
+                    named.insert(name, database);
+                }
+
+                let databases = async_runtime::block_on(async {
+                    let crate_name = app.package_info().crate_name;
+                    let mut databases = Databases::new(open_database(app, None, &default, crate_name).await?);
+
+                    for (name, database) in named {
+                        let database = database.named(&name, &default);
+                        let database = open_database(app, Some(&name), &database, &name).await?;
+                        databases.insert(name, database);
+                    }
+
+                    Ok::<_, Box<dyn std::error::Error>>(databases)
+                })?;
+
+                let default = databases.default_database().clone();
+
+                app.manage(default.keyv);
+                if let Some(backups) = default.backups {
+                    app.manage(backups);
+                }
+                app.manage(databases);
+
+                Ok(())
+            })
+            .invoke_handler(tauri::generate_handler![
+                cmd::acquire_lock,
+                cmd::clear,
+                cmd::compare_and_swap,
+                cmd::create_backup,
+                cmd::create_index,
+                cmd::disable_history,
+                cmd::disable_search,
+                cmd::drop_index,
+                cmd::enable_history,
+                cmd::enable_search,
+                cmd::export_file,
+                cmd::find,
+                cmd::get,
+                cmd::get_many,
+                cmd::get_revision,
+                cmd::get_versioned,
+                cmd::get_with_meta,
+                cmd::history,
+                cmd::import_file,
+                cmd::increment,
+                cmd::list,
+                cmd::list_backups,
+                cmd::list_page,
+                cmd::release_lock,
+                cmd::remove,
+                cmd::remove_many,
+                cmd::renew_lock,
+                cmd::restore_backup,
+                cmd::revert,
+                cmd::search,
+                cmd::set,
+                cmd::set_many,
+                cmd::set_versioned,
+                cmd::set_with_ttl,
+            ])
+            .on_navigation(|window, url| {
+                log::debug!("navigation {} {url}", window.label());
+                true
+            })
+            .build()
+    }
+}
+
+/// Opens the database configured by `config`, after applying the restore
+/// scheduled for it, and starts its background tasks. `name` is `None` for the
+/// default database.
+async fn open_database<R: Runtime>(
+    app: &AppHandle<R>,
+    name: Option<&str>,
+    config: &DatabaseConfig,
+    default_file_name: &str,
+) -> Result<Database, Box<dyn std::error::Error>> {
+    // In-memory databases have no file, so no directory is created for them
+    // and they cannot be backed up.
+    let (keyv, backups) = if config.is_in_memory() {
+        (open_store(None).await?, None)
+    } else {
+        let db_path = config.path(app, default_file_name)?;
+        let backups = BackupManager::new(&db_path);
+        backups.apply_pending_restore().await?;
+        (open_store(Some(&db_path)).await?, Some(backups))
+    };
+
+    spawn_expired_sweeper(keyv.clone(), EXPIRED_SWEEP_INTERVAL);
+    spawn_change_forwarder(app.clone(), name.map(str::to_string), keyv.clone());
+
+    Ok(Database { keyv, backups })
+}
+
+/// Opens a database file, or an in-memory database if `path` is `None`.
+async fn open_store(path: Option<&Path>) -> Result<Keyv, KeyvError> {
+    let store = match path {
+        Some(path) => {
+            KeyvStoreBuilder::new()
+                .uri(path)
+                .journal_mode(JournalMode::Wal)
+                .synchronous(Synchronous::Normal)
+                .busy_timeout(BUSY_TIMEOUT)
+                .read_connections(READ_CONNECTIONS)
+                .build()
+                .await?
+        }
+        None => KeyvStoreBuilder::new().uri(":memory:").build().await?,
+    };
+
+    Keyv::try_new(store).await
+}
+
+/// The payload of [`CHANGE_EVENT`]: a change, with the name of the database
+/// it was made in unless it is the default one.
+#[derive(Serialize)]
+struct ChangeEvent<'a> {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    database: Option<&'a str>,
+    #[serde(flatten)]
+    change: &'a KeyvChange,
+}
+
+/// Spawns a task that re-emits every store change as a Tauri event, so that
+/// all windows observe writes made by other windows or by Rust code.
+fn spawn_change_forwarder<R: Runtime>(app: AppHandle<R>, database: Option<String>, keyv: Keyv) {
+    let mut changes = keyv.changes();
+
+    async_runtime::spawn(async move {
+        while let Some(change) = changes.next().await {
+            let event = ChangeEvent {
+                database: database.as_deref(),
+                change: &change,
+            };
+
+            if let Err(e) = app.emit(CHANGE_EVENT, &event) {
+                log::error!("Failed to emit the store change event: {}", e);
+            }
+        }
+    });
+}
+
+/// Spawns a task that periodically deletes expired keys from every namespace
+/// for as long as the application is running.
+fn spawn_expired_sweeper(keyv: Keyv, interval: Duration) {
+    async_runtime::spawn(async move {
+        let mut ticker = tokio::time::interval(interval);
+
+        loop {
+            ticker.tick().await;
+
+            if let Err(e) = purge_all_namespaces(&keyv).await {
+                log::error!("Failed to purge expired keys: {}", e);
+            }
+        }
+    });
+}
+
+async fn purge_all_namespaces(keyv: &Keyv) -> Result<(), KeyvError> {
+    for name in keyv.namespaces().await? {
+        keyv.namespace(&name)?.purge_expired().await?;
+    }
+
+    Ok(())
 }
@@ -26,23 +26,39 @@ use tauri::{AppHandle, Manager, Runtime};
 ///
 /// The path to the application's database file.
 pub fn get_db_path<R: Runtime>(handle: &AppHandle<R>, file_name: Option<String>) -> tauri::Result<PathBuf> {
-    let pkg_name = handle.package_info().crate_name.to_string();
-    let debug = cfg!(debug_assertions);
+    let file_name = file_name.unwrap_or_else(|| handle.package_info().crate_name.to_string());
+    resolve_db_path(handle, &file_name, BaseDirectory::AppConfig, true)
+}
 
-    let db_file_name = match file_name {
-        Some(file) if debug => format!("{file}-debug.db"),
-        Some(file) => format!("{file}.db"),
-        None if debug => format!("{pkg_name}-debug.db"),
-        None => format!("{pkg_name}.db"),
+/// Resolves the path of a database file and creates its directory.
+///
+/// # Arguments
+///
+/// * `handle` - The application handle.
+/// * `file_name` - The file name of the database, without the `.db` extension.
+/// * `base_dir` - The directory the file is stored in.
+/// * `debug_suffix` - Whether `-debug` is appended to the file name in debug builds,
+///   so that development builds do not touch the data of installed ones.
+///
+/// # Returns
+///
+/// The path to the database file.
+pub fn resolve_db_path<R: Runtime>(
+    handle: &AppHandle<R>,
+    file_name: &str,
+    base_dir: BaseDirectory,
+    debug_suffix: bool,
+) -> tauri::Result<PathBuf> {
+    let db_file_name = if debug_suffix && cfg!(debug_assertions) {
+        format!("{file_name}-debug.db")
+    } else {
+        format!("{file_name}.db")
     };
 
-    let db_file_path = handle
-        .path()
-        .resolve(db_file_name, BaseDirectory::AppConfig)
-        .expect("failed to get db file path");
+    let db_file_path = handle.path().resolve(db_file_name, base_dir)?;
 
     if let Some(parent) = db_file_path.parent() {
-        fs::create_dir_all(parent).unwrap_or_else(|err| panic!("Failed to create application data directory: {}", err));
+        fs::create_dir_all(parent)?;
     }
 
     Ok(db_file_path)